@@ -0,0 +1,40 @@
+use anyhow::Result;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+
+use abstutil::{prettyprint_usize, Timer};
+use map_model::Map;
+
+pub async fn run(
+    map: String,
+    population_areas: Option<String>,
+    population_property: String,
+    scenario_name: String,
+    rng_seed: u64,
+) -> Result<()> {
+    let mut rng = XorShiftRng::seed_from_u64(rng_seed);
+    let map = Map::load_synchronously(map, &mut Timer::throwaway());
+
+    let source = match population_areas {
+        Some(path) => popdat::CensusSource::LocalFile {
+            path,
+            population_property,
+        },
+        None => popdat::CensusSource::Remote,
+    };
+    let scenario = popdat::generate_scenario_from_source(
+        &scenario_name,
+        &source,
+        popdat::Config::default(),
+        &map,
+        &mut rng,
+    )
+    .await?;
+    println!(
+        "Generated {} people. Wrote {}",
+        prettyprint_usize(scenario.people.len()),
+        abstio::path_scenario(&scenario.map_name, &scenario.scenario_name)
+    );
+    scenario.save();
+    Ok(())
+}
@@ -5,13 +5,16 @@
 extern crate log;
 
 mod augment_scenario;
+mod census_scenario;
 mod clip_osm;
 mod generate_houses;
 mod geojson_to_osmosis;
 mod import_grid2demand;
 mod import_scenario;
 mod one_step_import;
+mod optimize_signal_offsets;
 mod pick_geofabrik;
+mod traffic_assignment;
 
 use anyhow::Result;
 use structopt::StructOpt;
@@ -57,6 +60,26 @@ enum Command {
         #[structopt(long, default_value = "42")]
         rng_seed: u64,
     },
+    /// Generates a scenario from census data, by assigning people to homes and giving them
+    /// daily schedules.
+    CensusScenario {
+        /// The path to a map to generate a scenario for
+        #[structopt(long)]
+        map: String,
+        /// The path to a FlatGeobuf (.fgb) or GeoJSON file with polygons describing population.
+        /// If omitted, download the areas covering the map from A/B Street's S3 bucket.
+        #[structopt(long)]
+        population_areas: Option<String>,
+        /// When reading a local file, which property has the number of people in each area
+        #[structopt(long, default_value = "population")]
+        population_property: String,
+        /// The name of the scenario to generate
+        #[structopt(long, default_value = "census")]
+        scenario_name: String,
+        /// A seed for generating random numbers
+        #[structopt(long, default_value = "42")]
+        rng_seed: u64,
+    },
     /// Clips an OSM file to a boundary. This is a simple Rust port of `osmconvert large_map.osm
     /// -B=clipping.poly --complete-ways -o=smaller_map.osm`.
     ClipOSM {
@@ -181,6 +204,56 @@ enum Command {
         #[structopt(flatten)]
         opts: map_model::RawToMapOptions,
     },
+    /// Calculates traffic signal offsets for a corridor, so vehicles traveling at the speed limit
+    /// hit a green wave. The result is saved as map edits.
+    OptimizeSignalOffsets {
+        /// The path to a map
+        #[structopt(long)]
+        map: String,
+        /// The numeric IDs of traffic signals along the corridor, in order, separated by commas
+        #[structopt(long)]
+        corridor: String,
+        /// Which way to optimize for: forwards (in the order listed), backwards, or both
+        #[structopt(long, default_value = "both")]
+        direction: String,
+        /// The name of the map edits to write
+        #[structopt(long, default_value = "optimized offsets")]
+        edits_name: String,
+        /// If specified, simulate this scenario before and after changing the offsets, then
+        /// compare the delay at the signals.
+        #[structopt(long)]
+        validate_scenario: Option<String>,
+        /// How long to simulate, like "1:00:00" for one hour
+        #[structopt(long, default_value = "1:00:00")]
+        validate_duration: String,
+    },
+    /// Finds routes for drivers that account for congestion, using dynamic traffic assignment.
+    /// The scenario is simulated repeatedly, with some drivers switching to a faster route after
+    /// each day, until few drivers could do better. The final routes are saved as a new scenario,
+    /// so simulating that reproduces them.
+    AssignTraffic {
+        /// The path to a scenario
+        #[structopt(long)]
+        input: String,
+        /// The name of the scenario to write. Defaults to the input name with "_assigned" added.
+        #[structopt(long)]
+        output_name: Option<String>,
+        /// Stop after simulating this many days, even if the routes haven't converged
+        #[structopt(long, default_value = "10")]
+        max_iterations: usize,
+        /// Stop once the relative gap (the total time drivers could save by switching routes,
+        /// divided by the total time spent driving) drops below this
+        #[structopt(long, default_value = "0.01")]
+        convergence_gap: f64,
+        /// After each day, what fraction of the drivers who could do better switch routes?
+        #[structopt(long, default_value = "0.2")]
+        replan_fraction: f64,
+        /// A seed for choosing which drivers switch routes
+        #[structopt(long, default_value = "42")]
+        rng_seed: u64,
+        #[structopt(flatten)]
+        opts: sim::SimOptions,
+    },
     /// Regenerate all maps and scenarios from scratch.
     RegenerateEverything {
         /// If this command is being run in the cloud, parallelize the jobs by specifying which
@@ -228,6 +301,22 @@ async fn main() -> Result<()> {
             add_lunch_trips,
             rng_seed,
         } => augment_scenario::run(input_scenario, add_return_trips, add_lunch_trips, rng_seed),
+        Command::CensusScenario {
+            map,
+            population_areas,
+            population_property,
+            scenario_name,
+            rng_seed,
+        } => {
+            census_scenario::run(
+                map,
+                population_areas,
+                population_property,
+                scenario_name,
+                rng_seed,
+            )
+            .await?
+        }
         Command::ClipOSM {
             pbf_path,
             clip_path,
@@ -274,6 +363,40 @@ async fn main() -> Result<()> {
             filter_crosswalks,
             opts,
         } => importer::oneshot(osm_input, clip_path, drive_on_left, filter_crosswalks, opts),
+        Command::OptimizeSignalOffsets {
+            map,
+            corridor,
+            direction,
+            edits_name,
+            validate_scenario,
+            validate_duration,
+        } => optimize_signal_offsets::run(
+            map,
+            corridor,
+            direction,
+            edits_name,
+            validate_scenario,
+            validate_duration,
+        )?,
+        Command::AssignTraffic {
+            input,
+            output_name,
+            max_iterations,
+            convergence_gap,
+            replan_fraction,
+            rng_seed,
+            opts,
+        } => traffic_assignment::run(
+            input,
+            output_name,
+            sim::DynamicTrafficAssignment {
+                max_iterations,
+                convergence_gap,
+                replan_fraction,
+                rng_seed,
+            },
+            opts,
+        )?,
         Command::RegenerateEverything {
             shard_num,
             num_shards,
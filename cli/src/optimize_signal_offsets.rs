@@ -0,0 +1,121 @@
+use anyhow::{bail, Result};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+
+use abstutil::Timer;
+use geom::Duration;
+use map_model::signal_offsets::{optimize, GreenWave};
+use map_model::{IntersectionID, Map};
+use sim::{Scenario, Sim, SimFlags, SimOptions};
+
+pub fn run(
+    map: String,
+    corridor: String,
+    direction: String,
+    edits_name: String,
+    validate_scenario: Option<String>,
+    validate_duration: String,
+) -> Result<()> {
+    let mut timer = Timer::new("optimize signal offsets");
+    let mut map = Map::load_synchronously(map, &mut timer);
+
+    let corridor = corridor
+        .split(',')
+        .map(|x| x.trim().parse::<usize>().map(IntersectionID))
+        .collect::<Result<Vec<_>, _>>()?;
+    let direction = match direction.as_ref() {
+        "forwards" => GreenWave::Forwards,
+        "backwards" => GreenWave::Backwards,
+        "both" => GreenWave::Both,
+        x => bail!("Unknown direction {}; use forwards, backwards, or both", x),
+    };
+
+    let result = optimize(&map, &corridor, direction)?;
+    println!("Cycle length: {}", result.cycle_length);
+    for (i, offset) in &result.offsets {
+        println!(
+            "  {}: offset {} -> {}",
+            i,
+            map.get_traffic_signal(*i).offset,
+            offset
+        );
+    }
+    println!("Forwards bandwidth: {}", result.forwards_bandwidth);
+    println!("Backwards bandwidth: {}", result.backwards_bandwidth);
+
+    let cmds = result.to_edit_cmds(&map);
+    if cmds.is_empty() {
+        println!("The current offsets are already optimal");
+        return Ok(());
+    }
+
+    let before = validate_scenario
+        .as_ref()
+        .map(|path| simulate(&map, path, &validate_duration, &corridor, &mut timer))
+        .transpose()?;
+
+    let mut edits = map.get_edits().clone();
+    edits.edits_name = edits_name;
+    edits.commands.extend(cmds);
+    map.must_apply_edits(edits, &mut timer);
+    map.save_edits();
+    println!(
+        "Wrote {}",
+        abstio::path_edits(map.get_name(), &map.get_edits().edits_name)
+    );
+
+    if let Some((before_delay, before_count)) = before {
+        let (after_delay, after_count) = simulate(
+            &map,
+            validate_scenario.as_ref().unwrap(),
+            &validate_duration,
+            &corridor,
+            &mut timer,
+        )?;
+        println!(
+            "Delay along the corridor before: {} total for {} agents ({} average)",
+            before_delay,
+            before_count,
+            average(before_delay, before_count)
+        );
+        println!(
+            "Delay along the corridor after: {} total for {} agents ({} average)",
+            after_delay,
+            after_count,
+            average(after_delay, after_count)
+        );
+    }
+
+    Ok(())
+}
+
+/// Run part of a scenario, then measure delay at the signals.
+fn simulate(
+    map: &Map,
+    scenario: &str,
+    duration: &str,
+    corridor: &[IntersectionID],
+    timer: &mut Timer,
+) -> Result<(Duration, usize)> {
+    let scenario: Scenario = abstio::maybe_read_binary(scenario.to_string(), timer)?;
+    if scenario.map_name != *map.get_name() {
+        bail!(
+            "The scenario is for {}, not {}",
+            scenario.map_name.describe(),
+            map.get_name().describe()
+        );
+    }
+    let mut rng = XorShiftRng::seed_from_u64(SimFlags::RNG_SEED);
+    let mut sim = Sim::new(map, SimOptions::new("optimize_signal_offsets"));
+    scenario.instantiate(&mut sim, map, &mut rng, timer);
+    sim.timed_step(map, Duration::parse(duration)?, &mut None, timer);
+    Ok(sim.get_analytics().total_intersection_delay(corridor))
+}
+
+fn average(total: Duration, count: usize) -> Duration {
+    if count == 0 {
+        Duration::ZERO
+    } else {
+        total / (count as f64)
+    }
+}
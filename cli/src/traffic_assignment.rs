@@ -0,0 +1,45 @@
+use anyhow::Result;
+
+use abstutil::Timer;
+use map_model::Map;
+use sim::{DynamicTrafficAssignment, Scenario, SimOptions};
+
+pub fn run(
+    input: String,
+    output_name: Option<String>,
+    dta: DynamicTrafficAssignment,
+    opts: SimOptions,
+) -> Result<()> {
+    let mut timer = Timer::new("dynamic traffic assignment");
+    let scenario: Scenario = abstio::maybe_read_binary(input, &mut timer)?;
+    let map = Map::load_synchronously(scenario.map_name.path(), &mut timer);
+    let output_name = output_name.unwrap_or_else(|| format!("{}_assigned", scenario.scenario_name));
+
+    let (mut scenario, results) = dta.run(scenario, &map, &opts, &mut timer);
+    for (idx, result) in results.iter().enumerate() {
+        println!(
+            "Day {}: relative gap {:.4}, {} total driving time over {} trips, {} switched routes",
+            idx + 1,
+            result.relative_gap,
+            result.total_driving_time,
+            result.num_trips,
+            result.num_replanned
+        );
+    }
+    if let Some(result) = results.last() {
+        if result.relative_gap > dta.convergence_gap {
+            println!(
+                "Didn't converge after {} days; the routes might not be in equilibrium",
+                results.len()
+            );
+        }
+    }
+
+    scenario.scenario_name = output_name;
+    scenario.save();
+    println!(
+        "Wrote {}",
+        abstio::path_scenario(&scenario.map_name, &scenario.scenario_name)
+    );
+    Ok(())
+}
@@ -0,0 +1,316 @@
+//! Read digital elevation models directly from local files, without needing any external tools.
+//! Two formats are supported:
+//!
+//! - SRTM `.hgt` tiles, like `N47W122.hgt`, covering one degree of longitude and latitude each
+//! - GeoTIFF rasters using WGS84 coordinates (EPSG:4326)
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::Result;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+
+use geom::{Distance, GPSBounds, LonLat};
+
+/// SRTM tiles use this value to indicate missing data.
+const SRTM_VOID: i16 = -32768;
+
+/// A bunch of elevation rasters, all overlapping some area of interest.
+pub struct ElevationData {
+    tiles: Vec<Tile>,
+}
+
+impl ElevationData {
+    /// Recursively look through a directory for `.hgt` and `.tif` files, keeping the ones that
+    /// overlap `bounds`. Files that can't be parsed are skipped with a warning.
+    pub fn load(dir: &str, bounds: &GPSBounds) -> Result<ElevationData> {
+        let mut paths = Vec::new();
+        find_files(Path::new(dir), &mut paths)?;
+        paths.sort();
+
+        let mut tiles = Vec::new();
+        for path in paths {
+            let ext = path
+                .extension()
+                .and_then(|x| x.to_str())
+                .map(|x| x.to_ascii_lowercase());
+            let result = match ext.as_deref() {
+                Some("hgt") => {
+                    // Check the bounds before reading the file; SRTM tiles are huge.
+                    match srtm_corner(&path) {
+                        Some(corner) if overlaps(bounds, corner, 1.0, 1.0) => read_srtm(&path),
+                        _ => continue,
+                    }
+                }
+                Some("tif") | Some("tiff") => read_geotiff(&path),
+                _ => continue,
+            };
+            match result {
+                Ok(tile) => {
+                    if tile.overlaps(bounds) {
+                        info!("Using elevation data from {}", path.display());
+                        tiles.push(tile);
+                    }
+                }
+                Err(err) => {
+                    warn!("Skipping elevation data from {}: {}", path.display(), err);
+                }
+            }
+        }
+        if tiles.is_empty() {
+            bail!("No .hgt or .tif files in {} cover this map", dir);
+        }
+        Ok(ElevationData { tiles })
+    }
+
+    /// Bilinearly interpolate the elevation at some point. Returns `None` if no raster covers the
+    /// point or the data there is missing.
+    pub fn get(&self, pt: LonLat) -> Option<Distance> {
+        self.tiles
+            .iter()
+            .find_map(|tile| tile.get(pt))
+            .map(Distance::meters)
+    }
+}
+
+/// A regular grid of elevation samples in meters. Row 0 is the northernmost row.
+struct Tile {
+    /// The position of the center of the top-left sample
+    top_left: LonLat,
+    /// Degrees of longitude between columns
+    dx: f64,
+    /// Degrees of latitude between rows; positive
+    dy: f64,
+    width: usize,
+    height: usize,
+    /// Missing data is NaN
+    values: Vec<f64>,
+}
+
+impl Tile {
+    fn overlaps(&self, bounds: &GPSBounds) -> bool {
+        let min_lat = self.top_left.y() - self.dy * (self.height - 1) as f64;
+        overlaps(
+            bounds,
+            LonLat::new(self.top_left.x(), min_lat),
+            self.dx * (self.width - 1) as f64,
+            self.dy * (self.height - 1) as f64,
+        )
+    }
+
+    fn get(&self, pt: LonLat) -> Option<f64> {
+        let col = (pt.x() - self.top_left.x()) / self.dx;
+        let row = (self.top_left.y() - pt.y()) / self.dy;
+        if col < 0.0 || row < 0.0 {
+            return None;
+        }
+        let (col0, row0) = (col.floor() as usize, row.floor() as usize);
+        if col0 >= self.width || row0 >= self.height {
+            return None;
+        }
+        // On the last row or column, just reuse the same sample
+        let col1 = (col0 + 1).min(self.width - 1);
+        let row1 = (row0 + 1).min(self.height - 1);
+        let (fx, fy) = (col - col0 as f64, row - row0 as f64);
+
+        let v00 = self.values[row0 * self.width + col0];
+        let v01 = self.values[row0 * self.width + col1];
+        let v10 = self.values[row1 * self.width + col0];
+        let v11 = self.values[row1 * self.width + col1];
+        let value = (v00 * (1.0 - fx) + v01 * fx) * (1.0 - fy) + (v10 * (1.0 - fx) + v11 * fx) * fy;
+        if value.is_finite() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+}
+
+fn overlaps(bounds: &GPSBounds, min: LonLat, width: f64, height: f64) -> bool {
+    min.x() <= bounds.max_lon
+        && min.x() + width >= bounds.min_lon
+        && min.y() <= bounds.max_lat
+        && min.y() + height >= bounds.min_lat
+}
+
+fn find_files(dir: &Path, results: &mut Vec<std::path::PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_files(&path, results)?;
+        } else {
+            results.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Parse the southwest corner from a name like `N47W122.hgt`.
+fn srtm_corner(path: &Path) -> Option<LonLat> {
+    let name = path.file_stem()?.to_str()?.to_ascii_uppercase();
+    if name.len() != 7 {
+        return None;
+    }
+    let lat = name[1..3].parse::<f64>().ok()?;
+    let lon = name[4..7].parse::<f64>().ok()?;
+    let lat = match &name[0..1] {
+        "N" => lat,
+        "S" => -lat,
+        _ => return None,
+    };
+    let lon = match &name[3..4] {
+        "E" => lon,
+        "W" => -lon,
+        _ => return None,
+    };
+    Some(LonLat::new(lon, lat))
+}
+
+fn read_srtm(path: &Path) -> Result<Tile> {
+    let corner = srtm_corner(path).unwrap();
+    let bytes = std::fs::read(path)?;
+    // SRTM1 tiles are 3601x3601, SRTM3 are 1201x1201. Both are big-endian i16s.
+    let size = ((bytes.len() / 2) as f64).sqrt() as usize;
+    if size < 2 || size * size * 2 != bytes.len() {
+        bail!("{} bytes isn't a square SRTM tile", bytes.len());
+    }
+    let values = bytes
+        .chunks_exact(2)
+        .map(|pair| {
+            let x = i16::from_be_bytes([pair[0], pair[1]]);
+            if x == SRTM_VOID {
+                f64::NAN
+            } else {
+                x as f64
+            }
+        })
+        .collect();
+    let spacing = 1.0 / (size - 1) as f64;
+    Ok(Tile {
+        top_left: LonLat::new(corner.x(), corner.y() + 1.0),
+        dx: spacing,
+        dy: spacing,
+        width: size,
+        height: size,
+        values,
+    })
+}
+
+/// Only single-band GeoTIFFs in WGS84 are handled; there's no reprojection. The georeferencing is
+/// read from the tiepoint and pixel scale tags, assuming pixels represent areas.
+fn read_geotiff(path: &Path) -> Result<Tile> {
+    let mut decoder = Decoder::new(BufReader::new(File::open(path)?))?;
+    let (width, height) = decoder.dimensions()?;
+    let scale = decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag)?;
+    let tiepoint = decoder.get_tag_f64_vec(Tag::ModelTiepointTag)?;
+    if scale.len() < 2 || tiepoint.len() < 6 {
+        bail!("Missing GeoTIFF georeferencing");
+    }
+    let nodata = decoder
+        .get_tag_ascii_string(Tag::GdalNodata)
+        .ok()
+        .and_then(|x| x.trim_matches(char::from(0)).trim().parse::<f64>().ok());
+
+    let (dx, dy) = (scale[0], scale[1]);
+    // The tiepoint maps raster (i, j) to model (x, y). Shift to the center of the top-left pixel.
+    let top_left = LonLat::new(
+        tiepoint[3] - tiepoint[0] * dx + dx / 2.0,
+        tiepoint[4] + tiepoint[1] * dy - dy / 2.0,
+    );
+
+    let mut values: Vec<f64> = match decoder.read_image()? {
+        DecodingResult::U8(x) => x.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::U16(x) => x.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::U32(x) => x.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::U64(x) => x.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::I8(x) => x.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::I16(x) => x.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::I32(x) => x.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::I64(x) => x.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::F32(x) => x.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::F64(x) => x,
+    };
+    let (width, height) = (width as usize, height as usize);
+    if values.len() != width * height {
+        bail!(
+            "Expected {}x{} samples, but got {}; only single-band rasters are supported",
+            width,
+            height,
+            values.len()
+        );
+    }
+    if let Some(nodata) = nodata {
+        for x in &mut values {
+            if *x == nodata {
+                *x = f64::NAN;
+            }
+        }
+    }
+
+    Ok(Tile {
+        top_left,
+        dx,
+        dy,
+        width,
+        height,
+        values,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_srtm() {
+        assert_eq!(
+            srtm_corner(Path::new("N47W122.hgt")),
+            Some(LonLat::new(-122.0, 47.0))
+        );
+        assert_eq!(
+            srtm_corner(Path::new("s33e151.HGT")),
+            Some(LonLat::new(151.0, -33.0))
+        );
+        assert_eq!(srtm_corner(Path::new("elevation.hgt")), None);
+
+        // A tiny 3x3 tile, with the northwest sample missing
+        let dir = std::env::temp_dir().join(format!("abst_dem_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("N47W122.hgt");
+        let samples: Vec<i16> = vec![SRTM_VOID, 10, 20, 30, 40, 50, 60, 70, 80];
+        let bytes: Vec<u8> = samples.iter().flat_map(|x| x.to_be_bytes()).collect();
+        std::fs::write(&path, bytes).unwrap();
+        let tile = read_srtm(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!((tile.width, tile.height), (3, 3));
+        assert_eq!(tile.top_left, LonLat::new(-122.0, 48.0));
+        assert_eq!((tile.dx, tile.dy), (0.5, 0.5));
+
+        // Exactly on a sample
+        assert_eq!(tile.get(LonLat::new(-121.5, 47.5)), Some(40.0));
+        assert_eq!(tile.get(LonLat::new(-121.0, 47.0)), Some(80.0));
+        // Between samples in the southeast, interpolate
+        assert_eq!(tile.get(LonLat::new(-121.25, 47.25)), Some(60.0));
+        assert_eq!(tile.get(LonLat::new(-121.5, 47.25)), Some(55.0));
+        // Next to the missing sample
+        assert_eq!(tile.get(LonLat::new(-121.75, 47.75)), None);
+        // Off the tile
+        assert_eq!(tile.get(LonLat::new(-122.5, 47.5)), None);
+        assert_eq!(tile.get(LonLat::new(-121.5, 46.5)), None);
+    }
+
+    #[test]
+    fn test_bad_srtm() {
+        let dir = std::env::temp_dir().join(format!("abst_bad_dem_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("N47W122.hgt");
+        // 3 samples don't make a square
+        std::fs::write(&path, vec![0u8; 6]).unwrap();
+        let result = read_srtm(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_err());
+    }
+}
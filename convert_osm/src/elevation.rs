@@ -7,7 +7,9 @@ use anyhow::Result;
 use geom::{Distance, PolyLine};
 use map_model::raw::{OriginalRoad, RawMap};
 
-pub fn add_data(map: &mut RawMap) -> Result<()> {
+use crate::dem::ElevationData;
+
+pub fn add_data_from_docker(map: &mut RawMap) -> Result<()> {
     // TODO It'd be nice to include more timing breakdown here, but if we bail out early,
     // it's tedious to call timer.stop().
     let ids = generate_input(map)?;
@@ -133,6 +135,40 @@ fn scrape_output(map: &mut RawMap, ids: Vec<OriginalRoad>) -> Result<()> {
         bail!("Output had {} lines, but we made {} queries", cnt, num_ids);
     }
 
+    calculate_inclines(map);
+    Ok(())
+}
+
+/// Instead of running an external tool, read SRTM or GeoTIFF files from
+/// `data/input/shared/elevation` directly. These have to be downloaded manually.
+pub fn add_data_from_local_files(map: &mut RawMap) -> Result<()> {
+    let data = ElevationData::load(&abstio::path_shared_input("elevation"), &map.gps_bounds)?;
+
+    // Inclines only depend on the elevation where roads end, so just look up each intersection.
+    // Unlike the Docker tool, there's no need to sample points along the roads.
+    let mut missing = 0;
+    for i in map.intersections.values_mut() {
+        match data.get(i.point.to_gps(&map.gps_bounds)) {
+            Some(elevation) => {
+                i.elevation = elevation;
+            }
+            None => {
+                missing += 1;
+            }
+        }
+    }
+    if missing > 0 {
+        warn!(
+            "{} intersections aren't covered by any elevation data",
+            missing
+        );
+    }
+
+    calculate_inclines(map);
+    Ok(())
+}
+
+fn calculate_inclines(map: &mut RawMap) {
     // Calculate the incline for each road here, before the road gets trimmed for intersection
     // geometry. If we did this after trimming, we'd miss some of the horizontal distance.
     for (id, road) in &mut map.roads {
@@ -154,6 +190,4 @@ fn scrape_output(map: &mut RawMap, ids: Vec<OriginalRoad>) -> Result<()> {
             );
         }
     }
-
-    Ok(())
 }
@@ -6,11 +6,13 @@ use abstio::MapName;
 use abstutil::{Tags, Timer};
 use geom::{Distance, FindClosest, HashablePt2D, Polygon, Pt2D, Ring};
 use kml::{ExtraShape, ExtraShapes};
-use map_model::raw::{RawArea, RawBuilding, RawMap, RawParkingLot, RawRoad, RestrictionType};
+use map_model::raw::{
+    RawArea, RawBikeShareDock, RawBuilding, RawMap, RawParkingLot, RawRoad, RestrictionType,
+};
 use map_model::{osm, Amenity, AreaType, Direction, DrivingSide, NamePerLanguage};
 
 use crate::osm_geom::{get_multipolygon_members, glue_multipolygon, multipoly_geometry};
-use crate::{transit, Options};
+use crate::{transit, Options, DEFAULT_DOCK_CAPACITY};
 
 pub struct OsmExtract {
     /// Unsplit roads
@@ -79,6 +81,19 @@ pub fn extract_osm(map: &mut RawMap, opts: &Options, timer: &mut Timer) -> OsmEx
         for amenity in get_bldg_amenities(&node.tags) {
             out.amenities.push((node.pt, amenity));
         }
+        if is_bike_share_dock(&node.tags) && map.boundary_polygon.contains_pt(node.pt) {
+            map.bike_share_docks.push(RawBikeShareDock {
+                point: node.pt,
+                name: node.tags.get("name").cloned(),
+                capacity: node
+                    .tags
+                    .get("capacity")
+                    .and_then(|x| x.parse::<usize>().ok())
+                    .unwrap_or(DEFAULT_DOCK_CAPACITY),
+                osm_id: Some(*id),
+                gbfs_station_id: None,
+            });
+        }
     }
 
     // and cycleways
@@ -551,6 +566,12 @@ fn is_bldg(tags: &Tags) -> bool {
     tags.contains_key("building") && !tags.contains_key("abandoned:man_made")
 }
 
+/// Docked bike-share and e-scooter stations. Shops that rent bikes aren't included.
+fn is_bike_share_dock(tags: &Tags) -> bool {
+    tags.is_any("amenity", vec!["bicycle_rental", "kick-scooter_rental"])
+        && !tags.is("bicycle_rental", "shop")
+}
+
 fn get_bldg_amenities(tags: &Tags) -> Vec<Amenity> {
     let mut amenities = Vec::new();
     for key in ["amenity", "shop", "craft", "office", "tourism", "leisure"] {
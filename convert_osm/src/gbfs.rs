@@ -0,0 +1,79 @@
+//! Read bike-share and e-scooter docks from a GBFS feed's `station_information.json`. See
+//! <https://github.com/MobilityData/gbfs/blob/master/gbfs.md#station_informationjson>. Live
+//! availability from `station_status.json` isn't used; the simulation tracks its own inventory.
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use abstutil::Timer;
+use geom::LonLat;
+use map_model::raw::{RawBikeShareDock, RawMap};
+
+use crate::DEFAULT_DOCK_CAPACITY;
+
+#[derive(Deserialize)]
+struct StationInformation {
+    data: Data,
+}
+
+#[derive(Deserialize)]
+struct Data {
+    stations: Vec<Station>,
+}
+
+#[derive(Deserialize)]
+struct Station {
+    station_id: String,
+    name: Name,
+    lat: f64,
+    lon: f64,
+    capacity: Option<usize>,
+}
+
+/// GBFS v2 names a station with one string. v3 gives a list of translations instead.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Name {
+    Plain(String),
+    Localized(Vec<LocalizedString>),
+}
+
+#[derive(Deserialize)]
+struct LocalizedString {
+    text: String,
+}
+
+impl Name {
+    /// Just use the first translation
+    fn into_string(self) -> Option<String> {
+        match self {
+            Name::Plain(name) => Some(name),
+            Name::Localized(names) => names.into_iter().next().map(|name| name.text),
+        }
+    }
+}
+
+/// Replace any docks found in OSM with the stations in the feed that're inside the map boundary.
+pub fn import_stations(map: &mut RawMap, path: &str, timer: &mut Timer) -> Result<()> {
+    let feed: StationInformation = abstio::maybe_read_json(path.to_string(), timer)?;
+    map.bike_share_docks.clear();
+    for station in feed.data.stations {
+        let point = LonLat::new(station.lon, station.lat).to_pt(&map.gps_bounds);
+        if !map.boundary_polygon.contains_pt(point) {
+            continue;
+        }
+        map.bike_share_docks.push(RawBikeShareDock {
+            point,
+            name: station.name.into_string(),
+            capacity: station.capacity.unwrap_or(DEFAULT_DOCK_CAPACITY),
+            osm_id: None,
+            gbfs_station_id: Some(station.station_id),
+        });
+    }
+    info!(
+        "Imported {} bike-share docks from {}",
+        map.bike_share_docks.len(),
+        path
+    );
+    Ok(())
+}
@@ -15,8 +15,10 @@ use map_model::{osm, raw, Amenity, MapConfig};
 use serde::{Deserialize, Serialize};
 
 mod clip;
+mod dem;
 mod elevation;
 mod extract;
+mod gbfs;
 pub mod osm_geom;
 mod parking;
 pub mod reader;
@@ -43,8 +45,15 @@ pub struct Options {
     pub skip_local_roads: bool,
     /// Only include crosswalks that match a `highway=crossing` OSM node.
     pub filter_crosswalks: bool,
+    pub elevation: ElevationSource,
+    /// If provided, read bike-share docks from this GBFS `station_information.json` file instead
+    /// of using `amenity=bicycle_rental` and `amenity=kick-scooter_rental` from OSM.
+    pub bike_share_stations: Option<String>,
 }
 
+/// How many vehicles fit at a bike-share dock, if the source data doesn't say
+pub(crate) const DEFAULT_DOCK_CAPACITY: usize = 10;
+
 /// What roads will have on-street parking lanes? Data from
 /// <https://wiki.openstreetmap.org/wiki/Key:parking:lane> is always used if available.
 #[derive(Clone, Serialize, Deserialize)]
@@ -80,6 +89,25 @@ pub enum PrivateOffstreetParking {
     // TODO Based on the number of residents?
 }
 
+/// Where should elevation data for intersections come from?
+#[derive(Clone, Serialize, Deserialize)]
+pub enum ElevationSource {
+    /// Every road will be flat.
+    None,
+    /// Run the <https://github.com/eldang/elevation_lookups> tool through Docker. It downloads
+    /// and caches data sources in `data/input/shared/elevation`.
+    Docker,
+    /// Read SRTM `.hgt` tiles or GeoTIFF DEMs already present in `data/input/shared/elevation`.
+    /// No external dependencies needed.
+    LocalFiles,
+}
+
+impl Default for ElevationSource {
+    fn default() -> ElevationSource {
+        ElevationSource::Docker
+    }
+}
+
 pub fn convert(opts: Options, timer: &mut abstutil::Timer) -> RawMap {
     let mut map = RawMap::blank(opts.name.clone());
     if let Some(ref path) = opts.clip {
@@ -90,6 +118,14 @@ pub fn convert(opts: Options, timer: &mut abstutil::Timer) -> RawMap {
     }
 
     let extract = extract::extract_osm(&mut map, &opts, timer);
+    if let Some(ref path) = opts.bike_share_stations {
+        if let Err(err) = gbfs::import_stations(&mut map, path, timer) {
+            error!(
+                "Keeping bike-share docks from OSM; couldn't read {}: {}",
+                path, err
+            );
+        }
+    }
     let (amenities, crosswalks, pt_to_road) = split_ways::split_up_roads(&mut map, extract, timer);
     clip::clip_map(&mut map, timer);
 
@@ -118,7 +154,12 @@ pub fn convert(opts: Options, timer: &mut abstutil::Timer) -> RawMap {
 
     // TODO Make this bail out on failure, after the new dependencies are clearly explained.
     timer.start("add elevation data");
-    if let Err(err) = elevation::add_data(&mut map) {
+    let result = match opts.elevation {
+        ElevationSource::None => Ok(()),
+        ElevationSource::Docker => elevation::add_data_from_docker(&mut map),
+        ElevationSource::LocalFiles => elevation::add_data_from_local_files(&mut map),
+    };
+    if let Err(err) = result {
         error!("No elevation data: {}", err);
     }
     timer.stop("add elevation data");
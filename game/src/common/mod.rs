@@ -425,6 +425,9 @@ pub fn color_for_mode(app: &App, m: TripMode) -> Color {
         TripMode::Bike => app.cs.unzoomed_bike,
         TripMode::Transit => app.cs.unzoomed_bus,
         TripMode::Drive => app.cs.unzoomed_car,
+        TripMode::Carpool => app.cs.carpool_trip,
+        TripMode::RideHail => app.cs.ride_hail_trip,
+        TripMode::BikeShare => app.cs.bike_share_trip,
     }
 }
 
@@ -446,6 +449,8 @@ pub fn color_for_trip_phase(app: &App, tpt: TripPhaseType) -> Color {
         TripPhaseType::Parking => app.cs.parking_trip,
         TripPhaseType::WaitingForBus(_, _) => app.cs.bus_layer,
         TripPhaseType::RidingBus(_, _, _) => app.cs.bus_trip,
+        TripPhaseType::WaitingForRide => app.cs.bus_layer,
+        TripPhaseType::RidingInCar(_) => app.cs.carpool_trip,
         TripPhaseType::Cancelled | TripPhaseType::Finished => unreachable!(),
         TripPhaseType::DelayedStart => Color::YELLOW,
     }
@@ -55,6 +55,10 @@ impl ChangeDuration {
                         Line("Variable timing").small_heading(),
                         Line(" (Set either values below to 0 to use fixed timing."),
                     ],
+                    StageType::Actuated { .. } => vec![
+                        Line("Actuated timing").small_heading(),
+                        Line(" (Applying changes here replaces the detector-based timing)"),
+                    ],
                 })
                 .into_widget(ctx)
                 .named("timing type"),
@@ -67,7 +71,7 @@ impl ChangeDuration {
                         "additional",
                         (Duration::ZERO, Duration::minutes(5)),
                         match signal.stages[idx].stage_type {
-                            StageType::Fixed(_) => Duration::ZERO,
+                            StageType::Fixed(_) | StageType::Actuated { .. } => Duration::ZERO,
                             StageType::Variable(_, _, additional) => additional,
                         },
                         Duration::seconds(1.0),
@@ -82,7 +86,7 @@ impl ChangeDuration {
                         "delay",
                         (Duration::ZERO, Duration::seconds(300.0)),
                         match signal.stages[idx].stage_type {
-                            StageType::Fixed(_) => Duration::ZERO,
+                            StageType::Fixed(_) | StageType::Actuated { .. } => Duration::ZERO,
                             StageType::Variable(_, delay, _) => delay,
                         },
                         Duration::seconds(1.0),
@@ -169,6 +173,93 @@ impl SimpleState<App> for ChangeDuration {
     }
 }
 
+pub struct ChangePlanStartTime {
+    plan: usize,
+}
+
+impl ChangePlanStartTime {
+    /// `plan` must not be the default plan, which always starts at midnight.
+    pub fn new_state(
+        ctx: &mut EventCtx,
+        signal: &ControlTrafficSignal,
+        plan: usize,
+    ) -> Box<dyn State<App>> {
+        // Plans have to stay in order
+        let earliest = if plan == 1 {
+            Duration::minutes(1)
+        } else {
+            signal.plans[plan - 2].start_time + Duration::minutes(1)
+        };
+        let latest = if plan == signal.plans.len() {
+            Duration::hours(24) - Duration::minutes(1)
+        } else {
+            signal.plans[plan].start_time - Duration::minutes(1)
+        };
+        let panel = Panel::new_builder(Widget::col(vec![
+            Widget::row(vec![
+                Line("When should this timing plan start?")
+                    .small_heading()
+                    .into_widget(ctx),
+                ctx.style().btn_close_widget(ctx),
+            ]),
+            Widget::row(vec![
+                "Time after midnight:".text_widget(ctx).centered_vert(),
+                Spinner::widget(
+                    ctx,
+                    "start time",
+                    (earliest, latest),
+                    signal.plans[plan - 1].start_time,
+                    Duration::minutes(1),
+                ),
+            ]),
+            Line("The plan lasts until the next one starts")
+                .secondary()
+                .into_widget(ctx),
+            ctx.style()
+                .btn_solid_primary
+                .text("Apply")
+                .hotkey(Key::Enter)
+                .build_def(ctx),
+        ]))
+        .build(ctx);
+        <dyn SimpleState<_>>::new_state(panel, Box::new(ChangePlanStartTime { plan }))
+    }
+}
+
+impl SimpleState<App> for ChangePlanStartTime {
+    fn on_click(&mut self, _: &mut EventCtx, _: &mut App, x: &str, panel: &Panel) -> Transition {
+        match x {
+            "close" => Transition::Pop,
+            "Apply" => {
+                let start_time = panel.spinner("start time");
+                let plan = self.plan;
+                Transition::Multi(vec![
+                    Transition::Pop,
+                    Transition::ModifyState(Box::new(move |state, ctx, app| {
+                        let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
+                        let idx = editor.current_stage;
+                        editor.add_new_edit(ctx, app, idx, |ts| {
+                            ts.plans[plan - 1].start_time = start_time;
+                        });
+                    })),
+                ])
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn other_event(&mut self, ctx: &mut EventCtx, _: &mut App) -> Transition {
+        if ctx.normal_left_click() && ctx.canvas.get_cursor_in_screen_space().is_none() {
+            return Transition::Pop;
+        }
+        Transition::Keep
+    }
+
+    fn draw_baselayer(&self) -> DrawBaselayer {
+        DrawBaselayer::PreviousState
+    }
+}
+
 pub fn edit_entire_signal(
     ctx: &mut EventCtx,
     app: &App,
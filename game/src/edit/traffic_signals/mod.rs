@@ -3,13 +3,13 @@ use std::collections::{BTreeSet, VecDeque};
 use anyhow::Result;
 
 use abstutil::Timer;
-use geom::{Distance, Line, Polygon, Pt2D};
+use geom::{Distance, Duration, Line, Polygon, Pt2D, Time};
 use map_gui::options::TrafficSignalStyle;
 use map_gui::render::{traffic_signal, DrawMovement, DrawOptions};
 use map_gui::tools::PopupMsg;
 use map_model::{
     ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, MovementID, Stage, StageType,
-    TurnPriority,
+    TimingPlan, TurnPriority,
 };
 use widgetry::{
     include_labeled_bytes, lctrl, Color, ControlState, DragDrop, DrawBaselayer, Drawable, EventCtx,
@@ -36,6 +36,9 @@ pub struct TrafficSignalEditor {
 
     mode: GameplayMode,
     members: BTreeSet<IntersectionID>,
+    // Only one timing plan is edited at a time. It's swapped into the default plan's place on the
+    // map; see current_bundle.
+    current_plan: usize,
     current_stage: usize,
 
     movements: Vec<DrawMovement>,
@@ -74,9 +77,10 @@ impl TrafficSignalEditor {
         synced.apply(app);
 
         let mut editor = TrafficSignalEditor {
-            side_panel: make_side_panel(ctx, app, &members, 0),
+            side_panel: make_side_panel(ctx, app, &members, 0, 0),
             top_panel: make_top_panel(ctx, app, false, false),
             mode,
+            current_plan: 0,
             current_stage: 0,
             movements: Vec::new(),
             movement_selected: None,
@@ -95,17 +99,30 @@ impl TrafficSignalEditor {
 
     fn change_stage(&mut self, ctx: &mut EventCtx, app: &App, idx: usize) {
         if self.current_stage == idx {
-            let mut new = make_side_panel(ctx, app, &self.members, self.current_stage);
+            let mut new = make_side_panel(
+                ctx,
+                app,
+                &self.members,
+                self.current_plan,
+                self.current_stage,
+            );
             new.restore(ctx, &self.side_panel);
             self.side_panel = new;
         } else {
             self.current_stage = idx;
-            self.side_panel = make_side_panel(ctx, app, &self.members, self.current_stage);
+            self.side_panel = make_side_panel(
+                ctx,
+                app,
+                &self.members,
+                self.current_plan,
+                self.current_stage,
+            );
         }
 
         self.recalc_draw_current(ctx, app);
     }
 
+    /// The edit operates on the plan currently being edited, as if it was the default plan.
     fn add_new_edit<F: Fn(&mut ControlTrafficSignal)>(
         &mut self,
         ctx: &mut EventCtx,
@@ -113,18 +130,82 @@ impl TrafficSignalEditor {
         idx: usize,
         fxn: F,
     ) {
-        let mut bundle = BundleEdits::get_current(app, &self.members);
-        self.command_stack.push(bundle.clone());
+        self.command_stack.push(self.current_bundle(app));
         self.redo_stack.clear();
+        let mut bundle = BundleEdits::get_current(app, &self.members);
         for ts in &mut bundle.signals {
             fxn(ts);
         }
+        // If the whole signal was replaced, the other plans are gone
+        if bundle
+            .signals
+            .iter()
+            .any(|ts| self.current_plan > ts.plans.len())
+        {
+            self.current_plan = 0;
+        }
         bundle.apply(app);
 
         self.top_panel = make_top_panel(ctx, app, true, false);
         self.change_stage(ctx, app, idx);
     }
 
+    /// The current state of all signals, with every plan in its proper place. The undo stack and
+    /// the final edits use this form. On the map itself, the plan being edited is swapped with the
+    /// default plan, so that everything else in the editor can just look at `stages`.
+    fn current_bundle(&self, app: &App) -> BundleEdits {
+        let mut bundle = BundleEdits::get_current(app, &self.members);
+        bundle.swap_plan(self.current_plan);
+        bundle
+    }
+
+    /// The opposite of `current_bundle`.
+    fn apply_bundle(&mut self, app: &mut App, mut bundle: BundleEdits) {
+        // Undoing might remove the plan being edited
+        if bundle
+            .signals
+            .iter()
+            .any(|ts| self.current_plan > ts.plans.len())
+        {
+            self.current_plan = 0;
+        }
+        bundle.swap_plan(self.current_plan);
+        bundle.apply(app);
+    }
+
+    fn change_plan(&mut self, ctx: &mut EventCtx, app: &mut App, plan: usize) {
+        let bundle = self.current_bundle(app);
+        self.current_plan = plan;
+        self.apply_bundle(app, bundle);
+        self.current_stage = 0;
+        self.side_panel = make_side_panel(ctx, app, &self.members, self.current_plan, 0);
+        self.recalc_draw_current(ctx, app);
+    }
+
+    /// Changes to the set of plans don't fit into add_new_edit, since they affect more than the
+    /// current plan.
+    fn edit_plans<F: Fn(&mut ControlTrafficSignal)>(
+        &mut self,
+        ctx: &mut EventCtx,
+        app: &mut App,
+        new_plan: usize,
+        fxn: F,
+    ) {
+        let mut bundle = self.current_bundle(app);
+        self.command_stack.push(bundle.clone());
+        self.redo_stack.clear();
+        for ts in &mut bundle.signals {
+            fxn(ts);
+        }
+        self.current_plan = new_plan;
+        self.apply_bundle(app, bundle);
+
+        self.top_panel = make_top_panel(ctx, app, true, false);
+        self.current_stage = 0;
+        self.side_panel = make_side_panel(ctx, app, &self.members, self.current_plan, 0);
+        self.recalc_draw_current(ctx, app);
+    }
+
     fn recalc_draw_current(&mut self, ctx: &mut EventCtx, app: &App) {
         let mut batch = GeomBatch::new();
         let mut movements = Vec::new();
@@ -253,6 +334,55 @@ impl State<App> for TrafficSignalEditor {
                     self.change_stage(ctx, app, self.current_stage - 1);
                     return Transition::Keep;
                 }
+                "previous plan" => {
+                    self.change_plan(ctx, app, self.current_plan - 1);
+                    return Transition::Keep;
+                }
+                "next plan" => {
+                    self.change_plan(ctx, app, self.current_plan + 1);
+                    return Transition::Keep;
+                }
+                "add a new plan" => {
+                    let last_start = canonical_signal
+                        .plans
+                        .last()
+                        .map(|p| p.start_time)
+                        .unwrap_or(Duration::ZERO);
+                    let start_time = last_start + Duration::hours(1);
+                    if start_time >= Duration::hours(24) {
+                        return Transition::Push(PopupMsg::new_state(
+                            ctx,
+                            "Error",
+                            vec!["There's no room left in the day for another plan"],
+                        ));
+                    }
+                    // Start from a copy of the current plan
+                    let new_plan = canonical_signal.plans.len() + 1;
+                    let stages = canonical_signal.stages.clone();
+                    let offset = canonical_signal.offset;
+                    self.edit_plans(ctx, app, new_plan, |ts| {
+                        ts.plans.push(TimingPlan {
+                            start_time,
+                            stages: stages.clone(),
+                            offset,
+                        });
+                    });
+                    return Transition::Keep;
+                }
+                "delete plan" => {
+                    let plan = self.current_plan;
+                    self.edit_plans(ctx, app, plan - 1, |ts| {
+                        ts.plans.remove(plan - 1);
+                    });
+                    return Transition::Keep;
+                }
+                "change plan start time" => {
+                    return Transition::Push(edits::ChangePlanStartTime::new_state(
+                        ctx,
+                        canonical_signal,
+                        self.current_plan,
+                    ));
+                }
                 "next stage" => {
                     self.change_stage(ctx, app, self.current_stage + 1);
                     return Transition::Keep;
@@ -280,7 +410,7 @@ impl State<App> for TrafficSignalEditor {
                 "Finish" => {
                     if let Some(bundle) = check_for_missing_turns(app, &self.members) {
                         bundle.apply(app);
-                        self.command_stack.push(bundle);
+                        self.command_stack.push(self.current_bundle(app));
                         self.redo_stack.clear();
 
                         self.top_panel = make_top_panel(ctx, app, true, false);
@@ -305,14 +435,14 @@ impl State<App> for TrafficSignalEditor {
                             vec!["This signal configuration is somehow invalid; check the console logs"]
                         ));
                     } else {
-                        let changes = BundleEdits::get_current(app, &self.members);
+                        let changes = self.current_bundle(app);
                         self.original.apply(app);
                         changes.commit(ctx, app);
                         return Transition::Pop;
                     }
                 }
                 "Cancel" => {
-                    if BundleEdits::get_current(app, &self.members) == self.original {
+                    if self.current_bundle(app) == self.original {
                         self.original.apply(app);
                         return Transition::Pop;
                     }
@@ -336,8 +466,9 @@ impl State<App> for TrafficSignalEditor {
 
                     // First commit the current changes, so we enter SignalPicker with clean state.
                     // This UX flow is a little unintuitive.
-                    let changes = check_for_missing_turns(app, &self.members)
+                    let mut changes = check_for_missing_turns(app, &self.members)
                         .unwrap_or_else(|| BundleEdits::get_current(app, &self.members));
+                    changes.swap_plan(self.current_plan);
                     self.original.apply(app);
                     changes.commit(ctx, app);
                     return Transition::Replace(picker::SignalPicker::new_state(
@@ -347,7 +478,7 @@ impl State<App> for TrafficSignalEditor {
                     ));
                 }
                 "Export" => {
-                    for signal in BundleEdits::get_current(app, &self.members).signals {
+                    for signal in self.current_bundle(app).signals {
                         let ts = signal.export(&app.primary.map);
                         abstio::write_json(
                             format!("traffic_signal_data/{}.json", ts.intersection_osm_node_id),
@@ -356,6 +487,9 @@ impl State<App> for TrafficSignalEditor {
                     }
                 }
                 "Preview" => {
+                    // TODO When editing a plan besides the default, the preview treats it as the
+                    // default plan.
+
                     // Might have to do this first!
                     app.primary
                         .map
@@ -369,19 +503,19 @@ impl State<App> for TrafficSignalEditor {
                     ));
                 }
                 "undo" => {
-                    self.redo_stack
-                        .push(BundleEdits::get_current(app, &self.members));
-                    self.command_stack.pop().unwrap().apply(app);
+                    self.redo_stack.push(self.current_bundle(app));
+                    let bundle = self.command_stack.pop().unwrap();
+                    self.apply_bundle(app, bundle);
                     self.top_panel = make_top_panel(ctx, app, !self.command_stack.is_empty(), true);
-                    self.change_stage(ctx, app, 0);
+                    self.change_plan(ctx, app, self.current_plan);
                     return Transition::Keep;
                 }
                 "redo" => {
-                    self.command_stack
-                        .push(BundleEdits::get_current(app, &self.members));
-                    self.redo_stack.pop().unwrap().apply(app);
+                    self.command_stack.push(self.current_bundle(app));
+                    let bundle = self.redo_stack.pop().unwrap();
+                    self.apply_bundle(app, bundle);
                     self.top_panel = make_top_panel(ctx, app, true, !self.redo_stack.is_empty());
-                    self.change_stage(ctx, app, 0);
+                    self.change_plan(ctx, app, self.current_plan);
                     return Transition::Keep;
                 }
                 _ => unreachable!(),
@@ -604,6 +738,7 @@ fn make_side_panel(
     ctx: &mut EventCtx,
     app: &App,
     members: &BTreeSet<IntersectionID>,
+    plan: usize,
     selected: usize,
 ) -> Panel {
     let map = &app.primary.map;
@@ -642,6 +777,62 @@ fn make_side_panel(
     }
     let mut col = vec![txt.into_widget(ctx)];
 
+    // Plan controls. Editing plans for many signals at once isn't supported yet.
+    if members.len() == 1 {
+        let num_plans = canonical_signal.plans.len() + 1;
+        col.push(
+            Widget::row(vec![
+                ctx.style()
+                    .btn_plain
+                    .icon_bytes(include_labeled_bytes!(
+                        "../../../../widgetry/icons/arrow_left.svg"
+                    ))
+                    .disabled(plan == 0)
+                    .build_widget(ctx, "previous plan"),
+                ctx.style()
+                    .btn_plain
+                    .icon_bytes(include_labeled_bytes!(
+                        "../../../../widgetry/icons/arrow_right.svg"
+                    ))
+                    .disabled(plan == num_plans - 1)
+                    .build_widget(ctx, "next plan"),
+                if plan == 0 {
+                    format!("Timing plan 1 of {}, starting at midnight", num_plans)
+                } else {
+                    format!(
+                        "Timing plan {} of {}, starting at {}",
+                        plan + 1,
+                        num_plans,
+                        (Time::START_OF_DAY + canonical_signal.plans[plan - 1].start_time)
+                            .ampm_tostring()
+                    )
+                }
+                .text_widget(ctx)
+                .centered_vert(),
+                if plan == 0 {
+                    Widget::nothing()
+                } else {
+                    Widget::row(vec![
+                        ctx.style()
+                            .btn_plain
+                            .icon("system/assets/tools/pencil.svg")
+                            .build_widget(ctx, "change plan start time"),
+                        ctx.style()
+                            .btn_solid_destructive
+                            .icon("system/assets/tools/trash.svg")
+                            .build_widget(ctx, "delete plan"),
+                    ])
+                },
+                ctx.style()
+                    .btn_plain
+                    .icon("system/assets/speed/plus.svg")
+                    .build_widget(ctx, "add a new plan"),
+            ])
+            .padding(10)
+            .bg(app.cs.inner_panel_bg),
+        );
+    }
+
     // Stage controls
     col.push(
         Widget::row(vec![
@@ -665,6 +856,11 @@ fn make_side_panel(
                     "Stage duration: {}, {}, {} (variable)",
                     min, delay, additional
                 ),
+                StageType::Actuated {
+                    min_green,
+                    max_green,
+                    ..
+                } => format!("Stage duration: {} to {} (actuated)", min_green, max_green),
             }
             .text_widget(ctx)
             .centered_vert(),
@@ -706,6 +902,7 @@ fn make_side_panel(
                 match canonical_signal.stages[idx].stage_type {
                     StageType::Fixed(d) => format!("{}", d),
                     StageType::Variable(min, _, _) => format!("{} (v)", min),
+                    StageType::Actuated { min_green, .. } => format!("{} (a)", min_green),
                 },
             )))
             .render(ctx),
@@ -772,6 +969,12 @@ fn make_side_panel(
 }
 
 impl BundleEdits {
+    fn swap_plan(&mut self, plan: usize) {
+        for ts in &mut self.signals {
+            ts.swap_plan(plan);
+        }
+    }
+
     fn apply(&self, app: &mut App) {
         for s in &self.signals {
             app.primary.map.incremental_edit_traffic_signal(s.clone());
@@ -1,5 +1,5 @@
 use abstutil::{prettyprint_usize, Counter};
-use geom::{Circle, Distance, Time};
+use geom::{Circle, Distance, Duration, Time};
 use map_gui::tools::ColorNetwork;
 use map_gui::ID;
 use map_model::{BusRoute, BusRouteID, BusStopID, PathStep};
@@ -38,15 +38,25 @@ fn stop_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusStopID
         );
         details.hyperlinks.insert(label, Tab::BusRoute(r.id));
 
-        let arrivals: Vec<(Time, CarID)> = all_arrivals
+        let arrivals: Vec<(Time, CarID, Option<Duration>)> = all_arrivals
             .iter()
-            .filter(|(_, _, route, stop)| r.id == *route && id == *stop)
-            .map(|(t, car, _, _)| (*t, *car))
+            .filter(|(_, _, route, stop, _)| r.id == *route && id == *stop)
+            .map(|(t, car, _, _, lateness)| (*t, *car, *lateness))
             .collect();
         let mut txt = Text::new();
-        if let Some((t, _)) = arrivals.last() {
+        if let Some((t, _, lateness)) = arrivals.last() {
             // TODO Button to jump to the bus
             txt.add_line(Line(format!("  Last bus arrived {} ago", sim.time() - *t)).secondary());
+            if let Some(lateness) = lateness {
+                txt.add_line(
+                    Line(if *lateness >= Duration::ZERO {
+                        format!("  ({} behind schedule)", lateness)
+                    } else {
+                        format!("  ({} ahead of schedule)", -*lateness)
+                    })
+                    .secondary(),
+                );
+            }
         } else {
             txt.add_line(Line("  No arrivals yet").secondary());
         }
@@ -225,6 +235,25 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusRoute
         }
     }
 
+    if let Some(cv) = app
+        .primary
+        .sim
+        .get_analytics()
+        .headway_coefficient_of_variation(id)
+    {
+        rows.push(
+            Text::from_all(vec![
+                Line("Bunching"),
+                Line(format!(
+                    ": headways vary by {:.0}% (coefficient of variation)",
+                    cv * 100.0
+                ))
+                .secondary(),
+            ])
+            .into_widget(ctx),
+        );
+    }
+
     let mut boardings: Counter<BusStopID> = Counter::new();
     let mut alightings: Counter<BusStopID> = Counter::new();
     let mut waiting: Counter<BusStopID> = Counter::new();
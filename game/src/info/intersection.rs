@@ -314,13 +314,23 @@ fn traffic_signal_body(ctx: &mut EventCtx, app: &App, id: IntersectionID) -> Wid
     let bbox = Polygon::rectangle(zoom * bounds.width(), zoom * bounds.height());
 
     let signal = app.primary.map.get_traffic_signal(id);
+    // Describe the plan in effect right now
+    let plan = app.primary.sim.current_signal_plan(id);
+    let stages = signal.plan_stages(plan);
     {
         let mut txt = Text::new();
-        txt.add_line(Line(format!("{} stages", signal.stages.len())).small_heading());
-        txt.add_line(format!("Signal offset: {}", signal.offset));
+        txt.add_line(Line(format!("{} stages", stages.len())).small_heading());
+        if !signal.plans.is_empty() {
+            txt.add_line(format!(
+                "Currently using timing plan {} of {}",
+                plan + 1,
+                signal.plans.len() + 1
+            ));
+        }
+        txt.add_line(format!("Signal offset: {}", signal.plan_offset(plan)));
         {
             let mut total = Duration::ZERO;
-            for s in &signal.stages {
+            for s in stages {
                 total += s.stage_type.simple_duration();
             }
             // TODO Say "normally" or something?
@@ -329,7 +339,7 @@ fn traffic_signal_body(ctx: &mut EventCtx, app: &App, id: IntersectionID) -> Wid
         rows.push(txt.into_widget(ctx));
     }
 
-    for (idx, stage) in signal.stages.iter().enumerate() {
+    for (idx, stage) in stages.iter().enumerate() {
         rows.push(
             match stage.stage_type {
                 StageType::Fixed(d) => Line(format!("Stage {}: {}", idx + 1, d)),
@@ -340,6 +350,18 @@ fn traffic_signal_body(ctx: &mut EventCtx, app: &App, id: IntersectionID) -> Wid
                     delay,
                     additional
                 )),
+                StageType::Actuated {
+                    min_green,
+                    passage_time,
+                    max_green,
+                    ..
+                } => Line(format!(
+                    "Stage {}: {} to {}, extended by {} (actuated)",
+                    idx + 1,
+                    min_green,
+                    max_green,
+                    passage_time
+                )),
             }
             .into_widget(ctx),
         );
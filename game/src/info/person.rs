@@ -146,8 +146,10 @@ fn trips_body(
                     ctx.prerender,
                     match trip.mode {
                         TripMode::Walk => "system/assets/meters/pedestrian.svg",
-                        TripMode::Bike => "system/assets/meters/bike.svg",
-                        TripMode::Drive => "system/assets/meters/car.svg",
+                        TripMode::Bike | TripMode::BikeShare => "system/assets/meters/bike.svg",
+                        TripMode::Drive | TripMode::Carpool | TripMode::RideHail => {
+                            "system/assets/meters/car.svg"
+                        }
                         TripMode::Transit => "system/assets/meters/bus.svg",
                     },
                 )
@@ -628,7 +630,13 @@ fn header(
                     AgentID::Car(c) => match c.vehicle_type {
                         VehicleType::Car => ("driving", Some("system/assets/meters/car.svg")),
                         VehicleType::Bike => ("biking", Some("system/assets/meters/bike.svg")),
-                        VehicleType::Bus | VehicleType::Train => unreachable!(),
+                        VehicleType::Bus
+                        | VehicleType::Train
+                        | VehicleType::Emergency
+                        | VehicleType::Van
+                        | VehicleType::Truck => {
+                            unreachable!()
+                        }
                     },
                     AgentID::BusPassenger(_, _) => {
                         ("riding a bus", Some("system/assets/meters/bus.svg"))
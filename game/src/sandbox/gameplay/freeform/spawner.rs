@@ -48,6 +48,8 @@ impl AgentSpawner {
                         TripMode::Drive,
                         TripMode::all()
                             .into_iter()
+                            // Passengers need somebody to give them a ride
+                            .filter(|m| *m != TripMode::Carpool)
                             .map(|m| Choice::new(m.ongoing_verb(), m))
                             .collect(),
                     ),
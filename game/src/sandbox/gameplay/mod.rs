@@ -140,7 +140,12 @@ impl GameplayMode {
             let mut rng = sim::fork_rng(&mut rng);
 
             LoadScenario::Future(Box::pin(async move {
-                let areas = popdat::CensusArea::fetch_all_for_map(&map_area, &map_bounds).await?;
+                let areas = popdat::CensusArea::fetch_all_for_map(
+                    &popdat::CensusSource::Remote,
+                    &map_area,
+                    &map_bounds,
+                )
+                .await?;
 
                 let scenario_from_app: Box<dyn Send + FnOnce(&App) -> Scenario> =
                     Box::new(move |app: &App| {
@@ -426,7 +426,11 @@ impl ChangeMode {
                     "Change to trip type:".text_widget(ctx),
                     Widget::dropdown(ctx, "to_mode", Some(TripMode::Bike), {
                         let mut choices = vec![Choice::new("cancel trip", None)];
+                        // Passengers need somebody to give them a ride
                         for m in TripMode::all() {
+                            if m == TripMode::Carpool {
+                                continue;
+                            }
                             choices.push(Choice::new(m.ongoing_verb(), Some(m)));
                         }
                         choices
@@ -8,46 +8,59 @@
 //! it's now 01:01:00.0
 //! > curl http://localhost:1234/data/get-road-thruput
 //! ... huge JSON blob
+//! > curl "http://localhost:1234/sim/goto-time-stream?t=02:00:00&interval=30&positions=true"
+//! ... one JSON object per line, published as the simulation runs
+//!
+//! Many simulations can run independently in one server. Create a session, then pass its ID to
+//! any other endpoint:
+//!
+//! > curl http://localhost:1234/session/create
+//! 1
+//! > curl "http://localhost:1234/sim/goto-time?t=01:00:00&session=1"
+//!
+//! To train traffic signal controllers, configure the signals to control, then repeatedly choose
+//! stages and step the simulation:
+//!
+//! > curl -X POST http://localhost:1234/rl/configure -d '{"intersections": [42], "step_seconds": 10.0, "reward": "TotalDelay"}'
+//! > curl -X POST http://localhost:1234/rl/step -d '{"stages": [[42, 1]]}'
+//! ... JSON with the observation and reward
 
 #[macro_use]
 extern crate anyhow;
 #[macro_use]
 extern crate log;
 
+mod rl;
+mod session;
+mod stream;
+
 use std::collections::{BTreeMap, BTreeSet, HashMap};
-use std::sync::RwLock;
+use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
 use hyper::{Body, Request, Response, Server, StatusCode};
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use structopt::StructOpt;
 
-use abstio::MapName;
 use abstutil::{serialize_btreemap, Timer};
 use geom::{Distance, Duration, LonLat, Time};
 use map_model::{
-    CompressedMovementID, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, Map,
-    MovementID, PermanentMapEdits, RoadID, TurnID,
+    BuildingID, CompressedMovementID, ControlTrafficSignal, EditCmd, EditIntersection,
+    IntersectionID, Map, MovementID, RoadID, TurnID,
 };
 use sim::{
-    AgentID, AgentType, DelayCause, ExternalPerson, PersonID, Scenario, ScenarioModifier, Sim,
-    SimFlags, SimOptions, TripID, TripMode, VehicleType,
+    AgentID, AgentType, CarID, CurbUse, DelayCause, DeliveryTour, EmergencyCall, ExternalPerson,
+    PersonID, Scenario, Sim, SimCallback, SimOptions, TripID, TripMode, VehicleType,
 };
 
+use crate::rl::{Action, RLConfig};
+use crate::session::{lock_session, try_lock_session, LoadSim, Session, Sessions, DEFAULT_SESSION};
+use crate::stream::StreamEvents;
+
 lazy_static::lazy_static! {
-    static ref MAP: RwLock<Map> = RwLock::new(Map::blank());
-    static ref SIM: RwLock<Sim> = RwLock::new(Sim::new(&Map::blank(), SimOptions::new("tmp")));
-    static ref LOAD: RwLock<LoadSim> = RwLock::new({
-        LoadSim {
-            scenario: abstio::path_scenario(&MapName::seattle("montlake"), "weekday"),
-            modifiers: Vec::new(),
-            edits: None,
-            rng_seed: SimFlags::RNG_SEED,
-            opts: SimOptions::default(),
-        }
-    });
+    static ref SESSIONS: Mutex<Sessions> = Mutex::new(Sessions::empty());
 }
 
 #[derive(StructOpt)]
@@ -64,6 +77,16 @@ struct Args {
     // TODO default_value can only handle strings, so copying SimFlags::RNG_SEED
     #[structopt(long, default_value = "42")]
     rng_seed: u64,
+    /// Sessions besides the default one are deleted after being unused for this many minutes.
+    #[structopt(long, default_value = "30")]
+    idle_timeout_minutes: usize,
+    /// The maximum number of sessions, including the default one.
+    #[structopt(long, default_value = "10")]
+    max_sessions: usize,
+    /// Refuse to create new sessions once all sessions are estimated to use more than this many
+    /// megabytes of memory.
+    #[structopt(long)]
+    max_memory_mb: Option<usize>,
     #[structopt(flatten)]
     opts: SimOptions,
 }
@@ -74,15 +97,32 @@ async fn main() {
     let args = Args::from_args();
 
     {
-        let mut load = LOAD.write().unwrap();
+        let mut load = LoadSim::default();
         load.rng_seed = args.rng_seed;
         load.opts = args.opts;
 
-        let (map, sim) = load.setup(&mut Timer::new("setup headless"));
-        *MAP.write().unwrap() = map;
-        *SIM.write().unwrap() = sim;
+        let mut sessions = Sessions::new(
+            load.clone(),
+            Duration::minutes(args.idle_timeout_minutes),
+            args.max_sessions,
+            args.max_memory_mb.map(|mb| mb * 1024 * 1024),
+        );
+        let id = sessions
+            .insert(Session::new(load, &mut Timer::new("setup headless")).unwrap())
+            .unwrap();
+        assert_eq!(id, DEFAULT_SESSION);
+        *SESSIONS.lock().unwrap() = sessions;
     }
 
+    // Periodically clean up idle sessions
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            SESSIONS.lock().unwrap().delete_idle();
+        }
+    });
+
     let addr = std::net::SocketAddr::from(([127, 0, 0, 1], args.port));
     info!("Listening on http://{}", addr);
     let serve_future = Server::bind(&addr).serve(hyper::service::make_service_fn(|_| async {
@@ -104,34 +144,172 @@ async fn serve_req(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
             .collect();
     let body = hyper::body::to_bytes(req).await?.to_vec();
     info!("Handling {}", path);
-    Ok(
-        match handle_command(
-            &path,
-            &params,
-            &body,
-            &mut SIM.write().unwrap(),
-            &mut MAP.write().unwrap(),
-            &mut LOAD.write().unwrap(),
-        ) {
-            Ok(resp) => Response::new(Body::from(resp)),
-            Err(err) => {
-                error!("{}: {}", path, err);
-                Response::builder()
-                    .status(StatusCode::BAD_REQUEST)
-                    .body(Body::from(format!("Bad command {}: {}", path, err)))
-                    .unwrap()
+    if path == "/sim/goto-time-stream" {
+        return Ok(match goto_time_streaming(&params) {
+            Ok(resp) => resp,
+            Err(err) => bad_request(&path, err),
+        });
+    }
+
+    // Loading maps and running the simulation may take a while, so don't block the async runtime.
+    // Requests to different sessions can run at the same time.
+    let path_copy = path.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        if path.starts_with("/session/") {
+            handle_session_command(&path, &params, &body)
+        } else {
+            let session = get_session(&params)?;
+            let mut session = lock_session(&session);
+            session.touch();
+            let result = handle_command(&path, &params, &body, &mut session);
+            session.touch();
+            result
+        }
+    })
+    .await;
+    Ok(match result {
+        Ok(Ok(resp)) => Response::new(Body::from(resp)),
+        Ok(Err(err)) => bad_request(&path_copy, err),
+        // The handler panicked
+        Err(err) => {
+            error!("{}: {}", path_copy, err);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(format!("{} crashed: {}", path_copy, err)))
+                .unwrap()
+        }
+    })
+}
+
+fn bad_request(path: &str, err: anyhow::Error) -> Response<Body> {
+    error!("{}: {}", path, err);
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Body::from(format!("Bad command {}: {}", path, err)))
+        .unwrap()
+}
+
+/// Requests can specify a `session` parameter; otherwise they use the default session.
+fn get_session(params: &HashMap<String, String>) -> Result<Arc<Mutex<Session>>> {
+    let id = match params.get("session") {
+        Some(x) => x.parse::<usize>()?,
+        None => DEFAULT_SESSION,
+    };
+    SESSIONS.lock().unwrap().get(id)
+}
+
+fn handle_session_command(
+    path: &str,
+    params: &HashMap<String, String>,
+    body: &[u8],
+) -> Result<String> {
+    match path {
+        "/session/create" => {
+            // Like /sim/load, the POST body can specify the scenario, modifiers, and edits. If it's
+            // empty, use the defaults.
+            let mut load = SESSIONS.lock().unwrap().default_load();
+            if !body.is_empty() {
+                let args: LoadSim = abstutil::from_json(body)?;
+                load.scenario = args.scenario;
+                load.modifiers = args.modifiers;
+                load.edits = args.edits;
             }
-        },
-    )
+            // Fail fast, before spending time loading
+            SESSIONS
+                .lock()
+                .unwrap()
+                .check_capacity(load.estimate_bytes()?)?;
+            let session = Session::new(load, &mut Timer::new("create session"))?;
+            let id = SESSIONS.lock().unwrap().insert(session)?;
+            Ok(id.to_string())
+        }
+        "/session/delete" => {
+            let id = params
+                .get("session")
+                .ok_or_else(|| anyhow!("missing GET parameter session"))?
+                .parse::<usize>()?;
+            SESSIONS.lock().unwrap().delete(id)?;
+            Ok(format!("session {} deleted", id))
+        }
+        "/session/list" => Ok(abstutil::to_json(&SESSIONS.lock().unwrap().describe())),
+        _ => Err(anyhow!("Unknown command")),
+    }
+}
+
+/// Like /sim/goto-time, but the response is streamed as newline-delimited JSON while the sim runs.
+/// Everything that happens is published every `interval` of simulated time.
+fn goto_time_streaming(params: &HashMap<String, String>) -> Result<Response<Body>> {
+    let t = Time::parse(
+        params
+            .get("t")
+            .ok_or_else(|| anyhow!("missing GET parameter t"))?,
+    )?;
+    let interval = match params.get("interval") {
+        Some(x) => Duration::parse(x)?,
+        None => Duration::minutes(1),
+    };
+    if interval <= Duration::ZERO {
+        bail!("interval must be positive");
+    }
+    let include_positions = params
+        .get("positions")
+        .map(|x| x == "true")
+        .unwrap_or(false);
+    let session = get_session(params)?;
+    // This runs on the async runtime, so don't wait for another request to finish simulating.
+    let now = try_lock_session(&session)
+        .ok_or_else(|| anyhow!("session is busy; try again later"))?
+        .sim
+        .time();
+    if t <= now {
+        bail!("{} is in the past. call /sim/reset first?", t);
+    }
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    let (mut body_tx, body) = Body::channel();
+
+    // Simulating may take a while, so don't block the async runtime.
+    tokio::task::spawn_blocking(move || {
+        let mut session = lock_session(&session);
+        session.touch();
+        let Session {
+            ref mut sim,
+            ref map,
+            ..
+        } = *session;
+        let mut maybe_cb: Option<Box<dyn SimCallback>> =
+            Some(Box::new(StreamEvents::new(tx, include_positions, sim)));
+        sim.set_periodic_callback(interval);
+        // Another request might've advanced the sim since checking
+        if t > sim.time() {
+            let dt = t - sim.time();
+            sim.timed_step(map, dt, &mut maybe_cb, &mut Timer::new("goto-time-stream"));
+        }
+        sim.unset_periodic_callback();
+        if let Ok(cb) = maybe_cb.unwrap().downcast::<StreamEvents>() {
+            cb.finish(sim, map);
+        }
+        session.touch();
+    });
+    tokio::spawn(async move {
+        while let Some(line) = rx.recv().await {
+            if body_tx.send_data(line.into()).await.is_err() {
+                // The client disconnected. Dropping rx will make the sim stop.
+                break;
+            }
+        }
+    });
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/x-ndjson")
+        .body(body)?)
 }
 
 fn handle_command(
     path: &str,
     params: &HashMap<String, String>,
     body: &[u8],
-    sim: &mut Sim,
-    map: &mut Map,
-    load: &mut LoadSim,
+    session: &mut Session,
 ) -> Result<String> {
     let get = |key: &str| {
         params
@@ -139,28 +317,40 @@ fn handle_command(
             .ok_or_else(|| anyhow!("missing GET parameter {}", key))
     };
 
+    // These replace the whole map and simulation
     match path {
-        // Controlling the simulation
         "/sim/reset" => {
-            let (new_map, new_sim) = load.setup(&mut Timer::new("reset sim"));
-            *map = new_map;
-            *sim = new_sim;
-            Ok("sim reloaded".to_string())
+            session.reset(&mut Timer::new("reset sim"))?;
+            return Ok("sim reloaded".to_string());
         }
         "/sim/load" => {
             let args: LoadSim = abstutil::from_json(body)?;
 
-            load.scenario = args.scenario;
-            load.modifiers = args.modifiers;
-            load.edits = args.edits;
+            let old_load = session.load.clone();
+            session.load.scenario = args.scenario;
+            session.load.modifiers = args.modifiers;
+            session.load.edits = args.edits;
 
-            // Also reset
-            let (new_map, new_sim) = load.setup(&mut Timer::new("reset sim"));
-            *map = new_map;
-            *sim = new_sim;
+            // Also reset. If the new settings don't work, keep the old ones.
+            if let Err(err) = session.reset(&mut Timer::new("reset sim")) {
+                session.load = old_load;
+                return Err(err);
+            }
 
-            Ok("flags changed and sim reloaded".to_string())
+            return Ok("flags changed and sim reloaded".to_string());
         }
+        _ => {}
+    }
+
+    let Session {
+        ref mut sim,
+        ref mut map,
+        ref load,
+        ref mut rl,
+        ..
+    } = *session;
+    match path {
+        // Controlling the simulation
         "/sim/get-time" => Ok(sim.time().to_string()),
         "/sim/goto-time" => {
             let t = Time::parse(get("t")?)?;
@@ -193,6 +383,16 @@ fn handle_command(
                 sim.get_all_people().last().unwrap().id
             ))
         }
+        "/sim/dispatch-emergency-vehicle" => {
+            let call: EmergencyCall = abstutil::from_json(body)?;
+            let car = sim.dispatch_emergency_vehicle(&call, map)?;
+            Ok(format!("{} dispatched", car))
+        }
+        "/sim/start-delivery-tour" => {
+            let tour: DeliveryTour = abstutil::from_json(body)?;
+            let car = sim.start_delivery_tour(tour, map)?;
+            Ok(format!("{} starting delivery tour", car))
+        }
         // Traffic signals
         "/traffic-signals/get" => {
             let i = IntersectionID(get("id")?.parse::<usize>()?);
@@ -205,6 +405,11 @@ fn handle_command(
         "/traffic-signals/set" => {
             let ts: ControlTrafficSignal = abstutil::from_json(body)?;
             let id = ts.id;
+            // Applying the edit panics on signals that don't match the intersection
+            let i = map
+                .maybe_get_i(id)
+                .ok_or_else(|| anyhow!("{} doesn't exist", id))?;
+            ts.validate(i)?;
 
             // incremental_edit_traffic_signal is the cheap option, but since we may need to call
             // get-edits later, go through the proper flow.
@@ -280,6 +485,7 @@ fn handle_command(
                 all_state.insert(
                     i.id,
                     TrafficSignalState {
+                        current_plan: sim.current_signal_plan(i.id),
                         current_stage_idx,
                         remaining_time,
                         accepted: sim
@@ -293,6 +499,37 @@ fn handle_command(
             }
             Ok(abstutil::to_json(&all_state))
         }
+        // Reinforcement learning
+        "/rl/configure" => {
+            let config: RLConfig = abstutil::from_json(body)?;
+            if let Some(old) = rl.take() {
+                old.release(sim);
+            }
+            config.start(sim, map)?;
+            let observation = config.observe(sim, map);
+            *rl = Some(config);
+            Ok(abstutil::to_json(&observation))
+        }
+        "/rl/observe" => {
+            let config = rl
+                .as_ref()
+                .ok_or_else(|| anyhow!("call /rl/configure first"))?;
+            Ok(abstutil::to_json(&config.observe(sim, map)))
+        }
+        "/rl/step" => {
+            let config = rl
+                .as_ref()
+                .ok_or_else(|| anyhow!("call /rl/configure first"))?;
+            let action: Action = abstutil::from_json(body)?;
+            Ok(abstutil::to_json(&config.step(action, sim, map)?))
+        }
+        "/rl/release" => {
+            let config = rl
+                .take()
+                .ok_or_else(|| anyhow!("call /rl/configure first"))?;
+            config.release(sim);
+            Ok("traffic signals returned to normal timing".to_string())
+        }
         // Querying data
         "/data/get-finished-trips" => {
             let mut trips = Vec::new();
@@ -312,21 +549,34 @@ fn handle_command(
             }
             Ok(abstutil::to_json(&trips))
         }
-        "/data/get-agent-positions" => Ok(abstutil::to_json(&AgentPositions {
-            agents: sim
-                .get_unzoomed_agents(map)
-                .into_iter()
-                .chain(sim.get_unzoomed_transit_riders(map))
-                .map(|a| AgentPosition {
-                    id: a.id,
-                    trip: sim.agent_to_trip(a.id),
-                    person: a.person,
-                    vehicle_type: a.id.to_vehicle_type(),
-                    pos: a.pos.to_gps(map.get_gps_bounds()),
-                    distance_crossed: sim.agent_properties(map, a.id).dist_crossed,
+        "/data/get-emergency-responses" => Ok(abstutil::to_json(
+            &sim.get_analytics()
+                .emergency_responses
+                .iter()
+                .map(
+                    |(arrived, car, incident, response_time)| EmergencyResponse {
+                        car: *car,
+                        incident: *incident,
+                        arrived: *arrived,
+                        response_time: *response_time,
+                    },
+                )
+                .collect::<Vec<_>>(),
+        )),
+        "/data/get-deliveries" => Ok(abstutil::to_json(
+            &sim.get_analytics()
+                .deliveries
+                .iter()
+                .map(|(time, car, b, curb, dwell_time)| Delivery {
+                    car: *car,
+                    building: *b,
+                    time: *time,
+                    curb: *curb,
+                    dwell_time: *dwell_time,
                 })
-                .collect(),
-        })),
+                .collect::<Vec<_>>(),
+        )),
+        "/data/get-agent-positions" => Ok(abstutil::to_json(&get_agent_positions(sim, map))),
         "/data/get-road-thruput" => Ok(abstutil::to_json(&RoadThroughput {
             counts: sim
                 .get_analytics()
@@ -391,6 +641,24 @@ fn handle_command(
     }
 }
 
+fn get_agent_positions(sim: &Sim, map: &Map) -> AgentPositions {
+    AgentPositions {
+        agents: sim
+            .get_unzoomed_agents(map)
+            .into_iter()
+            .chain(sim.get_unzoomed_transit_riders(map))
+            .map(|a| AgentPosition {
+                id: a.id,
+                trip: sim.agent_to_trip(a.id),
+                person: a.person,
+                vehicle_type: a.id.to_vehicle_type(),
+                pos: a.pos.to_gps(map.get_gps_bounds()),
+                distance_crossed: sim.agent_properties(map, a.id).dist_crossed,
+            })
+            .collect(),
+    }
+}
+
 // TODO I think specifying the API with protobufs or similar will be a better idea.
 
 #[derive(Serialize)]
@@ -402,6 +670,23 @@ struct FinishedTrip {
     mode: TripMode,
 }
 
+#[derive(Serialize)]
+struct EmergencyResponse {
+    car: CarID,
+    incident: BuildingID,
+    arrived: Time,
+    response_time: Duration,
+}
+
+#[derive(Serialize)]
+struct Delivery {
+    car: CarID,
+    building: BuildingID,
+    time: Time,
+    curb: CurbUse,
+    dwell_time: Duration,
+}
+
 #[derive(Serialize)]
 struct Delays {
     #[serde(serialize_with = "serialize_btreemap")]
@@ -453,6 +738,10 @@ struct RoadThroughput {
 
 #[derive(Serialize)]
 struct TrafficSignalState {
+    /// 0 is the default plan, and anything else indexes into the signal's other plans, starting
+    /// from 1
+    current_plan: usize,
+    /// Indexes into the stages of the current plan
     current_stage_idx: usize,
     remaining_time: Duration,
     accepted: BTreeSet<AgentID>,
@@ -469,41 +758,6 @@ struct BlockedByGraph {
     blocked_by: BTreeMap<AgentID, (Duration, DelayCause, Option<TripID>, Option<PersonID>)>,
 }
 
-#[derive(Deserialize)]
-struct LoadSim {
-    scenario: String,
-    modifiers: Vec<ScenarioModifier>,
-    edits: Option<PermanentMapEdits>,
-    // These are fixed from the initial command line flags
-    #[serde(skip_deserializing)]
-    rng_seed: u64,
-    #[serde(skip_deserializing)]
-    opts: SimOptions,
-}
-
-impl LoadSim {
-    fn setup(&self, timer: &mut Timer) -> (Map, Sim) {
-        let mut scenario: Scenario = abstio::must_read_object(self.scenario.clone(), timer);
-
-        let mut map = Map::load_synchronously(scenario.map_name.path(), timer);
-        if let Some(perma) = self.edits.clone() {
-            let edits = perma.into_edits(&map).unwrap();
-            map.must_apply_edits(edits, timer);
-            map.recalculate_pathfinding_after_edits(timer);
-        }
-
-        for m in &self.modifiers {
-            scenario = m.apply(&map, scenario);
-        }
-
-        let mut rng = XorShiftRng::seed_from_u64(self.rng_seed);
-        let mut sim = Sim::new(&map, self.opts.clone());
-        scenario.instantiate(&mut sim, &map, &mut rng, timer);
-
-        (map, sim)
-    }
-}
-
 fn export_geometry(map: &Map, i: IntersectionID) -> geojson::GeoJson {
     use geojson::{Feature, FeatureCollection, GeoJson};
 
@@ -0,0 +1,233 @@
+//! A step/observe/reward interface for training traffic signal controllers. After configuring a
+//! set of signals, an external agent repeatedly picks the next stage for each of them, the
+//! simulation advances by a fixed step, and the agent gets back an observation and a reward.
+//!
+//! Controlled signals hold whatever stage they were last told to use, ignoring their normal timing,
+//! until they're released.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap, Timer};
+use geom::{Duration, Time};
+use map_model::{IntersectionID, Map, MovementID};
+use sim::Sim;
+
+#[derive(Clone, Deserialize)]
+pub struct RLConfig {
+    /// The traffic signals controlled by the agent
+    pub intersections: Vec<IntersectionID>,
+    /// How many seconds of simulated time pass during every /rl/step
+    pub step_seconds: f64,
+    #[serde(default)]
+    pub reward: Reward,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum Reward {
+    /// The negative sum of delays experienced by everybody crossing a controlled signal during the
+    /// step
+    TotalDelay,
+    /// The number of agents crossing a controlled signal during the step
+    Throughput,
+}
+
+impl Default for Reward {
+    fn default() -> Reward {
+        Reward::TotalDelay
+    }
+}
+
+/// The POST body for /rl/step. Signals not mentioned keep their current stage.
+#[derive(Deserialize)]
+pub struct Action {
+    #[serde(deserialize_with = "deserialize_btreemap")]
+    pub stages: BTreeMap<IntersectionID, usize>,
+}
+
+#[derive(Serialize)]
+pub struct Observation {
+    time: Time,
+    signals: BTreeMap<IntersectionID, SignalObservation>,
+}
+
+#[derive(Serialize)]
+struct SignalObservation {
+    /// Indexes into the stages of the signal's current timing plan
+    current_stage: usize,
+    num_stages: usize,
+    /// How long until the current stage would normally end. Controlled signals just stay in the
+    /// same stage afterwards.
+    remaining_time: Duration,
+    #[serde(serialize_with = "serialize_btreemap")]
+    movements: BTreeMap<MovementID, MovementObservation>,
+}
+
+#[derive(Default, Serialize)]
+struct MovementObservation {
+    /// How many agents plan to use this movement in the future, including those waiting now
+    demand: usize,
+    /// How many agents are at the front of a queue, waiting to start this movement
+    waiting: usize,
+    /// How long the first agent waiting for this movement has been there
+    longest_wait: Duration,
+}
+
+#[derive(Serialize)]
+pub struct StepResult {
+    observation: Observation,
+    reward: f64,
+    /// The total delay of agents crossing controlled signals during the step
+    total_delay: Duration,
+    /// The number of agents crossing controlled signals during the step
+    throughput: usize,
+    /// True if the simulation has nothing left to do
+    done: bool,
+}
+
+impl RLConfig {
+    /// Take control of the signals, holding each one in its current stage.
+    pub fn start(&self, sim: &mut Sim, map: &Map) -> Result<()> {
+        if self.step_seconds <= 0.0 {
+            bail!("step_seconds must be positive");
+        }
+        if self.intersections.is_empty() {
+            bail!("no intersections to control");
+        }
+        for i in &self.intersections {
+            if map.maybe_get_traffic_signal(*i).is_none() {
+                bail!("{} isn't a traffic signal", i);
+            }
+        }
+        for i in &self.intersections {
+            let (stage, _) = sim.current_stage_and_remaining_time(*i);
+            sim.force_traffic_signal_stage(map, *i, stage)?;
+        }
+        Ok(())
+    }
+
+    /// Return the signals to their normal timing.
+    pub fn release(&self, sim: &mut Sim) {
+        for i in &self.intersections {
+            sim.release_traffic_signal(*i);
+        }
+    }
+
+    pub fn observe(&self, sim: &Sim, map: &Map) -> Observation {
+        let analytics = sim.get_analytics();
+        let mut signals = BTreeMap::new();
+        for i in &self.intersections {
+            let (current_stage, remaining_time) = sim.current_stage_and_remaining_time(*i);
+            let mut movements: BTreeMap<MovementID, MovementObservation> = BTreeMap::new();
+            for m in map.get_i(*i).movements.keys() {
+                movements.insert(
+                    *m,
+                    MovementObservation {
+                        demand: analytics.demand.get(m).cloned().unwrap_or(0),
+                        ..Default::default()
+                    },
+                );
+            }
+            for (_, turn, started_waiting) in sim.get_waiting_agents(*i) {
+                if let Some((m, _)) = map.get_movement_for_traffic_signal(turn) {
+                    let obs = movements.entry(m).or_insert_with(Default::default);
+                    obs.waiting += 1;
+                    obs.longest_wait = obs.longest_wait.max(sim.time() - started_waiting);
+                }
+            }
+            signals.insert(
+                *i,
+                SignalObservation {
+                    current_stage,
+                    num_stages: map
+                        .get_traffic_signal(*i)
+                        .plan_stages(sim.current_signal_plan(*i))
+                        .len(),
+                    remaining_time,
+                    movements,
+                },
+            );
+        }
+        Observation {
+            time: sim.time(),
+            signals,
+        }
+    }
+
+    /// Apply the action, run the simulation for one step, and measure what happened.
+    pub fn step(&self, action: Action, sim: &mut Sim, map: &Map) -> Result<StepResult> {
+        for (i, stage) in action.stages {
+            if !self.intersections.contains(&i) {
+                bail!("{} isn't controlled; call /rl/configure first", i);
+            }
+            sim.force_traffic_signal_stage(map, i, stage)?;
+        }
+
+        let before = self.measure(sim);
+        sim.timed_step(
+            map,
+            Duration::seconds(self.step_seconds),
+            &mut None,
+            &mut Timer::throwaway(),
+        );
+        let after = self.measure(sim);
+
+        let total_delay = after.0 - before.0;
+        let throughput = after.1 - before.1;
+        let reward = match self.reward {
+            Reward::TotalDelay => -total_delay.inner_seconds(),
+            Reward::Throughput => throughput as f64,
+        };
+        Ok(StepResult {
+            observation: self.observe(sim, map),
+            reward,
+            total_delay,
+            throughput,
+            done: sim.is_done(),
+        })
+    }
+
+    /// Returns the cumulative (delay, throughput) over all controlled signals.
+    fn measure(&self, sim: &Sim) -> (Duration, usize) {
+        let analytics = sim.get_analytics();
+        let mut delay = Duration::ZERO;
+        let mut throughput = 0;
+        for i in &self.intersections {
+            if let Some(list) = analytics.intersection_delays.get(i) {
+                for (_, _, dt, _) in list {
+                    delay += *dt;
+                }
+            }
+            throughput += analytics.intersection_thruput.total_for(*i);
+        }
+        (delay, throughput)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_requests() {
+        // The examples from the docs in main.rs
+        let config: RLConfig = abstutil::from_json(
+            br#"{"intersections": [42], "step_seconds": 10.0, "reward": "TotalDelay"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.intersections, vec![IntersectionID(42)]);
+        assert_eq!(config.step_seconds, 10.0);
+        assert!(matches!(config.reward, Reward::TotalDelay));
+
+        let action: Action = abstutil::from_json(br#"{"stages": [[42, 1]]}"#).unwrap();
+        assert_eq!(action.stages.get(&IntersectionID(42)), Some(&1));
+
+        // Whole seconds and the default reward work too
+        let config: RLConfig =
+            abstutil::from_json(br#"{"intersections": [1, 2], "step_seconds": 5}"#).unwrap();
+        assert_eq!(config.step_seconds, 5.0);
+        assert!(matches!(config.reward, Reward::TotalDelay));
+    }
+}
@@ -0,0 +1,332 @@
+//! The headless server can host many independent simulations at once. Each session has its own
+//! map (possibly with edits) and simulation. Requests without a `session` parameter go to the
+//! default session, which is created on startup and never expires.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::time::Instant;
+
+use anyhow::Result;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{prettyprint_usize, Timer};
+use geom::{Duration, Time};
+use map_model::{Map, PermanentMapEdits};
+use sim::{Scenario, ScenarioModifier, Sim, SimOptions};
+
+use crate::rl::RLConfig;
+
+pub const DEFAULT_SESSION: usize = 0;
+
+pub struct Session {
+    pub map: Map,
+    pub sim: Sim,
+    pub load: LoadSim,
+    /// Set when an external agent is controlling traffic signals through /rl/configure
+    pub rl: Option<RLConfig>,
+
+    last_used: Instant,
+    /// A rough guess of how much memory the map and simulation use, from `LoadSim::estimate_bytes`
+    estimated_bytes: usize,
+}
+
+impl Session {
+    pub fn new(load: LoadSim, timer: &mut Timer) -> Result<Session> {
+        let estimated_bytes = load.estimate_bytes()?;
+        let (map, sim) = load.setup(timer)?;
+        Ok(Session {
+            map,
+            sim,
+            load,
+            rl: None,
+            last_used: Instant::now(),
+            estimated_bytes,
+        })
+    }
+
+    /// Recreate the map and simulation from the current `LoadSim`. If that fails, the current map
+    /// and simulation are kept.
+    pub fn reset(&mut self, timer: &mut Timer) -> Result<()> {
+        let estimated_bytes = self.load.estimate_bytes()?;
+        let (map, sim) = self.load.setup(timer)?;
+        self.map = map;
+        self.sim = sim;
+        // The new simulation starts with normal signal timing
+        self.rl = None;
+        self.estimated_bytes = estimated_bytes;
+        Ok(())
+    }
+
+    pub fn touch(&mut self) {
+        self.last_used = Instant::now();
+    }
+}
+
+pub struct Sessions {
+    sessions: BTreeMap<usize, Arc<Mutex<Session>>>,
+    /// The most recent estimate of each session's size, remembered for when a session is busy
+    last_known_bytes: BTreeMap<usize, usize>,
+    next_id: usize,
+    /// The settings used for new sessions, unless overridden.
+    default_load: LoadSim,
+
+    /// Sessions unused for this long are deleted. The default session is never deleted.
+    idle_timeout: Duration,
+    max_sessions: usize,
+    /// Don't create new sessions if the estimated size of all sessions exceeds this.
+    max_memory_bytes: Option<usize>,
+}
+
+impl Sessions {
+    pub fn new(
+        default_load: LoadSim,
+        idle_timeout: Duration,
+        max_sessions: usize,
+        max_memory_bytes: Option<usize>,
+    ) -> Sessions {
+        Sessions {
+            sessions: BTreeMap::new(),
+            last_known_bytes: BTreeMap::new(),
+            next_id: DEFAULT_SESSION,
+            default_load,
+            idle_timeout,
+            max_sessions,
+            max_memory_bytes,
+        }
+    }
+
+    /// A placeholder, before the server has been configured.
+    pub fn empty() -> Sessions {
+        Sessions::new(LoadSim::default(), Duration::ZERO, 0, None)
+    }
+
+    pub fn default_load(&self) -> LoadSim {
+        self.default_load.clone()
+    }
+
+    pub fn get(&self, id: usize) -> Result<Arc<Mutex<Session>>> {
+        self.sessions
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| anyhow!("unknown session {}", id))
+    }
+
+    /// Checks if there's room for another session needing about `new_bytes`. Loading a session can
+    /// be slow, so call this first, don't hold a lock on all sessions while loading, and call
+    /// `insert` afterwards.
+    pub fn check_capacity(&mut self, new_bytes: usize) -> Result<()> {
+        if self.sessions.len() >= self.max_sessions {
+            bail!(
+                "there are already {} sessions; delete some first",
+                self.sessions.len()
+            );
+        }
+        if let Some(limit) = self.max_memory_bytes {
+            let used = self.total_estimated_bytes();
+            if used + new_bytes > limit {
+                bail!(
+                    "sessions already use about {} bytes, and this one needs {} more; the limit \
+                     is {}",
+                    prettyprint_usize(used),
+                    prettyprint_usize(new_bytes),
+                    prettyprint_usize(limit)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the new session's ID, or fails if there's not enough room for it. Other sessions
+    /// may have been created while this one was loading, so this checks capacity again.
+    pub fn insert(&mut self, session: Session) -> Result<usize> {
+        self.check_capacity(session.estimated_bytes)?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.last_known_bytes.insert(id, session.estimated_bytes);
+        self.sessions.insert(id, Arc::new(Mutex::new(session)));
+        Ok(id)
+    }
+
+    pub fn delete(&mut self, id: usize) -> Result<()> {
+        if id == DEFAULT_SESSION {
+            bail!("the default session can't be deleted");
+        }
+        if self.sessions.remove(&id).is_none() {
+            bail!("unknown session {}", id);
+        }
+        self.last_known_bytes.remove(&id);
+        Ok(())
+    }
+
+    /// Removes sessions that haven't been used recently. Sessions in the middle of handling a
+    /// request are never idle.
+    pub fn delete_idle(&mut self) {
+        let idle_timeout = self.idle_timeout;
+        self.sessions.retain(|id, session| {
+            if *id == DEFAULT_SESSION {
+                return true;
+            }
+            match try_lock_session(session) {
+                Some(session) => {
+                    let idle = Duration::realtime_elapsed(session.last_used);
+                    if idle >= idle_timeout {
+                        info!("Deleting session {}, idle for {}", id, idle);
+                        false
+                    } else {
+                        true
+                    }
+                }
+                None => true,
+            }
+        });
+        let sessions = &self.sessions;
+        self.last_known_bytes
+            .retain(|id, _| sessions.contains_key(id));
+    }
+
+    pub fn describe(&self) -> Vec<SessionInfo> {
+        let mut results = Vec::new();
+        for (id, session) in &self.sessions {
+            // Don't wait for busy sessions
+            if let Some(session) = try_lock_session(session) {
+                results.push(SessionInfo {
+                    id: *id,
+                    scenario: session.load.scenario.clone(),
+                    time: Some(session.sim.time()),
+                    idle: Duration::realtime_elapsed(session.last_used),
+                    estimated_bytes: session.estimated_bytes,
+                });
+            } else {
+                results.push(SessionInfo {
+                    id: *id,
+                    scenario: String::new(),
+                    time: None,
+                    idle: Duration::ZERO,
+                    estimated_bytes: 0,
+                });
+            }
+        }
+        results
+    }
+
+    fn total_estimated_bytes(&mut self) -> usize {
+        let mut total = 0;
+        for (id, session) in &self.sessions {
+            // Don't wait for busy sessions; use the last estimate
+            if let Some(session) = try_lock_session(session) {
+                self.last_known_bytes.insert(*id, session.estimated_bytes);
+            }
+            total += self.last_known_bytes.get(id).cloned().unwrap_or(0);
+        }
+        total
+    }
+}
+
+/// If a request panicked while simulating, the session's lock is poisoned. Keep using the session
+/// anyway; `/sim/reset` or `/sim/load` can start over from a clean state.
+pub fn lock_session(session: &Mutex<Session>) -> MutexGuard<Session> {
+    session.lock().unwrap_or_else(|err| {
+        warn!("An earlier request to this session crashed; its state may be inconsistent");
+        err.into_inner()
+    })
+}
+
+/// Like `lock_session`, but returns None if the session is busy.
+pub fn try_lock_session(session: &Mutex<Session>) -> Option<MutexGuard<Session>> {
+    match session.try_lock() {
+        Ok(session) => Some(session),
+        Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+#[derive(Serialize)]
+pub struct SessionInfo {
+    id: usize,
+    /// Empty if the session is busy
+    scenario: String,
+    /// None if the session is busy
+    time: Option<Time>,
+    /// How long since the session was last used
+    idle: Duration,
+    /// A rough guess of how much memory the session uses
+    estimated_bytes: usize,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct LoadSim {
+    pub scenario: String,
+    pub modifiers: Vec<ScenarioModifier>,
+    pub edits: Option<PermanentMapEdits>,
+    // These are fixed from the initial command line flags
+    #[serde(skip_deserializing)]
+    pub rng_seed: u64,
+    #[serde(skip_deserializing)]
+    pub opts: SimOptions,
+}
+
+impl LoadSim {
+    pub fn default() -> LoadSim {
+        LoadSim {
+            scenario: abstio::path_scenario(&abstio::MapName::seattle("montlake"), "weekday"),
+            modifiers: Vec::new(),
+            edits: None,
+            rng_seed: sim::SimFlags::RNG_SEED,
+            opts: SimOptions::default(),
+        }
+    }
+
+    /// A rough guess of how much memory loading this will use, from the size of the scenario and
+    /// map files. This is much cheaper than loading them.
+    pub fn estimate_bytes(&self) -> Result<usize> {
+        // Scenarios normally live in .../scenarios/{map}/{name}.bin. Otherwise, read the scenario
+        // to find its map.
+        let parts: Vec<&str> = self.scenario.split('/').collect();
+        let map_path = if parts.len() >= 5 && parts[parts.len() - 3] == "scenarios" {
+            abstio::parse_scenario_path(&self.scenario).0.path()
+        } else {
+            let scenario: Scenario =
+                abstio::read_object(self.scenario.clone(), &mut Timer::throwaway())
+                    .map_err(|err| anyhow!("can't load scenario {}: {}", self.scenario, err))?;
+            scenario.map_name.path()
+        };
+
+        let mut total = 0;
+        for path in [&self.scenario, &map_path] {
+            total += std::fs::metadata(path)
+                .map_err(|err| anyhow!("can't read {}: {}", path, err))?
+                .len() as usize;
+        }
+        Ok(total)
+    }
+
+    fn setup(&self, timer: &mut Timer) -> Result<(Map, Sim)> {
+        let mut scenario: Scenario = abstio::read_object(self.scenario.clone(), timer)
+            .map_err(|err| anyhow!("can't load scenario {}: {}", self.scenario, err))?;
+
+        let map_path = scenario.map_name.path();
+        if !abstio::file_exists(&map_path) {
+            bail!("scenario {} uses a missing map {}", self.scenario, map_path);
+        }
+        let mut map = Map::load_synchronously(map_path, timer);
+        if let Some(perma) = self.edits.clone() {
+            let edits = perma
+                .into_edits(&map)
+                .map_err(|err| anyhow!("bad edits: {}", err))?;
+            map.must_apply_edits(edits, timer);
+            map.recalculate_pathfinding_after_edits(timer);
+        }
+
+        for m in &self.modifiers {
+            scenario = m.apply(&map, scenario);
+        }
+
+        let mut rng = XorShiftRng::seed_from_u64(self.rng_seed);
+        let mut sim = Sim::new(&map, self.opts.clone());
+        scenario.instantiate(&mut sim, &map, &mut rng, timer);
+
+        Ok((map, sim))
+    }
+}
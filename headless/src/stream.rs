@@ -0,0 +1,193 @@
+//! Instead of polling, a client can advance the simulation and receive a stream of everything
+//! that happens along the way. The response is newline-delimited JSON, with one `StreamMessage`
+//! per line, sent as the simulation runs.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+use tokio::sync::mpsc::UnboundedSender;
+
+use geom::{Duration, Time};
+use map_model::{IntersectionID, Map, MovementID};
+use sim::{AgentType, AlertLocation, PersonID, Sim, SimCallback, TripID, TripMode};
+
+use crate::AgentPositions;
+
+#[derive(Serialize)]
+pub enum StreamMessage {
+    TripStarted {
+        time: Time,
+        trip: TripID,
+        person: Option<PersonID>,
+    },
+    TripFinished {
+        time: Time,
+        trip: TripID,
+        mode: TripMode,
+        /// None if the trip was cancelled
+        duration: Option<Duration>,
+    },
+    /// Only measured at traffic signals
+    IntersectionDelay {
+        time: Time,
+        intersection: IntersectionID,
+        movement: MovementID,
+        delay: Duration,
+        agent_type: AgentType,
+    },
+    Alert {
+        time: Time,
+        location: AlertLocation,
+        message: String,
+    },
+    AgentPositions {
+        time: Time,
+        positions: AgentPositions,
+    },
+    /// The final message, once the simulation has reached the requested time.
+    Done { time: Time },
+}
+
+/// Registered as the sim's periodic callback. Every time it runs, it publishes everything that's
+/// happened since the last run.
+pub struct StreamEvents {
+    tx: UnboundedSender<String>,
+    include_positions: bool,
+
+    // What's already been published?
+    last_time: Time,
+    started_at_last_time: BTreeSet<TripID>,
+    num_finished_trips: usize,
+    num_delays: BTreeMap<IntersectionID, usize>,
+
+    // The client disconnected
+    closed: bool,
+}
+
+impl StreamEvents {
+    /// Only new events from this point onwards will be published.
+    pub fn new(tx: UnboundedSender<String>, include_positions: bool, sim: &Sim) -> StreamEvents {
+        let analytics = sim.get_analytics();
+        StreamEvents {
+            tx,
+            include_positions,
+
+            last_time: sim.time(),
+            started_at_last_time: analytics
+                .started_trips
+                .iter()
+                .filter(|(_, t)| **t == sim.time())
+                .map(|(id, _)| *id)
+                .collect(),
+            num_finished_trips: analytics.finished_trips.len(),
+            num_delays: analytics
+                .intersection_delays
+                .iter()
+                .map(|(i, list)| (*i, list.len()))
+                .collect(),
+
+            closed: false,
+        }
+    }
+
+    /// Publish anything that's happened since the last callback, then indicate the stream is done.
+    pub fn finish(mut self, sim: &Sim, map: &Map) {
+        self.publish(sim, map);
+        self.send(StreamMessage::Done { time: sim.time() });
+    }
+
+    fn publish(&mut self, sim: &Sim, map: &Map) {
+        let analytics = sim.get_analytics();
+        let now = sim.time();
+        let first_publish_now = now > self.last_time;
+
+        // started_trips isn't ordered by time, so scan everything recent.
+        let mut started: Vec<(Time, TripID)> = analytics
+            .started_trips
+            .iter()
+            .filter(|(id, t)| {
+                **t > self.last_time
+                    || (**t == self.last_time && !self.started_at_last_time.contains(*id))
+            })
+            .map(|(id, t)| (*t, *id))
+            .collect();
+        started.sort();
+        if first_publish_now {
+            self.started_at_last_time.clear();
+        }
+        for (time, trip) in started {
+            if time == now {
+                self.started_at_last_time.insert(trip);
+            }
+            self.send(StreamMessage::TripStarted {
+                time,
+                trip,
+                person: sim.trip_to_person(trip),
+            });
+        }
+
+        for (time, trip, mode, duration) in &analytics.finished_trips[self.num_finished_trips..] {
+            self.send(StreamMessage::TripFinished {
+                time: *time,
+                trip: *trip,
+                mode: *mode,
+                duration: *duration,
+            });
+        }
+        self.num_finished_trips = analytics.finished_trips.len();
+
+        for (i, list) in &analytics.intersection_delays {
+            let num_seen = self.num_delays.get(i).cloned().unwrap_or(0);
+            if list.len() == num_seen {
+                continue;
+            }
+            let movements: Vec<&MovementID> = map.get_i(*i).movements.keys().collect();
+            for (idx, time, delay, agent_type) in &list[num_seen..] {
+                self.send(StreamMessage::IntersectionDelay {
+                    time: *time,
+                    intersection: *i,
+                    movement: *movements[*idx as usize],
+                    delay: *delay,
+                    agent_type: *agent_type,
+                });
+            }
+            self.num_delays.insert(*i, list.len());
+        }
+
+        // The last callback may have happened right when the sim stopped
+        if self.include_positions && first_publish_now {
+            self.send(StreamMessage::AgentPositions {
+                time: now,
+                positions: crate::get_agent_positions(sim, map),
+            });
+        }
+
+        self.last_time = now;
+    }
+
+    fn send(&mut self, msg: StreamMessage) {
+        let mut line = serde_json::to_string(&msg).unwrap();
+        line.push('\n');
+        if self.tx.send(line).is_err() {
+            self.closed = true;
+        }
+    }
+}
+
+impl SimCallback for StreamEvents {
+    fn run(&mut self, sim: &Sim, map: &Map) -> bool {
+        self.publish(sim, map);
+        // If nobody's listening anymore, stop running the simulation
+        self.closed
+    }
+
+    fn alerts(&mut self, alerts: &[(Time, AlertLocation, String)]) {
+        for (time, location, message) in alerts {
+            self.send(StreamMessage::Alert {
+                time: *time,
+                location: location.clone(),
+                message: message.clone(),
+            });
+        }
+    }
+}
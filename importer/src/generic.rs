@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use abstio::MapName;
+use abstio::{CityName, MapName};
 use map_model::raw::RawMap;
 
 use crate::configuration::ImporterConfiguration;
@@ -31,6 +31,20 @@ pub struct GenericCityImporter {
     pub extra_buildings: Option<String>,
     /// Only include crosswalks that match a `highway=crossing` OSM node.
     pub filter_crosswalks: bool,
+    /// Where to get elevation data. If unspecified, run the Docker tool.
+    #[serde(default)]
+    pub elevation: convert_osm::ElevationSource,
+    /// The URL to a GTFS feed for transit schedules. You can also put a local path to a .zip
+    /// file or a directory here.
+    #[serde(default)]
+    pub gtfs_url: Option<String>,
+    /// Which day's GTFS trips to use, like 20210930. If unspecified, use the busiest day.
+    #[serde(default)]
+    pub gtfs_service_date: Option<u32>,
+    /// A local GBFS `station_information.json` file with bike-share docks. If unspecified, docks
+    /// come from OSM.
+    #[serde(default)]
+    pub bike_share_stations: Option<String>,
 }
 
 impl GenericCityImporter {
@@ -56,6 +70,12 @@ impl GenericCityImporter {
             self.osm_url.clone()
         };
 
+        if let Some(ref url) = self.gtfs_url {
+            if url.starts_with("http") {
+                download(config, self.local_gtfs_path(&name.city).unwrap(), url).await;
+            }
+        }
+
         osmconvert(
             local_osm_file,
             format!(
@@ -84,10 +104,26 @@ impl GenericCityImporter {
                 // TODO Total hack! Need to figure out how to express per-map config overrides
                 skip_local_roads: name == MapName::new("us", "phoenix", "loop101"),
                 filter_crosswalks: self.filter_crosswalks,
+                elevation: self.elevation.clone(),
+                bike_share_stations: self.bike_share_stations.clone(),
             },
             timer,
         );
         map.save();
         map
     }
+
+    /// Where the GTFS feed is stored locally, if there is one
+    pub fn local_gtfs_path(&self, city: &CityName) -> Option<String> {
+        let url = self.gtfs_url.as_ref()?;
+        if !url.starts_with("http") {
+            return Some(url.clone());
+        }
+        // download() only unzips into a directory if the URL looks like a .zip
+        Some(if url.ends_with(".zip") {
+            city.input_path("gtfs/")
+        } else {
+            city.input_path("gtfs.zip")
+        })
+    }
 }
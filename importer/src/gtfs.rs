@@ -0,0 +1,835 @@
+//! Import transit schedules from any GTFS feed (https://developers.google.com/transit/gtfs/reference).
+//!
+//! Only trips running on one service day are used. Trips of the same GTFS route that serve the
+//! same sequence of stops (a "pattern") become one `BusRoute`, clipped to the stops inside the map.
+//! If the map already has a route from OSM for a pattern -- either tagged with the same shape ID or
+//! serving nearby stops in the same order -- its schedule is overwritten. Otherwise, a new route is
+//! created by snapping the GTFS stops to the nearest lanes heading in the right direction.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use abstutil::{must_run_cmd, Timer};
+use geom::{Angle, Distance, Duration, FindClosest, LonLat, PolyLine, Pt2D, Time};
+use map_model::{BusRouteID, LaneID, Map, PathConstraints, Position};
+
+use crate::configuration::ImporterConfiguration;
+
+/// How far can a GTFS stop be from a stop on a route from OSM, and still match?
+const MAX_DIST_TO_OSM_STOP: Distance = Distance::const_meters(30.0);
+/// How far can a GTFS stop be from the lane it's snapped to?
+const MAX_DIST_TO_LANE: Distance = Distance::const_meters(30.0);
+/// Train platforms are sometimes far from any sidewalk
+const MAX_DIST_TO_SIDEWALK: Distance = Distance::const_meters(50.0);
+
+/// Fill out the schedules of transit routes, creating new routes if needed. `path` is a directory
+/// or a .zip file with the GTFS feed. `service_date` is formatted like 20210930; if it's not
+/// specified, the busiest day in the feed is used.
+pub fn import(
+    map: &mut Map,
+    path: &str,
+    service_date: Option<u32>,
+    config: &ImporterConfiguration,
+    timer: &mut Timer,
+) -> Result<()> {
+    timer.start("load GTFS");
+    let dir = prepare_dir(path, config)?;
+    let routes: Vec<RouteRecord> = read_csv(&dir, "routes.txt")?;
+    let trips: Vec<TripRecord> = read_csv(&dir, "trips.txt")?;
+    let stop_times: Vec<StopTimeRecord> = read_csv(&dir, "stop_times.txt")?;
+    let stops: Vec<StopRecord> = read_csv(&dir, "stops.txt")?;
+    let calendar: Vec<CalendarRecord> = read_optional_csv(&dir, "calendar.txt")?;
+    let calendar_dates: Vec<CalendarDateRecord> = read_optional_csv(&dir, "calendar_dates.txt")?;
+    let frequencies: Vec<FrequencyRecord> = read_optional_csv(&dir, "frequencies.txt")?;
+    let shapes: Vec<ShapeRecord> = read_optional_csv(&dir, "shapes.txt")?;
+    timer.stop("load GTFS");
+
+    let services = active_services(&calendar, &calendar_dates, &trips, service_date)?;
+    let patterns = group_patterns(&trips, stop_times, &frequencies, services.as_ref())?;
+
+    let routes: BTreeMap<String, RouteRecord> = routes
+        .into_iter()
+        .map(|r| (r.route_id.clone(), r))
+        .collect();
+    let stops: BTreeMap<String, StopRecord> =
+        stops.into_iter().map(|s| (s.stop_id.clone(), s)).collect();
+    let shapes = make_shapes(shapes, map);
+    let snapper = Snapper::new(map);
+    let mut stop_pts: BTreeMap<String, (Pt2D, bool)> = BTreeMap::new();
+
+    // The trips from every pattern matching each route
+    let mut schedules: BTreeMap<BusRouteID, Vec<(Time, Vec<Duration>)>> = BTreeMap::new();
+    timer.start_iter("match GTFS patterns to routes", patterns.len());
+    'PATTERN: for (key, trips) in patterns {
+        timer.next();
+        let route = match routes.get(&key.route_id) {
+            Some(r) => r,
+            None => {
+                warn!("Trips refer to unknown route {}", key.route_id);
+                continue;
+            }
+        };
+        let route_type = match gtfs_route_type(route.route_type) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        // Which stops are inside the map?
+        let mut pts = Vec::new();
+        let mut inside = Vec::new();
+        for id in &key.stops {
+            let stop = match stops.get(id) {
+                Some(s) => s,
+                None => {
+                    warn!(
+                        "Trips on route {} refer to unknown stop {}; skipping them",
+                        key.route_id, id
+                    );
+                    continue 'PATTERN;
+                }
+            };
+            let (pt, is_inside) = *stop_pts.entry(id.clone()).or_insert_with(|| {
+                let gps = LonLat::new(stop.stop_lon, stop.stop_lat);
+                let pt = gps.to_pt(map.get_gps_bounds());
+                (
+                    pt,
+                    map.get_gps_bounds().contains(gps)
+                        && map.get_boundary_polygon().contains_pt(pt),
+                )
+            });
+            pts.push(pt);
+            inside.push(is_inside);
+        }
+        let (first, last) = match longest_run_inside(&inside) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let br = if let Some(br) =
+            match_existing_route(map, key.shape_id.as_ref(), route_type, &pts[first..=last])
+        {
+            br
+        } else {
+            let shape = key.shape_id.as_ref().and_then(|id| shapes.get(id));
+            let mut route_stops = Vec::new();
+            for idx in first..=last {
+                // Which way is the vehicle heading at this stop?
+                let angle = if let Some((_, angle)) =
+                    shape.and_then(|pl| pl.dist_along_of_point(pl.project_pt(pts[idx])))
+                {
+                    angle
+                } else if idx == last {
+                    pts[idx - 1].angle_to(pts[idx])
+                } else {
+                    pts[idx].angle_to(pts[idx + 1])
+                };
+                let stop = &stops[&key.stops[idx]];
+                match snapper.snap(map, pts[idx], angle, route_type) {
+                    Ok((sidewalk_pos, driving_pos)) => {
+                        route_stops.push((stop.name(), sidewalk_pos, driving_pos));
+                    }
+                    Err(err) => {
+                        warn!("Couldn't snap stop {}: {}", stop.stop_id, err);
+                        break;
+                    }
+                }
+            }
+            if route_stops.len() != last - first + 1 {
+                continue;
+            }
+
+            let short_name = route
+                .route_short_name
+                .clone()
+                .unwrap_or_else(|| route.route_id.clone());
+            let full_name = route
+                .route_long_name
+                .clone()
+                .map(|long| format!("{} ({})", long, short_name))
+                .unwrap_or_else(|| short_name.clone());
+            match map.hack_add_bus_route(full_name, short_name, route_type, route_stops) {
+                Ok(br) => br,
+                Err(err) => {
+                    warn!("Couldn't create route for {}: {}", key.route_id, err);
+                    continue;
+                }
+            }
+        };
+
+        // Clip the trips to the stops inside the map
+        let entry = schedules.entry(br).or_insert_with(Vec::new);
+        for (start, offsets) in trips {
+            let mut spawn_time = start + offsets[first];
+            // Maybe we should duplicate these to handle beginning and end of the simulation
+            if spawn_time >= Time::START_OF_DAY + Duration::hours(24) {
+                spawn_time = spawn_time - Duration::hours(24);
+            }
+            entry.push((
+                spawn_time,
+                offsets[first..=last]
+                    .iter()
+                    .map(|x| *x - offsets[first])
+                    .collect(),
+            ));
+        }
+    }
+
+    for (br, trips) in schedules {
+        let num_stops = map.get_br(br).stops.len();
+        let mut times: Vec<Time> = trips.iter().map(|(t, _)| *t).collect();
+        times.sort();
+        times.dedup();
+        // Routes matched by shape ID might have different stops than GTFS; only use the
+        // per-stop times if they line up.
+        let stop_offsets = if trips.iter().all(|(_, offsets)| offsets.len() == num_stops) {
+            (0..num_stops)
+                .map(|idx| median(trips.iter().map(|(_, offsets)| offsets[idx]).collect()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        info!(
+            "{} has {} trips from GTFS",
+            map.get_br(br).full_name,
+            times.len()
+        );
+        map.hack_override_schedule(br, times, stop_offsets);
+    }
+    Ok(())
+}
+
+/// Returns the directory containing the feed, unzipping it first if needed.
+fn prepare_dir(path: &str, config: &ImporterConfiguration) -> Result<String> {
+    if let Some(base) = path.strip_suffix(".zip") {
+        let dir = format!("{}/", base);
+        if !Path::new(&dir).exists() {
+            if !Path::new(path).exists() {
+                bail!("{} doesn't exist", path);
+            }
+            std::fs::create_dir_all(&dir)?;
+            must_run_cmd(Command::new(&config.unzip).arg(path).arg("-d").arg(&dir));
+        }
+        return Ok(dir);
+    }
+    if !Path::new(path).is_dir() {
+        bail!("{} isn't a directory or .zip file", path);
+    }
+    Ok(path.to_string())
+}
+
+fn read_csv<T: DeserializeOwned>(dir: &str, file: &str) -> Result<Vec<T>> {
+    let path = Path::new(dir).join(file);
+    let mut results = Vec::new();
+    for rec in csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(&path)
+        .map_err(|err| anyhow!("{}: {}", path.display(), err))?
+        .deserialize()
+    {
+        results.push(rec.map_err(|err| anyhow!("{}: {}", path.display(), err))?);
+    }
+    Ok(results)
+}
+
+fn read_optional_csv<T: DeserializeOwned>(dir: &str, file: &str) -> Result<Vec<T>> {
+    if Path::new(dir).join(file).exists() {
+        read_csv(dir, file)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Which services run on the given date? If no date is given, use the day with the most trips.
+/// None means the feed doesn't describe services at all, so every trip counts.
+fn active_services(
+    calendar: &[CalendarRecord],
+    calendar_dates: &[CalendarDateRecord],
+    trips: &[TripRecord],
+    service_date: Option<u32>,
+) -> Result<Option<BTreeSet<String>>> {
+    if calendar.is_empty() && calendar_dates.is_empty() {
+        if let Some(date) = service_date {
+            warn!(
+                "GTFS has no calendar, so using all trips, not just ones on {}",
+                date
+            );
+        }
+        return Ok(None);
+    }
+    let services_on = |date: u32| -> Result<BTreeSet<String>> {
+        let day = days_from_date(date)?;
+        let mut services = BTreeSet::new();
+        for c in calendar {
+            if c.start_date <= date && date <= c.end_date && c.runs_on_weekday(day) {
+                services.insert(c.service_id.clone());
+            }
+        }
+        for c in calendar_dates.iter().filter(|c| c.date == date) {
+            if c.exception_type == 1 {
+                services.insert(c.service_id.clone());
+            } else if c.exception_type == 2 {
+                services.remove(&c.service_id);
+            }
+        }
+        Ok(services)
+    };
+
+    if let Some(date) = service_date {
+        let services = services_on(date)?;
+        if services.is_empty() {
+            bail!("No GTFS services run on {}", date);
+        }
+        return Ok(Some(services));
+    }
+
+    let mut trips_per_service: BTreeMap<&str, usize> = BTreeMap::new();
+    for trip in trips {
+        *trips_per_service
+            .entry(trip.service_id.as_str())
+            .or_insert(0) += 1;
+    }
+    let mut candidates: BTreeSet<u32> = calendar_dates.iter().map(|c| c.date).collect();
+    if let Some(start) = calendar.iter().map(|c| c.start_date).min() {
+        let end = calendar.iter().map(|c| c.end_date).max().unwrap();
+        // A few weeks of the regular calendar is enough to see every day of the week
+        let mut day = days_from_date(start)?;
+        for _ in 0..28 {
+            let date = date_from_days(day);
+            if date > end {
+                break;
+            }
+            candidates.insert(date);
+            day += 1;
+        }
+    }
+    let mut best: Option<(usize, u32, BTreeSet<String>)> = None;
+    for date in candidates {
+        let services = services_on(date)?;
+        let num_trips = services
+            .iter()
+            .map(|s| trips_per_service.get(s.as_str()).cloned().unwrap_or(0))
+            .sum();
+        if best
+            .as_ref()
+            .map(|(n, _, _)| num_trips > *n)
+            .unwrap_or(true)
+        {
+            best = Some((num_trips, date, services));
+        }
+    }
+    let (_, date, services) = best.ok_or_else(|| anyhow!("GTFS calendar is empty"))?;
+    info!("Using GTFS trips running on {}", date);
+    Ok(Some(services))
+}
+
+/// Trips on the same route and shape serving the same stops
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct PatternKey {
+    route_id: String,
+    shape_id: Option<String>,
+    stops: Vec<String>,
+}
+
+/// For every pattern, returns each trip's start time and the offset from there to each stop.
+fn group_patterns(
+    trips: &[TripRecord],
+    stop_times: Vec<StopTimeRecord>,
+    frequencies: &[FrequencyRecord],
+    services: Option<&BTreeSet<String>>,
+) -> Result<BTreeMap<PatternKey, Vec<(Time, Vec<Duration>)>>> {
+    let trips: BTreeMap<&str, &TripRecord> = trips
+        .iter()
+        .filter(|t| services.map(|s| s.contains(&t.service_id)).unwrap_or(true))
+        .map(|t| (t.trip_id.as_str(), t))
+        .collect();
+    let mut stop_times_per_trip: BTreeMap<String, Vec<StopTimeRecord>> = BTreeMap::new();
+    for rec in stop_times {
+        if trips.contains_key(rec.trip_id.as_str()) {
+            stop_times_per_trip
+                .entry(rec.trip_id.clone())
+                .or_insert_with(Vec::new)
+                .push(rec);
+        }
+    }
+    let mut frequencies_per_trip: BTreeMap<&str, Vec<&FrequencyRecord>> = BTreeMap::new();
+    for f in frequencies {
+        frequencies_per_trip
+            .entry(f.trip_id.as_str())
+            .or_insert_with(Vec::new)
+            .push(f);
+    }
+
+    let mut patterns: BTreeMap<PatternKey, Vec<(Time, Vec<Duration>)>> = BTreeMap::new();
+    for (trip_id, mut stop_times) in stop_times_per_trip {
+        if stop_times.len() < 2 {
+            continue;
+        }
+        stop_times.sort_by_key(|s| s.stop_sequence);
+        let times =
+            interpolate_times(&stop_times).map_err(|err| anyhow!("Trip {}: {}", trip_id, err))?;
+        let offsets: Vec<Duration> = times.iter().map(|t| *t - times[0]).collect();
+        let trip = trips[trip_id.as_str()];
+        let entry = patterns
+            .entry(PatternKey {
+                route_id: trip.route_id.clone(),
+                shape_id: trip.shape_id.clone(),
+                stops: stop_times.into_iter().map(|s| s.stop_id).collect(),
+            })
+            .or_insert_with(Vec::new);
+
+        if let Some(frequencies) = frequencies_per_trip.get(trip_id.as_str()) {
+            // The trip's times are just a template; it repeats throughout each period
+            for f in frequencies {
+                if f.headway_secs <= 0.0 {
+                    bail!("Trip {} has a headway of {}", trip_id, f.headway_secs);
+                }
+                let headway = Duration::seconds(f.headway_secs);
+                let mut start = Time::parse(&f.start_time)?;
+                let end = Time::parse(&f.end_time)?;
+                while start < end {
+                    entry.push((start, offsets.clone()));
+                    start += headway;
+                }
+            }
+        } else {
+            entry.push((times[0], offsets));
+        }
+    }
+    Ok(patterns)
+}
+
+/// Stops without an exact time are assumed to be evenly spaced between the ones with a time.
+fn interpolate_times(stop_times: &[StopTimeRecord]) -> Result<Vec<Time>> {
+    let mut known: Vec<(usize, Time)> = Vec::new();
+    for (idx, s) in stop_times.iter().enumerate() {
+        if let Some(time) = s
+            .arrival_time
+            .as_ref()
+            .or_else(|| s.departure_time.as_ref())
+        {
+            known.push((idx, Time::parse(time)?));
+        }
+    }
+    if known.first().map(|(idx, _)| *idx) != Some(0)
+        || known.last().map(|(idx, _)| *idx) != Some(stop_times.len() - 1)
+    {
+        bail!("the first and last stops don't have times");
+    }
+
+    let mut times = Vec::new();
+    for pair in known.windows(2) {
+        let (idx1, t1) = pair[0];
+        let (idx2, t2) = pair[1];
+        for idx in idx1..idx2 {
+            let pct = ((idx - idx1) as f64) / ((idx2 - idx1) as f64);
+            times.push(t1 + pct * (t2 - t1));
+        }
+    }
+    times.push(known.last().unwrap().1);
+    Ok(times)
+}
+
+fn make_shapes(mut records: Vec<ShapeRecord>, map: &Map) -> BTreeMap<String, PolyLine> {
+    records.sort_by(|a, b| {
+        (&a.shape_id, a.shape_pt_sequence).cmp(&(&b.shape_id, b.shape_pt_sequence))
+    });
+    let mut pts_per_shape: BTreeMap<String, Vec<Pt2D>> = BTreeMap::new();
+    for rec in records {
+        let pt = LonLat::new(rec.shape_pt_lon, rec.shape_pt_lat).to_pt(map.get_gps_bounds());
+        pts_per_shape
+            .entry(rec.shape_id)
+            .or_insert_with(Vec::new)
+            .push(pt);
+    }
+    pts_per_shape
+        .into_iter()
+        .filter_map(|(id, pts)| PolyLine::deduping_new(pts).ok().map(|pl| (id, pl)))
+        .collect()
+}
+
+/// Returns the indices of the first and last stop of the longest contiguous run of stops inside the
+/// map, if there are at least two.
+fn longest_run_inside(inside: &[bool]) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, usize)> = None;
+    let mut current_start: Option<usize> = None;
+    for (idx, is_inside) in inside.iter().enumerate() {
+        if !is_inside {
+            current_start = None;
+            continue;
+        }
+        let start = *current_start.get_or_insert(idx);
+        if idx > start && best.map(|(a, b)| idx - start > b - a).unwrap_or(true) {
+            best = Some((start, idx));
+        }
+    }
+    best
+}
+
+/// Find a route from OSM for a pattern: one tagged with the same shape, or one with the same type
+/// serving stops close to each GTFS stop, in order.
+fn match_existing_route(
+    map: &Map,
+    shape_id: Option<&String>,
+    route_type: PathConstraints,
+    pts: &[Pt2D],
+) -> Option<BusRouteID> {
+    if let Some(shape_id) = shape_id {
+        for br in map.all_bus_routes() {
+            // The suffix after the : varies
+            if br
+                .gtfs_trip_marker
+                .as_ref()
+                .map(|m| m.split(':').next().unwrap() == shape_id)
+                .unwrap_or(false)
+            {
+                return Some(br.id);
+            }
+        }
+    }
+
+    map.all_bus_routes()
+        .iter()
+        .find(|br| {
+            br.route_type == route_type
+                && br.stops.len() == pts.len()
+                && br.stops.iter().zip(pts.iter()).all(|(bs, pt)| {
+                    map.get_bs(*bs).driving_pos.pt(map).dist_to(*pt) <= MAX_DIST_TO_OSM_STOP
+                })
+        })
+        .map(|br| br.id)
+}
+
+/// Finds lanes near GTFS stops
+struct Snapper {
+    vehicle_lanes: BTreeMap<PathConstraints, FindClosest<LaneID>>,
+    sidewalks: FindClosest<LaneID>,
+}
+
+impl Snapper {
+    fn new(map: &Map) -> Snapper {
+        let mut vehicle_lanes = BTreeMap::new();
+        for constraints in [PathConstraints::Bus, PathConstraints::Train] {
+            let mut closest = FindClosest::new(map.get_bounds());
+            for l in map.all_lanes() {
+                if constraints.can_use(l, map) {
+                    closest.add(l.id, l.lane_center_pts.points());
+                }
+            }
+            vehicle_lanes.insert(constraints, closest);
+        }
+        let mut sidewalks = FindClosest::new(map.get_bounds());
+        for l in map.all_lanes() {
+            if l.is_walkable() {
+                sidewalks.add(l.id, l.lane_center_pts.points());
+            }
+        }
+        Snapper {
+            vehicle_lanes,
+            sidewalks,
+        }
+    }
+
+    /// Returns (sidewalk, driving) positions for a stop, served by vehicles heading in some
+    /// direction.
+    fn snap(
+        &self,
+        map: &Map,
+        pt: Pt2D,
+        angle: Angle,
+        route_type: PathConstraints,
+    ) -> Result<(Position, Position)> {
+        let mut candidates = self.vehicle_lanes[&route_type].all_close_pts(pt, MAX_DIST_TO_LANE);
+        candidates.sort_by_key(|(_, _, dist)| *dist);
+        let mut driving_pos = candidates
+            .into_iter()
+            .find_map(|(l, pt, _)| {
+                let lane = map.get_l(l);
+                let dist = lane.dist_along_of_point(pt)?;
+                let (_, lane_angle) = lane.lane_center_pts.dist_along(dist).ok()?;
+                if lane_angle.approx_eq(angle, 60.0) {
+                    Some(Position::new(l, dist))
+                } else {
+                    None
+                }
+            })
+            .ok_or_else(|| anyhow!("no {:?} lane near {} heading {}", route_type, pt, angle))?;
+
+        // If we're a stop right at an incoming border, make sure to be at least past where the
+        // vehicle will spawn from the border.
+        if map
+            .get_i(map.get_l(driving_pos.lane()).src_i)
+            .is_incoming_border()
+        {
+            driving_pos = driving_pos
+                .min_dist(Distance::meters(1.0), map)
+                .ok_or_else(|| anyhow!("too close to start of a border {}", driving_pos.lane()))?;
+        }
+
+        let sidewalk_pos = if let Some(l) = map
+            .get_parent(driving_pos.lane())
+            .find_closest_lane(driving_pos.lane(), |l| {
+                PathConstraints::Pedestrian.can_use(l, map)
+            }) {
+            driving_pos.equiv_pos(l, map)
+        } else {
+            // Train platforms usually aren't part of the same road
+            let (l, sidewalk_pt) = self
+                .sidewalks
+                .closest_pt(pt, MAX_DIST_TO_SIDEWALK)
+                .ok_or_else(|| anyhow!("no sidewalk near {}", pt))?;
+            Position::new(l, map.get_l(l).dist_along_of_point(sidewalk_pt).unwrap())
+        };
+        Ok((sidewalk_pos, driving_pos))
+    }
+}
+
+/// See https://developers.google.com/transit/gtfs/reference#routestxt and the extended types at
+/// https://developers.google.com/transit/gtfs/reference/extended-route-types. Returns None for
+/// modes that aren't simulated, like ferries.
+fn gtfs_route_type(x: usize) -> Option<PathConstraints> {
+    match x {
+        3 | 11 | 200..=299 | 700..=799 | 800 => Some(PathConstraints::Bus),
+        0 | 1 | 2 | 12 | 100..=199 | 400..=599 | 900..=999 => Some(PathConstraints::Train),
+        _ => None,
+    }
+}
+
+fn median(mut list: Vec<Duration>) -> Duration {
+    list.sort();
+    list[list.len() / 2]
+}
+
+/// Days since 1970-01-01, from a date like 20210930
+fn days_from_date(date: u32) -> Result<i64> {
+    let (y, m, d) = (
+        (date / 10000) as i64,
+        ((date / 100) % 100) as i64,
+        (date % 100) as i64,
+    );
+    let leap_year = (y % 4 == 0 && y % 100 != 0) || y % 400 == 0;
+    let days_in_month = match m {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap_year => 29,
+        2 => 28,
+        _ => bail!("Invalid date {}", date),
+    };
+    if !(1..=days_in_month).contains(&d) {
+        bail!("Invalid date {}", date);
+    }
+    // From http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Ok(era * 146097 + doe - 719468)
+}
+
+/// The inverse of `days_from_date`
+fn date_from_days(days: i64) -> u32 {
+    // From http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y * 10000 + m * 100 + d) as u32
+}
+
+#[derive(Deserialize)]
+struct RouteRecord {
+    route_id: String,
+    #[serde(default)]
+    route_short_name: Option<String>,
+    #[serde(default)]
+    route_long_name: Option<String>,
+    route_type: usize,
+}
+
+#[derive(Deserialize)]
+struct TripRecord {
+    route_id: String,
+    service_id: String,
+    trip_id: String,
+    #[serde(default)]
+    shape_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StopTimeRecord {
+    trip_id: String,
+    /// Only required for the first and last stop
+    #[serde(default)]
+    arrival_time: Option<String>,
+    #[serde(default)]
+    departure_time: Option<String>,
+    stop_id: String,
+    stop_sequence: usize,
+}
+
+#[derive(Deserialize)]
+struct StopRecord {
+    stop_id: String,
+    #[serde(default)]
+    stop_name: Option<String>,
+    stop_lat: f64,
+    stop_lon: f64,
+}
+
+impl StopRecord {
+    fn name(&self) -> String {
+        self.stop_name
+            .clone()
+            .unwrap_or_else(|| self.stop_id.clone())
+    }
+}
+
+#[derive(Deserialize)]
+struct CalendarRecord {
+    service_id: String,
+    monday: u8,
+    tuesday: u8,
+    wednesday: u8,
+    thursday: u8,
+    friday: u8,
+    saturday: u8,
+    sunday: u8,
+    start_date: u32,
+    end_date: u32,
+}
+
+impl CalendarRecord {
+    /// `day` is days since 1970-01-01, which was a Thursday
+    fn runs_on_weekday(&self, day: i64) -> bool {
+        let flag = match day.rem_euclid(7) {
+            0 => self.thursday,
+            1 => self.friday,
+            2 => self.saturday,
+            3 => self.sunday,
+            4 => self.monday,
+            5 => self.tuesday,
+            _ => self.wednesday,
+        };
+        flag == 1
+    }
+}
+
+#[derive(Deserialize)]
+struct CalendarDateRecord {
+    service_id: String,
+    date: u32,
+    /// 1 means service is added on this date, 2 means it's removed
+    exception_type: u8,
+}
+
+#[derive(Deserialize)]
+struct FrequencyRecord {
+    trip_id: String,
+    start_time: String,
+    end_time: String,
+    headway_secs: f64,
+}
+
+#[derive(Deserialize)]
+struct ShapeRecord {
+    shape_id: String,
+    shape_pt_lat: f64,
+    shape_pt_lon: f64,
+    shape_pt_sequence: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(days_from_date(19700101).unwrap(), 0);
+        for date in [20000229, 20211231, 20220101, 19991015] {
+            assert_eq!(date_from_days(days_from_date(date).unwrap()), date);
+        }
+        for date in [20210931, 20210230, 20220229, 19001329, 20210000, 20211301] {
+            assert!(days_from_date(date).is_err());
+        }
+        // 2021-09-06 was a Monday
+        let cal = CalendarRecord {
+            service_id: "weekdays".to_string(),
+            monday: 1,
+            tuesday: 0,
+            wednesday: 0,
+            thursday: 0,
+            friday: 0,
+            saturday: 0,
+            sunday: 0,
+            start_date: 20210101,
+            end_date: 20211231,
+        };
+        assert!(cal.runs_on_weekday(days_from_date(20210906).unwrap()));
+        assert!(!cal.runs_on_weekday(days_from_date(20210907).unwrap()));
+    }
+
+    #[test]
+    fn test_fixture_feed() {
+        let dir = format!("{}/../tests/input/gtfs", env!("CARGO_MANIFEST_DIR"));
+        let trips: Vec<TripRecord> = read_csv(&dir, "trips.txt").unwrap();
+        let calendar: Vec<CalendarRecord> = read_csv(&dir, "calendar.txt").unwrap();
+        let calendar_dates: Vec<CalendarDateRecord> = read_csv(&dir, "calendar_dates.txt").unwrap();
+        let frequencies: Vec<FrequencyRecord> = read_csv(&dir, "frequencies.txt").unwrap();
+        let services = |date| {
+            active_services(&calendar, &calendar_dates, &trips, date)
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+
+        // A normal Tuesday
+        assert_eq!(services(Some(20210907)), vec!["weekday".to_string()]);
+        // A holiday runs the weekend schedule instead
+        assert_eq!(services(Some(20210906)), vec!["weekend".to_string()]);
+        // The busiest day is any weekday
+        assert_eq!(services(None), vec!["weekday".to_string()]);
+        // Nothing runs after the calendar ends
+        assert!(active_services(&calendar, &calendar_dates, &trips, Some(20220103)).is_err());
+
+        let t = |hours: usize, minutes: usize| {
+            Time::START_OF_DAY + Duration::hours(hours) + Duration::minutes(minutes)
+        };
+        let offsets = |minutes: Vec<usize>| -> Vec<Duration> {
+            minutes.into_iter().map(Duration::minutes).collect()
+        };
+        let weekday = services(Some(20210907)).into_iter().collect();
+        let patterns = group_patterns(
+            &trips,
+            read_csv(&dir, "stop_times.txt").unwrap(),
+            &frequencies,
+            Some(&weekday),
+        )
+        .unwrap();
+        // The weekend express serves different stops, so it's filtered out
+        assert_eq!(patterns.len(), 1);
+        let (key, schedule) = patterns.into_iter().next().unwrap();
+        assert_eq!(key.stops, vec!["A", "B", "C"]);
+        assert_eq!(
+            schedule,
+            vec![
+                // The midday trip is a template, repeating every 20 minutes for an hour
+                (t(12, 0), offsets(vec![0, 4, 10])),
+                (t(12, 20), offsets(vec![0, 4, 10])),
+                (t(12, 40), offsets(vec![0, 4, 10])),
+                // The time at the middle stop is interpolated
+                (t(8, 0), offsets(vec![0, 5, 10])),
+            ]
+        );
+    }
+}
@@ -21,6 +21,7 @@ use configuration::{load_configuration, ImporterConfiguration};
 mod berlin;
 mod configuration;
 mod generic;
+mod gtfs;
 mod seattle;
 mod soundcast;
 mod uk;
@@ -120,6 +121,8 @@ pub fn oneshot(
             extra_buildings: None,
             skip_local_roads: false,
             filter_crosswalks,
+            elevation: convert_osm::ElevationSource::Docker,
+            bike_share_stations: None,
         },
         &mut timer,
     );
@@ -256,14 +259,22 @@ impl Job {
                         "distribute residents from planning areas for {}",
                         name.describe()
                     ));
-                } else if name.city == CityName::seattle() {
-                    // TODO Slightly misleading, but hijack --skip_ch to also skip GTFS. The
-                    // intention of --skip_ch is usually to quickly iterate on the map importer,
-                    // not in release mode. This import is broken/unused right now anyway and takes
-                    // way too much time in debug mode.
-                    if !self.opts.skip_ch {
+                }
+
+                // TODO Slightly misleading, but hijack --skip_ch to also skip GTFS. The intention
+                // of --skip_ch is usually to quickly iterate on the map importer, not in release
+                // mode, and this import takes way too much time in debug mode.
+                if !self.opts.skip_ch {
+                    if let Some((path, service_date)) = gtfs_input(&name, timer) {
                         timer.start(format!("add GTFS schedules for {}", name.describe()));
-                        seattle::add_gtfs_schedules(&mut map);
+                        match gtfs::import(&mut map, &path, service_date, &config, timer) {
+                            Ok(()) => {
+                                map.save();
+                            }
+                            Err(err) => {
+                                warn!("Couldn't import GTFS for {}: {}", name.describe(), err);
+                            }
+                        }
                         timer.stop(format!("add GTFS schedules for {}", name.describe()));
                     }
                 }
@@ -350,3 +361,20 @@ impl Job {
         timer.stop(format!("import {}", self.city.describe()));
     }
 }
+
+/// Where's the GTFS feed for a map, and which day should be used?
+fn gtfs_input(name: &MapName, timer: &mut Timer) -> Option<(String, Option<u32>)> {
+    if name.city == CityName::seattle() {
+        return Some((name.city.input_path("google_transit/"), None));
+    }
+    let city_cfg = abstio::maybe_read_json::<generic::GenericCityImporter>(
+        format!(
+            "importer/config/{}/{}/cfg.json",
+            name.city.country, name.city.city
+        ),
+        timer,
+    )
+    .ok()?;
+    let path = city_cfg.local_gtfs_path(&name.city)?;
+    Some((path, city_cfg.gtfs_service_date))
+}
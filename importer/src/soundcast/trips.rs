@@ -204,9 +204,12 @@ fn clip_trips(
                 &osm_id_to_bldg,
                 borders.for_mode(orig.mode),
                 match orig.mode {
-                    TripMode::Walk | TripMode::Transit => PathConstraints::Pedestrian,
-                    TripMode::Drive => PathConstraints::Car,
+                    TripMode::Walk | TripMode::Transit | TripMode::Carpool => {
+                        PathConstraints::Pedestrian
+                    }
+                    TripMode::Drive | TripMode::RideHail => PathConstraints::Car,
                     TripMode::Bike => PathConstraints::Bike,
+                    TripMode::BikeShare => PathConstraints::Pedestrian,
                 },
                 maybe_huge_map.as_ref(),
                 only_passthrough_trips,
@@ -296,6 +299,9 @@ pub fn make_scenario(
         map_name: map.get_name().clone(),
         people,
         only_seed_buses: None,
+        ride_hail: None,
+        emergency_calls: Vec::new(),
+        deliveries: Vec::new(),
     }
     .remove_weird_schedules()
 }
@@ -78,6 +78,9 @@ impl AppLike for App {
     fn sim_time(&self) -> geom::Time {
         unreachable!()
     }
+    fn current_signal_plan(&self, _: map_model::IntersectionID) -> usize {
+        unreachable!()
+    }
     fn current_stage_and_remaining_time(
         &self,
         _: map_model::IntersectionID,
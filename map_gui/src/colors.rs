@@ -139,6 +139,7 @@ pub struct ColorScheme {
     pub bus_body: Color,
     pub bus_label: Color,
     pub train_body: Color,
+    pub emergency_body: Color,
     pub ped_head: Color,
     pub ped_foot: Color,
     pub ped_preparing_bike_body: Color,
@@ -156,6 +157,9 @@ pub struct ColorScheme {
     pub parking_trip: Color,
     pub bike_trip: Color,
     pub bus_trip: Color,
+    pub carpool_trip: Color,
+    pub ride_hail_trip: Color,
+    pub bike_share_trip: Color,
     pub before_changes: Color,
     pub after_changes: Color,
 }
@@ -265,6 +269,7 @@ impl ColorScheme {
             bus_body: Color::rgb(50, 133, 117),
             bus_label: Color::rgb(249, 206, 24),
             train_body: hex("#42B6E9"),
+            emergency_body: hex("#E8352B"),
             ped_head: Color::rgb(139, 69, 19),
             ped_foot: Color::BLACK,
             ped_preparing_bike_body: Color::rgb(255, 0, 144),
@@ -282,6 +287,9 @@ impl ColorScheme {
             parking_trip: hex("#4E30A6"),
             bike_trip: Color::rgb(15, 125, 75),
             bus_trip: Color::rgb(190, 74, 76),
+            carpool_trip: hex("#F4A261"),
+            ride_hail_trip: hex("#E9C46A"),
+            bike_share_trip: hex("#2A9D8F"),
             before_changes: Color::BLUE,
             after_changes: Color::RED,
         }
@@ -53,11 +53,14 @@ pub trait AppLike {
     where
         Self: Sized;
 
-    // These two are needed to render traffic signals. Splitting them from sim() allows
-    // applications that don't run a traffic sim to work.
+    // These are needed to render traffic signals. Splitting them from sim() allows applications
+    // that don't run a traffic sim to work.
     fn sim_time(&self) -> Time {
         self.sim().time()
     }
+    fn current_signal_plan(&self, id: IntersectionID) -> usize {
+        self.sim().current_signal_plan(id)
+    }
     fn current_stage_and_remaining_time(&self, id: IntersectionID) -> (usize, Duration) {
         self.sim().current_stage_and_remaining_time(id)
     }
@@ -222,11 +222,12 @@ impl Renderable for DrawIntersection {
                     .map(|(t, _)| *t != app.sim_time())
                     .unwrap_or(true);
                 if recalc {
+                    let plan = app.current_signal_plan(self.id);
                     let (idx, remaining) = app.current_stage_and_remaining_time(self.id);
                     let mut batch = GeomBatch::new();
                     traffic_signal::draw_signal_stage(
                         g.prerender,
-                        &signal.stages[idx],
+                        &signal.plan_stages(plan)[idx],
                         idx,
                         self.id,
                         Some(remaining),
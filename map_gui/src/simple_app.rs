@@ -311,10 +311,16 @@ impl<T: 'static> AppLike for SimpleApp<T> {
         self.time
     }
 
+    fn current_signal_plan(&self, id: IntersectionID) -> usize {
+        self.map.get_traffic_signal(id).plan_at(self.time)
+    }
+
     fn current_stage_and_remaining_time(&self, id: IntersectionID) -> (usize, Duration) {
         let signal = self.map.get_traffic_signal(id);
-        let mut time_left = (self.time - Time::START_OF_DAY) % signal.simple_cycle_duration();
-        for (idx, stage) in signal.stages.iter().enumerate() {
+        let stages = signal.plan_stages(signal.plan_at(self.time));
+        let cycle_duration: Duration = stages.iter().map(|s| s.stage_type.simple_duration()).sum();
+        let mut time_left = (self.time - Time::START_OF_DAY) % cycle_duration;
+        for (idx, stage) in stages.iter().enumerate() {
             if time_left < stage.stage_type.simple_duration() {
                 return (idx, time_left);
             }
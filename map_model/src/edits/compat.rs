@@ -111,6 +111,34 @@ pub fn upgrade(mut value: Value, map: &Map) -> Result<PermanentMapEdits> {
             .unwrap()
             .insert("version".to_string(), Value::Number(11.into()));
     }
+    if value["version"] == Value::Number(11.into()) {
+        fix_tolls(&mut value);
+        value
+            .as_object_mut()
+            .unwrap()
+            .insert("version".to_string(), Value::Number(12.into()));
+    }
+    if value["version"] == Value::Number(12.into()) {
+        fix_loading_zones(&mut value);
+        value
+            .as_object_mut()
+            .unwrap()
+            .insert("version".to_string(), Value::Number(13.into()));
+    }
+    if value["version"] == Value::Number(13.into()) {
+        fix_parking_policies(&mut value);
+        value
+            .as_object_mut()
+            .unwrap()
+            .insert("version".to_string(), Value::Number(14.into()));
+    }
+    if value["version"] == Value::Number(14.into()) {
+        fix_permit_zones(&mut value);
+        value
+            .as_object_mut()
+            .unwrap()
+            .insert("version".to_string(), Value::Number(15.into()));
+    }
 
     abstutil::from_json(&value.to_string().into_bytes())
 }
@@ -499,3 +527,35 @@ impl OriginalLane {
         Ok((r.id, l.offset))
     }
 }
+
+// Road pricing added a map-wide list of tolls
+fn fix_tolls(value: &mut Value) {
+    let obj = value.as_object_mut().unwrap();
+    if !obj.contains_key("tolls") {
+        obj.insert("tolls".to_string(), Value::Array(Vec::new()));
+    }
+}
+
+// Curbside management added a map-wide set of roads with loading zones
+fn fix_loading_zones(value: &mut Value) {
+    let obj = value.as_object_mut().unwrap();
+    if !obj.contains_key("loading_zones") {
+        obj.insert("loading_zones".to_string(), Value::Array(Vec::new()));
+    }
+}
+
+// Parking management added a map-wide list of parking prices and time limits
+fn fix_parking_policies(value: &mut Value) {
+    let obj = value.as_object_mut().unwrap();
+    if !obj.contains_key("parking_policies") {
+        obj.insert("parking_policies".to_string(), Value::Array(Vec::new()));
+    }
+}
+
+// Residential parking permits added a map-wide list of zones
+fn fix_permit_zones(value: &mut Value) {
+    let obj = value.as_object_mut().unwrap();
+    if !obj.contains_key("permit_zones") {
+        obj.insert("permit_zones".to_string(), Value::Array(Vec::new()));
+    }
+}
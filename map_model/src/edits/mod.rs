@@ -13,11 +13,12 @@ use geom::{Distance, HashablePt2D, Line, Speed, Time};
 pub use self::perma::PermanentMapEdits;
 use crate::make::initial::lane_specs::get_lane_specs_ltr;
 use crate::make::{match_points_to_lanes, snap_driveway, trim_path};
+use crate::objects::building::find_biking_connection;
 use crate::{
     connectivity, AccessRestrictions, BuildingID, BusRouteID, ControlStopSign,
     ControlTrafficSignal, Direction, IntersectionID, IntersectionType, LaneID, LaneSpec, LaneType,
-    Map, MapConfig, Movement, ParkingLotID, PathConstraints, Pathfinder, Road, RoadID, TurnID,
-    Zone,
+    Map, MapConfig, Movement, ParkingLotID, ParkingPolicy, PathConstraints, Pathfinder, PermitZone,
+    Road, RoadID, TollSchedule, TurnID, Zone,
 };
 
 mod compat;
@@ -35,6 +36,17 @@ pub struct MapEdits {
     /// Zone; every Road will be its own Zone. This is used to experiment with a per-road cap. Note
     /// this is a map-wide setting.
     pub merge_zones: bool,
+    /// Road pricing. Note this is a map-wide setting, not part of the undo stack.
+    pub tolls: Vec<TollSchedule>,
+    /// Every on-street parking lane along these roads is reserved for freight vehicles making
+    /// deliveries. Note this is a map-wide setting, not part of the undo stack.
+    pub loading_zones: BTreeSet<RoadID>,
+    /// Prices and time limits for parking. If several policies cover the same spot, the first
+    /// one applies. Note this is a map-wide setting, not part of the undo stack.
+    pub parking_policies: Vec<ParkingPolicy>,
+    /// On-street parking in these zones is reserved for residents during certain hours. Note this
+    /// is a map-wide setting, not part of the undo stack.
+    pub permit_zones: Vec<PermitZone>,
 
     /// Derived from commands, kept up to date by update_derived
     pub changed_roads: BTreeSet<RoadID>,
@@ -210,6 +222,10 @@ impl MapEdits {
             proposal_link: None,
             commands: Vec::new(),
             merge_zones: true,
+            tolls: Vec::new(),
+            loading_zones: BTreeSet::new(),
+            parking_policies: Vec::new(),
+            permit_zones: Vec::new(),
 
             changed_roads: BTreeSet::new(),
             original_intersections: BTreeMap::new(),
@@ -232,7 +248,12 @@ impl MapEdits {
             }
         };
         let edits = perma.into_edits_permissive(map);
-        if edits.commands.is_empty() {
+        if edits.commands.is_empty()
+            && edits.tolls.is_empty()
+            && edits.loading_zones.is_empty()
+            && edits.parking_policies.is_empty()
+            && edits.permit_zones.is_empty()
+        {
             bail!("None of the edits apply to this map");
         }
         Ok(edits)
@@ -252,7 +273,12 @@ impl MapEdits {
             }
         };
         let edits = perma.into_edits_permissive(map);
-        if edits.commands.is_empty() {
+        if edits.commands.is_empty()
+            && edits.tolls.is_empty()
+            && edits.loading_zones.is_empty()
+            && edits.parking_policies.is_empty()
+            && edits.permit_zones.is_empty()
+        {
             bail!("None of the edits apply to this map");
         }
         Ok(edits)
@@ -922,6 +948,10 @@ impl Map {
         }
 
         let merge_zones_changed = self.edits.merge_zones != new_edits.merge_zones;
+        // Custom pathfinders with tolls refer to them by index
+        if self.edits.tolls != new_edits.tolls {
+            self.clear_custom_pathfinder_cache();
+        }
 
         new_edits.update_derived(self);
         self.edits = new_edits;
@@ -981,6 +1011,15 @@ impl Map {
         }
         timer.stop("recompute blackholes");
 
+        // Where people start and stop biking from docks depends on the blackholes
+        timer.start("recompute bike-share dock connections");
+        for idx in 0..self.bike_share_docks.len() {
+            let sidewalk_pos = self.bike_share_docks[idx].sidewalk_pos;
+            self.bike_share_docks[idx].biking_connection =
+                find_biking_connection(sidewalk_pos, self);
+        }
+        timer.stop("recompute bike-share dock connections");
+
         self.pathfinder_dirty = false;
     }
 
@@ -5,11 +5,14 @@ use serde::{Deserialize, Serialize};
 
 use abstio::MapName;
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::Time;
+use geom::{Duration, Time};
 
 use crate::edits::{EditCmd, EditIntersection, EditRoad, MapEdits};
 use crate::raw::OriginalRoad;
-use crate::{osm, ControlStopSign, IntersectionID, Map};
+use crate::{
+    osm, ControlStopSign, IntersectionID, Map, ParkingPeriod, ParkingPolicy, PermitZone, RoadID,
+    TollPeriod, TollSchedule,
+};
 
 /// MapEdits are converted to this before serializing. Referencing things like LaneID in a Map won't
 /// work if the basemap is rebuilt from new OSM data, so instead we use stabler OSM IDs that're less
@@ -24,6 +27,10 @@ pub struct PermanentMapEdits {
     /// Zone; every Road will be its own Zone. This is used to experiment with a per-road cap. Note
     /// this is a map-wide setting.
     merge_zones: bool,
+    tolls: Vec<PermanentTollSchedule>,
+    loading_zones: Vec<OriginalRoad>,
+    parking_policies: Vec<PermanentParkingPolicy>,
+    permit_zones: Vec<PermanentPermitZone>,
 
     /// Edits without these are player generated.
     pub proposal_description: Vec<String>,
@@ -31,6 +38,32 @@ pub struct PermanentMapEdits {
     pub proposal_link: Option<String>,
 }
 
+/// A TollSchedule, with roads referenced by OSM IDs
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PermanentTollSchedule {
+    name: String,
+    roads: Vec<OriginalRoad>,
+    cordon: bool,
+    periods: Vec<TollPeriod>,
+}
+
+/// A ParkingPolicy, with roads and parking lots referenced by OSM IDs
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PermanentParkingPolicy {
+    name: String,
+    roads: Vec<OriginalRoad>,
+    lots: Vec<osm::OsmID>,
+    periods: Vec<ParkingPeriod>,
+}
+
+/// A PermitZone, with roads referenced by OSM IDs
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PermanentPermitZone {
+    name: String,
+    roads: Vec<OriginalRoad>,
+    hours: Vec<(Duration, Duration)>,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub enum PermanentEditIntersection {
     StopSign {
@@ -139,11 +172,49 @@ impl MapEdits {
             map_name: map.get_name().clone(),
             edits_name: self.edits_name.clone(),
             // Increase this every time there's a schema change
-            version: 11,
+            version: 15,
             proposal_description: self.proposal_description.clone(),
             proposal_link: self.proposal_link.clone(),
             commands: self.commands.iter().map(|cmd| cmd.to_perma(map)).collect(),
             merge_zones: self.merge_zones,
+            tolls: self
+                .tolls
+                .iter()
+                .map(|toll| PermanentTollSchedule {
+                    name: toll.name.clone(),
+                    roads: toll.roads.iter().map(|r| map.get_r(*r).orig_id).collect(),
+                    cordon: toll.cordon,
+                    periods: toll.periods.clone(),
+                })
+                .collect(),
+            loading_zones: self
+                .loading_zones
+                .iter()
+                .map(|r| map.get_r(*r).orig_id)
+                .collect(),
+            parking_policies: self
+                .parking_policies
+                .iter()
+                .map(|policy| PermanentParkingPolicy {
+                    name: policy.name.clone(),
+                    roads: policy.roads.iter().map(|r| map.get_r(*r).orig_id).collect(),
+                    lots: policy
+                        .lots
+                        .iter()
+                        .map(|pl| map.get_pl(*pl).osm_id)
+                        .collect(),
+                    periods: policy.periods.clone(),
+                })
+                .collect(),
+            permit_zones: self
+                .permit_zones
+                .iter()
+                .map(|zone| PermanentPermitZone {
+                    name: zone.name.clone(),
+                    roads: zone.roads.iter().map(|r| map.get_r(*r).orig_id).collect(),
+                    hours: zone.hours.clone(),
+                })
+                .collect(),
         }
     }
 }
@@ -162,6 +233,26 @@ impl PermanentMapEdits {
                 .map(|cmd| cmd.into_cmd(map))
                 .collect::<Result<Vec<EditCmd>>>()?,
             merge_zones: self.merge_zones,
+            tolls: self
+                .tolls
+                .into_iter()
+                .map(|toll| toll.into_toll(map))
+                .collect::<Result<Vec<TollSchedule>>>()?,
+            loading_zones: self
+                .loading_zones
+                .into_iter()
+                .map(|r| map.find_r_by_osm_id(r))
+                .collect::<Result<BTreeSet<RoadID>>>()?,
+            parking_policies: self
+                .parking_policies
+                .into_iter()
+                .map(|policy| policy.into_policy(map))
+                .collect::<Result<Vec<ParkingPolicy>>>()?,
+            permit_zones: self
+                .permit_zones
+                .into_iter()
+                .map(|zone| zone.into_zone(map))
+                .collect::<Result<Vec<PermitZone>>>()?,
 
             changed_roads: BTreeSet::new(),
             original_intersections: BTreeMap::new(),
@@ -190,6 +281,50 @@ impl PermanentMapEdits {
                 })
                 .collect(),
             merge_zones: self.merge_zones,
+            tolls: self
+                .tolls
+                .into_iter()
+                .filter_map(|toll| match toll.into_toll(map) {
+                    Ok(toll) => Some(toll),
+                    Err(err) => {
+                        warn!("Skipping broken toll: {}", err);
+                        None
+                    }
+                })
+                .collect(),
+            loading_zones: self
+                .loading_zones
+                .into_iter()
+                .filter_map(|r| match map.find_r_by_osm_id(r) {
+                    Ok(r) => Some(r),
+                    Err(err) => {
+                        warn!("Skipping broken loading zone: {}", err);
+                        None
+                    }
+                })
+                .collect(),
+            parking_policies: self
+                .parking_policies
+                .into_iter()
+                .filter_map(|policy| match policy.into_policy(map) {
+                    Ok(policy) => Some(policy),
+                    Err(err) => {
+                        warn!("Skipping broken parking policy: {}", err);
+                        None
+                    }
+                })
+                .collect(),
+            permit_zones: self
+                .permit_zones
+                .into_iter()
+                .filter_map(|zone| match zone.into_zone(map) {
+                    Ok(zone) => Some(zone),
+                    Err(err) => {
+                        warn!("Skipping broken permit zone: {}", err);
+                        None
+                    }
+                })
+                .collect(),
 
             changed_roads: BTreeSet::new(),
             original_intersections: BTreeMap::new(),
@@ -210,6 +345,65 @@ impl PermanentMapEdits {
     }
 }
 
+impl PermanentTollSchedule {
+    fn into_toll(self, map: &Map) -> Result<TollSchedule> {
+        let mut roads = BTreeSet::new();
+        for r in self.roads {
+            roads.insert(map.find_r_by_osm_id(r)?);
+        }
+        let toll = TollSchedule {
+            name: self.name,
+            roads,
+            cordon: self.cordon,
+            periods: self.periods,
+        };
+        toll.validate()?;
+        Ok(toll)
+    }
+}
+
+impl PermanentParkingPolicy {
+    fn into_policy(self, map: &Map) -> Result<ParkingPolicy> {
+        let mut roads = BTreeSet::new();
+        for r in self.roads {
+            roads.insert(map.find_r_by_osm_id(r)?);
+        }
+        let mut lots = BTreeSet::new();
+        for osm_id in self.lots {
+            let pl = map
+                .all_parking_lots()
+                .iter()
+                .find(|pl| pl.osm_id == osm_id)
+                .ok_or_else(|| anyhow!("can't find parking lot {}", osm_id))?;
+            lots.insert(pl.id);
+        }
+        let policy = ParkingPolicy {
+            name: self.name,
+            roads,
+            lots,
+            periods: self.periods,
+        };
+        policy.validate()?;
+        Ok(policy)
+    }
+}
+
+impl PermanentPermitZone {
+    fn into_zone(self, map: &Map) -> Result<PermitZone> {
+        let mut roads = BTreeSet::new();
+        for r in self.roads {
+            roads.insert(map.find_r_by_osm_id(r)?);
+        }
+        let zone = PermitZone {
+            name: self.name,
+            roads,
+            hours: self.hours,
+        };
+        zone.validate()?;
+        Ok(zone)
+    }
+}
+
 impl EditIntersection {
     fn to_permanent(&self, map: &Map) -> PermanentEditIntersection {
         match self {
@@ -43,6 +43,7 @@ pub use crate::edits::{
 pub use crate::make::RawToMapOptions;
 pub use crate::map::{DrivingSide, MapConfig};
 pub use crate::objects::area::{Area, AreaID, AreaType};
+pub use crate::objects::bike_share::{BikeShareDock, BikeShareDockID};
 pub use crate::objects::block::{Block, Perimeter};
 pub use crate::objects::building::{
     Amenity, AmenityType, Building, BuildingID, BuildingType, NamePerLanguage, OffstreetParking,
@@ -55,15 +56,21 @@ pub use crate::objects::lane::{
 };
 pub use crate::objects::movement::{CompressedMovementID, Movement, MovementID};
 pub use crate::objects::parking_lot::{ParkingLot, ParkingLotID};
+pub use crate::objects::parking_policy::{ParkingPeriod, ParkingPolicy};
+pub use crate::objects::permit_zone::PermitZone;
 pub use crate::objects::road::{DirectedRoadID, Direction, Road, RoadID, RoadSideID, SideOfRoad};
 pub use crate::objects::stop_signs::{ControlStopSign, RoadWithStopSign};
-pub use crate::objects::traffic_signals::{ControlTrafficSignal, Stage, StageType};
+pub use crate::objects::toll::{TollPeriod, TollSchedule};
+pub use crate::objects::traffic_signals::{
+    ControlTrafficSignal, Recall, Stage, StageType, TimingPlan, TransitPriority,
+};
 pub use crate::objects::turn::{Turn, TurnID, TurnPriority, TurnType};
 pub use crate::objects::zone::{AccessRestrictions, Zone};
 pub use crate::pathfind::uber_turns::{IntersectionCluster, UberTurn};
 use crate::pathfind::Pathfinder;
 pub use crate::pathfind::{
     Path, PathConstraints, PathRequest, PathStep, PathStepV2, PathV2, RoutingParams,
+    TravelTimeProfile,
 };
 pub use crate::traversable::{Position, Traversable, MAX_BIKE_SPEED, MAX_WALKING_SPEED};
 
@@ -76,6 +83,7 @@ mod objects;
 pub mod osm;
 mod pathfind;
 pub mod raw;
+pub mod signal_offsets;
 mod traversable;
 
 // The map used by the simulation and UI. This struct is declared here so that the rest of the
@@ -93,6 +101,7 @@ pub struct Map {
     bus_routes: Vec<BusRoute>,
     areas: Vec<Area>,
     parking_lots: Vec<ParkingLot>,
+    bike_share_docks: Vec<BikeShareDock>,
     boundary_polygon: Polygon,
 
     // Note that border nodes belong in neither!
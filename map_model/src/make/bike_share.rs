@@ -0,0 +1,49 @@
+use std::collections::HashSet;
+
+use abstutil::Timer;
+use geom::{Distance, HashablePt2D};
+
+use crate::make::match_points_to_lanes;
+use crate::objects::building::find_biking_connection;
+use crate::raw::RawBikeShareDock;
+use crate::{BikeShareDock, BikeShareDockID, Map};
+
+/// Match bike-share docks to the nearest sidewalk. Docks too far from any sidewalk are dropped.
+/// Turns and blackholes must already exist, to find where people start and stop biking.
+pub fn make_all_docks(
+    input: &[RawBikeShareDock],
+    map: &Map,
+    timer: &mut Timer,
+) -> Vec<BikeShareDock> {
+    let query: HashSet<HashablePt2D> = input.iter().map(|d| d.point.to_hashable()).collect();
+    let sidewalk_pts = match_points_to_lanes(
+        map,
+        query,
+        |l| l.is_walkable(),
+        Distance::ZERO,
+        Distance::meters(100.0),
+        timer,
+    );
+
+    let mut results = Vec::new();
+    for dock in input {
+        if let Some(sidewalk_pos) = sidewalk_pts.get(&dock.point.to_hashable()) {
+            results.push(BikeShareDock {
+                id: BikeShareDockID(results.len()),
+                name: dock.name.clone(),
+                point: dock.point,
+                capacity: dock.capacity,
+                osm_id: dock.osm_id,
+                gbfs_station_id: dock.gbfs_station_id.clone(),
+                sidewalk_pos: *sidewalk_pos,
+                biking_connection: find_biking_connection(*sidewalk_pos, map),
+            });
+        } else {
+            warn!(
+                "Bike-share dock {:?} at {} isn't near any sidewalk",
+                dock.name, dock.point
+            );
+        }
+    }
+    results
+}
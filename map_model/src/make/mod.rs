@@ -17,6 +17,7 @@ use crate::{
     Position, Road, RoadID, RoutingParams, Zone,
 };
 
+mod bike_share;
 mod bridges;
 mod buildings;
 pub mod collapse_intersections;
@@ -63,6 +64,7 @@ impl Map {
             bus_routes: Vec::new(),
             areas: Vec::new(),
             parking_lots: Vec::new(),
+            bike_share_docks: Vec::new(),
             zones: Vec::new(),
             boundary_polygon: raw.boundary_polygon.clone(),
             stop_signs: BTreeMap::new(),
@@ -241,6 +243,8 @@ impl Map {
             timer,
         );
 
+        map.bike_share_docks = bike_share::make_all_docks(&raw.bike_share_docks, &map, timer);
+
         map.zones = Zone::make_all(&map);
 
         for a in &raw.areas {
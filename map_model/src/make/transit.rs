@@ -8,7 +8,7 @@ use geom::{Distance, Duration, FindClosest, HashablePt2D, Time};
 use crate::make::match_points_to_lanes;
 use crate::raw::{RawBusRoute, RawBusStop};
 use crate::{
-    BusRoute, BusRouteID, BusStop, BusStopID, LaneID, LaneType, Map, PathConstraints, Position,
+    osm, BusRoute, BusRouteID, BusStop, BusStopID, LaneID, LaneType, Map, PathConstraints, Position,
 };
 
 /// Construct the final model of bus/train stops and routes. This is quite broken currently, so not
@@ -56,28 +56,14 @@ fn make_route(
     for stop in &r.stops {
         match matcher.lookup(route_type, stop, map) {
             Ok((sidewalk_pos, driving_pos)) => {
-                // Create a new bus stop if needed.
-                let stop_id = if let Some(id) = pt_to_stop.get(&(sidewalk_pos, driving_pos)) {
-                    *id
-                } else {
-                    let id = BusStopID {
-                        sidewalk: sidewalk_pos.lane(),
-                        idx: map.get_l(sidewalk_pos.lane()).bus_stops.len(),
-                    };
-                    pt_to_stop.insert((sidewalk_pos, driving_pos), id);
-                    map.mut_lane(sidewalk_pos.lane()).bus_stops.insert(id);
-                    map.bus_stops.insert(
-                        id,
-                        BusStop {
-                            id,
-                            name: stop.name.clone(),
-                            driving_pos,
-                            sidewalk_pos,
-                            is_train_stop: !r.is_bus,
-                        },
-                    );
-                    id
-                };
+                let stop_id = get_or_create_stop(
+                    map,
+                    pt_to_stop,
+                    stop.name.clone(),
+                    sidewalk_pos,
+                    driving_pos,
+                    !r.is_bus,
+                );
                 stops.push(stop_id);
             }
             Err(err) => {
@@ -141,8 +127,15 @@ fn make_route(
         end_border,
         spawn_times: default_spawn_times(),
         orig_spawn_times: default_spawn_times(),
+        scheduled_stop_offsets: Vec::new(),
+        passenger_capacity: None,
     };
+    add_route(map, route)?;
+    Ok(())
+}
 
+/// Make sure the route is connected, then add it to the map.
+fn add_route(map: &mut Map, route: BusRoute) -> Result<BusRouteID> {
     let mut debug_route = "All parts of the route:".to_string();
     debug_route = format!("{}\nStart at {}", debug_route, route.start);
     for (idx, bs) in route.stops.iter().enumerate() {
@@ -179,8 +172,109 @@ fn make_route(
         }
     }
 
+    let id = route.id;
     map.bus_routes.push(route);
-    Ok(())
+    Ok(id)
+}
+
+/// Create a new bus stop if needed.
+fn get_or_create_stop(
+    map: &mut Map,
+    pt_to_stop: &mut BTreeMap<(Position, Position), BusStopID>,
+    name: String,
+    sidewalk_pos: Position,
+    driving_pos: Position,
+    is_train_stop: bool,
+) -> BusStopID {
+    if let Some(id) = pt_to_stop.get(&(sidewalk_pos, driving_pos)) {
+        return *id;
+    }
+    let id = BusStopID {
+        sidewalk: sidewalk_pos.lane(),
+        idx: map.get_l(sidewalk_pos.lane()).bus_stops.len(),
+    };
+    pt_to_stop.insert((sidewalk_pos, driving_pos), id);
+    map.mut_lane(sidewalk_pos.lane()).bus_stops.insert(id);
+    map.bus_stops.insert(
+        id,
+        BusStop {
+            id,
+            name,
+            driving_pos,
+            sidewalk_pos,
+            is_train_stop,
+        },
+    );
+    id
+}
+
+impl Map {
+    /// Add a route that isn't in OSM, like one only described by GTFS. Each stop is given by its
+    /// name, sidewalk position, and driving position; existing stops at the same positions are
+    /// reused. Since OSM doesn't know about the route, it gets a made-up negative relation ID,
+    /// which edits can use to refer to it.
+    pub fn hack_add_bus_route(
+        &mut self,
+        full_name: String,
+        short_name: String,
+        route_type: PathConstraints,
+        stops: Vec<(String, Position, Position)>,
+    ) -> Result<BusRouteID> {
+        if stops.len() < 2 {
+            bail!("{} needs at least two stops", full_name);
+        }
+        let mut pt_to_stop: BTreeMap<(Position, Position), BusStopID> = self
+            .bus_stops
+            .values()
+            .map(|bs| ((bs.sidewalk_pos, bs.driving_pos), bs.id))
+            .collect();
+        let mut new_stops = Vec::new();
+        let mut route_stops = Vec::new();
+        for (name, sidewalk_pos, driving_pos) in stops {
+            let is_new = !pt_to_stop.contains_key(&(sidewalk_pos, driving_pos));
+            let id = get_or_create_stop(
+                self,
+                &mut pt_to_stop,
+                name,
+                sidewalk_pos,
+                driving_pos,
+                route_type == PathConstraints::Train,
+            );
+            if is_new {
+                new_stops.push(id);
+            }
+            route_stops.push(id);
+        }
+
+        let result = pick_start_lane(self.get_bs(route_stops[0]).driving_pos, route_type, self)
+            .and_then(|start| {
+                let route = BusRoute {
+                    id: BusRouteID(self.bus_routes.len()),
+                    full_name,
+                    short_name,
+                    osm_rel_id: osm::RelationID(-1 - self.bus_routes.len() as i64),
+                    gtfs_trip_marker: None,
+                    stops: route_stops,
+                    route_type,
+                    start,
+                    end_border: None,
+                    spawn_times: default_spawn_times(),
+                    orig_spawn_times: default_spawn_times(),
+                    scheduled_stop_offsets: Vec::new(),
+                    passenger_capacity: None,
+                };
+                add_route(self, route)
+            });
+        if result.is_err() {
+            // Don't leave behind stops that nothing serves. Undo in reverse order, so the indices
+            // stay consistent.
+            for id in new_stops.into_iter().rev() {
+                self.bus_stops.remove(&id);
+                self.mut_lane(id.sidewalk).bus_stops.remove(&id);
+            }
+        }
+        result
+    }
 }
 
 struct Matcher {
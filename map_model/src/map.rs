@@ -12,11 +12,13 @@ use geom::{Bounds, Distance, Duration, GPSBounds, Polygon, Pt2D, Ring, Time};
 
 use crate::raw::{OriginalRoad, RawMap};
 use crate::{
-    osm, Area, AreaID, AreaType, Building, BuildingID, BuildingType, BusRoute, BusRouteID, BusStop,
-    BusStopID, CompressedMovementID, ControlStopSign, ControlTrafficSignal, DirectedRoadID,
-    Direction, Intersection, IntersectionID, Lane, LaneID, LaneType, Map, MapEdits, Movement,
-    MovementID, OffstreetParking, ParkingLot, ParkingLotID, Path, PathConstraints, PathRequest,
-    PathV2, Pathfinder, Position, Road, RoadID, RoutingParams, Turn, TurnID, TurnType, Zone,
+    osm, Area, AreaID, AreaType, BikeShareDock, BikeShareDockID, Building, BuildingID,
+    BuildingType, BusRoute, BusRouteID, BusStop, BusStopID, CompressedMovementID, ControlStopSign,
+    ControlTrafficSignal, DirectedRoadID, Direction, Intersection, IntersectionID, Lane, LaneID,
+    LaneType, Map, MapEdits, Movement, MovementID, OffstreetParking, ParkingLot, ParkingLotID,
+    ParkingPolicy, Path, PathConstraints, PathRequest, PathStep, PathV2, Pathfinder, PermitZone,
+    Position, Road, RoadID, RoutingParams, TollSchedule, TravelTimeProfile, Turn, TurnID, TurnType,
+    Zone,
 };
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -121,6 +123,11 @@ impl Map {
                     self.parking_lots.len(),
                     serialized_size_bytes(&self.parking_lots),
                 ),
+                (
+                    "bike-share docks",
+                    self.bike_share_docks.len(),
+                    serialized_size_bytes(&self.bike_share_docks),
+                ),
                 (
                     "zones",
                     self.zones.len(),
@@ -156,6 +163,7 @@ impl Map {
             bus_routes: Vec::new(),
             areas: Vec::new(),
             parking_lots: Vec::new(),
+            bike_share_docks: Vec::new(),
             zones: Vec::new(),
             boundary_polygon: Ring::must_new(vec![
                 Pt2D::new(0.0, 0.0),
@@ -212,10 +220,63 @@ impl Map {
         &self.parking_lots
     }
 
+    pub fn all_bike_share_docks(&self) -> &Vec<BikeShareDock> {
+        &self.bike_share_docks
+    }
+
     pub fn all_zones(&self) -> &Vec<Zone> {
         &self.zones
     }
 
+    /// Tolls are part of the map edits.
+    pub fn get_tolls(&self) -> &Vec<TollSchedule> {
+        &self.edits.tolls
+    }
+
+    /// Loading zones are part of the map edits. Every on-street parking lane along these roads is
+    /// reserved for freight vehicles making deliveries.
+    pub fn get_loading_zones(&self) -> &BTreeSet<RoadID> {
+        &self.edits.loading_zones
+    }
+
+    pub fn is_loading_zone(&self, l: LaneID) -> bool {
+        self.get_l(l).lane_type == LaneType::Parking && self.edits.loading_zones.contains(&l.road)
+    }
+
+    /// Parking policies are part of the map edits.
+    pub fn get_parking_policies(&self) -> &Vec<ParkingPolicy> {
+        &self.edits.parking_policies
+    }
+
+    /// The policy governing on-street parking along a lane, if any
+    pub fn get_parking_policy_for_lane(&self, l: LaneID) -> Option<&ParkingPolicy> {
+        self.edits
+            .parking_policies
+            .iter()
+            .find(|policy| policy.roads.contains(&l.road))
+    }
+
+    /// The policy governing a parking lot, if any
+    pub fn get_parking_policy_for_lot(&self, pl: ParkingLotID) -> Option<&ParkingPolicy> {
+        self.edits
+            .parking_policies
+            .iter()
+            .find(|policy| policy.lots.contains(&pl))
+    }
+
+    /// Permit zones are part of the map edits.
+    pub fn get_permit_zones(&self) -> &Vec<PermitZone> {
+        &self.edits.permit_zones
+    }
+
+    /// The residential permit zone covering a lane, if any
+    pub fn get_permit_zone_for_lane(&self, l: LaneID) -> Option<&PermitZone> {
+        self.edits
+            .permit_zones
+            .iter()
+            .find(|zone| zone.roads.contains(&l.road))
+    }
+
     pub fn maybe_get_r(&self, id: RoadID) -> Option<&Road> {
         self.roads.get(id.0)
     }
@@ -247,6 +308,10 @@ impl Map {
         self.parking_lots.get(id.0)
     }
 
+    pub fn maybe_get_dock(&self, id: BikeShareDockID) -> Option<&BikeShareDock> {
+        self.bike_share_docks.get(id.0)
+    }
+
     pub fn maybe_get_a(&self, id: AreaID) -> Option<&Area> {
         self.areas.get(id.0)
     }
@@ -304,6 +369,10 @@ impl Map {
         &self.parking_lots[id.0]
     }
 
+    pub fn get_dock(&self, id: BikeShareDockID) -> &BikeShareDock {
+        &self.bike_share_docks[id.0]
+    }
+
     pub fn get_stop_sign(&self, id: IntersectionID) -> &ControlStopSign {
         &self.stop_signs[&id]
     }
@@ -596,6 +665,180 @@ impl Map {
             .pathfind_with_params(req.clone(), params, cache_custom, self)
             .ok_or_else(|| anyhow!("can't fulfill {}", req))
     }
+    /// Pathfind for somebody departing at some time. Vehicles try to avoid tolls in effect then,
+    /// trading off the price against the extra time of a detour. If a `TravelTimeProfile` has been
+    /// set, cars also avoid roads that're congested when they'd reach them.
+    pub fn pathfind_at(&self, req: PathRequest, time: Time) -> Result<Path> {
+        assert!(!self.pathfinder_dirty);
+        let params = self.routing_params_at(time, req.constraints);
+        if params == self.routing_params && self.pathfinder.get_travel_time_profile().is_none() {
+            return self.pathfind(req);
+        }
+        self.pathfinder
+            .pathfind_at(req.clone(), time, &params, true, self)
+            .ok_or_else(|| anyhow!("can't fulfill {}", req))?
+            .into_v1(self)
+    }
+
+    /// Like `pathfind`, but vehicles use the travel times observed at different times of day,
+    /// instead of static costs. Tolls in effect at the departure time are also avoided. This
+    /// doesn't need `set_travel_time_profile`.
+    pub fn pathfind_with_profile(
+        &self,
+        req: PathRequest,
+        departure: Time,
+        profile: &TravelTimeProfile,
+    ) -> Result<Path> {
+        self.pathfind_v2_with_profile(req, departure, profile)?
+            .into_v1(self)
+    }
+    pub fn pathfind_v2_with_profile(
+        &self,
+        req: PathRequest,
+        departure: Time,
+        profile: &TravelTimeProfile,
+    ) -> Result<PathV2> {
+        assert!(!self.pathfinder_dirty);
+        let params = self.routing_params_at(departure, req.constraints);
+        self.pathfinder
+            .pathfind_with_profile(req.clone(), departure, profile, &params, self)
+            .ok_or_else(|| anyhow!("can't fulfill {}", req))
+    }
+
+    /// Pathfind for a vehicle already driving at some time, avoiding roads with some extra delay
+    /// right now, like from vehicles queued there. The delays are assumed to last for the rest of
+    /// the trip.
+    pub fn pathfind_with_delays<F: Fn(DirectedRoadID) -> Duration>(
+        &self,
+        req: PathRequest,
+        time: Time,
+        delay: F,
+    ) -> Result<Path> {
+        assert!(!self.pathfinder_dirty);
+        let params = self.routing_params_at(time, req.constraints);
+        self.pathfinder
+            .pathfind_with_delays(req.clone(), &params, delay, self)
+            .ok_or_else(|| anyhow!("can't fulfill {}", req))?
+            .into_v1(self)
+    }
+
+    /// From now on, `pathfind_at` routes cars using these travel times, usually observed in a
+    /// previous simulation. None goes back to static costs. The profile isn't saved with the map.
+    pub fn set_travel_time_profile(&mut self, profile: Option<TravelTimeProfile>) {
+        self.pathfinder.set_travel_time_profile(profile);
+    }
+
+    pub fn get_travel_time_profile(&self) -> Option<&TravelTimeProfile> {
+        self.pathfinder.get_travel_time_profile()
+    }
+
+    /// The routing params, with any tolls charged to this type of vehicle at some time filled out
+    pub fn routing_params_at(&self, time: Time, constraints: PathConstraints) -> RoutingParams {
+        let mut params = self.routing_params.clone();
+        params.tolls = self.tolls_in_effect(time, constraints);
+        params
+    }
+
+    /// Like `pathfind_at` for cars, but trucks also stay off roads that restrict heavy goods
+    /// vehicles. Those roads are only penalized, not removed, and every path from or to a road
+    /// pays the same penalty, so trucks can still start or finish a delivery on one. The params
+    /// only change with the tolls in effect, so one graph per toll period gets cached.
+    pub fn pathfind_for_truck(&self, req: PathRequest, time: Time) -> Result<Path> {
+        let mut params = self.routing_params_at(time, PathConstraints::Car);
+        params.avoid_roads.extend(
+            self.all_roads()
+                .iter()
+                .filter(|r| r.restricts_trucks())
+                .map(|r| r.id),
+        );
+        self.pathfind_with_params(req, &params, true)
+    }
+
+    /// Follow a sequence of roads planned ahead of time. If the roads continue past the end of the
+    /// request, like when a driver had to search for parking, the rest is ignored. Fails if the
+    /// roads don't connect the start and end of the request.
+    pub fn pathfind_along_roads(
+        &self,
+        req: PathRequest,
+        mut roads: Vec<DirectedRoadID>,
+    ) -> Result<Path> {
+        let first = match roads.first() {
+            Some(dr) => *dr,
+            None => bail!("no roads given for {}", req),
+        };
+        let alt_start = req
+            .alt_start
+            .map(|(pos, _)| self.get_l(pos.lane()).get_directed_parent());
+        if first != self.get_l(req.start.lane()).get_directed_parent() && Some(first) != alt_start {
+            bail!("route starting at {} doesn't match {}", first, req);
+        }
+        let end = self.get_l(req.end.lane()).get_directed_parent();
+        match roads.iter().position(|dr| *dr == end) {
+            Some(idx) => roads.truncate(idx + 1),
+            None => bail!("route doesn't reach the end of {}", req),
+        }
+
+        let mut cost = Duration::ZERO;
+        for pair in roads.windows(2) {
+            let mvmnt = self
+                .get_movements_for(pair[0], req.constraints)
+                .into_iter()
+                .find(|mvmnt| mvmnt.to == pair[1])
+                .ok_or_else(|| anyhow!("route can't go from {} to {}", pair[0], pair[1]))?;
+            cost += crate::pathfind::vehicle_cost(
+                pair[0],
+                mvmnt,
+                req.constraints,
+                &self.routing_params,
+                self,
+            );
+        }
+        PathV2::from_roads(roads, req, cost, Vec::new(), self).into_v1(self)
+    }
+
+    /// Returns (index into `get_tolls`, price) for every toll charged to this type of vehicle
+    /// at some time.
+    pub fn tolls_in_effect(&self, time: Time, constraints: PathConstraints) -> Vec<(usize, f64)> {
+        self.get_tolls()
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, toll)| toll.price_at(time, constraints).map(|price| (idx, price)))
+            .collect()
+    }
+
+    /// How much, in dollars, does a vehicle pay to enter one road from another at some time?
+    pub fn toll_for_movement(
+        &self,
+        from: RoadID,
+        to: RoadID,
+        constraints: PathConstraints,
+        time: Time,
+    ) -> f64 {
+        self.get_tolls()
+            .iter()
+            .filter(|toll| toll.charges(from, to))
+            .filter_map(|toll| toll.price_at(time, constraints))
+            .sum()
+    }
+
+    /// How much, in dollars, would a vehicle starting at some time pay to follow this path? This
+    /// assumes the price when the path starts applies the whole way.
+    pub fn tolls_along_path(&self, path: &Path, time: Time) -> f64 {
+        if self.get_tolls().is_empty() {
+            return 0.0;
+        }
+        let constraints = path.get_req().constraints;
+        path.get_steps()
+            .iter()
+            .filter_map(|step| match step {
+                PathStep::Turn(t) => {
+                    Some(self.toll_for_movement(t.src.road, t.dst.road, constraints, time))
+                }
+                _ => None,
+            })
+            .sum()
+    }
+
     pub fn should_use_transit(
         &self,
         start: Position,
@@ -688,9 +931,32 @@ impl Map {
         self.buildings[b.0].bldg_type = bldg_type;
     }
 
-    pub fn hack_override_orig_spawn_times(&mut self, br: BusRouteID, times: Vec<Time>) {
-        self.bus_routes[br.0].orig_spawn_times = times.clone();
-        self.bus_routes[br.0].spawn_times = times;
+    /// Replace a route's original schedule. `stop_offsets` must be empty or have one entry per
+    /// stop; see `BusRoute::scheduled_stop_offsets`.
+    pub fn hack_override_schedule(
+        &mut self,
+        br: BusRouteID,
+        times: Vec<Time>,
+        stop_offsets: Vec<Duration>,
+    ) {
+        let route = &mut self.bus_routes[br.0];
+        assert!(stop_offsets.is_empty() || stop_offsets.len() == route.stops.len());
+        route.orig_spawn_times = times.clone();
+        route.spawn_times = times;
+        route.scheduled_stop_offsets = stop_offsets;
+    }
+
+    /// Change how many passengers, as (seated, standing), fit in each vehicle serving a route.
+    /// None uses the default for the type of vehicle.
+    pub fn hack_override_passenger_capacity(
+        &mut self,
+        br: BusRouteID,
+        capacity: Option<(usize, usize)>,
+    ) {
+        if let Some((seated, _)) = capacity {
+            assert!(seated > 0, "{} must have at least one seat", br);
+        }
+        self.bus_routes[br.0].passenger_capacity = capacity;
     }
 
     pub fn hack_add_area(&mut self, area_type: AreaType, polygon: Polygon, osm_tags: Tags) {
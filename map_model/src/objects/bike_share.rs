@@ -0,0 +1,40 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_usize, serialize_usize};
+use geom::Pt2D;
+
+use crate::{osm, Position};
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct BikeShareDockID(
+    #[serde(
+        serialize_with = "serialize_usize",
+        deserialize_with = "deserialize_usize"
+    )]
+    pub usize,
+);
+
+impl fmt::Display for BikeShareDockID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Bike-share dock #{}", self.0)
+    }
+}
+
+/// A station where people pick up and return shared bikes or e-scooters. The map only knows how
+/// many vehicles fit; the simulation tracks how many are actually docked.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BikeShareDock {
+    pub id: BikeShareDockID,
+    pub name: Option<String>,
+    pub point: Pt2D,
+    pub capacity: usize,
+    pub osm_id: Option<osm::NodeID>,
+    pub gbfs_station_id: Option<String>,
+    /// Where people walk to pick up or return a vehicle
+    pub sidewalk_pos: Position,
+    /// (biking position, sidewalk position) for starting or finishing a ride here. None if the
+    /// biking graph is disconnected. Recalculated after map edits.
+    pub biking_connection: Option<(Position, Position)>,
+}
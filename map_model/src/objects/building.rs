@@ -164,36 +164,7 @@ impl Building {
     /// Returns (biking position, sidewalk position). Could fail if the biking graph is
     /// disconnected.
     pub fn biking_connection(&self, map: &Map) -> Option<(Position, Position)> {
-        // Easy case: the building is directly next to a usable lane
-        if let Some(pair) = sidewalk_to_bike(self.sidewalk_pos, map) {
-            return Some(pair);
-        }
-
-        // Floodfill the sidewalk graph until we find a sidewalk<->bike connection.
-        let mut queue: VecDeque<LaneID> = VecDeque::new();
-        let mut visited: HashSet<LaneID> = HashSet::new();
-        queue.push_back(self.sidewalk());
-
-        loop {
-            if queue.is_empty() {
-                return None;
-            }
-            let l = queue.pop_front().unwrap();
-            if visited.contains(&l) {
-                continue;
-            }
-            visited.insert(l);
-            // TODO Could search by sidewalk endpoint
-            if let Some(pair) = sidewalk_to_bike(Position::new(l, map.get_l(l).length() / 2.0), map)
-            {
-                return Some(pair);
-            }
-            for t in map.get_turns_from_lane(l) {
-                if !visited.contains(&t.id.dst) {
-                    queue.push_back(t.id.dst);
-                }
-            }
-        }
+        find_biking_connection(self.sidewalk_pos, map)
     }
 
     pub fn num_parking_spots(&self) -> usize {
@@ -214,6 +185,43 @@ impl Building {
     }
 }
 
+/// Returns (biking position, sidewalk position) for somewhere along a sidewalk. Could fail if the
+/// biking graph is disconnected.
+pub(crate) fn find_biking_connection(
+    sidewalk_pos: Position,
+    map: &Map,
+) -> Option<(Position, Position)> {
+    // Easy case: the sidewalk is directly next to a usable lane
+    if let Some(pair) = sidewalk_to_bike(sidewalk_pos, map) {
+        return Some(pair);
+    }
+
+    // Floodfill the sidewalk graph until we find a sidewalk<->bike connection.
+    let mut queue: VecDeque<LaneID> = VecDeque::new();
+    let mut visited: HashSet<LaneID> = HashSet::new();
+    queue.push_back(sidewalk_pos.lane());
+
+    loop {
+        if queue.is_empty() {
+            return None;
+        }
+        let l = queue.pop_front().unwrap();
+        if visited.contains(&l) {
+            continue;
+        }
+        visited.insert(l);
+        // TODO Could search by sidewalk endpoint
+        if let Some(pair) = sidewalk_to_bike(Position::new(l, map.get_l(l).length() / 2.0), map) {
+            return Some(pair);
+        }
+        for t in map.get_turns_from_lane(l) {
+            if !visited.contains(&t.id.dst) {
+                queue.push_back(t.id.dst);
+            }
+        }
+    }
+}
+
 fn sidewalk_to_bike(sidewalk_pos: Position, map: &Map) -> Option<(Position, Position)> {
     let lane = map
         .get_parent(sidewalk_pos.lane())
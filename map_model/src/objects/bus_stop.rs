@@ -6,7 +6,7 @@ use std::fmt;
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_usize, serialize_usize};
-use geom::Time;
+use geom::{Duration, Time};
 
 use crate::{osm, LaneID, Map, PathConstraints, PathRequest, Position};
 
@@ -67,6 +67,12 @@ pub struct BusRoute {
     /// Explicitly store whatever the original was, since this can't be reconstructed without side
     /// input.
     pub orig_spawn_times: Vec<Time>,
+    /// According to the schedule, how long after spawning should a vehicle reach each stop? This
+    /// is either empty if there's no schedule, or has one entry per stop.
+    pub scheduled_stop_offsets: Vec<Duration>,
+    /// How many passengers fit in each vehicle, as (seated, standing). If None, the simulation
+    /// uses a default for the type of vehicle.
+    pub passenger_capacity: Option<(usize, usize)>,
 }
 
 impl BusRoute {
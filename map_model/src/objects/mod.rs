@@ -1,4 +1,5 @@
 pub mod area;
+pub mod bike_share;
 pub mod block;
 pub mod building;
 pub mod bus_stop;
@@ -6,8 +7,11 @@ pub mod intersection;
 pub mod lane;
 pub mod movement;
 pub mod parking_lot;
+pub mod parking_policy;
+pub mod permit_zone;
 pub mod road;
 pub mod stop_signs;
+pub mod toll;
 pub mod traffic_signals;
 pub mod turn;
 pub mod zone;
@@ -0,0 +1,181 @@
+//! Parking management. A ParkingPolicy prices and limits on-street parking along some roads and in
+//! some parking lots, varying by time of day. Spots not covered by any policy are free and
+//! unlimited.
+//!
+//! Drivers pay for each part of their stay at the rate in effect then, so a car parked before
+//! metering starts only pays for the metered time. Time limits are judged by the period in effect
+//! when the car arrives; nobody is towed, but overstays are recorded.
+
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use geom::{Duration, Time};
+
+use crate::{ParkingLotID, RoadID};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ParkingPolicy {
+    pub name: String,
+    /// Every on-street parking lane along these roads
+    pub roads: BTreeSet<RoadID>,
+    pub lots: BTreeSet<ParkingLotID>,
+    /// Outside of these periods, parking is free and unlimited.
+    pub periods: Vec<ParkingPeriod>,
+}
+
+/// The rules during one part of the day
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ParkingPeriod {
+    /// Relative to midnight
+    pub start_time: Duration,
+    /// Relative to midnight. Must be after `start_time`; to charge overnight, split into two
+    /// periods.
+    pub end_time: Duration,
+    /// In dollars. Zero means free.
+    pub price_per_hour: f64,
+    /// The longest somebody arriving during this period may stay. None means no limit.
+    pub time_limit: Option<Duration>,
+}
+
+impl ParkingPolicy {
+    /// The period in effect at some time. After the first day, the schedule repeats.
+    fn period_at(&self, time: Time) -> Option<&ParkingPeriod> {
+        let time_of_day = (time - Time::START_OF_DAY) % Duration::hours(24);
+        self.periods
+            .iter()
+            .find(|p| time_of_day >= p.start_time && time_of_day < p.end_time)
+    }
+
+    /// The hourly rate at some time
+    pub fn price_per_hour_at(&self, time: Time) -> f64 {
+        self.period_at(time)
+            .map(|p| p.price_per_hour)
+            .unwrap_or(0.0)
+    }
+
+    /// The time limit for somebody arriving at some time, if any
+    pub fn time_limit_at(&self, time: Time) -> Option<Duration> {
+        self.period_at(time).and_then(|p| p.time_limit)
+    }
+
+    /// How much does somebody parked from `start` to `end` pay? Each part of the stay is charged
+    /// at the rate in effect then.
+    pub fn cost(&self, start: Time, end: Time) -> f64 {
+        let day = Duration::hours(24);
+        let start = start - Time::START_OF_DAY;
+        let end = end - Time::START_OF_DAY;
+        let first_day = (start / day).floor() as usize;
+        let last_day = (end / day).floor() as usize;
+
+        let mut total = 0.0;
+        for d in first_day..=last_day {
+            let midnight = (d as f64) * day;
+            for p in &self.periods {
+                let overlap_start = start.max(midnight + p.start_time);
+                let overlap_end = end.min(midnight + p.end_time);
+                if overlap_end > overlap_start {
+                    total += p.price_per_hour * (overlap_end - overlap_start) / Duration::hours(1);
+                }
+            }
+        }
+        total
+    }
+
+    /// If somebody parked from `start` to `end` stayed longer than the time limit in effect when
+    /// they arrived, returns by how much.
+    pub fn overstay(&self, start: Time, end: Time) -> Option<Duration> {
+        let limit = self.time_limit_at(start)?;
+        let stay = end - start;
+        if stay > limit {
+            Some(stay - limit)
+        } else {
+            None
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.roads.is_empty() && self.lots.is_empty() {
+            bail!("Parking policy {} doesn't cover any parking", self.name);
+        }
+        for p in &self.periods {
+            if p.start_time >= p.end_time || p.end_time > Duration::hours(24) {
+                bail!(
+                    "Parking policy {} has a period from {} to {}; it must end after it starts, \
+                     by the end of the day",
+                    self.name,
+                    p.start_time,
+                    p.end_time
+                );
+            }
+            if p.price_per_hour < 0.0 {
+                bail!("Parking policy {} has a negative price", self.name);
+            }
+            if p.time_limit.map(|limit| limit <= Duration::ZERO) == Some(true) {
+                bail!("Parking policy {} has a time limit of zero", self.name);
+            }
+        }
+        let mut periods: Vec<&ParkingPeriod> = self.periods.iter().collect();
+        periods.sort_by_key(|p| p.start_time);
+        for pair in periods.windows(2) {
+            if pair[1].start_time < pair[0].end_time {
+                bail!(
+                    "Parking policy {} has overlapping periods starting at {} and {}",
+                    self.name,
+                    pair[0].start_time,
+                    pair[1].start_time
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metered_parking() {
+        let mut policy = ParkingPolicy {
+            name: "downtown meters".to_string(),
+            roads: vec![RoadID(1)].into_iter().collect(),
+            lots: BTreeSet::new(),
+            periods: vec![ParkingPeriod {
+                start_time: Duration::hours(8),
+                end_time: Duration::hours(18),
+                price_per_hour: 2.0,
+                time_limit: Some(Duration::hours(2)),
+            }],
+        };
+        assert!(policy.validate().is_ok());
+
+        let t = |hours: usize| Time::START_OF_DAY + Duration::hours(hours);
+        assert_eq!(policy.price_per_hour_at(t(9)), 2.0);
+        assert_eq!(policy.price_per_hour_at(t(20)), 0.0);
+
+        // Only the metered part of the stay costs anything
+        assert_eq!(policy.cost(t(7), t(10)), 4.0);
+        assert_eq!(policy.cost(t(17), t(22)), 2.0);
+        // Overnight stays pay for both days
+        assert_eq!(policy.cost(t(17), t(24 + 9)), 4.0);
+
+        assert_eq!(policy.overstay(t(9), t(10)), None);
+        assert_eq!(policy.overstay(t(9), t(12)), Some(Duration::hours(1)));
+        // The limit isn't in effect when arriving in the evening
+        assert_eq!(policy.overstay(t(19), t(24 + 9)), None);
+
+        // Cheaper meters in the evening can start right when the day rate ends, but not before
+        let evening = |start_time| ParkingPeriod {
+            start_time,
+            end_time: Duration::hours(22),
+            price_per_hour: 1.0,
+            time_limit: None,
+        };
+        policy.periods.insert(0, evening(Duration::hours(18)));
+        assert!(policy.validate().is_ok());
+        policy.periods[0] = evening(Duration::hours(17));
+        assert!(policy.validate().is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//! Residential parking permits. During certain hours, on-street parking along the roads in a
+//! PermitZone is reserved for people who live in the zone -- in buildings along those roads.
+//! Everybody else has to park outside the zone, or in a lot or garage. Parking lots and garages
+//! are never restricted.
+
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use geom::{Duration, Polygon, Time};
+
+use crate::{BuildingID, Map, RoadID};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PermitZone {
+    pub name: String,
+    pub roads: BTreeSet<RoadID>,
+    /// When permits are required, as (start, end) relative to midnight. Each period must end after
+    /// it starts; to restrict parking overnight, split into two periods. Outside of these, anybody
+    /// may park in the zone.
+    pub hours: Vec<(Duration, Duration)>,
+}
+
+impl PermitZone {
+    /// A zone covering every road whose middle is inside a polygon
+    pub fn from_polygon(
+        name: String,
+        polygon: &Polygon,
+        hours: Vec<(Duration, Duration)>,
+        map: &Map,
+    ) -> PermitZone {
+        PermitZone {
+            name,
+            roads: map
+                .all_roads()
+                .iter()
+                .filter(|r| polygon.contains_pt(r.center_pts.middle()))
+                .map(|r| r.id)
+                .collect(),
+            hours,
+        }
+    }
+
+    /// Are permits required at some time? After the first day, the schedule repeats.
+    pub fn in_effect(&self, time: Time) -> bool {
+        let time_of_day = (time - Time::START_OF_DAY) % Duration::hours(24);
+        self.hours
+            .iter()
+            .any(|(start, end)| time_of_day >= *start && time_of_day < *end)
+    }
+
+    /// Do people living in this building get a permit for the zone?
+    pub fn is_resident(&self, b: BuildingID, map: &Map) -> bool {
+        self.roads.contains(&map.get_b(b).sidewalk().road)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.roads.is_empty() {
+            bail!("Permit zone {} doesn't cover any roads", self.name);
+        }
+        for (start, end) in &self.hours {
+            if start >= end || *end > Duration::hours(24) {
+                bail!(
+                    "Permit zone {} has hours from {} to {}; they must end after they start, by \
+                     the end of the day",
+                    self.name,
+                    start,
+                    end
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permit_hours() {
+        let zone = PermitZone {
+            name: "stadium".to_string(),
+            roads: vec![RoadID(1)].into_iter().collect(),
+            hours: vec![
+                (Duration::ZERO, Duration::hours(2)),
+                (Duration::hours(17), Duration::hours(24)),
+            ],
+        };
+        assert!(zone.validate().is_ok());
+
+        let t = |hours: usize| Time::START_OF_DAY + Duration::hours(hours);
+        assert!(!zone.in_effect(t(9)));
+        assert!(zone.in_effect(t(18)));
+        // Overnight, split into two periods
+        assert!(zone.in_effect(t(23)));
+        assert!(zone.in_effect(t(24 + 1)));
+        assert!(!zone.in_effect(t(24 + 2)));
+    }
+}
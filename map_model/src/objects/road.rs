@@ -432,6 +432,26 @@ impl Road {
         self.osm_tags.is(osm::HIGHWAY, "service")
     }
 
+    /// Can vehicles heading in some direction briefly use an oncoming lane to pass somebody
+    /// slower? Follows OSM's `overtaking` tags if they're present. Otherwise, only allowed on
+    /// local roads.
+    pub fn allows_oncoming_overtaking(&self, dir: Direction) -> bool {
+        let specific = match dir {
+            Direction::Fwd => "overtaking:forward",
+            Direction::Back => "overtaking:backward",
+        };
+        if let Some(x) = self.osm_tags.get(specific) {
+            return x != "no";
+        }
+        match self.osm_tags.get("overtaking").map(|x| x.as_str()) {
+            Some("no") => false,
+            Some("forward") => dir == Direction::Fwd,
+            Some("backward") => dir == Direction::Back,
+            Some(_) => true,
+            None => self.get_rank() == osm::RoadRank::Local,
+        }
+    }
+
     pub fn is_cycleway(&self) -> bool {
         let mut bike = false;
         for lane in &self.lanes {
@@ -473,6 +493,15 @@ impl Road {
         self.access_restrictions != AccessRestrictions::new() && !self.is_light_rail()
     }
 
+    /// Does OSM keep heavy goods vehicles off this road? Trucks may still use it to make a
+    /// delivery along it.
+    pub fn restricts_trucks(&self) -> bool {
+        self.osm_tags.is_any(
+            "hgv",
+            vec!["no", "destination", "delivery", "private", "discouraged"],
+        )
+    }
+
     pub(crate) fn access_restrictions_from_osm(&self) -> AccessRestrictions {
         let allow_through_traffic = if self.osm_tags.is("access", "private") {
             EnumSet::new()
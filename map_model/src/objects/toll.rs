@@ -0,0 +1,121 @@
+//! Road pricing. A TollSchedule charges vehicles for using some set of roads, with prices varying
+//! by time of day and vehicle type. This covers both:
+//! 1) cordon charging, where vehicles pay once when they cross into a zone, like the central
+//!    London congestion charge
+//! 2) per-road tolls, where vehicles pay every time they enter a priced road, like express lanes
+//!    or a toll bridge
+//!
+//! Vehicles pay when they enter a road from an intersection, so a trip starting inside a cordon
+//! doesn't pay to leave it. Routing treats each toll as extra delay; see `RoutingParams`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use geom::{Duration, Time};
+
+use crate::{PathConstraints, RoadID};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TollSchedule {
+    pub name: String,
+    pub roads: BTreeSet<RoadID>,
+    /// If true, vehicles pay once when they cross into `roads` from somewhere else. Otherwise they
+    /// pay every time they enter one of the roads.
+    pub cordon: bool,
+    /// Outside of these periods, the roads are free.
+    pub periods: Vec<TollPeriod>,
+}
+
+/// Prices during one part of the day
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TollPeriod {
+    /// Relative to midnight
+    pub start_time: Duration,
+    /// Relative to midnight. Must be after `start_time`; to charge overnight, split into two
+    /// periods.
+    pub end_time: Duration,
+    /// In dollars. Types of vehicles not listed here don't pay.
+    pub prices: BTreeMap<PathConstraints, f64>,
+}
+
+impl TollSchedule {
+    /// How much does this type of vehicle pay at some time? None if it's free. After the first
+    /// day, the schedule repeats.
+    pub fn price_at(&self, time: Time, constraints: PathConstraints) -> Option<f64> {
+        let time_of_day = (time - Time::START_OF_DAY) % Duration::hours(24);
+        self.periods
+            .iter()
+            .find(|p| time_of_day >= p.start_time && time_of_day < p.end_time)
+            .and_then(|p| p.prices.get(&constraints).cloned())
+            .filter(|price| *price > 0.0)
+    }
+
+    /// Does a vehicle moving from one road to another pay this toll (when it's in effect)?
+    pub fn charges(&self, from: RoadID, to: RoadID) -> bool {
+        if !self.roads.contains(&to) {
+            return false;
+        }
+        !self.cordon || !self.roads.contains(&from)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.roads.is_empty() {
+            bail!("Toll {} doesn't cover any roads", self.name);
+        }
+        for p in &self.periods {
+            if p.start_time >= p.end_time || p.end_time > Duration::hours(24) {
+                bail!(
+                    "Toll {} has a period from {} to {}; it must end after it starts, by the end \
+                     of the day",
+                    self.name,
+                    p.start_time,
+                    p.end_time
+                );
+            }
+            if p.prices.values().any(|price| *price < 0.0) {
+                bail!("Toll {} has a negative price", self.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cordon_pricing() {
+        let toll = TollSchedule {
+            name: "downtown".to_string(),
+            roads: vec![RoadID(1), RoadID(2)].into_iter().collect(),
+            cordon: true,
+            periods: vec![TollPeriod {
+                start_time: Duration::hours(7),
+                end_time: Duration::hours(10),
+                prices: vec![(PathConstraints::Car, 5.0)].into_iter().collect(),
+            }],
+        };
+        assert!(toll.validate().is_ok());
+
+        // Only crossing into the cordon costs anything
+        assert!(toll.charges(RoadID(0), RoadID(1)));
+        assert!(!toll.charges(RoadID(1), RoadID(2)));
+        assert!(!toll.charges(RoadID(1), RoadID(0)));
+
+        let morning = Time::START_OF_DAY + Duration::hours(8);
+        assert_eq!(toll.price_at(morning, PathConstraints::Car), Some(5.0));
+        assert_eq!(toll.price_at(morning, PathConstraints::Bike), None);
+        assert_eq!(
+            toll.price_at(morning + Duration::hours(3), PathConstraints::Car),
+            None
+        );
+        // The schedule repeats every day
+        assert_eq!(
+            toll.price_at(morning + Duration::hours(24), PathConstraints::Car),
+            Some(5.0)
+        );
+    }
+}
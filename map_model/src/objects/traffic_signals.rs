@@ -3,7 +3,7 @@ use std::collections::{BTreeMap, BTreeSet};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use geom::{Distance, Duration, Speed};
+use geom::{Distance, Duration, Speed, Time};
 
 use crate::make::traffic_signals::get_possible_policies;
 use crate::raw::OriginalRoad;
@@ -22,6 +22,34 @@ const CROSSWALK_PACE: Speed = Speed::const_meters_per_second(1.4);
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ControlTrafficSignal {
     pub id: IntersectionID,
+    /// The default plan, starting at midnight
+    pub stages: Vec<Stage>,
+    pub offset: Duration,
+    /// Different plans for later in the day, like for rush hour or late at night, ordered by
+    /// start time. Each plan lasts until the next one starts. Before the first one, the default
+    /// `stages` and `offset` apply.
+    pub plans: Vec<TimingPlan>,
+    /// If present, approaching transit vehicles get priority, no matter which plan is in effect.
+    pub transit_priority: Option<TransitPriority>,
+}
+
+/// Transit signal priority. When a bus or train approaches, the signal can extend a stage serving
+/// it, or end a stage that doesn't serve it early.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TransitPriority {
+    /// A stage serving the vehicle can last at most this much longer than usual.
+    pub max_extension: Duration,
+    /// A stage not serving the vehicle can end at most this much earlier than usual.
+    pub max_early_end: Duration,
+    /// Only give priority to vehicles running behind their schedule.
+    pub only_late_vehicles: bool,
+}
+
+/// An alternate configuration for a traffic signal, taking effect at some time of day.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TimingPlan {
+    /// Relative to midnight
+    pub start_time: Duration,
     pub stages: Vec<Stage>,
     pub offset: Duration,
 }
@@ -42,6 +70,29 @@ pub enum StageType {
     /// Delay is the elapsed time with no demand that ends a cycle.
     /// Additional is the additional duration for an extended cycle.
     Variable(Duration, Duration, Duration),
+    /// Actuated control using virtual loop detectors covering the last `detector_length` of every
+    /// lane feeding the stage's protected movements. After `min_green`, the stage is extended by
+    /// `passage_time` whenever a detector is occupied. It ends when nobody is detected (gapping
+    /// out) or after `max_green` (maxing out). Without a call from a detected vehicle or a waiting
+    /// pedestrian, the stage may be skipped entirely, depending on `recall`.
+    Actuated {
+        min_green: Duration,
+        passage_time: Duration,
+        max_green: Duration,
+        detector_length: Distance,
+        recall: Recall,
+    },
+}
+
+/// Does an actuated stage get served even when nobody has called for it?
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Recall {
+    /// Skip the stage if there are no calls.
+    None,
+    /// Always serve the stage for at least its minimum green time.
+    Minimum,
+    /// Always serve the stage for its maximum green time, ignoring the detectors.
+    Maximum,
 }
 
 impl StageType {
@@ -50,6 +101,7 @@ impl StageType {
         match self {
             StageType::Fixed(d) => *d,
             StageType::Variable(duration, _, _) => *duration,
+            StageType::Actuated { min_green, .. } => *min_green,
         }
     }
 }
@@ -96,6 +148,71 @@ impl ControlTrafficSignal {
     }
 
     pub fn validate(&self, i: &Intersection) -> Result<()> {
+        let mut last_start = Duration::ZERO;
+        for plan in &self.plans {
+            if plan.start_time <= last_start || plan.start_time >= Duration::hours(24) {
+                bail!(
+                    "Traffic signal plans must start in increasing order, after midnight and \
+                     before the end of the day, but one starts at {}",
+                    plan.start_time
+                );
+            }
+            last_start = plan.start_time;
+        }
+
+        self.validate_stages(i)?;
+        for idx in 1..=self.plans.len() {
+            let mut copy = self.clone();
+            copy.swap_plan(idx);
+            copy.validate_stages(i)
+                .map_err(|err| anyhow!("Plan {}: {}", idx, err))?;
+        }
+        Ok(())
+    }
+
+    /// Which plan is active at some time? 0 means the default plan, and anything else refers to
+    /// `plans[idx - 1]`. After the first day, the plans repeat.
+    pub fn plan_at(&self, time: Time) -> usize {
+        let time_of_day = (time - Time::START_OF_DAY) % Duration::hours(24);
+        let mut idx = 0;
+        for (plan_idx, plan) in self.plans.iter().enumerate() {
+            if time_of_day >= plan.start_time {
+                idx = plan_idx + 1;
+            }
+        }
+        idx
+    }
+
+    /// The stages of a plan. See `plan_at` for how plans are numbered.
+    pub fn plan_stages(&self, plan: usize) -> &Vec<Stage> {
+        if plan == 0 {
+            &self.stages
+        } else {
+            &self.plans[plan - 1].stages
+        }
+    }
+
+    pub fn plan_offset(&self, plan: usize) -> Duration {
+        if plan == 0 {
+            self.offset
+        } else {
+            self.plans[plan - 1].offset
+        }
+    }
+
+    /// Exchange the stages and offset of the default plan with another plan, keeping start times
+    /// in place. Doing this twice with the same plan undoes it. This lets tools that only know
+    /// about `stages` and `offset` work on any one plan.
+    pub fn swap_plan(&mut self, plan: usize) {
+        if plan == 0 {
+            return;
+        }
+        let other = &mut self.plans[plan - 1];
+        std::mem::swap(&mut self.stages, &mut other.stages);
+        std::mem::swap(&mut self.offset, &mut other.offset);
+    }
+
+    fn validate_stages(&self, i: &Intersection) -> Result<()> {
         // Does the assignment cover the correct set of movements?
         let expected_movements: BTreeSet<MovementID> = i.movements.keys().cloned().collect();
         let mut actual_movements: BTreeSet<MovementID> = BTreeSet::new();
@@ -148,6 +265,29 @@ impl ControlTrafficSignal {
                     stage.stage_type.simple_duration()
                 );
             }
+            if let StageType::Actuated {
+                min_green,
+                passage_time,
+                max_green,
+                ..
+            } = stage.stage_type
+            {
+                if passage_time <= Duration::ZERO {
+                    bail!(
+                        "Stage {} has a passage time of {}",
+                        stage_index,
+                        passage_time
+                    );
+                }
+                if max_green < min_green {
+                    bail!(
+                        "Stage {} has a maximum green of {}, less than the minimum of {}",
+                        stage_index,
+                        max_green,
+                        min_green
+                    );
+                }
+            }
         }
         Ok(())
     }
@@ -361,6 +501,19 @@ impl Stage {
                 StageType::Variable(_, delay, additional) => {
                     StageType::Variable(time, delay, additional)
                 }
+                StageType::Actuated {
+                    passage_time,
+                    max_green,
+                    detector_length,
+                    recall,
+                    ..
+                } => StageType::Actuated {
+                    min_green: time,
+                    passage_time,
+                    max_green: max_green.max(time),
+                    detector_length,
+                    recall,
+                },
             };
         }
     }
@@ -388,106 +541,185 @@ impl Stage {
 
 impl ControlTrafficSignal {
     pub fn export(&self, map: &Map) -> traffic_signal_data::TrafficSignal {
+        let mut plans = vec![traffic_signal_data::Plan {
+            start_time_seconds: 0,
+            stages: export_stages(&self.stages, map),
+            offset_seconds: self.offset.inner_seconds() as usize,
+        }];
+        for plan in &self.plans {
+            plans.push(traffic_signal_data::Plan {
+                start_time_seconds: plan.start_time.inner_seconds() as usize,
+                stages: export_stages(&plan.stages, map),
+                offset_seconds: plan.offset.inner_seconds() as usize,
+            });
+        }
         traffic_signal_data::TrafficSignal {
             intersection_osm_node_id: map.get_i(self.id).orig_id.0,
-            plans: vec![traffic_signal_data::Plan {
-                start_time_seconds: 0,
-                stages: self
-                    .stages
-                    .iter()
-                    .map(|s| traffic_signal_data::Stage {
-                        protected_turns: s
-                            .protected_movements
-                            .iter()
-                            .map(|t| export_movement(t, map))
-                            .collect(),
-                        permitted_turns: s
-                            .yield_movements
-                            .iter()
-                            .map(|t| export_movement(t, map))
-                            .collect(),
-                        stage_type: match s.stage_type {
-                            StageType::Fixed(d) => {
-                                traffic_signal_data::StageType::Fixed(d.inner_seconds() as usize)
-                            }
-                            StageType::Variable(min, delay, additional) => {
-                                traffic_signal_data::StageType::Variable(
-                                    min.inner_seconds() as usize,
-                                    delay.inner_seconds() as usize,
-                                    additional.inner_seconds() as usize,
-                                )
-                            }
-                        },
-                    })
-                    .collect(),
-                offset_seconds: self.offset.inner_seconds() as usize,
-            }],
+            plans,
+            transit_priority: self.transit_priority.as_ref().map(|tsp| {
+                traffic_signal_data::TransitPriority {
+                    max_extension_seconds: tsp.max_extension.inner_seconds() as usize,
+                    max_early_end_seconds: tsp.max_early_end.inner_seconds() as usize,
+                    only_late_vehicles: tsp.only_late_vehicles,
+                }
+            }),
         }
     }
 
     pub(crate) fn import(
-        mut raw: traffic_signal_data::TrafficSignal,
+        raw: traffic_signal_data::TrafficSignal,
         id: IntersectionID,
         map: &Map,
     ) -> Result<ControlTrafficSignal> {
-        // TODO Only import the first plan. Will import all of them later.
-        let plan = raw.plans.remove(0);
-        let mut stages = Vec::new();
-        for s in plan.stages {
-            let mut errors = Vec::new();
-            let mut protected_movements = BTreeSet::new();
-            for t in s.protected_turns {
-                match import_movement(t, map) {
-                    Ok(mvmnt) => {
-                        protected_movements.insert(mvmnt);
-                    }
-                    Err(err) => {
-                        errors.push(err.to_string());
-                    }
-                }
-            }
-            let mut permitted_movements = BTreeSet::new();
-            for t in s.permitted_turns {
-                match import_movement(t, map) {
-                    Ok(mvmnt) => {
-                        permitted_movements.insert(mvmnt);
-                    }
-                    Err(err) => {
-                        errors.push(err.to_string());
-                    }
-                }
-            }
-            if errors.is_empty() {
-                stages.push(Stage {
-                    protected_movements,
-                    yield_movements: permitted_movements,
-                    stage_type: match s.stage_type {
-                        traffic_signal_data::StageType::Fixed(d) => {
-                            StageType::Fixed(Duration::seconds(d as f64))
-                        }
-                        traffic_signal_data::StageType::Variable(min, delay, additional) => {
-                            StageType::Variable(
-                                Duration::seconds(min as f64),
-                                Duration::seconds(delay as f64),
-                                Duration::seconds(additional as f64),
-                            )
-                        }
-                    },
-                });
-            } else {
-                bail!("{}", errors.join("; "));
-            }
+        let mut raw_plans = raw.plans.into_iter();
+        let first = raw_plans
+            .next()
+            .ok_or_else(|| anyhow!("Traffic signal {} has no plans", id))?;
+        if first.start_time_seconds != 0 {
+            bail!(
+                "The first plan for {} starts at {}s, not midnight",
+                id,
+                first.start_time_seconds
+            );
+        }
+        let mut plans = Vec::new();
+        for plan in raw_plans {
+            plans.push(TimingPlan {
+                start_time: Duration::seconds(plan.start_time_seconds as f64),
+                stages: import_stages(plan.stages, map)?,
+                offset: Duration::seconds(plan.offset_seconds as f64),
+            });
         }
         let ts = ControlTrafficSignal {
             id,
-            stages,
-            offset: Duration::seconds(plan.offset_seconds as f64),
+            stages: import_stages(first.stages, map)?,
+            offset: Duration::seconds(first.offset_seconds as f64),
+            plans,
+            transit_priority: raw.transit_priority.map(|tsp| TransitPriority {
+                max_extension: Duration::seconds(tsp.max_extension_seconds as f64),
+                max_early_end: Duration::seconds(tsp.max_early_end_seconds as f64),
+                only_late_vehicles: tsp.only_late_vehicles,
+            }),
         };
         ts.validate(map.get_i(id))?;
         Ok(ts)
     }
 }
 
+fn export_stages(stages: &[Stage], map: &Map) -> Vec<traffic_signal_data::Stage> {
+    stages
+        .iter()
+        .map(|s| traffic_signal_data::Stage {
+            protected_turns: s
+                .protected_movements
+                .iter()
+                .map(|t| export_movement(t, map))
+                .collect(),
+            permitted_turns: s
+                .yield_movements
+                .iter()
+                .map(|t| export_movement(t, map))
+                .collect(),
+            stage_type: match s.stage_type {
+                StageType::Fixed(d) => {
+                    traffic_signal_data::StageType::Fixed(d.inner_seconds() as usize)
+                }
+                StageType::Variable(min, delay, additional) => {
+                    traffic_signal_data::StageType::Variable(
+                        min.inner_seconds() as usize,
+                        delay.inner_seconds() as usize,
+                        additional.inner_seconds() as usize,
+                    )
+                }
+                StageType::Actuated {
+                    min_green,
+                    passage_time,
+                    max_green,
+                    detector_length,
+                    recall,
+                } => traffic_signal_data::StageType::Actuated {
+                    min_green: min_green.inner_seconds() as usize,
+                    passage_time: passage_time.inner_seconds() as usize,
+                    max_green: max_green.inner_seconds() as usize,
+                    detector_length_meters: detector_length.inner_meters() as usize,
+                    recall: match recall {
+                        Recall::None => traffic_signal_data::Recall::None,
+                        Recall::Minimum => traffic_signal_data::Recall::Minimum,
+                        Recall::Maximum => traffic_signal_data::Recall::Maximum,
+                    },
+                },
+            },
+        })
+        .collect()
+}
+
+fn import_stages(raw: Vec<traffic_signal_data::Stage>, map: &Map) -> Result<Vec<Stage>> {
+    let mut stages = Vec::new();
+    for s in raw {
+        let mut errors = Vec::new();
+        let mut protected_movements = BTreeSet::new();
+        for t in s.protected_turns {
+            match import_movement(t, map) {
+                Ok(mvmnt) => {
+                    protected_movements.insert(mvmnt);
+                }
+                Err(err) => {
+                    errors.push(err.to_string());
+                }
+            }
+        }
+        let mut permitted_movements = BTreeSet::new();
+        for t in s.permitted_turns {
+            match import_movement(t, map) {
+                Ok(mvmnt) => {
+                    permitted_movements.insert(mvmnt);
+                }
+                Err(err) => {
+                    errors.push(err.to_string());
+                }
+            }
+        }
+        if errors.is_empty() {
+            stages.push(Stage {
+                protected_movements,
+                yield_movements: permitted_movements,
+                stage_type: match s.stage_type {
+                    traffic_signal_data::StageType::Fixed(d) => {
+                        StageType::Fixed(Duration::seconds(d as f64))
+                    }
+                    traffic_signal_data::StageType::Variable(min, delay, additional) => {
+                        StageType::Variable(
+                            Duration::seconds(min as f64),
+                            Duration::seconds(delay as f64),
+                            Duration::seconds(additional as f64),
+                        )
+                    }
+                    traffic_signal_data::StageType::Actuated {
+                        min_green,
+                        passage_time,
+                        max_green,
+                        detector_length_meters,
+                        recall,
+                    } => StageType::Actuated {
+                        min_green: Duration::seconds(min_green as f64),
+                        passage_time: Duration::seconds(passage_time as f64),
+                        max_green: Duration::seconds(max_green as f64),
+                        detector_length: Distance::meters(detector_length_meters as f64),
+                        recall: match recall {
+                            traffic_signal_data::Recall::None => Recall::None,
+                            traffic_signal_data::Recall::Minimum => Recall::Minimum,
+                            traffic_signal_data::Recall::Maximum => Recall::Maximum,
+                        },
+                    },
+                },
+            });
+        } else {
+            bail!("{}", errors.join("; "));
+        }
+    }
+    Ok(stages)
+}
+
 fn export_movement(id: &MovementID, map: &Map) -> traffic_signal_data::Turn {
     let from = map.get_r(id.from.road).orig_id;
     let to = map.get_r(id.to.road).orig_id;
@@ -532,3 +764,71 @@ fn find_r(id: traffic_signal_data::DirectedRoad, map: &Map) -> Result<DirectedRo
         },
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed_stages(durations: Vec<f64>) -> Vec<Stage> {
+        durations
+            .into_iter()
+            .map(|secs| {
+                let mut stage = Stage::new();
+                stage.stage_type = StageType::Fixed(Duration::seconds(secs));
+                stage
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_timing_plans() {
+        let mut signal = ControlTrafficSignal {
+            id: IntersectionID(0),
+            stages: fixed_stages(vec![30.0, 30.0]),
+            offset: Duration::ZERO,
+            plans: vec![
+                TimingPlan {
+                    start_time: Duration::hours(7),
+                    stages: fixed_stages(vec![45.0, 15.0, 20.0]),
+                    offset: Duration::seconds(10.0),
+                },
+                TimingPlan {
+                    start_time: Duration::hours(10),
+                    stages: fixed_stages(vec![20.0]),
+                    offset: Duration::seconds(5.0),
+                },
+            ],
+            transit_priority: None,
+        };
+        let at =
+            |h: usize, secs: f64| Time::START_OF_DAY + Duration::hours(h) + Duration::seconds(secs);
+
+        // Each plan starts exactly at its start time and lasts until the next one
+        assert_eq!(signal.plan_at(at(0, 0.0)), 0);
+        assert_eq!(signal.plan_at(at(6, 3599.9)), 0);
+        assert_eq!(signal.plan_at(at(7, 0.0)), 1);
+        assert_eq!(signal.plan_at(at(9, 3599.9)), 1);
+        assert_eq!(signal.plan_at(at(10, 0.0)), 2);
+        assert_eq!(signal.plan_at(at(23, 3599.9)), 2);
+        // Plans repeat every day
+        assert_eq!(signal.plan_at(at(24, 0.0)), 0);
+        assert_eq!(signal.plan_at(at(31, 0.0)), 1);
+
+        assert_eq!(signal.plan_stages(0).len(), 2);
+        assert_eq!(signal.plan_stages(1).len(), 3);
+        assert_eq!(signal.plan_offset(2), Duration::seconds(5.0));
+
+        // Swapping a plan into the default slot keeps the start times, and swapping again undoes
+        // it
+        let orig = signal.clone();
+        signal.swap_plan(1);
+        assert_eq!(signal.stages.len(), 3);
+        assert_eq!(signal.offset, Duration::seconds(10.0));
+        assert_eq!(signal.plan_stages(1).len(), 2);
+        assert_eq!(signal.plans[0].start_time, Duration::hours(7));
+        signal.swap_plan(1);
+        assert_eq!(signal, orig);
+        signal.swap_plan(0);
+        assert_eq!(signal, orig);
+    }
+}
@@ -3,7 +3,9 @@
 //!    use any of the private roads
 //! 2) Stay Healthy Streets, where most car traffic is banned, except for trips beginning/ending in
 //!    the zone
-//! 3) Congestion capping, where only so many cars per hour can enter the zone
+//!
+//! Congestion capping, where only so many cars per hour could enter a zone, used to live here too.
+//! Charging vehicles to enter a zone is modeled by `TollSchedule` instead.
 
 use std::collections::BTreeSet;
 
@@ -1,5 +1,6 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 
 use fast_paths::{deserialize_32, serialize_32, FastGraph, InputGraph, PathCalculator};
 use petgraph::graph::{DiGraph, NodeIndex};
@@ -126,6 +127,52 @@ impl PathfindEngine {
     }
 }
 
+/// Neither engine can handle edge costs that change over time, so this is a plain Dijkstra search
+/// that asks for the edges leaving each node as it goes. The cost of an edge may include penalties
+/// that don't take any real time, so the time elapsed to reach each node is tracked separately.
+/// Given a node and the time elapsed to reach it, `neighbors` returns (next node ID, edge cost,
+/// edge time). As long as arriving somewhere later never means leaving earlier, this finds the
+/// cheapest path.
+///
+/// Returns (path cost, node IDs in path). Input starts are pairs of (node ID, extra weight); the
+/// extra weight counts as both cost and time.
+pub fn calculate_time_dependent_path<F: Fn(usize, usize) -> Vec<(usize, usize, usize)>>(
+    starts: Vec<(usize, usize)>,
+    end: usize,
+    neighbors: F,
+) -> Option<(usize, Vec<usize>)> {
+    // (cost, node, elapsed time, previous node)
+    let mut queue: BinaryHeap<Reverse<(usize, usize, usize, Option<usize>)>> = BinaryHeap::new();
+    for (node, weight) in starts {
+        queue.push(Reverse((weight, node, weight, None)));
+    }
+
+    // Once a node is reached, remember where it was reached from
+    let mut backrefs: HashMap<usize, Option<usize>> = HashMap::new();
+    while let Some(Reverse((cost, node, time, prev))) = queue.pop() {
+        if backrefs.contains_key(&node) {
+            continue;
+        }
+        backrefs.insert(node, prev);
+
+        if node == end {
+            let mut path = vec![node];
+            while let Some(Some(prev)) = backrefs.get(path.last().unwrap()) {
+                path.push(*prev);
+            }
+            path.reverse();
+            return Some((cost, path));
+        }
+
+        for (next, weight, duration) in neighbors(node, time) {
+            if !backrefs.contains_key(&next) {
+                queue.push(Reverse((cost + weight, next, time + duration, Some(node))));
+            }
+        }
+    }
+    None
+}
+
 pub enum CreateEngine<'a> {
     Dijkstra,
     CH,
@@ -181,3 +228,50 @@ impl<'a> CreateEngine<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_dependent_path() {
+        // Two ways from 0 to 3: directly through 1, or a longer way through 2. The road from 1 to
+        // 3 is congested for a while, so entering it then takes much longer.
+        let congested = |time: usize| {
+            if (100..200).contains(&time) {
+                1000
+            } else {
+                100
+            }
+        };
+        let neighbors = |node: usize, time: usize| match node {
+            0 => vec![(1, 100, 100), (2, 150, 150)],
+            1 => vec![(3, congested(time), congested(time))],
+            2 => vec![(3, 150, 150)],
+            _ => Vec::new(),
+        };
+
+        // Leaving right away hits the congestion, so go around
+        assert_eq!(
+            calculate_time_dependent_path(vec![(0, 0)], 3, neighbors),
+            Some((300, vec![0, 2, 3]))
+        );
+        // Later, the direct route is clear again
+        assert_eq!(
+            calculate_time_dependent_path(vec![(0, 500)], 3, neighbors),
+            Some((700, vec![0, 1, 3]))
+        );
+
+        // Reaching 1 is penalized, but only takes 50, so the congestion hasn't started yet
+        let penalized = |node: usize, time: usize| match node {
+            0 => vec![(1, 150, 50), (2, 200, 200)],
+            1 => vec![(3, congested(time), congested(time))],
+            2 => vec![(3, 100, 100)],
+            _ => Vec::new(),
+        };
+        assert_eq!(
+            calculate_time_dependent_path(vec![(0, 0)], 3, penalized),
+            Some((250, vec![0, 1, 3]))
+        );
+    }
+}
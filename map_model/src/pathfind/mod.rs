@@ -9,6 +9,7 @@ use geom::Duration;
 
 pub use self::engine::CreateEngine;
 pub use self::pathfinder::Pathfinder;
+pub use self::time_dependent::TravelTimeProfile;
 pub use self::v1::{Path, PathRequest, PathStep};
 pub use self::v2::{PathStepV2, PathV2};
 pub use self::vehicles::vehicle_cost;
@@ -18,6 +19,7 @@ use crate::{osm, Lane, LaneID, LaneType, Map, MovementID, RoadID, TurnType};
 mod engine;
 mod node_map;
 mod pathfinder;
+mod time_dependent;
 // TODO tmp
 pub mod uber_turns;
 mod v1;
@@ -183,6 +185,19 @@ pub struct RoutingParams {
     // TODO Include in serde during the next full map importing
     #[serde(skip_serializing, skip_deserializing)]
     pub avoid_roads: BTreeSet<RoadID>,
+
+    /// The tolls in effect, as an index into `Map::get_tolls` and the price in dollars. Only
+    /// affects vehicle routing. `Map::routing_params_at` fills this out.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub tolls: Vec<(usize, f64)>,
+    /// Every dollar of tolls is added to the cost of a movement as this much additional delay.
+    /// Effectively, this is how much a driver values their time.
+    #[serde(
+        skip_serializing,
+        skip_deserializing,
+        default = "default_delay_per_toll_dollar"
+    )]
+    pub delay_per_toll_dollar: Duration,
 }
 
 impl Default for RoutingParams {
@@ -200,10 +215,18 @@ impl Default for RoutingParams {
             avoid_high_stress: 1.0,
 
             avoid_roads: BTreeSet::new(),
+
+            tolls: Vec::new(),
+            delay_per_toll_dollar: default_delay_per_toll_dollar(),
         }
     }
 }
 
+fn default_delay_per_toll_dollar() -> Duration {
+    // About $20 an hour
+    Duration::const_seconds(3.0 * 60.0)
+}
+
 pub fn round(cost: Duration) -> usize {
     // Round up! 0 cost edges are ignored
     (cost.inner_seconds().round() as usize).max(1)
@@ -5,14 +5,14 @@ use serde::{Deserialize, Serialize};
 use thread_local::ThreadLocal;
 
 use abstutil::{Timer, VecMap};
-use geom::Duration;
+use geom::{Duration, Time};
 
 use crate::pathfind::engine::CreateEngine;
 use crate::pathfind::vehicles::VehiclePathfinder;
 use crate::pathfind::walking::SidewalkPathfinder;
 use crate::{
     BusRouteID, BusStopID, DirectedRoadID, Map, PathConstraints, PathRequest, PathV2, Position,
-    RoutingParams,
+    RoutingParams, TravelTimeProfile,
 };
 
 #[derive(Serialize, Deserialize)]
@@ -27,6 +27,12 @@ pub struct Pathfinder {
     // These params cover the main graphs
     params: RoutingParams,
 
+    /// If present, `pathfind_at` routes cars using time-dependent costs. This comes from one
+    /// simulation run, not the map, so it's never saved; after reloading the map, it has to be set
+    /// again, like by passing `--travel-times-from` again.
+    #[serde(skip_serializing, skip_deserializing)]
+    travel_times: Option<TravelTimeProfile>,
+
     // Callers can opt into caching with pathfind_with_params
     // TODO VecMap is probably fast enough. RoutingParams is annoying to implement Hash.
     #[serde(skip_serializing, skip_deserializing)]
@@ -44,6 +50,7 @@ impl Clone for Pathfinder {
             walking_graph: self.walking_graph.clone(),
             walking_with_transit_graph: self.walking_with_transit_graph.clone(),
             params: self.params.clone(),
+            travel_times: self.travel_times.clone(),
             cached_alternatives: ThreadLocal::new(),
         }
     }
@@ -61,6 +68,7 @@ impl Pathfinder {
             walking_graph: SidewalkPathfinder::empty(),
             walking_with_transit_graph: SidewalkPathfinder::empty(),
             params: RoutingParams::default(),
+            travel_times: None,
             cached_alternatives: ThreadLocal::new(),
         }
     }
@@ -119,6 +127,7 @@ impl Pathfinder {
             walking_with_transit_graph,
 
             params,
+            travel_times: None,
             cached_alternatives: ThreadLocal::new(),
         }
     }
@@ -213,6 +222,76 @@ impl Pathfinder {
         result
     }
 
+    /// Finds a path for somebody departing at some time. If a travel time profile has been set,
+    /// cars avoid the roads that're congested when they'd reach them. Otherwise, this is the same
+    /// as `pathfind_with_params`.
+    pub fn pathfind_at(
+        &self,
+        req: PathRequest,
+        departure: Time,
+        params: &RoutingParams,
+        cache_custom: bool,
+        map: &Map,
+    ) -> Option<PathV2> {
+        if let Some(ref profile) = self.travel_times {
+            if req.constraints == PathConstraints::Car {
+                return self.pathfind_with_profile(req, departure, profile, params, map);
+            }
+        }
+        self.pathfind_with_params(req, params, cache_custom, map)
+    }
+
+    /// Finds a path for a vehicle departing at some time, with the cost of each road looked up in
+    /// a profile by when the vehicle would reach it.
+    pub fn pathfind_with_profile(
+        &self,
+        req: PathRequest,
+        departure: Time,
+        profile: &TravelTimeProfile,
+        params: &RoutingParams,
+        map: &Map,
+    ) -> Option<PathV2> {
+        let graph = match req.constraints {
+            PathConstraints::Pedestrian => {
+                return None;
+            }
+            PathConstraints::Car => &self.car_graph,
+            PathConstraints::Bike => &self.bike_graph,
+            PathConstraints::Bus => &self.bus_graph,
+            PathConstraints::Train => &self.train_graph,
+        };
+        graph.pathfind_time_dependent(req, departure, profile, params, map)
+    }
+
+    /// Finds a path for a vehicle, avoiding roads with some extra delay right now.
+    pub fn pathfind_with_delays<F: Fn(DirectedRoadID) -> Duration>(
+        &self,
+        req: PathRequest,
+        params: &RoutingParams,
+        delay: F,
+        map: &Map,
+    ) -> Option<PathV2> {
+        let graph = match req.constraints {
+            PathConstraints::Pedestrian => {
+                return None;
+            }
+            PathConstraints::Car => &self.car_graph,
+            PathConstraints::Bike => &self.bike_graph,
+            PathConstraints::Bus => &self.bus_graph,
+            PathConstraints::Train => &self.train_graph,
+        };
+        graph.pathfind_with_delays(req, params, delay, map)
+    }
+
+    /// Use time-dependent costs from now on for `pathfind_at`, or go back to static costs.
+    pub fn set_travel_time_profile(&mut self, profile: Option<TravelTimeProfile>) {
+        self.travel_times = profile;
+    }
+
+    pub fn get_travel_time_profile(&self) -> Option<&TravelTimeProfile> {
+        self.travel_times.as_ref()
+    }
+
     pub fn clear_custom_pathfinder_cache(&self) {
         self.cached_alternatives
             .get_or(|| RefCell::new(VecMap::new()))
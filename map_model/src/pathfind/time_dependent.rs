@@ -0,0 +1,117 @@
+//! Time-dependent routing for vehicles. The usual pathfinders use one static cost per road, derived
+//! from speed limits. A `TravelTimeProfile` instead records how long vehicles actually took to cross
+//! each road at different times of day -- usually observed in a previous simulation -- so a path can
+//! avoid roads that're jammed at the time the vehicle would reach them. See
+//! `VehiclePathfinder::pathfind_time_dependent` for the search itself.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Duration, Time};
+
+use crate::pathfind::vehicles::{free_flow_time, vehicle_cost};
+use crate::{DirectedRoadID, Map, MovementID, PathConstraints, RoutingParams};
+
+/// How long did it take to cross each road, plus the intersection at the end of it, grouped by the
+/// time the vehicle entered the road?
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TravelTimeProfile {
+    bin_size: Duration,
+    /// For each road and time bin, (total travel time, number of vehicles measured)
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    bins: BTreeMap<DirectedRoadID, Vec<(Duration, usize)>>,
+}
+
+impl TravelTimeProfile {
+    pub fn new(bin_size: Duration) -> TravelTimeProfile {
+        assert!(bin_size > Duration::ZERO);
+        TravelTimeProfile {
+            bin_size,
+            bins: BTreeMap::new(),
+        }
+    }
+
+    pub fn bin_size(&self) -> Duration {
+        self.bin_size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bins.is_empty()
+    }
+
+    /// A vehicle entered a road at some time, and took some duration to reach the next road.
+    pub fn record(&mut self, dr: DirectedRoadID, entered: Time, duration: Duration) {
+        let idx = self.bin(entered);
+        let bins = self.bins.entry(dr).or_insert_with(Vec::new);
+        if bins.len() <= idx {
+            bins.resize(idx + 1, (Duration::ZERO, 0));
+        }
+        bins[idx].0 += duration;
+        bins[idx].1 += 1;
+    }
+
+    /// The average time to cross a road (and the intersection after it) when entering at some
+    /// time. None if no vehicles were measured then.
+    pub fn get(&self, dr: DirectedRoadID, entered: Time) -> Option<Duration> {
+        let (total, count) = *self.bins.get(&dr)?.get(self.bin(entered))?;
+        if count == 0 {
+            return None;
+        }
+        Some(total / (count as f64))
+    }
+
+    /// What's the time-dependent pathfinding cost of following a sequence of roads, departing at
+    /// some time? Like pathfinding, this counts crossing every road except for the last. None if
+    /// the roads aren't connected.
+    pub fn cost_along(
+        &self,
+        roads: &[DirectedRoadID],
+        departure: Time,
+        constraints: PathConstraints,
+        params: &RoutingParams,
+        map: &Map,
+    ) -> Option<Duration> {
+        let mut cost = Duration::ZERO;
+        let mut time = departure;
+        for pair in roads.windows(2) {
+            let mvmnt = map
+                .get_movements_for(pair[0], constraints)
+                .into_iter()
+                .find(|mvmnt| mvmnt.to == pair[1])?;
+            let (step_cost, duration) =
+                time_dependent_cost(mvmnt, time, constraints, params, self, map);
+            cost += step_cost;
+            time += duration;
+        }
+        Some(cost)
+    }
+
+    fn bin(&self, time: Time) -> usize {
+        ((time - Time::START_OF_DAY) / self.bin_size).max(0.0) as usize
+    }
+}
+
+/// The usual cost of crossing a road and movement, plus any extra delay observed when entering the
+/// road at some time, beyond how long it'd take at the speed limit. Also returns how long crossing
+/// actually takes -- the observed time, or the time at the speed limit -- since the cost can
+/// include penalties that don't take any time.
+pub(crate) fn time_dependent_cost(
+    mvmnt: MovementID,
+    entered: Time,
+    constraints: PathConstraints,
+    params: &RoutingParams,
+    profile: &TravelTimeProfile,
+    map: &Map,
+) -> (Duration, Duration) {
+    let cost = vehicle_cost(mvmnt.from, mvmnt, constraints, params, map);
+    let free_flow = free_flow_time(mvmnt.from, mvmnt, constraints, map);
+    match profile.get(mvmnt.from, entered) {
+        Some(observed) => (cost + (observed - free_flow).max(Duration::ZERO), observed),
+        None => (cost, free_flow),
+    }
+}
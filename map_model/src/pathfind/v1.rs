@@ -311,6 +311,30 @@ impl Path {
         // TODO Maybe need to amend uber_turns?
     }
 
+    /// Replace the rest of the path with a different route to the same place, found starting
+    /// from the current step. Progress along the original path is kept.
+    pub fn replace_remaining(&mut self, new: Path, map: &Map) -> Result<()> {
+        if self.currently_inside_ut.is_some() {
+            bail!("can't change a path in the middle of an uber-turn");
+        }
+        if new.steps.front() != self.steps.front() || new.steps.back() != self.steps.back() {
+            bail!(
+                "new path from {:?} to {:?} doesn't match the current one from {:?} to {:?}",
+                new.steps.front(),
+                new.steps.back(),
+                self.steps.front(),
+                self.steps.back()
+            );
+        }
+        self.steps = new.steps;
+        self.uber_turns = new.uber_turns;
+        self.total_length = self.crossed_so_far;
+        for step in &self.steps {
+            self.total_length += self.dist_crossed_from_step(map, step);
+        }
+        Ok(())
+    }
+
     pub fn is_upcoming_uber_turn_component(&self, t: TurnID) -> bool {
         self.uber_turns
             .front()
@@ -6,16 +6,17 @@ use fast_paths::InputGraph;
 use serde::{Deserialize, Serialize};
 
 use abstutil::MultiMap;
-use geom::Duration;
+use geom::{Duration, Time};
 
-use crate::pathfind::engine::{CreateEngine, PathfindEngine};
+use crate::pathfind::engine::{calculate_time_dependent_path, CreateEngine, PathfindEngine};
 use crate::pathfind::node_map::{deserialize_nodemap, NodeMap};
+use crate::pathfind::time_dependent::time_dependent_cost;
 use crate::pathfind::uber_turns::{IntersectionCluster, UberTurnV2};
 use crate::pathfind::zone_cost;
 use crate::pathfind::{round, unround};
 use crate::{
     DirectedRoadID, Direction, LaneType, Map, MovementID, PathConstraints, PathRequest, PathV2,
-    Position, RoutingParams, Traversable,
+    Position, RoutingParams, TravelTimeProfile, Traversable,
 };
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -114,7 +115,134 @@ impl VehiclePathfinder {
                 0,
             )],
         )?;
+        Some(self.translate_path(raw_weight, raw_nodes, req, map))
+    }
+
+    /// Like `pathfind`, but the cost of crossing each road depends on when the vehicle would get
+    /// there. The edge costs baked into the engine aren't used, so `params` can differ from the
+    /// ones this pathfinder was built with.
+    pub fn pathfind_time_dependent(
+        &self,
+        req: PathRequest,
+        departure: Time,
+        profile: &TravelTimeProfile,
+        params: &RoutingParams,
+        map: &Map,
+    ) -> Option<PathV2> {
+        self.pathfind_with_cost_fn(req, departure, map, |mvmnt, time| {
+            time_dependent_cost(mvmnt, time, self.constraints, params, profile, map)
+        })
+    }
+
+    /// Like `pathfind`, but some roads have extra delay right now, like from vehicles queued
+    /// there. Vehicles assume the delays last for the rest of their trip.
+    pub fn pathfind_with_delays<F: Fn(DirectedRoadID) -> Duration>(
+        &self,
+        req: PathRequest,
+        params: &RoutingParams,
+        delay: F,
+        map: &Map,
+    ) -> Option<PathV2> {
+        self.pathfind_with_cost_fn(req, Time::START_OF_DAY, map, |mvmnt, _| {
+            let delay = delay(mvmnt.from);
+            (
+                vehicle_cost(mvmnt.from, mvmnt, self.constraints, params, map) + delay,
+                free_flow_time(mvmnt.from, mvmnt, self.constraints, map) + delay,
+            )
+        })
+    }
+
+    /// Walk through the same graph as make_input_graph, but calculate the cost of each movement on
+    /// the fly, given the time the vehicle would start it. `cost_fn` returns the cost of the
+    /// movement and how long it takes; only the latter advances the clock.
+    fn pathfind_with_cost_fn<F: Fn(MovementID, Time) -> (Duration, Duration)>(
+        &self,
+        req: PathRequest,
+        departure: Time,
+        map: &Map,
+        cost_fn: F,
+    ) -> Option<PathV2> {
+        if matches!(self.engine, PathfindEngine::Empty) {
+            return None;
+        }
 
+        assert!(!map.get_l(req.start.lane()).is_walkable());
+        let mut starts = vec![(
+            self.nodes.get(Node::Road(
+                map.get_l(req.start.lane()).get_directed_parent(),
+            )),
+            0,
+        )];
+        if let Some((pos, cost)) = req.alt_start {
+            starts.push((
+                self.nodes
+                    .get(Node::Road(map.get_l(pos.lane()).get_directed_parent())),
+                round(cost),
+            ));
+        }
+        let end = self
+            .nodes
+            .get(Node::Road(map.get_l(req.end.lane()).get_directed_parent()));
+
+        let uber_turn_entrances = find_uber_turn_entrances(self.constraints, &self.uber_turns, map);
+        let (raw_weight, raw_nodes) = calculate_time_dependent_path(starts, end, |node, time| {
+            // The search tracks the time elapsed since departing
+            let time = departure + unround(time);
+            match self.nodes.translate_id(node) {
+                Node::Road(dr) => {
+                    let indices = uber_turn_entrances.get(dr);
+                    if indices.is_empty() {
+                        map.get_movements_for(dr, self.constraints)
+                            .into_iter()
+                            .map(|mvmnt| {
+                                let (cost, duration) = cost_fn(mvmnt, time);
+                                (
+                                    self.nodes.get(Node::Road(mvmnt.to)),
+                                    round(cost),
+                                    round(duration),
+                                )
+                            })
+                            .collect()
+                    } else {
+                        indices
+                            .iter()
+                            .map(|idx| {
+                                let mut sum_cost = Duration::ZERO;
+                                let mut sum_duration = Duration::ZERO;
+                                for mvmnt in &self.uber_turns[*idx].path {
+                                    let (cost, duration) = cost_fn(*mvmnt, time + sum_duration);
+                                    sum_cost += cost;
+                                    sum_duration += duration;
+                                }
+                                (
+                                    self.nodes.get(Node::UberTurn(*idx)),
+                                    round(sum_cost),
+                                    round(sum_duration),
+                                )
+                            })
+                            .collect()
+                    }
+                }
+                // The cost is already captured for entering the uber-turn
+                Node::UberTurn(idx) => {
+                    vec![(
+                        self.nodes.get(Node::Road(self.uber_turns[idx].exit())),
+                        1,
+                        0,
+                    )]
+                }
+            }
+        })?;
+        Some(self.translate_path(raw_weight, raw_nodes, req, map))
+    }
+
+    fn translate_path(
+        &self,
+        raw_weight: usize,
+        raw_nodes: Vec<usize>,
+        req: PathRequest,
+        map: &Map,
+    ) -> PathV2 {
         let mut road_steps = Vec::new();
         let mut uber_turns = Vec::new();
         for node in raw_nodes.into_iter().map(|id| self.nodes.translate_id(id)) {
@@ -134,7 +262,7 @@ impl VehiclePathfinder {
             }
         }
         let cost = unround(raw_weight);
-        Some(PathV2::from_roads(road_steps, req, cost, uber_turns, map))
+        PathV2::from_roads(road_steps, req, cost, uber_turns, map)
     }
 
     pub fn apply_edits(&mut self, map: &Map) {
@@ -202,25 +330,11 @@ fn make_input_graph(
 ) -> InputGraph {
     let mut input_graph = InputGraph::new();
 
-    // From some roads, instead of adding edges to movements, add edges to these (indexed)
-    // uber-turns.
-    let mut uber_turn_entrances: MultiMap<DirectedRoadID, usize> = MultiMap::new();
-    for (idx, ut) in uber_turns.iter().enumerate() {
-        // Force the nodes to always match up in the graph for different vehicle types.
+    // Force the nodes to always match up in the graph for different vehicle types.
+    for idx in 0..uber_turns.len() {
         nodes.get(Node::UberTurn(idx));
-
-        // But actually, make sure this uber-turn only contains roads that can be used by this
-        // vehicle.
-        // TODO Need to test editing lanes inside an IntersectionCluster very carefully. See Mercer
-        // and Dexter.
-        if ut
-            .path
-            .iter()
-            .all(|mvmnt| !mvmnt.to.lanes(constraints, map).is_empty())
-        {
-            uber_turn_entrances.insert(ut.entry(), idx);
-        }
     }
+    let uber_turn_entrances = find_uber_turn_entrances(constraints, uber_turns, map);
 
     for r in map.all_roads() {
         for dr in r.id.both_directions() {
@@ -265,6 +379,28 @@ fn make_input_graph(
     input_graph
 }
 
+/// From some roads, instead of adding edges to movements, add edges to these (indexed) uber-turns.
+fn find_uber_turn_entrances(
+    constraints: PathConstraints,
+    uber_turns: &[UberTurnV2],
+    map: &Map,
+) -> MultiMap<DirectedRoadID, usize> {
+    let mut uber_turn_entrances = MultiMap::new();
+    for (idx, ut) in uber_turns.iter().enumerate() {
+        // Make sure this uber-turn only contains roads that can be used by this vehicle.
+        // TODO Need to test editing lanes inside an IntersectionCluster very carefully. See Mercer
+        // and Dexter.
+        if ut
+            .path
+            .iter()
+            .all(|mvmnt| !mvmnt.to.lanes(constraints, map).is_empty())
+        {
+            uber_turn_entrances.insert(ut.entry(), idx);
+        }
+    }
+    uber_turn_entrances
+}
+
 /// This returns the pathfinding cost of crossing one road and turn. This is also expressed in
 /// units of time. It factors in the ideal time to cross the space, along with penalties for
 /// entering an access-restricted zone, taking an unprotected turn, and so on.
@@ -276,18 +412,10 @@ pub fn vehicle_cost(
     map: &Map,
 ) -> Duration {
     let movement = &map.get_i(mvmnt.parent).movements[&mvmnt];
-    let max_speed = match constraints {
-        PathConstraints::Car | PathConstraints::Bus | PathConstraints::Train => None,
-        PathConstraints::Bike => Some(crate::MAX_BIKE_SPEED),
-        PathConstraints::Pedestrian => unreachable!(),
-    };
-    let t1 = map.get_r(dr.road).length()
-        / Traversable::max_speed_along_road(dr, max_speed, constraints, map).0;
-    let t2 = movement.geom.length()
-        / Traversable::max_speed_along_movement(mvmnt, max_speed, constraints, map);
+    let free_flow = free_flow_time(dr, mvmnt, constraints, map);
 
     let base = match constraints {
-        PathConstraints::Car | PathConstraints::Train => t1 + t2,
+        PathConstraints::Car | PathConstraints::Train => free_flow,
         PathConstraints::Bike => {
             // TODO If we're on a driving lane, higher speed limit is worse.
             // TODO Bike lanes next to parking is dangerous.
@@ -302,7 +430,7 @@ pub fn vehicle_cost(
                 params.driving_lane_penalty
             };
 
-            lt_penalty * (t1 + t2)
+            lt_penalty * free_flow
         }
         PathConstraints::Bus => {
             // Like Car, but prefer bus lanes.
@@ -311,7 +439,7 @@ pub fn vehicle_cost(
             } else {
                 1.1
             };
-            lt_penalty * (t1 + t2)
+            lt_penalty * free_flow
         }
         PathConstraints::Pedestrian => unreachable!(),
     };
@@ -349,5 +477,31 @@ pub fn vehicle_cost(
         extra += Duration::hours(3);
     }
 
+    for (idx, price) in &params.tolls {
+        if map.get_tolls()[*idx].charges(dr.road, mvmnt.to.road) {
+            extra += *price * params.delay_per_toll_dollar;
+        }
+    }
+
     multiplier * base + extra
 }
+
+/// The time to cross one road and turn as fast as allowed, without any penalties.
+pub(crate) fn free_flow_time(
+    dr: DirectedRoadID,
+    mvmnt: MovementID,
+    constraints: PathConstraints,
+    map: &Map,
+) -> Duration {
+    let movement = &map.get_i(mvmnt.parent).movements[&mvmnt];
+    let max_speed = match constraints {
+        PathConstraints::Car | PathConstraints::Bus | PathConstraints::Train => None,
+        PathConstraints::Bike => Some(crate::MAX_BIKE_SPEED),
+        PathConstraints::Pedestrian => unreachable!(),
+    };
+    let t1 = map.get_r(dr.road).length()
+        / Traversable::max_speed_along_road(dr, max_speed, constraints, map).0;
+    let t2 = movement.geom.length()
+        / Traversable::max_speed_along_movement(mvmnt, max_speed, constraints, map);
+    t1 + t2
+}
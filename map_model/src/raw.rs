@@ -40,6 +40,7 @@ pub struct RawMap {
     pub areas: Vec<RawArea>,
     pub parking_lots: Vec<RawParkingLot>,
     pub parking_aisles: Vec<(osm::WayID, Vec<Pt2D>)>,
+    pub bike_share_docks: Vec<RawBikeShareDock>,
 
     pub boundary_polygon: Polygon,
     pub gps_bounds: GPSBounds,
@@ -116,6 +117,7 @@ impl RawMap {
             areas: Vec::new(),
             parking_lots: Vec::new(),
             parking_aisles: Vec::new(),
+            bike_share_docks: Vec::new(),
             // Some nonsense thing
             boundary_polygon: Polygon::rectangle(1.0, 1.0),
             gps_bounds: GPSBounds::new(),
@@ -695,6 +697,19 @@ pub struct RawParkingLot {
     pub osm_tags: Tags,
 }
 
+/// A station for picking up and returning shared bikes or e-scooters
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RawBikeShareDock {
+    pub point: Pt2D,
+    pub name: Option<String>,
+    /// How many vehicles fit
+    pub capacity: usize,
+    /// Set if the dock came from OSM
+    pub osm_id: Option<osm::NodeID>,
+    /// Set if the dock came from a GBFS feed
+    pub gbfs_station_id: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum RestrictionType {
     BanTurns,
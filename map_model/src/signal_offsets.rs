@@ -0,0 +1,355 @@
+//! Automatically pick offsets for a sequence of traffic signals along a corridor, so that vehicles
+//! traveling at the speed limit catch a "green wave." This finds offsets that maximize the
+//! bandwidth -- the window of departure times from one end of the corridor that make it through
+//! every signal without stopping.
+//!
+//! Only the default timing plan of each signal is used. Queues, turning vehicles, and actuated
+//! timing are all ignored, so it's a good idea to check the results by simulating.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use geom::Duration;
+
+use crate::{
+    ControlTrafficSignal, DirectedRoadID, EditCmd, EditIntersection, IntersectionID, Map,
+    PathConstraints,
+};
+
+/// Which way along the corridor should vehicles get a green wave?
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GreenWave {
+    /// From the first intersection to the last
+    Forwards,
+    /// From the last intersection to the first
+    Backwards,
+    /// Maximize the total bandwidth in both directions
+    Both,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CorridorOffsets {
+    /// The new offset for every signal along the corridor, in order. The first signal keeps its
+    /// current offset.
+    pub offsets: Vec<(IntersectionID, Duration)>,
+    /// Every signal along the corridor must share this cycle length.
+    pub cycle_length: Duration,
+    /// The window of departure times from the first signal that make it through all of them
+    /// without stopping. Zero if this direction wasn't considered.
+    pub forwards_bandwidth: Duration,
+    /// Like `forwards_bandwidth`, but departing from the last signal.
+    pub backwards_bandwidth: Duration,
+}
+
+impl CorridorOffsets {
+    /// Express the new offsets as map edits. Signals whose offset doesn't change are skipped.
+    pub fn to_edit_cmds(&self, map: &Map) -> Vec<EditCmd> {
+        let mut cmds = Vec::new();
+        for (i, offset) in &self.offsets {
+            let mut ts = map.get_traffic_signal(*i).clone();
+            if ts.offset == *offset {
+                continue;
+            }
+            ts.offset = *offset;
+            cmds.push(EditCmd::ChangeIntersection {
+                i: *i,
+                old: map.get_i_edit(*i),
+                new: EditIntersection::TrafficSignal(ts.export(map)),
+            });
+        }
+        cmds
+    }
+}
+
+/// Calculate offsets for a sequence of signalized intersections, listed in the order they're
+/// visited when driving along the corridor.
+pub fn optimize(
+    map: &Map,
+    corridor: &[IntersectionID],
+    direction: GreenWave,
+) -> Result<CorridorOffsets> {
+    if corridor.len() < 2 {
+        bail!("A corridor needs at least two intersections");
+    }
+    let mut signals = Vec::new();
+    for i in corridor {
+        if signals.iter().any(|ts: &&ControlTrafficSignal| ts.id == *i) {
+            bail!("{} is listed twice", i);
+        }
+        match map.maybe_get_traffic_signal(*i) {
+            Some(ts) => signals.push(ts),
+            None => bail!("{} isn't a traffic signal", i),
+        }
+    }
+
+    // Everything is calculated in whole seconds
+    let cycle_length = signals[0].simple_cycle_duration();
+    let cycle = cycle_length.inner_seconds().round() as usize;
+    if cycle == 0 {
+        bail!("{} has no cycle", signals[0].id);
+    }
+    for ts in &signals {
+        if (ts.simple_cycle_duration() - cycle_length).abs() >= Duration::seconds(1.0) {
+            bail!(
+                "{} has a cycle length of {}, but {} has {}. A green wave needs the same cycle \
+                 length everywhere.",
+                ts.id,
+                ts.simple_cycle_duration(),
+                signals[0].id,
+                cycle_length
+            );
+        }
+    }
+
+    let mut waves = Vec::new();
+    if direction != GreenWave::Backwards {
+        waves.push(Wave::new(map, &signals, cycle, false)?);
+    }
+    if direction != GreenWave::Forwards {
+        waves.push(Wave::new(map, &signals, cycle, true)?);
+    }
+    let offsets = search_offsets(
+        &waves,
+        signals
+            .iter()
+            .map(|ts| (ts.offset.inner_seconds().round() as usize) % cycle)
+            .collect(),
+        cycle,
+    );
+    let last = offsets.len() - 1;
+
+    let mut forwards_bandwidth = Duration::ZERO;
+    let mut backwards_bandwidth = Duration::ZERO;
+    for wave in &waves {
+        let dt = Duration::seconds(wave.bandwidth(&offsets, last, cycle) as f64);
+        if wave.backwards {
+            backwards_bandwidth = dt;
+        } else {
+            forwards_bandwidth = dt;
+        }
+    }
+
+    Ok(CorridorOffsets {
+        offsets: signals
+            .iter()
+            .zip(offsets)
+            .enumerate()
+            .map(|(k, (ts, offset))| {
+                if k == 0 {
+                    (ts.id, ts.offset)
+                } else {
+                    (ts.id, Duration::seconds(offset as f64))
+                }
+            })
+            .collect(),
+        cycle_length,
+        forwards_bandwidth,
+        backwards_bandwidth,
+    })
+}
+
+/// Starting from the current offsets, find ones that maximize the total bandwidth of all waves.
+/// The first offset never changes.
+fn search_offsets(waves: &[Wave], mut offsets: Vec<usize>, cycle: usize) -> Vec<usize> {
+    let bandwidth = |offsets: &[usize], upto: usize| -> usize {
+        waves
+            .iter()
+            .map(|w| w.bandwidth(offsets, upto, cycle))
+            .sum()
+    };
+
+    // Start by greedily adding one signal at a time, then repeatedly tune each offset with the
+    // others fixed. When there's a tie, keep the current offset.
+    for k in 1..offsets.len() {
+        let best = best_offset(&mut offsets, k, cycle, |o| bandwidth(o, k));
+        offsets[k] = best;
+    }
+    let last = offsets.len() - 1;
+    for _ in 0..10 {
+        let mut changed = false;
+        for k in 1..offsets.len() {
+            let best = best_offset(&mut offsets, k, cycle, |o| bandwidth(o, last));
+            changed |= offsets[k] != best;
+            offsets[k] = best;
+        }
+        if !changed {
+            break;
+        }
+    }
+    offsets
+}
+
+fn best_offset<F: Fn(&[usize]) -> usize>(
+    offsets: &mut [usize],
+    k: usize,
+    cycle: usize,
+    score: F,
+) -> usize {
+    let current = offsets[k];
+    let mut best = (score(offsets), current);
+    for candidate in 0..cycle {
+        offsets[k] = candidate;
+        let x = score(offsets);
+        if x > best.0 {
+            best = (x, candidate);
+        }
+    }
+    offsets[k] = current;
+    best.1
+}
+
+/// Platoons traveling one direction along the corridor.
+struct Wave {
+    backwards: bool,
+    /// Per signal along the corridor, is the movement continuing along the corridor protected at
+    /// each second of the cycle?
+    green: Vec<Vec<bool>>,
+    /// Per signal, when does a vehicle arrive there, relative to some arbitrary reference time?
+    arrival: Vec<usize>,
+}
+
+impl Wave {
+    fn new(
+        map: &Map,
+        signals: &[&ControlTrafficSignal],
+        cycle: usize,
+        backwards: bool,
+    ) -> Result<Wave> {
+        let mut order: Vec<usize> = (0..signals.len()).collect();
+        if backwards {
+            order.reverse();
+        }
+
+        // Find the path and free-flow travel time between each pair of signals
+        let mut from: Vec<Option<DirectedRoadID>> = vec![None; signals.len()];
+        let mut to: Vec<Option<DirectedRoadID>> = vec![None; signals.len()];
+        let mut arrival = vec![0; signals.len()];
+        let mut time = Duration::ZERO;
+        for pair in order.windows(2) {
+            let (i1, i2) = (signals[pair[0]].id, signals[pair[1]].id);
+            let (roads, _) = map
+                .simple_path_btwn_v2(i1, i2, PathConstraints::Car)
+                .ok_or_else(|| anyhow!("Can't drive from {} to {}", i1, i2))?;
+            to[pair[0]] = Some(map.get_r(roads[0]).directed_id_from(i1));
+            from[pair[1]] = Some(map.get_r(*roads.last().unwrap()).directed_id_to(i2));
+            for r in roads {
+                let r = map.get_r(r);
+                time += r.length() / r.speed_limit;
+            }
+            arrival[pair[1]] = time.inner_seconds().round() as usize;
+        }
+
+        let mut green = Vec::new();
+        for (idx, ts) in signals.iter().enumerate() {
+            let mut mask = Vec::new();
+            let mut stage_end = Duration::ZERO;
+            for stage in &ts.stages {
+                stage_end += stage.stage_type.simple_duration();
+                let protected = stage.protected_movements.iter().any(|m| {
+                    !m.crosswalk
+                        && from[idx].map(|r| r == m.from).unwrap_or(true)
+                        && to[idx].map(|r| r == m.to).unwrap_or(true)
+                });
+                while mask.len() < cycle && Duration::seconds(mask.len() as f64 + 0.5) < stage_end {
+                    mask.push(protected);
+                }
+            }
+            // Rounding might leave the very end of the cycle unfilled
+            while mask.len() < cycle {
+                mask.push(*mask.last().unwrap());
+            }
+            if !mask.contains(&true) {
+                bail!(
+                    "{} never has a protected green continuing along the corridor",
+                    ts.id
+                );
+            }
+            green.push(mask);
+        }
+
+        Ok(Wave {
+            backwards,
+            green,
+            arrival,
+        })
+    }
+
+    /// How many seconds of departures make it through every signal up to index `upto` without
+    /// stopping?
+    fn bandwidth(&self, offsets: &[usize], upto: usize, cycle: usize) -> usize {
+        (0..cycle)
+            .filter(|depart| {
+                (0..=upto).all(|k| self.green[k][(depart + self.arrival[k] + offsets[k]) % cycle])
+            })
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A corridor of 3 signals with a 60s cycle. Each one is green along the corridor for the
+    // first 30s. Driving between the first and second takes `dt1`, and between the second and
+    // third takes `dt2`.
+    fn corridor(dt1: usize, dt2: usize, backwards: bool) -> Wave {
+        let green = vec![(0..60).map(|s| s < 30).collect::<Vec<_>>(); 3];
+        let arrival = if backwards {
+            vec![dt1 + dt2, dt2, 0]
+        } else {
+            vec![0, dt1, dt1 + dt2]
+        };
+        Wave {
+            backwards,
+            green,
+            arrival,
+        }
+    }
+
+    #[test]
+    fn test_forwards_wave() {
+        let waves = vec![corridor(20, 25, false)];
+        // With every signal starting its cycle at the same time, few departures make it through
+        // without stopping
+        assert_eq!(waves[0].bandwidth(&[0, 0, 0], 1, 60), 10);
+        assert_eq!(waves[0].bandwidth(&[0, 0, 0], 2, 60), 0);
+
+        // Each signal should turn green just as the platoon from the first one arrives, so the
+        // entire green period is usable
+        let offsets = search_offsets(&waves, vec![0, 0, 0], 60);
+        assert_eq!(offsets, vec![0, 40, 15]);
+        assert_eq!(waves[0].bandwidth(&offsets, 2, 60), 30);
+
+        // The first offset is kept, and the others shift along with it
+        let offsets = search_offsets(&waves, vec![10, 0, 0], 60);
+        assert_eq!(offsets, vec![10, 50, 25]);
+    }
+
+    #[test]
+    fn test_both_directions() {
+        // When the travel time between signals is half a cycle, both directions can get the
+        // entire green period
+        let waves = vec![corridor(30, 30, false), corridor(30, 30, true)];
+        let offsets = search_offsets(&waves, vec![0, 0, 0], 60);
+        assert_eq!(offsets, vec![0, 30, 0]);
+        assert_eq!(waves[0].bandwidth(&offsets, 2, 60), 30);
+        assert_eq!(waves[1].bandwidth(&offsets, 2, 60), 30);
+
+        // Otherwise there's a tradeoff. Make sure the search finds the best total, by trying
+        // everything.
+        let waves = vec![corridor(20, 40, false), corridor(20, 40, true)];
+        let total = |offsets: &[usize]| -> usize {
+            waves.iter().map(|w| w.bandwidth(offsets, 2, 60)).sum()
+        };
+        let mut best = 0;
+        for o1 in 0..60 {
+            for o2 in 0..60 {
+                best = best.max(total(&[0, o1, o2]));
+            }
+        }
+        let offsets = search_offsets(&waves, vec![0, 0, 0], 60);
+        assert_eq!(total(&offsets), best);
+        assert!(waves[0].bandwidth(&offsets, 2, 60) > 0);
+        assert!(waves[1].bandwidth(&offsets, 2, 60) > 0);
+    }
+}
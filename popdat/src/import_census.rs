@@ -1,29 +1,89 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+
 use anyhow::Result;
 use geo::algorithm::intersects::Intersects;
+use geo::algorithm::{bounding_rect::BoundingRect, map_coords::MapCoordsInplace};
+use geozero::geo_types::GeoWriter;
 
 use geom::{GPSBounds, Polygon};
 
-use crate::CensusArea;
+use crate::{CensusArea, CensusSource};
 
 impl CensusArea {
     pub async fn fetch_all_for_map(
+        source: &CensusSource,
         map_area: &Polygon,
         bounds: &GPSBounds,
     ) -> Result<Vec<CensusArea>> {
-        use flatgeobuf::HttpFgbReader;
-        use geozero::geo_types::GeoWriter;
+        match source {
+            CensusSource::Remote => fetch_remote(map_area, bounds).await,
+            CensusSource::LocalFile {
+                path,
+                population_property,
+            } => read_local(path, population_property, map_area, bounds),
+        }
+    }
+}
+
+async fn fetch_remote(map_area: &Polygon, bounds: &GPSBounds) -> Result<Vec<CensusArea>> {
+    use flatgeobuf::HttpFgbReader;
+
+    let geo_map_area = to_gps_polygon(map_area, bounds);
 
-        use geo::algorithm::{bounding_rect::BoundingRect, map_coords::MapCoordsInplace};
-        let mut geo_map_area: geo::Polygon<_> = map_area.clone().into();
-        geo_map_area.map_coords_inplace(|c| {
-            let projected = geom::Pt2D::new(c.0, c.1).to_gps(bounds);
-            (projected.x(), projected.y())
-        });
+    // See the import handbook for how to prepare this file.
+    let mut fgb =
+        HttpFgbReader::open("https://abstreet.s3.amazonaws.com/population_areas.fgb").await?;
 
-        // See the import handbook for how to prepare this file.
-        let mut fgb =
-            HttpFgbReader::open("https://abstreet.s3.amazonaws.com/population_areas.fgb").await?;
+    let bounding_rect = geo_map_area
+        .bounding_rect()
+        .ok_or_else(|| anyhow!("missing bound rect"))?;
+    fgb.select_bbox(
+        bounding_rect.min().x,
+        bounding_rect.min().y,
+        bounding_rect.max().x,
+        bounding_rect.max().y,
+    )
+    .await?;
 
+    let mut results = vec![];
+    while let Some(feature) = fgb.next().await? {
+        use flatgeobuf::FeatureProperties;
+        // PERF TODO: how to parse into usize directly? And avoid parsing entire props dict?
+        let props = feature.properties()?;
+        let geometry = match feature.geometry() {
+            Some(g) => g,
+            None => {
+                warn!("skipping feature with missing geometry");
+                continue;
+            }
+        };
+        let mut geo = GeoWriter::new();
+        geometry.process(&mut geo, flatgeobuf::GeometryType::MultiPolygon)?;
+        if let Some(area) = fgb_area(geo.geometry(), props, "population", &geo_map_area, bounds)? {
+            results.push(area);
+        }
+    }
+
+    Ok(results)
+}
+
+/// Reads census areas from a FlatGeobuf (.fgb) or GeoJSON file on disk, using
+/// `population_property` as the number of people living in each area.
+fn read_local(
+    path: &str,
+    population_property: &str,
+    map_area: &Polygon,
+    bounds: &GPSBounds,
+) -> Result<Vec<CensusArea>> {
+    let geo_map_area = to_gps_polygon(map_area, bounds);
+
+    if path.ends_with(".fgb") {
+        use flatgeobuf::{FallibleStreamingIterator, FgbReader};
+
+        let mut file = BufReader::new(File::open(path)?);
+        let mut fgb = FgbReader::open(&mut file)?;
         let bounding_rect = geo_map_area
             .bounding_rect()
             .ok_or_else(|| anyhow!("missing bound rect"))?;
@@ -32,19 +92,12 @@ impl CensusArea {
             bounding_rect.min().y,
             bounding_rect.max().x,
             bounding_rect.max().y,
-        )
-        .await?;
+        )?;
 
         let mut results = vec![];
-        while let Some(feature) = fgb.next().await? {
+        while let Some(feature) = fgb.next()? {
             use flatgeobuf::FeatureProperties;
-            // PERF TODO: how to parse into usize directly? And avoid parsing entire props dict?
             let props = feature.properties()?;
-            if !props.contains_key("population") {
-                warn!("skipping feature with missing population");
-                continue;
-            }
-            let population: usize = props["population"].parse()?;
             let geometry = match feature.geometry() {
                 Some(g) => g,
                 None => {
@@ -54,42 +107,190 @@ impl CensusArea {
             };
             let mut geo = GeoWriter::new();
             geometry.process(&mut geo, flatgeobuf::GeometryType::MultiPolygon)?;
-            if let geo::Geometry::MultiPolygon(multi_poly) = geo.geometry() {
-                let geo_polygon = multi_poly
-                    .0
-                    .first()
-                    .ok_or_else(|| anyhow!("multipolygon was unexpectedly empty"))?;
-                if multi_poly.0.len() > 1 {
-                    warn!(
-                        "dropping {} extra polygons from census area: {:?}",
-                        multi_poly.0.len() - 1,
-                        props
-                    );
-                }
-
-                if !geo_polygon.intersects(&geo_map_area) {
-                    debug!(
-                        "skipping polygon outside of map area. polygon: {:?}, map_area: {:?}",
-                        geo_polygon, geo_map_area
-                    );
-                    continue;
-                }
+            if let Some(area) = fgb_area(
+                geo.geometry(),
+                props,
+                population_property,
+                &geo_map_area,
+                bounds,
+            )? {
+                results.push(area);
+            }
+        }
+        return Ok(results);
+    }
 
-                let mut polygon = geo_polygon.clone();
-                polygon.map_coords_inplace(|(x, y)| {
-                    let point = geom::LonLat::new(*x, *y).to_pt(bounds);
-                    (point.x(), point.y())
-                });
-                results.push(CensusArea {
-                    polygon,
-                    population,
-                });
-            } else {
-                warn!("skipping unexpected geometry");
+    // Otherwise assume GeoJSON
+    let raw_string = std::fs::read_to_string(path)?;
+    let features = match raw_string.parse::<geojson::GeoJson>()? {
+        geojson::GeoJson::Feature(feature) => vec![feature],
+        geojson::GeoJson::FeatureCollection(collection) => collection.features,
+        _ => bail!("{} isn't a GeoJSON feature collection", path),
+    };
+    let mut results = vec![];
+    for feature in features {
+        // Population is often a number, but sometimes a string
+        let population = match feature.property(population_property) {
+            Some(serde_json::Value::Number(x)) => x.as_f64().map(|x| x.round() as usize),
+            Some(serde_json::Value::String(x)) => x.parse::<usize>().ok(),
+            _ => None,
+        };
+        let population = match population {
+            Some(x) => x,
+            None => {
+                warn!("skipping feature with missing {}", population_property);
+                continue;
+            }
+        };
+        let polygons = match feature.geometry.map(|g| g.value) {
+            Some(geojson::Value::Polygon(rings)) => vec![rings],
+            Some(geojson::Value::MultiPolygon(polygons)) => polygons,
+            _ => {
+                warn!("skipping feature without polygon geometry");
+                continue;
+            }
+        };
+        if polygons.len() > 1 {
+            warn!(
+                "dropping {} extra polygons from census area",
+                polygons.len() - 1
+            );
+        }
+        let mut rings = polygons
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("multipolygon was unexpectedly empty"))?
+            .into_iter()
+            .map(|ring| {
+                geo::LineString::from(
+                    ring.into_iter()
+                        .map(|pt| (pt[0], pt[1]))
+                        .collect::<Vec<_>>(),
+                )
+            });
+        let exterior = match rings.next() {
+            Some(ring) => ring,
+            None => {
+                warn!("skipping polygon with no points");
                 continue;
             }
+        };
+        let geo_polygon = geo::Polygon::new(exterior, rings.collect());
+        if let Some(area) = to_census_area(geo_polygon, population, &geo_map_area, bounds) {
+            results.push(area);
         }
+    }
+    Ok(results)
+}
+
+/// Transform a polygon in map-space to WGS84.
+fn to_gps_polygon(map_area: &Polygon, bounds: &GPSBounds) -> geo::Polygon<f64> {
+    let mut geo_map_area: geo::Polygon<_> = map_area.clone().into();
+    geo_map_area.map_coords_inplace(|c| {
+        let projected = geom::Pt2D::new(c.0, c.1).to_gps(bounds);
+        (projected.x(), projected.y())
+    });
+    geo_map_area
+}
 
-        Ok(results)
+/// Handles one feature read from a FlatGeobuf file. Returns `None` for features that should be
+/// skipped.
+fn fgb_area(
+    geometry: &geo::Geometry<f64>,
+    props: HashMap<String, String>,
+    population_property: &str,
+    geo_map_area: &geo::Polygon<f64>,
+    bounds: &GPSBounds,
+) -> Result<Option<CensusArea>> {
+    if !props.contains_key(population_property) {
+        warn!("skipping feature with missing {}", population_property);
+        return Ok(None);
+    }
+    let population: usize = props[population_property].parse()?;
+    if let geo::Geometry::MultiPolygon(multi_poly) = geometry {
+        let geo_polygon = multi_poly
+            .0
+            .first()
+            .ok_or_else(|| anyhow!("multipolygon was unexpectedly empty"))?;
+        if multi_poly.0.len() > 1 {
+            warn!(
+                "dropping {} extra polygons from census area: {:?}",
+                multi_poly.0.len() - 1,
+                props
+            );
+        }
+        Ok(to_census_area(
+            geo_polygon.clone(),
+            population,
+            geo_map_area,
+            bounds,
+        ))
+    } else {
+        warn!("skipping unexpected geometry");
+        Ok(None)
+    }
+}
+
+/// Takes a polygon in WGS84 and transforms it to map-space, if it overlaps the map at all.
+fn to_census_area(
+    mut polygon: geo::Polygon<f64>,
+    population: usize,
+    geo_map_area: &geo::Polygon<f64>,
+    bounds: &GPSBounds,
+) -> Option<CensusArea> {
+    if !polygon.intersects(geo_map_area) {
+        debug!(
+            "skipping polygon outside of map area. polygon: {:?}, map_area: {:?}",
+            polygon, geo_map_area
+        );
+        return None;
+    }
+
+    polygon.map_coords_inplace(|(x, y)| {
+        let point = geom::LonLat::new(*x, *y).to_pt(bounds);
+        (point.x(), point.y())
+    });
+    Some(CensusArea {
+        polygon,
+        population,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geom::{LonLat, Pt2D};
+
+    #[test]
+    fn test_read_local() {
+        let bounds = GPSBounds::from(vec![
+            LonLat::new(-122.46, 47.72),
+            LonLat::new(-122.44, 47.73),
+        ]);
+        let map_area = bounds.to_bounds().get_rectangle();
+        // Both files have an area inside the map, one partly overlapping it, and one far away. The
+        // GeoJSON file also has an area without a population, and one population as a string.
+        for ext in ["geojson", "fgb"] {
+            let path = format!(
+                "{}/../tests/input/census/areas.{}",
+                env!("CARGO_MANIFEST_DIR"),
+                ext
+            );
+            let areas = read_local(&path, "population", &map_area, &bounds).unwrap();
+            let mut populations: Vec<usize> = areas.iter().map(|a| a.population).collect();
+            populations.sort_unstable();
+            assert_eq!(populations, vec![100, 250], "{}", path);
+
+            // The area inside the map is transformed to map-space
+            let inside = areas.iter().find(|a| a.population == 100).unwrap();
+            for c in &inside.polygon.exterior().0 {
+                assert!(
+                    bounds.to_bounds().contains(Pt2D::new(c.x, c.y)),
+                    "{} has {:?} outside the map",
+                    path,
+                    c
+                );
+            }
+        }
     }
 }
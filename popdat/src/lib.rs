@@ -24,6 +24,8 @@ extern crate log;
 
 use rand_xorshift::XorShiftRng;
 
+use anyhow::Result;
+
 use abstutil::Timer;
 use geom::{Distance, Time};
 use map_model::{BuildingID, Map};
@@ -48,6 +50,20 @@ pub struct CensusArea {
     // TODO Not sure what goes here, whatever census data actually has that could be useful
 }
 
+/// Where should `CensusArea`s be read from?
+#[derive(Clone, Debug)]
+pub enum CensusSource {
+    /// Stream just the areas covering a map from the global FlatGeobuf file hosted in A/B
+    /// Street's S3 bucket. Requires network access.
+    Remote,
+    /// Read a FlatGeobuf (.fgb) or GeoJSON file from disk. Each feature must be a polygon or
+    /// multipolygon, with the number of residents in `population_property`.
+    LocalFile {
+        path: String,
+        population_property: String,
+    },
+}
+
 /// Demographic information for a single person
 pub struct CensusPerson {
     pub home: BuildingID,
@@ -131,3 +147,17 @@ pub fn generate_scenario(
 
     scenario
 }
+
+/// Like `generate_scenario`, but first find census data for the map from the given source.
+pub async fn generate_scenario_from_source(
+    scenario_name: &str,
+    source: &CensusSource,
+    config: Config,
+    map: &Map,
+    rng: &mut XorShiftRng,
+) -> Result<Scenario> {
+    let areas =
+        CensusArea::fetch_all_for_map(source, map.get_boundary_polygon(), map.get_gps_bounds())
+            .await?;
+    Ok(generate_scenario(scenario_name, areas, config, map, rng))
+}
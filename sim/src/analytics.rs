@@ -5,17 +5,22 @@ use std::io::Write;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use abstutil::Counter;
-use geom::{Duration, Time};
+use abstutil::{deserialize_btreemap, serialize_btreemap, Counter};
+use geom::{Distance, Duration, Time};
 use map_model::{
-    BusRouteID, BusStopID, CompressedMovementID, IntersectionID, LaneID, Map, MovementID,
-    ParkingLotID, Path, PathRequest, RoadID, Traversable, TurnID,
+    BikeShareDockID, BuildingID, BusRouteID, BusStopID, CompressedMovementID, DirectedRoadID,
+    IntersectionID, LaneID, Map, MovementID, ParkingLotID, Path, PathRequest, RoadID,
+    TravelTimeProfile, Traversable, TurnID,
 };
 
 use crate::{
-    AgentID, AgentType, AlertLocation, CarID, Event, ParkingSpot, TripID, TripMode, TripPhaseType,
+    AgentID, AgentType, AlertLocation, CarID, CurbUse, Event, OvertakeManeuver, ParkingSpot,
+    RerouteReason, RideHailActivity, TripID, TripMode, TripPhaseType, VehicleType,
 };
 
+/// Road travel times are grouped by when cars enter the road.
+const TRAVEL_TIME_BIN: Duration = Duration::const_seconds(15.0 * 60.0);
+
 /// As a simulation runs, different pieces emit Events. The Analytics object listens to these,
 /// organizing and storing some information from them. The UI queries Analytics to draw time-series
 /// and display statistics.
@@ -37,11 +42,20 @@ pub struct Analytics {
     /// in time.
     pub demand: BTreeMap<MovementID, usize>,
 
-    // TODO Reconsider this one
-    pub bus_arrivals: Vec<(Time, CarID, BusRouteID, BusStopID)>,
+    /// Every time a transit vehicle reaches a stop. If the route has a schedule, also how late the
+    /// vehicle is (negative if early).
+    pub bus_arrivals: Vec<(Time, CarID, BusRouteID, BusStopID, Option<Duration>)>,
+    /// When did transit vehicles wait at a stop to regularize their schedule or headway, and for
+    /// how long?
+    pub transit_holding: BTreeMap<BusRouteID, Vec<(Time, BusStopID, Duration)>>,
     /// For each passenger boarding, how long did they wait at the stop?
     pub passengers_boarding: BTreeMap<BusStopID, Vec<(Time, BusRouteID, Duration)>>,
     pub passengers_alighting: BTreeMap<BusStopID, Vec<(Time, BusRouteID)>>,
+    /// Each time a transit vehicle leaves a stop, how crowded is it? This is the number of
+    /// passengers divided by the number of seats, so anything over 1 means people are standing.
+    pub transit_load_factors: BTreeMap<BusRouteID, Vec<(Time, BusStopID, f64)>>,
+    /// When were people waiting at a stop passed up by a full vehicle?
+    pub denied_boardings: BTreeMap<BusStopID, Vec<(Time, BusRouteID)>>,
 
     pub started_trips: BTreeMap<TripID, Time>,
     /// Finish time, ID, mode, trip duration if successful (or None if cancelled)
@@ -56,10 +70,75 @@ pub struct Analytics {
     // TODO Transit riders aren't represented here yet, just the vehicle they're riding.
     /// Only for traffic signals. The u8 is the movement index from a CompressedMovementID.
     pub intersection_delays: BTreeMap<IntersectionID, Vec<(u8, Time, Duration, AgentType)>>,
+    /// When did traffic signals extend (positive) or cut short (negative) a stage for a transit
+    /// vehicle? Compare against `intersection_delays` to see the effect on everybody else.
+    pub transit_signal_priority: BTreeMap<IntersectionID, Vec<(Time, CarID, Duration)>>,
 
     /// Per parking lane or lot, when does a spot become filled (true) or free (false)
     pub parking_lane_changes: BTreeMap<LaneID, Vec<(Time, bool)>>,
     pub parking_lot_changes: BTreeMap<ParkingLotID, Vec<(Time, bool)>>,
+    /// Every time a driver started parking or gave up: how long they spent circling past their
+    /// destination, how far they drove doing so, and whether they found a spot. Drivers who parked
+    /// right away count with zero.
+    pub parking_searches: Vec<(Time, Option<TripID>, Duration, Distance, bool)>,
+    /// When cars left a priced spot, how much did they pay, in dollars?
+    pub parking_fees: Vec<(Time, CarID, ParkingSpot, f64)>,
+    /// When did cars leave a spot after staying past its time limit, and by how much?
+    pub parking_overstays: Vec<(Time, CarID, ParkingSpot, Duration)>,
+
+    /// For each ride-hail passenger picked up, how long did they wait for the vehicle?
+    pub ride_hail_waits: Vec<(Time, TripID, Duration)>,
+    /// When does each ride-hail vehicle start doing something different?
+    pub ride_hail_activity: Vec<(Time, CarID, RideHailActivity)>,
+    /// The total distance ride-hail vehicles have driven while doing each activity
+    pub ride_hail_distance: BTreeMap<RideHailActivity, Distance>,
+
+    /// When each emergency vehicle reached its incident, and the response time since the call.
+    /// These aren't trips, so they're not in `finished_trips`.
+    pub emergency_responses: Vec<(Time, CarID, BuildingID, Duration)>,
+    /// When did traffic signals switch stages early for an emergency vehicle?
+    pub emergency_preemptions: BTreeMap<IntersectionID, Vec<(Time, CarID)>>,
+
+    /// Every delivery made by a freight vehicle: when it stopped, the building served, whether it
+    /// used a loading zone or double-parked, and for how long.
+    pub deliveries: Vec<(Time, CarID, BuildingID, CurbUse, Duration)>,
+    /// When each freight vehicle got back to its depot, and how long its whole tour took
+    pub delivery_tours: Vec<(Time, CarID, Duration)>,
+
+    /// How many vehicles are docked at each bike-share station over time. Docks start half full;
+    /// ones nobody has used are omitted.
+    pub bike_share_inventory: BTreeMap<BikeShareDockID, Vec<(Time, usize)>>,
+    /// When did somebody find a bike-share dock empty when trying to pick up a vehicle?
+    pub bike_share_empty_docks: Vec<(Time, TripID, BikeShareDockID)>,
+    /// When did somebody find a bike-share dock full when trying to return a vehicle?
+    pub bike_share_full_docks: Vec<(Time, TripID, BikeShareDockID)>,
+
+    /// How much has each trip paid in tolls so far, in dollars? Trips that haven't paid anything
+    /// are omitted.
+    pub trip_tolls: BTreeMap<TripID, f64>,
+
+    /// How long did cars take to cross each road, plus the intersection at the end of it? This is
+    /// large, so it's only recorded with `SimOptions::record_travel_times`.
+    pub road_travel_times: Option<TravelTimeProfile>,
+    /// The sequence of roads each car trip drove along. Only recorded with
+    /// `SimOptions::record_travel_times`, and to keep prebaked results small, never saved.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub driven_routes: BTreeMap<TripID, Vec<DirectedRoadID>>,
+    /// For car trips in progress, when did they enter their current road?
+    #[serde(skip_serializing, skip_deserializing)]
+    trip_entered_road: BTreeMap<TripID, (DirectedRoadID, Time)>,
+    /// When did each parked car arrive? Cars parked since before the simulation started are
+    /// omitted and assumed to have arrived at midnight.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    car_parked_since: BTreeMap<CarID, Time>,
+
+    /// When did informed drivers switch routes to avoid congestion, and why?
+    pub reroutes: Vec<(Time, CarID, Option<TripID>, RerouteReason)>,
+    /// When did vehicles change lanes or use an oncoming lane to pass somebody slower?
+    pub overtakes: Vec<(Time, CarID, Option<TripID>, OvertakeManeuver)>,
 
     pub(crate) alerts: Vec<(Time, AlertLocation, String)>,
 
@@ -80,22 +159,50 @@ pub enum Problem {
 }
 
 impl Analytics {
-    pub fn new(record_anything: bool) -> Analytics {
+    pub fn new(record_anything: bool, record_travel_times: bool) -> Analytics {
         Analytics {
             road_thruput: TimeSeriesCount::new(),
             intersection_thruput: TimeSeriesCount::new(),
             traffic_signal_thruput: TimeSeriesCount::new(),
             demand: BTreeMap::new(),
             bus_arrivals: Vec::new(),
+            transit_holding: BTreeMap::new(),
             passengers_boarding: BTreeMap::new(),
             passengers_alighting: BTreeMap::new(),
+            transit_load_factors: BTreeMap::new(),
+            denied_boardings: BTreeMap::new(),
             started_trips: BTreeMap::new(),
             finished_trips: Vec::new(),
             problems_per_trip: BTreeMap::new(),
             trip_log: Vec::new(),
             intersection_delays: BTreeMap::new(),
+            transit_signal_priority: BTreeMap::new(),
             parking_lane_changes: BTreeMap::new(),
             parking_lot_changes: BTreeMap::new(),
+            parking_searches: Vec::new(),
+            parking_fees: Vec::new(),
+            parking_overstays: Vec::new(),
+            ride_hail_waits: Vec::new(),
+            ride_hail_activity: Vec::new(),
+            ride_hail_distance: BTreeMap::new(),
+            emergency_responses: Vec::new(),
+            emergency_preemptions: BTreeMap::new(),
+            deliveries: Vec::new(),
+            delivery_tours: Vec::new(),
+            bike_share_inventory: BTreeMap::new(),
+            bike_share_empty_docks: Vec::new(),
+            bike_share_full_docks: Vec::new(),
+            trip_tolls: BTreeMap::new(),
+            road_travel_times: if record_travel_times {
+                Some(TravelTimeProfile::new(TRAVEL_TIME_BIN))
+            } else {
+                None
+            },
+            driven_routes: BTreeMap::new(),
+            trip_entered_road: BTreeMap::new(),
+            car_parked_since: BTreeMap::new(),
+            reroutes: Vec::new(),
+            overtakes: Vec::new(),
             alerts: Vec::new(),
             record_anything,
         }
@@ -156,8 +263,14 @@ impl Analytics {
         }
 
         // Bus arrivals
-        if let Event::BusArrivedAtStop(bus, route, stop) = ev {
-            self.bus_arrivals.push((time, bus, route, stop));
+        if let Event::BusArrivedAtStop(bus, route, stop, lateness) = ev {
+            self.bus_arrivals.push((time, bus, route, stop, lateness));
+        }
+        if let Event::BusHeldAtStop(_, route, stop, duration) = ev {
+            self.transit_holding
+                .entry(route)
+                .or_insert_with(Vec::new)
+                .push((time, stop, duration));
         }
 
         // Passengers boarding/alighting
@@ -174,6 +287,21 @@ impl Analytics {
                 .push((time, route));
         }
 
+        // Crowding
+        if let Event::BusDepartedFromStop(bus, route, stop, passengers) = ev {
+            let (seated, _) = bus.vehicle_type.passenger_capacity_on(map.get_br(route));
+            self.transit_load_factors
+                .entry(route)
+                .or_insert_with(Vec::new)
+                .push((time, stop, (passengers as f64) / (seated as f64)));
+        }
+        if let Event::PassengerDeniedBoarding(_, _, route, stop) = ev {
+            self.denied_boardings
+                .entry(stop)
+                .or_insert_with(Vec::new)
+                .push((time, route));
+        }
+
         // Started trips
         if let Event::TripPhaseStarting(id, _, _, _) = ev {
             self.started_trips.entry(id).or_insert(time);
@@ -189,9 +317,11 @@ impl Analytics {
         {
             self.finished_trips
                 .push((time, trip, mode, Some(total_time)));
+            self.trip_entered_road.remove(&trip);
         } else if let Event::TripCancelled(id, mode) = ev {
             self.started_trips.entry(id).or_insert(time);
             self.finished_trips.push((time, id, mode, None));
+            self.trip_entered_road.remove(&id);
         }
 
         // Intersection delay
@@ -218,6 +348,12 @@ impl Analytics {
                     .push((compressed.idx, time, delay, agent.to_type()));
             }
         }
+        if let Event::TransitSignalPriority(i, car, change) = ev {
+            self.transit_signal_priority
+                .entry(i)
+                .or_insert_with(Vec::new)
+                .push((time, car, change));
+        }
 
         // Parking spot changes
         if let Event::CarReachedParkingSpot(_, spot) = ev {
@@ -247,6 +383,104 @@ impl Analytics {
             }
         }
 
+        // Parking policy
+        match ev {
+            Event::CarReachedParkingSpot(car, _) => {
+                self.car_parked_since.insert(car, time);
+            }
+            Event::CarLeftParkingSpot(car, spot) => {
+                let parked_since = self
+                    .car_parked_since
+                    .remove(&car)
+                    .unwrap_or(Time::START_OF_DAY);
+                if let Some(policy) = spot.policy(map) {
+                    let fee = policy.cost(parked_since, time);
+                    if fee > 0.0 {
+                        self.parking_fees.push((time, car, spot, fee));
+                    }
+                    if let Some(overstay) = policy.overstay(parked_since, time) {
+                        self.parking_overstays.push((time, car, spot, overstay));
+                    }
+                }
+            }
+            Event::ParkingSearchFinished {
+                trip,
+                time_searching,
+                cruising_distance,
+                found_spot,
+                ..
+            } => {
+                self.parking_searches.push((
+                    time,
+                    trip,
+                    time_searching,
+                    cruising_distance,
+                    found_spot,
+                ));
+            }
+            _ => {}
+        }
+
+        // Ride-hail
+        match ev {
+            Event::RideHailActivity(car, activity) => {
+                self.ride_hail_activity.push((time, car, activity));
+            }
+            Event::RideHailDrove(_, activity, dist) => {
+                *self
+                    .ride_hail_distance
+                    .entry(activity)
+                    .or_insert(Distance::ZERO) += dist;
+            }
+            Event::RideHailPickup(trip, _, wait) => {
+                self.ride_hail_waits.push((time, trip, wait));
+            }
+            _ => {}
+        }
+
+        // Emergency vehicles
+        match ev {
+            Event::EmergencyVehicleArrived(car, incident, response_time) => {
+                self.emergency_responses
+                    .push((time, car, incident, response_time));
+            }
+            Event::EmergencySignalPreemption(i, car) => {
+                self.emergency_preemptions
+                    .entry(i)
+                    .or_insert_with(Vec::new)
+                    .push((time, car));
+            }
+            _ => {}
+        }
+
+        // Freight
+        match ev {
+            Event::Delivery(car, b, curb, dwell_time) => {
+                self.deliveries.push((time, car, b, curb, dwell_time));
+            }
+            Event::DeliveryTourFinished(car, duration) => {
+                self.delivery_tours.push((time, car, duration));
+            }
+            _ => {}
+        }
+
+        // Bike-share
+        match ev {
+            Event::BikeShareDockInventory(dock, count) => {
+                self.bike_share_inventory
+                    .entry(dock)
+                    .or_insert_with(Vec::new)
+                    .push((time, count));
+            }
+            Event::BikeShareDockEmpty(trip, dock) => {
+                self.bike_share_empty_docks.push((time, trip, dock));
+            }
+            Event::BikeShareDockFull(trip, dock) => {
+                self.bike_share_full_docks.push((time, trip, dock));
+            }
+            _ => {}
+        }
+
         // Safety metrics
         if let Event::AgentEntersTraversable(a, Some(trip), Traversable::Turn(t), _) = ev {
             if a.to_type() == AgentType::Bike && map.get_i(t.parent).roads.len() > 4 {
@@ -260,6 +494,56 @@ impl Analytics {
             }
         }
 
+        // Tolls are charged when entering a road, so the turn leading there is enough to know
+        if let Event::AgentEntersTraversable(
+            AgentID::Car(car),
+            Some(trip),
+            Traversable::Turn(t),
+            _,
+        ) = ev
+        {
+            let toll = map.toll_for_movement(
+                t.src.road,
+                t.dst.road,
+                car.vehicle_type.to_constraints(),
+                time,
+            );
+            if toll > 0.0 {
+                *self.trip_tolls.entry(trip).or_insert(0.0) += toll;
+            }
+        }
+
+        // Road travel times and routes. Only measure a road once a car has driven all the way
+        // along it, so the start and end of each trip are skipped.
+        if let Event::AgentEntersTraversable(AgentID::Car(car), Some(trip), on, _) = ev {
+            if let (VehicleType::Car, Some(profile)) =
+                (car.vehicle_type, self.road_travel_times.as_mut())
+            {
+                match on {
+                    Traversable::Lane(l) => {
+                        let dr = map.get_l(l).get_directed_parent();
+                        if let Some((prev_dr, entered)) =
+                            self.trip_entered_road.insert(trip, (dr, time))
+                        {
+                            if prev_dr == dr {
+                                // Still on the same road, so keep the original time
+                                self.trip_entered_road.insert(trip, (dr, entered));
+                            } else {
+                                profile.record(prev_dr, entered, time - entered);
+                            }
+                        }
+                    }
+                    Traversable::Turn(t) => {
+                        let route = self.driven_routes.entry(trip).or_insert_with(Vec::new);
+                        if route.is_empty() {
+                            route.push(map.get_l(t.src).get_directed_parent());
+                        }
+                        route.push(map.get_l(t.dst).get_directed_parent());
+                    }
+                }
+            }
+        }
+
         if let Event::AgentEntersTraversable(a, Some(trip), Traversable::Turn(t), _) = ev {
             let turn = map.get_t(t);
             if a.to_type() == AgentType::Pedestrian && turn.is_crossing_arterial_intersection(map) {
@@ -286,6 +570,13 @@ impl Analytics {
             Event::PathAmended(path) => {
                 self.record_demand(&path, map);
             }
+            Event::PathRerouted(car, trip, reason, path) => {
+                self.record_demand(&path, map);
+                self.reroutes.push((time, car, trip, reason));
+            }
+            Event::Overtook(car, trip, maneuver) => {
+                self.overtakes.push((time, car, trip, maneuver));
+            }
             Event::Alert(loc, msg) => {
                 self.alerts.push((time, loc, msg));
             }
@@ -309,6 +600,79 @@ impl Analytics {
         }
     }
 
+    /// Returns the total delay and number of agents delayed at some traffic signals so far.
+    pub fn total_intersection_delay(&self, intersections: &[IntersectionID]) -> (Duration, usize) {
+        let mut total = Duration::ZERO;
+        let mut count = 0;
+        for i in intersections {
+            if let Some(list) = self.intersection_delays.get(i) {
+                for (_, _, dt, _) in list {
+                    total += *dt;
+                }
+                count += list.len();
+            }
+        }
+        (total, count)
+    }
+
+    /// How much time has the ride-hail fleet spent doing each activity, summed over every
+    /// vehicle?
+    pub fn ride_hail_fleet_time(&self, now: Time) -> BTreeMap<RideHailActivity, Duration> {
+        let mut totals = BTreeMap::new();
+        let mut current: BTreeMap<CarID, (Time, RideHailActivity)> = BTreeMap::new();
+        for (time, car, activity) in &self.ride_hail_activity {
+            if let Some((since, prev)) = current.insert(*car, (*time, *activity)) {
+                *totals.entry(prev).or_insert(Duration::ZERO) += *time - since;
+            }
+        }
+        for (since, activity) in current.values() {
+            *totals.entry(*activity).or_insert(Duration::ZERO) += now - *since;
+        }
+        totals
+    }
+
+    /// The fraction of the fleet's time spent carrying passengers so far. None if there's no
+    /// fleet.
+    pub fn ride_hail_utilization(&self, now: Time) -> Option<f64> {
+        let totals = self.ride_hail_fleet_time(now);
+        let total: Duration = totals.values().cloned().sum();
+        if total == Duration::ZERO {
+            return None;
+        }
+        let occupied = totals
+            .get(&RideHailActivity::Occupied)
+            .cloned()
+            .unwrap_or(Duration::ZERO);
+        Some(occupied / total)
+    }
+
+    /// How bunched up are a route's vehicles? At every stop, find the time between consecutive
+    /// arrivals, then return the coefficient of variation (standard deviation divided by the mean)
+    /// of all of those headways. 0 means perfectly even spacing, while values around 1 mean
+    /// vehicles are bunching. None if there aren't enough arrivals yet.
+    pub fn headway_coefficient_of_variation(&self, route: BusRouteID) -> Option<f64> {
+        headway_coefficient_of_variation(
+            self.bus_arrivals
+                .iter()
+                .filter(|(_, _, r, _, _)| *r == route)
+                .map(|(t, _, _, stop, _)| (*stop, *t)),
+        )
+    }
+
+    /// The total tolls paid by all trips so far, in dollars
+    pub fn total_toll_revenue(&self) -> f64 {
+        self.trip_tolls.values().sum()
+    }
+
+    /// The total distance ride-hail vehicles have driven without a passenger
+    pub fn ride_hail_empty_distance(&self) -> Distance {
+        [RideHailActivity::Deadheading, RideHailActivity::Cruising]
+            .iter()
+            .filter_map(|a| self.ride_hail_distance.get(a))
+            .cloned()
+            .sum()
+    }
+
     // TODO If these ever need to be speeded up, just cache the histogram and index in the events
     // list.
 
@@ -510,10 +874,40 @@ impl Analytics {
 
 impl Default for Analytics {
     fn default() -> Analytics {
-        Analytics::new(false)
+        Analytics::new(false, false)
     }
 }
 
+/// Given (stop, time) for every arrival along a route in order, calculate the coefficient of
+/// variation of the headways at each stop.
+fn headway_coefficient_of_variation<K: Ord>(
+    arrivals: impl Iterator<Item = (K, Time)>,
+) -> Option<f64> {
+    let mut arrivals_per_stop: BTreeMap<K, Vec<Time>> = BTreeMap::new();
+    for (stop, t) in arrivals {
+        arrivals_per_stop
+            .entry(stop)
+            .or_insert_with(Vec::new)
+            .push(t);
+    }
+    let mut headways = Vec::new();
+    for times in arrivals_per_stop.values() {
+        for pair in times.windows(2) {
+            headways.push((pair[1] - pair[0]).inner_seconds());
+        }
+    }
+    if headways.len() < 2 {
+        return None;
+    }
+    let mean = headways.iter().sum::<f64>() / (headways.len() as f64);
+    if mean == 0.0 {
+        return None;
+    }
+    let variance =
+        headways.iter().map(|h| (h - mean).powi(2)).sum::<f64>() / (headways.len() as f64);
+    Some(variance.sqrt() / mean)
+}
+
 #[derive(Debug)]
 pub struct TripPhase {
     pub start_time: Time,
@@ -703,3 +1097,32 @@ impl SlidingWindow {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headway_coefficient_of_variation() {
+        let t = |minutes: usize| Time::START_OF_DAY + Duration::minutes(minutes);
+
+        // Not enough arrivals
+        assert_eq!(
+            headway_coefficient_of_variation(vec![(0, t(0)), (0, t(10))].into_iter()),
+            None
+        );
+        // Perfectly even spacing at both stops
+        assert_eq!(
+            headway_coefficient_of_variation(
+                vec![(0, t(0)), (1, t(5)), (0, t(10)), (1, t(15)), (0, t(20))].into_iter()
+            ),
+            Some(0.0)
+        );
+        // Two vehicles bunched together, then a long gap. The headways are 2 and 18 minutes, so
+        // the mean is 10 and the standard deviation is 8.
+        let cv =
+            headway_coefficient_of_variation(vec![(0, t(0)), (0, t(2)), (0, t(20))].into_iter())
+                .unwrap();
+        assert!((cv - 0.8).abs() < 1e-9);
+    }
+}
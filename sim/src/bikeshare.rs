@@ -0,0 +1,176 @@
+//! Docked bike-share and e-scooter systems. Somebody walks from their start to a nearby dock with a
+//! vehicle available, rides to a dock near their destination with room to return it, then walks
+//! the rest of the way. If no dock nearby has a vehicle when they set off, they walk the whole way.
+//! If their dock runs out before they get there, they try another one or give up and walk. If the
+//! dock at the end fills up while they're riding, they continue to the closest one with room.
+//!
+//! The map only knows each dock's capacity. Every dock starts the day half full, and the inventory
+//! evolves as people ride around; `Analytics::bike_share_inventory` records it.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Pt2D, Speed};
+use map_model::{BikeShareDockID, Map};
+
+use crate::{CarID, Event, TripID, Vehicle, VehicleSpec, VehicleType, BIKE_LENGTH};
+
+/// People won't walk farther than this, as the crow flies, between a dock and their start or
+/// destination.
+pub(crate) const MAX_WALK_TO_DOCK: Distance = Distance::const_meters(800.0);
+/// Shared bikes and e-scooters all go about 12mph.
+pub(crate) const SHARED_VEHICLE_SPEED: Speed = Speed::const_meters_per_second(5.4);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct BikeShareSimState {
+    /// How many vehicles are at each dock. Docks that nobody has used yet are omitted; they're
+    /// still at their starting inventory.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    inventory: BTreeMap<BikeShareDockID, usize>,
+    /// The shared vehicle each trip is riding
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    riders: BTreeMap<TripID, Vehicle>,
+
+    events: Vec<Event>,
+}
+
+impl BikeShareSimState {
+    pub fn new() -> BikeShareSimState {
+        BikeShareSimState {
+            inventory: BTreeMap::new(),
+            riders: BTreeMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    fn vehicles_at(&self, dock: BikeShareDockID, map: &Map) -> usize {
+        self.inventory
+            .get(&dock)
+            .cloned()
+            .unwrap_or_else(|| map.get_dock(dock).capacity / 2)
+    }
+
+    /// The closest dock to a point that has a vehicle to pick up.
+    pub fn dock_with_vehicle(
+        &self,
+        pt: Pt2D,
+        max_dist: Option<Distance>,
+        exclude: Option<BikeShareDockID>,
+        map: &Map,
+    ) -> Option<BikeShareDockID> {
+        self.closest_dock(pt, max_dist, exclude, map, |count, _| count > 0)
+    }
+
+    /// The closest dock to a point that has room to return a vehicle.
+    pub fn dock_with_room(
+        &self,
+        pt: Pt2D,
+        max_dist: Option<Distance>,
+        exclude: Option<BikeShareDockID>,
+        map: &Map,
+    ) -> Option<BikeShareDockID> {
+        self.closest_dock(pt, max_dist, exclude, map, |count, capacity| {
+            count < capacity
+        })
+    }
+
+    fn closest_dock<F: Fn(usize, usize) -> bool>(
+        &self,
+        pt: Pt2D,
+        max_dist: Option<Distance>,
+        exclude: Option<BikeShareDockID>,
+        map: &Map,
+        usable: F,
+    ) -> Option<BikeShareDockID> {
+        map.all_bike_share_docks()
+            .iter()
+            .filter(|dock| {
+                Some(dock.id) != exclude
+                    && dock.biking_connection.is_some()
+                    && max_dist
+                        .map(|max| dock.point.dist_to(pt) <= max)
+                        .unwrap_or(true)
+                    && usable(self.vehicles_at(dock.id, map), dock.capacity)
+            })
+            .min_by_key(|dock| dock.point.dist_to(pt))
+            .map(|dock| dock.id)
+    }
+
+    /// Somebody reached a dock and tries to take a vehicle. If the dock is empty, returns None.
+    pub fn take_vehicle(
+        &mut self,
+        trip: TripID,
+        dock: BikeShareDockID,
+        id: usize,
+        map: &Map,
+    ) -> Option<Vehicle> {
+        let count = self.vehicles_at(dock, map);
+        if count == 0 {
+            self.events.push(Event::BikeShareDockEmpty(trip, dock));
+            return None;
+        }
+        self.set_inventory(dock, count - 1);
+
+        let vehicle = VehicleSpec {
+            vehicle_type: VehicleType::Bike,
+            length: BIKE_LENGTH,
+            max_speed: Some(SHARED_VEHICLE_SPEED),
+        }
+        .make(
+            CarID {
+                id,
+                vehicle_type: VehicleType::Bike,
+            },
+            None,
+        );
+        self.riders.insert(trip, vehicle.clone());
+        Some(vehicle)
+    }
+
+    /// Somebody reached a dock and tries to return their vehicle. If the dock is full, returns
+    /// false and they keep the vehicle, unless `force` is set.
+    pub fn return_vehicle(
+        &mut self,
+        trip: TripID,
+        dock: BikeShareDockID,
+        force: bool,
+        map: &Map,
+    ) -> bool {
+        let count = self.vehicles_at(dock, map);
+        if count >= map.get_dock(dock).capacity && !force {
+            self.events.push(Event::BikeShareDockFull(trip, dock));
+            return false;
+        }
+        self.set_inventory(dock, count + 1);
+        self.riders.remove(&trip);
+        true
+    }
+
+    fn set_inventory(&mut self, dock: BikeShareDockID, count: usize) {
+        self.inventory.insert(dock, count);
+        self.events.push(Event::BikeShareDockInventory(dock, count));
+    }
+
+    pub fn get_vehicle(&self, trip: TripID) -> Option<&Vehicle> {
+        self.riders.get(&trip)
+    }
+
+    /// If a cancelled trip was riding a shared vehicle, warp it to the dock where it was headed.
+    pub fn cancel_ride(&mut self, trip: TripID, dock: BikeShareDockID, map: &Map) {
+        if self.riders.contains_key(&trip) {
+            self.return_vehicle(trip, dock, true, map);
+        }
+    }
+
+    pub fn collect_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+}
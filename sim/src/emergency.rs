@@ -0,0 +1,77 @@
+//! Emergency vehicles, like ambulances and fire engines, respond to calls. Each one drives from its
+//! station to the incident and vanishes once it arrives. Along the way, other vehicles pull aside
+//! to let it pass, everybody holds back from crossing its path at intersections, and traffic
+//! signals switch to serve it. The time between the call and arriving at the scene is the response
+//! time, recorded separately from normal trips in `Analytics::emergency_responses`.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use geom::Time;
+use map_model::{BuildingID, Map, PathConstraints, PathRequest};
+
+use crate::{CarID, CreateCar, Router, VehicleSpec, VehicleType, MAX_CAR_LENGTH};
+
+/// Somebody calls for help at a building, and a station sends an emergency vehicle.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EmergencyCall {
+    /// When the call comes in. The vehicle leaves the station immediately.
+    pub time: Time,
+    /// Where the emergency vehicle starts
+    pub station: BuildingID,
+    /// Where the emergency vehicle is needed
+    pub incident: BuildingID,
+}
+
+impl EmergencyCall {
+    /// Plan the vehicle's route from the station to the incident.
+    pub(crate) fn make_vehicle(&self, id: usize, map: &Map) -> Result<CreateCar> {
+        let start = map
+            .get_b(self.station)
+            .driving_connection(map)
+            .ok_or_else(|| anyhow!("vehicles can't leave {}", self.station))?
+            .0;
+        let end = map
+            .get_b(self.incident)
+            .driving_connection(map)
+            .ok_or_else(|| anyhow!("vehicles can't stop near {}", self.incident))?
+            .0;
+        if start == end {
+            bail!(
+                "{} is right at the incident {}",
+                self.station,
+                self.incident
+            );
+        }
+        let path = map
+            .pathfind(PathRequest::vehicle(start, end, PathConstraints::Car))
+            .map_err(|err| {
+                anyhow!(
+                    "can't reach {} from {}: {}",
+                    self.incident,
+                    self.station,
+                    err
+                )
+            })?;
+
+        let vehicle = VehicleSpec {
+            vehicle_type: VehicleType::Emergency,
+            length: MAX_CAR_LENGTH,
+            max_speed: None,
+        }
+        .make(
+            CarID {
+                id,
+                vehicle_type: VehicleType::Emergency,
+            },
+            None,
+        );
+        Ok(CreateCar {
+            router: Router::respond_to_emergency(vehicle.id, path, self.incident, self.time),
+            vehicle,
+            maybe_parked_car: None,
+            trip_and_person: None,
+            maybe_route: None,
+        })
+    }
+}
@@ -1,12 +1,15 @@
 use serde::{Deserialize, Serialize};
 
-use geom::Duration;
+use geom::{Distance, Duration};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, Path, PathRequest, Traversable,
-    TurnID,
+    BikeShareDockID, BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, Path,
+    PathRequest, Traversable, TurnID,
 };
 
-use crate::{AgentID, CarID, ParkingSpot, PedestrianID, PersonID, Problem, TripID, TripMode};
+use crate::{
+    AgentID, CarID, CurbUse, ParkingSpot, PedestrianID, PersonID, Problem, RideHailActivity,
+    TripID, TripMode,
+};
 
 /// As a simulation runs, different systems emit Events. This cleanly separates the internal
 /// mechanics of the simulation from consumers that just want to know what's happening.
@@ -21,11 +24,26 @@ pub enum Event {
     CarReachedParkingSpot(CarID, ParkingSpot),
     CarLeftParkingSpot(CarID, ParkingSpot),
 
-    BusArrivedAtStop(CarID, BusRouteID, BusStopID),
-    BusDepartedFromStop(CarID, BusRouteID, BusStopID),
+    /// If the route has a schedule, also includes how late the vehicle is. It's negative if the
+    /// vehicle is early.
+    BusArrivedAtStop(CarID, BusRouteID, BusStopID, Option<Duration>),
+    /// The vehicle waits this long at a stop beyond boarding and alighting, to keep to its schedule
+    /// or to stay evenly spaced behind the previous vehicle.
+    BusHeldAtStop(CarID, BusRouteID, BusStopID, Duration),
+    /// Also includes how many passengers are on board
+    BusDepartedFromStop(CarID, BusRouteID, BusStopID, usize),
     /// How long waiting at the stop?
     PassengerBoardsTransit(PersonID, CarID, BusRouteID, BusStopID, Duration),
     PassengerAlightsTransit(PersonID, CarID, BusRouteID, BusStopID),
+    /// A full transit vehicle left somebody waiting at the stop
+    PassengerDeniedBoarding(PedestrianID, CarID, BusRouteID, BusStopID),
+
+    /// A ride-hail vehicle starts doing something different
+    RideHailActivity(CarID, RideHailActivity),
+    /// A ride-hail vehicle finished driving somewhere
+    RideHailDrove(CarID, RideHailActivity, Distance),
+    /// How long did the passenger wait for the vehicle to arrive?
+    RideHailPickup(TripID, CarID, Duration),
 
     PersonEntersBuilding(PersonID, BuildingID),
     PersonLeavesBuilding(PersonID, BuildingID),
@@ -44,6 +62,34 @@ pub enum Event {
     AgentEntersTraversable(AgentID, Option<TripID>, Traversable, Option<usize>),
     /// TripID, TurnID (Where the delay was encountered), Time spent waiting at that turn
     IntersectionDelayMeasured(TripID, TurnID, AgentID, Duration),
+    /// A traffic signal changed when its current stage ends for an approaching transit vehicle.
+    /// Positive means the stage was extended, negative means it ended early.
+    TransitSignalPriority(IntersectionID, CarID, Duration),
+    /// A traffic signal switched stages early for an approaching emergency vehicle.
+    EmergencySignalPreemption(IntersectionID, CarID),
+    /// An emergency vehicle reached the incident it was called to. The duration is the response
+    /// time, measured from the call.
+    EmergencyVehicleArrived(CarID, BuildingID, Duration),
+    /// A freight vehicle stopped to make a delivery at a building, occupying the curb or a lane
+    /// for some time.
+    Delivery(CarID, BuildingID, CurbUse, Duration),
+    /// A freight vehicle returned to its depot, after some time out on its tour.
+    DeliveryTourFinished(CarID, Duration),
+    /// The number of vehicles docked at a bike-share station changed.
+    BikeShareDockInventory(BikeShareDockID, usize),
+    /// Somebody reached a bike-share dock to pick up a vehicle, but there weren't any left.
+    BikeShareDockEmpty(TripID, BikeShareDockID),
+    /// Somebody reached a bike-share dock to return their vehicle, but it was full.
+    BikeShareDockFull(TripID, BikeShareDockID),
+    /// A driver started parking, or gave up because there's no parking anywhere nearby. If they
+    /// had to circle beyond their destination, this measures how long and how far.
+    ParkingSearchFinished {
+        car: CarID,
+        trip: Option<TripID>,
+        time_searching: Duration,
+        cruising_distance: Distance,
+        found_spot: bool,
+    },
 
     TripFinished {
         trip: TripID,
@@ -57,6 +103,11 @@ pub enum Event {
     /// Just use for parking replanning. Not happy about copying the full path in here, but the way
     /// to plumb info into Analytics is Event.
     PathAmended(Path),
+    /// An informed driver switched to a different route to avoid congestion. Like PathAmended,
+    /// the full new path is included.
+    PathRerouted(CarID, Option<TripID>, RerouteReason, Path),
+    /// A vehicle got around somebody slower in the middle of a lane.
+    Overtook(CarID, Option<TripID>, OvertakeManeuver),
 
     Alert(AlertLocation, String),
 }
@@ -69,6 +120,29 @@ pub enum AlertLocation {
     Building(BuildingID),
 }
 
+/// Why did a driver reconsider their route?
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum RerouteReason {
+    /// They've been stuck waiting to turn for this long
+    Blocked(Duration),
+    /// They regularly check for a faster route
+    Periodic,
+}
+
+/// How did a vehicle get around somebody slower in front of it?
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum OvertakeManeuver {
+    /// They changed to an adjacent lane going the same direction, and stayed there
+    ChangedLanes { from: LaneID, to: LaneID },
+    /// They briefly used an oncoming lane to get in front of the slower vehicle, then returned to
+    /// their original lane
+    UsedOncomingLane {
+        lane: LaneID,
+        oncoming: LaneID,
+        passed: CarID,
+    },
+}
+
 #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum TripPhaseType {
     Driving,
@@ -78,6 +152,9 @@ pub enum TripPhaseType {
     WaitingForBus(BusRouteID, BusStopID),
     /// What stop did they board at?
     RidingBus(BusRouteID, BusStopID, CarID),
+    WaitingForRide,
+    /// Riding as a passenger in somebody else's car
+    RidingInCar(CarID),
     Cancelled,
     Finished,
     DelayedStart,
@@ -94,6 +171,8 @@ impl TripPhaseType {
                 format!("Waiting for bus {}", map.get_br(r).full_name)
             }
             TripPhaseType::RidingBus(r, _, _) => format!("Riding bus {}", map.get_br(r).full_name),
+            TripPhaseType::WaitingForRide => "Waiting to get picked up".to_string(),
+            TripPhaseType::RidingInCar(car) => format!("Riding in {}", car),
             TripPhaseType::Cancelled => "Trip was cancelled due to some bug".to_string(),
             TripPhaseType::Finished => "Trip finished".to_string(),
             TripPhaseType::DelayedStart => "Delayed by a previous trip taking too long".to_string(),
@@ -0,0 +1,307 @@
+//! Freight vehicles, like delivery vans and trucks, make tours from a depot. Each one drives to a
+//! sequence of buildings, stops at each for a while to load or unload, then returns to its depot
+//! and vanishes.
+//!
+//! At each stop, the vehicle pulls into a loading zone on the same side of the road if there's
+//! room, getting out of the way of traffic. Otherwise it double-parks, blocking the driving lane
+//! until it leaves. `Analytics::deliveries` records which one happened, for evaluating curbside
+//! management.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, Speed, Time};
+use map_model::{BuildingID, LaneID, Map, PathConstraints, PathRequest, Position};
+
+use crate::sim::Ctx;
+use crate::{
+    CarID, Command, CreateCar, Event, Router, Vehicle, VehicleSpec, VehicleType, TRUCK_LENGTH,
+    VAN_LENGTH,
+};
+
+/// A freight vehicle leaves a depot, makes deliveries to some buildings, and returns.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryTour {
+    /// When the vehicle leaves the depot
+    pub depart: Time,
+    pub depot: BuildingID,
+    /// Visited in order. Stops the vehicle can't reach are skipped.
+    pub stops: Vec<BuildingID>,
+    /// Must be a van or a truck
+    pub vehicle_type: VehicleType,
+    /// How long the vehicle spends loading or unloading at each stop
+    pub dwell_time: Duration,
+}
+
+/// Where a freight vehicle stopped to make a delivery
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum CurbUse {
+    /// Pulled into a loading zone along this parking lane, out of the way of traffic
+    LoadingZone(LaneID),
+    /// Stopped in this driving lane, blocking it
+    DoubleParked(LaneID),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct FreightSimState {
+    /// Every tour ever started, for regenerating the scenario
+    tours: Vec<DeliveryTour>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    vehicles: BTreeMap<CarID, TourProgress>,
+    /// Per parking lane, the vehicles occupying a loading zone, how much of the curb they take,
+    /// and when they leave
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    loading_zones: BTreeMap<LaneID, Vec<(CarID, Distance, Time)>>,
+
+    events: Vec<Event>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct TourProgress {
+    /// Index into `tours`
+    tour: usize,
+    vehicle: Vehicle,
+    /// Index into the tour's stops of where the vehicle is headed. Once this reaches the end, the
+    /// vehicle is headed back to its depot.
+    next_stop: usize,
+}
+
+impl FreightSimState {
+    pub fn new() -> FreightSimState {
+        FreightSimState {
+            tours: Vec::new(),
+            vehicles: BTreeMap::new(),
+            loading_zones: BTreeMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Register a new tour. Returns the vehicle and where it starts. The caller should call
+    /// `plan_next_leg` when the tour departs.
+    pub fn start_tour(
+        &mut self,
+        id: usize,
+        tour: DeliveryTour,
+        map: &Map,
+    ) -> Result<(CarID, Position)> {
+        let (length, max_speed) = match tour.vehicle_type {
+            VehicleType::Van => (VAN_LENGTH, Some(Speed::miles_per_hour(65.0))),
+            VehicleType::Truck => (TRUCK_LENGTH, Some(Speed::miles_per_hour(55.0))),
+            x => bail!("a {} can't make deliveries", x),
+        };
+        if tour.stops.is_empty() {
+            bail!("a delivery tour from {} has no stops", tour.depot);
+        }
+        let start = driving_pos(tour.depot, map)
+            .ok_or_else(|| anyhow!("vehicles can't leave {}", tour.depot))?;
+
+        let vehicle = VehicleSpec {
+            vehicle_type: tour.vehicle_type,
+            length,
+            max_speed,
+        }
+        .make(
+            CarID {
+                id,
+                vehicle_type: tour.vehicle_type,
+            },
+            None,
+        );
+        let car = vehicle.id;
+        self.vehicles.insert(
+            car,
+            TourProgress {
+                tour: self.tours.len(),
+                vehicle,
+                next_stop: 0,
+            },
+        );
+        self.tours.push(tour);
+        Ok((car, start))
+    }
+
+    /// The vehicle is ready to leave its depot or a loading zone. Plan the route to the next stop
+    /// with current traffic, returning the vehicle to spawn. If there's no way to go anywhere, the
+    /// tour is abandoned.
+    pub fn plan_next_leg(
+        &mut self,
+        now: Time,
+        car: CarID,
+        start: Position,
+        map: &Map,
+    ) -> Option<CreateCar> {
+        match self.route_to_next_stop(car, start, now, map) {
+            Some(router) => Some(CreateCar {
+                vehicle: self.vehicles[&car].vehicle.clone(),
+                router,
+                maybe_parked_car: None,
+                trip_and_person: None,
+                maybe_route: None,
+            }),
+            None => {
+                self.abandon_tour(car);
+                None
+            }
+        }
+    }
+
+    /// A freight vehicle reached the end of its route. If it should double-park, returns how long
+    /// to stay in the lane. Otherwise the vehicle leaves the road, either into a loading zone
+    /// (reappearing after its dwell time) or back into its depot.
+    pub fn vehicle_reached_stop(
+        &mut self,
+        now: Time,
+        car: CarID,
+        pos: Position,
+        ctx: &mut Ctx,
+    ) -> Option<Duration> {
+        let progress = self.vehicles.get_mut(&car).unwrap();
+        let tour = &self.tours[progress.tour];
+        if progress.next_stop == tour.stops.len() {
+            self.events
+                .push(Event::DeliveryTourFinished(car, now - tour.depart));
+            self.vehicles.remove(&car);
+            return None;
+        }
+        let b = tour.stops[progress.next_stop];
+        let dwell_time = tour.dwell_time;
+        progress.next_stop += 1;
+        let length = progress.vehicle.length;
+
+        let parking_lane = match self.free_loading_zone(now, pos.lane(), length, ctx.map) {
+            Some(l) => l,
+            None => {
+                self.events.push(Event::Delivery(
+                    car,
+                    b,
+                    CurbUse::DoubleParked(pos.lane()),
+                    dwell_time,
+                ));
+                return Some(dwell_time);
+            }
+        };
+        self.events.push(Event::Delivery(
+            car,
+            b,
+            CurbUse::LoadingZone(parking_lane),
+            dwell_time,
+        ));
+        self.loading_zones
+            .entry(parking_lane)
+            .or_insert_with(Vec::new)
+            .push((car, length, now + dwell_time));
+
+        // Reappear in the driving lane once the delivery is done
+        ctx.scheduler
+            .push(now + dwell_time, Command::ContinueDeliveryTour(car, pos));
+        None
+    }
+
+    /// A double-parked freight vehicle is done with its delivery. Returns the route to the next
+    /// stop or back to the depot. If there's no way to go anywhere, the vehicle should vanish.
+    pub fn vehicle_left_stop(
+        &mut self,
+        now: Time,
+        car: CarID,
+        start: Position,
+        map: &Map,
+    ) -> Option<Router> {
+        let router = self.route_to_next_stop(car, start, now, map);
+        if router.is_none() {
+            self.abandon_tour(car);
+        }
+        router
+    }
+
+    /// Returns a route to the next stop the vehicle can reach, skipping any unreachable ones. After
+    /// the last stop, heads back to the depot. None if even that fails.
+    fn route_to_next_stop(
+        &mut self,
+        car: CarID,
+        start: Position,
+        now: Time,
+        map: &Map,
+    ) -> Option<Router> {
+        let progress = self.vehicles.get_mut(&car).unwrap();
+        let tour = &self.tours[progress.tour];
+        loop {
+            let (b, headed_home) = match tour.stops.get(progress.next_stop) {
+                Some(b) => (*b, false),
+                None => (tour.depot, true),
+            };
+            let path = driving_pos(b, map).and_then(|end| {
+                let req = PathRequest::vehicle(start, end, PathConstraints::Car);
+                if car.vehicle_type == VehicleType::Truck {
+                    map.pathfind_for_truck(req, now).ok()
+                } else {
+                    map.pathfind_at(req, now).ok()
+                }
+            });
+            if let Some(path) = path {
+                return Some(Router::stop_for_delivery(car, path));
+            }
+            if headed_home {
+                return None;
+            }
+            warn!(
+                "{} can't reach its delivery stop at {}; skipping it",
+                car, b
+            );
+            progress.next_stop += 1;
+        }
+    }
+
+    pub fn abandon_tour(&mut self, car: CarID) {
+        warn!("{} can't finish its delivery tour", car);
+        self.vehicles.remove(&car);
+    }
+
+    /// Find a loading zone next to the driving lane with enough free curb for a vehicle.
+    fn free_loading_zone(
+        &mut self,
+        now: Time,
+        driving_lane: LaneID,
+        length: Distance,
+        map: &Map,
+    ) -> Option<LaneID> {
+        let road = map.get_parent(driving_lane);
+        for lane in &road.lanes {
+            let l = lane.id;
+            if !map.is_loading_zone(l) || road.parking_to_driving(l) != Some(driving_lane) {
+                continue;
+            }
+            let capacity = map.get_config().street_parking_spot_length
+                * (lane.number_parking_spots(map.get_config()) as f64);
+            let occupants = self.loading_zones.entry(l).or_insert_with(Vec::new);
+            occupants.retain(|(_, _, until)| *until > now);
+            let used: Distance = occupants
+                .iter()
+                .fold(Distance::ZERO, |sum, (_, len, _)| sum + *len);
+            if used + length <= capacity {
+                return Some(l);
+            }
+        }
+        None
+    }
+
+    pub fn collect_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn tours(&self) -> Vec<DeliveryTour> {
+        self.tours.clone()
+    }
+}
+
+fn driving_pos(b: BuildingID, map: &Map) -> Option<Position> {
+    map.get_b(b).driving_connection(map).map(|(pos, _)| pos)
+}
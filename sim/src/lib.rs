@@ -6,8 +6,10 @@
 //! glue together individual goals executed by the agents.
 //!
 //! Helpful terminology:
-//! - sov = single occupancy vehicle, a car with just a driver and no passengers. (Car passengers
-//!   are not currently modelled)
+//! - sov = single occupancy vehicle, a car with just a driver and no passengers
+//! - carpool = a passenger riding in somebody else's car. The driver does a normal driving trip,
+//!   detouring to pick up and drop off passengers along the way.
+//! - deadheading = a ride-hail vehicle driving empty to pick somebody up
 
 // Disable some noisy clippy warnings
 #![allow(clippy::type_complexity, clippy::too_many_arguments)]
@@ -24,8 +26,8 @@ use serde::{Deserialize, Serialize};
 use abstutil::{deserialize_usize, serialize_usize};
 use geom::{Distance, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, ParkingLotID, Path,
-    PathConstraints, Position,
+    BikeShareDockID, BuildingID, BusRoute, BusRouteID, BusStopID, IntersectionID, LaneID, Map,
+    ParkingLotID, ParkingPolicy, Path, PathConstraints, Position,
 };
 
 pub use crate::render::{
@@ -34,12 +36,17 @@ pub use crate::render::{
 };
 
 pub use self::analytics::{Analytics, Problem, SlidingWindow, TripPhase};
+pub(crate) use self::bikeshare::{BikeShareSimState, MAX_WALK_TO_DOCK, SHARED_VEHICLE_SPEED};
+pub use self::emergency::EmergencyCall;
 pub(crate) use self::events::Event;
-pub use self::events::{AlertLocation, TripPhaseType};
+pub use self::events::{AlertLocation, OvertakeManeuver, RerouteReason, TripPhaseType};
+pub(crate) use self::freight::FreightSimState;
+pub use self::freight::{CurbUse, DeliveryTour};
 pub use self::make::{
-    fork_rng, BorderSpawnOverTime, ExternalPerson, ExternalTrip, ExternalTripEndpoint, IndividTrip,
-    MapBorders, PersonSpec, Scenario, ScenarioGenerator, ScenarioModifier, SimFlags, SpawnOverTime,
-    TripEndpoint, TripPurpose,
+    fork_rng, AssignmentIteration, BorderSpawnOverTime, DynamicTrafficAssignment, ExternalPerson,
+    ExternalTrip, ExternalTripEndpoint, IndividTrip, MapBorders, PersonSpec, Scenario,
+    ScenarioGenerator, ScenarioModifier, SharedRide, SimFlags, SpawnOverTime, TripEndpoint,
+    TripPurpose,
 };
 pub(crate) use self::make::{StartTripArgs, TripSpec};
 pub(crate) use self::mechanics::{
@@ -47,6 +54,8 @@ pub(crate) use self::mechanics::{
 };
 pub(crate) use self::pandemic::PandemicModel;
 pub(crate) use self::recorder::TrafficRecorder;
+pub use self::ridehail::{DispatchPolicy, RideHailActivity, RideHailFleet};
+pub(crate) use self::ridehail::{RideHailSimState, RideHailStop};
 pub(crate) use self::router::{ActionAtEnd, Router};
 pub(crate) use self::scheduler::{Command, Scheduler};
 pub use self::sim::{AgentProperties, AlertHandler, DelayCause, Sim, SimCallback, SimOptions};
@@ -56,12 +65,16 @@ pub use self::trips::{CommutersVehiclesCounts, Person, PersonState, TripInfo, Tr
 pub(crate) use self::trips::{TripLeg, TripManager};
 
 mod analytics;
+mod bikeshare;
+mod emergency;
 mod events;
+mod freight;
 mod make;
 mod mechanics;
 mod pandemic;
 mod recorder;
 mod render;
+mod ridehail;
 mod router;
 mod scheduler;
 mod sim;
@@ -74,6 +87,8 @@ pub(crate) const MIN_CAR_LENGTH: Distance = Distance::const_meters(4.5);
 pub(crate) const MAX_CAR_LENGTH: Distance = Distance::const_meters(6.5);
 // Note this is more than MAX_CAR_LENGTH
 pub(crate) const BUS_LENGTH: Distance = Distance::const_meters(12.5);
+pub(crate) const VAN_LENGTH: Distance = Distance::const_meters(6.0);
+pub(crate) const TRUCK_LENGTH: Distance = Distance::const_meters(10.0);
 pub(crate) const LIGHT_RAIL_LENGTH: Distance = Distance::const_meters(60.0);
 
 /// At all speeds (including at rest), cars must be at least this far apart, measured from front of
@@ -104,6 +119,9 @@ impl fmt::Display for CarID {
             VehicleType::Bus => write!(f, "Bus #{}", self.id),
             VehicleType::Train => write!(f, "Train #{}", self.id),
             VehicleType::Bike => write!(f, "Bike #{}", self.id),
+            VehicleType::Emergency => write!(f, "Emergency vehicle #{}", self.id),
+            VehicleType::Van => write!(f, "Van #{}", self.id),
+            VehicleType::Truck => write!(f, "Truck #{}", self.id),
         }
     }
 }
@@ -146,6 +164,9 @@ impl AgentID {
                 VehicleType::Bike => AgentType::Bike,
                 VehicleType::Bus => AgentType::Bus,
                 VehicleType::Train => AgentType::Train,
+                VehicleType::Emergency => AgentType::Car,
+                VehicleType::Van => AgentType::Car,
+                VehicleType::Truck => AgentType::Car,
             },
             AgentID::Pedestrian(_) => AgentType::Pedestrian,
             AgentID::BusPassenger(_, _) => AgentType::TransitRider,
@@ -287,6 +308,12 @@ pub enum VehicleType {
     Bus,
     Train,
     Bike,
+    /// Ambulances, fire engines, and police responding to a call. Other vehicles yield to them.
+    Emergency,
+    /// Delivery vans can go anywhere cars can.
+    Van,
+    /// Heavy goods vehicles avoid roads that restrict them, unless they're delivering there.
+    Truck,
 }
 
 impl fmt::Display for VehicleType {
@@ -296,6 +323,9 @@ impl fmt::Display for VehicleType {
             VehicleType::Bus => write!(f, "bus"),
             VehicleType::Train => write!(f, "train"),
             VehicleType::Bike => write!(f, "bike"),
+            VehicleType::Emergency => write!(f, "emergency vehicle"),
+            VehicleType::Van => write!(f, "van"),
+            VehicleType::Truck => write!(f, "truck"),
         }
     }
 }
@@ -307,17 +337,63 @@ impl VehicleType {
             VehicleType::Bus => PathConstraints::Bus,
             VehicleType::Train => PathConstraints::Train,
             VehicleType::Bike => PathConstraints::Bike,
+            VehicleType::Emergency => PathConstraints::Car,
+            VehicleType::Van => PathConstraints::Car,
+            VehicleType::Truck => PathConstraints::Car,
         }
     }
 
+    /// How quickly does this type of vehicle comfortably speed up and brake, in meters per second
+    /// squared? Only used when `SimOptions::use_acceleration` is on.
+    pub fn acceleration_limits(self) -> (f64, f64) {
+        match self {
+            VehicleType::Car => (2.0, 3.0),
+            VehicleType::Bus => (1.0, 2.0),
+            VehicleType::Train => (0.8, 1.0),
+            VehicleType::Bike => (1.0, 2.0),
+            VehicleType::Emergency => (2.5, 4.0),
+            VehicleType::Van => (1.5, 3.0),
+            VehicleType::Truck => (0.8, 2.0),
+        }
+    }
+
+    /// How many passengers can this type of vehicle carry, as (seated, standing)? Only transit
+    /// vehicles pick up passengers.
+    pub fn passenger_capacity(self) -> (usize, usize) {
+        match self {
+            VehicleType::Car => (0, 0),
+            VehicleType::Bus => (40, 30),
+            VehicleType::Train => (120, 180),
+            VehicleType::Bike => (0, 0),
+            VehicleType::Emergency => (0, 0),
+            VehicleType::Van => (0, 0),
+            VehicleType::Truck => (0, 0),
+        }
+    }
+
+    /// How many passengers can this type of vehicle carry while serving a route, as (seated,
+    /// standing)? Routes may override the default for the vehicle type.
+    pub fn passenger_capacity_on(self, route: &BusRoute) -> (usize, usize) {
+        route
+            .passenger_capacity
+            .unwrap_or_else(|| self.passenger_capacity())
+    }
+
     pub(crate) fn is_transit(self) -> bool {
         match self {
             VehicleType::Car => false,
             VehicleType::Bus => true,
             VehicleType::Train => true,
             VehicleType::Bike => false,
+            VehicleType::Emergency => false,
+            VehicleType::Van => false,
+            VehicleType::Truck => false,
         }
     }
+
+    pub(crate) fn is_freight(self) -> bool {
+        matches!(self, VehicleType::Van | VehicleType::Truck)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -358,6 +434,31 @@ pub enum ParkingSpot {
     Lot(ParkingLotID, usize),
 }
 
+impl ParkingSpot {
+    /// The prices and time limits governing this spot, if any. Spots inside buildings are private
+    /// and always free.
+    pub fn policy<'a>(&self, map: &'a Map) -> Option<&'a ParkingPolicy> {
+        match self {
+            ParkingSpot::Onstreet(l, _) => map.get_parking_policy_for_lane(*l),
+            ParkingSpot::Offstreet(_, _) => None,
+            ParkingSpot::Lot(pl, _) => map.get_parking_policy_for_lot(*pl),
+        }
+    }
+
+    /// The hourly rate for parking here at some time
+    pub fn price_per_hour(&self, time: Time, map: &Map) -> f64 {
+        self.policy(map)
+            .map(|policy| policy.price_per_hour_at(time))
+            .unwrap_or(0.0)
+    }
+
+    /// When choosing between parking spots, drivers would walk this much farther to save a dollar
+    /// per hour. Expresses the price of this spot as extra distance, to trade off against walking.
+    pub(crate) fn price_as_distance(&self, time: Time, map: &Map) -> Distance {
+        Distance::meters(100.0) * self.price_per_hour(time, map)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct ParkedCar {
     pub vehicle: Vehicle,
@@ -467,6 +568,14 @@ impl SidewalkSpot {
         })
     }
 
+    pub fn bike_share_dock(dock: BikeShareDockID, map: &Map) -> Option<SidewalkSpot> {
+        let (bike_pos, sidewalk_pos) = map.get_dock(dock).biking_connection?;
+        Some(SidewalkSpot {
+            connection: SidewalkPOI::BikeRack(bike_pos),
+            sidewalk_pos,
+        })
+    }
+
     pub fn bus_stop(stop: BusStopID, map: &Map) -> SidewalkSpot {
         SidewalkSpot {
             sidewalk_pos: map.get_bs(stop).sidewalk_pos,
@@ -611,7 +720,8 @@ pub(crate) struct CreateCar {
     pub maybe_parked_car: Option<ParkedCar>,
     /// None for buses
     pub trip_and_person: Option<(TripID, PersonID)>,
-    pub maybe_route: Option<BusRouteID>,
+    /// For transit vehicles, the route and the time they were scheduled to start
+    pub maybe_route: Option<(BusRouteID, Time)>,
 }
 
 impl CreateCar {
@@ -187,9 +187,16 @@ impl MapBorders {
         &Vec<(IntersectionID, LonLat)>,
     ) {
         match mode {
-            TripMode::Walk | TripMode::Transit => (&self.incoming_walking, &self.outgoing_walking),
-            TripMode::Drive => (&self.incoming_driving, &self.outgoing_driving),
+            // Passengers walk to and from their ride
+            TripMode::Walk | TripMode::Transit | TripMode::Carpool => {
+                (&self.incoming_walking, &self.outgoing_walking)
+            }
+            TripMode::Drive | TripMode::RideHail => {
+                (&self.incoming_driving, &self.outgoing_driving)
+            }
             TripMode::Bike => (&self.incoming_biking, &self.outgoing_biking),
+            // Bike-share docks are inside the map
+            TripMode::BikeShare => (&self.incoming_walking, &self.outgoing_walking),
         }
     }
 }
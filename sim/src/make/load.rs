@@ -6,7 +6,7 @@ use structopt::StructOpt;
 use abstio::MapName;
 use map_model::{Map, MapEdits};
 
-use crate::{Scenario, ScenarioModifier, Sim, SimOptions};
+use crate::{Analytics, Scenario, ScenarioModifier, Sim, SimOptions};
 
 /// SimFlags specifies a simulation to setup. After parsing from structopt, you must call
 /// `initialize`.
@@ -31,6 +31,12 @@ pub struct SimFlags {
     // TODO default_value can only handle strings, so copying SimFlags::RNG_SEED
     #[structopt(long, default_value = "42")]
     pub rng_seed: u64,
+    /// The path to Analytics saved from a previous run with `--record-travel-times`. If specified,
+    /// drivers avoid roads that were congested in that run at the time they'd reach them, instead
+    /// of routing using just speed limits. The travel times aren't saved with the map or
+    /// savestates, so pass this again whenever loading them.
+    #[structopt(long)]
+    pub travel_times_from: Option<String>,
     #[structopt(flatten)]
     pub opts: SimOptions,
 }
@@ -60,6 +66,7 @@ impl SimFlags {
             load: MapName::seattle("montlake").path(),
             scenario_modifiers: Vec::new(),
             rng_seed: SimFlags::RNG_SEED,
+            travel_times_from: None,
             opts: SimOptions::new(run_name),
         }
     }
@@ -97,6 +104,7 @@ impl SimFlags {
                     panic!("Couldn't load edits \"{}\": {}", sim.edits_name, err);
                 }
             }
+            self.load_travel_times(&mut map, timer);
 
             (map, sim, rng)
         } else if self.load.contains("/scenarios/") {
@@ -104,7 +112,8 @@ impl SimFlags {
 
             let mut scenario: Scenario = abstio::must_read_object(self.load.clone(), timer);
 
-            let map = Map::load_synchronously(scenario.map_name.path(), timer);
+            let mut map = Map::load_synchronously(scenario.map_name.path(), timer);
+            self.load_travel_times(&mut map, timer);
 
             for m in &self.scenario_modifiers {
                 scenario = m.apply(&map, scenario);
@@ -120,7 +129,8 @@ impl SimFlags {
         } else if self.load.contains("/raw_maps/") || self.load.contains("/maps/") {
             info!("Loading map {}", self.load);
 
-            let map = Map::load_synchronously(self.load.clone(), timer);
+            let mut map = Map::load_synchronously(self.load.clone(), timer);
+            self.load_travel_times(&mut map, timer);
 
             timer.start("create sim");
             let sim = Sim::new(&map, opts);
@@ -131,4 +141,18 @@ impl SimFlags {
             panic!("Don't know how to load {}", self.load);
         }
     }
+
+    fn load_travel_times(&self, map: &mut Map, timer: &mut abstutil::Timer) {
+        if let Some(ref path) = self.travel_times_from {
+            info!("Routing with travel times observed in {}", path);
+            let analytics: Analytics = abstio::must_read_object(path.clone(), timer);
+            let profile = analytics.road_travel_times.unwrap_or_else(|| {
+                panic!(
+                    "{} has no travel times; record them with --record-travel-times",
+                    path
+                )
+            });
+            map.set_travel_time_profile(Some(profile));
+        }
+    }
 }
@@ -8,9 +8,10 @@ pub use self::external::{ExternalPerson, ExternalTrip, ExternalTripEndpoint, Map
 pub use self::generator::{BorderSpawnOverTime, ScenarioGenerator, SpawnOverTime};
 pub use self::load::SimFlags;
 pub use self::modifier::ScenarioModifier;
-pub use self::scenario::{IndividTrip, PersonSpec, Scenario, TripPurpose};
+pub use self::scenario::{IndividTrip, PersonSpec, Scenario, SharedRide, TripPurpose};
 pub use self::spawner::TripEndpoint;
 pub(crate) use self::spawner::{StartTripArgs, TripSpec};
+pub use self::traffic_assignment::{AssignmentIteration, DynamicTrafficAssignment};
 
 mod activity_model;
 mod external;
@@ -19,6 +20,7 @@ mod load;
 mod modifier;
 mod scenario;
 mod spawner;
+mod traffic_assignment;
 
 /// Need to explain this trick -- basically keeps consistency between two different simulations when
 /// each one might make slightly different sequences of calls to the RNG.
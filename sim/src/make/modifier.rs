@@ -6,7 +6,7 @@ use abstutil::Timer;
 use geom::{Duration, Time};
 use map_model::Map;
 
-use crate::{Scenario, TripMode};
+use crate::{Scenario, TripEndpoint, TripMode};
 
 /// Transforms an existing Scenario before instantiating it.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
@@ -21,6 +21,12 @@ pub enum ScenarioModifier {
     },
     /// Scenario name
     AddExtraTrips(String),
+    /// People who'd pay more than this in tolls to drive (using the route that best avoids them)
+    /// switch modes instead. If `to_mode` is `None`, they cancel the trip.
+    AvoidTolls {
+        max_toll_cents: usize,
+        to_mode: Option<TripMode>,
+    },
 }
 
 impl ScenarioModifier {
@@ -76,14 +82,58 @@ impl ScenarioModifier {
                     abstio::path_scenario(map.get_name(), name),
                     &mut Timer::throwaway(),
                 );
+                // Shared rides refer to drivers by index
+                let offset = s.people.len();
                 for mut p in other.people {
                     for trip in &mut p.trips {
                         trip.modified = true;
+                        if let Some(ref mut ride) = trip.shared_ride {
+                            ride.driver += offset;
+                        }
                     }
                     s.people.push(p);
                 }
                 s
             }
+            ScenarioModifier::AvoidTolls {
+                max_toll_cents,
+                to_mode,
+            } => {
+                let max_toll = (*max_toll_cents as f64) / 100.0;
+                for person in &mut s.people {
+                    let mut cancel_rest = false;
+                    for trip in &mut person.trips {
+                        if cancel_rest {
+                            trip.modified = true;
+                            trip.cancelled = true;
+                            continue;
+                        }
+                        if trip.mode != TripMode::Drive {
+                            continue;
+                        }
+                        let toll = TripEndpoint::path_req(
+                            trip.origin,
+                            trip.destination,
+                            TripMode::Drive,
+                            map,
+                        )
+                        .and_then(|req| map.pathfind_at(req, trip.depart).ok())
+                        .map(|path| map.tolls_along_path(&path, trip.depart))
+                        .unwrap_or(0.0);
+                        if toll <= max_toll {
+                            continue;
+                        }
+                        trip.modified = true;
+                        if let Some(to_mode) = *to_mode {
+                            trip.mode = to_mode;
+                        } else {
+                            trip.cancelled = true;
+                            cancel_rest = true;
+                        }
+                    }
+                }
+                s
+            }
         }
     }
 
@@ -105,6 +155,14 @@ impl ScenarioModifier {
                 to_mode.map(|m| m.verb())
             ),
             ScenarioModifier::AddExtraTrips(name) => format!("Add extra trips from {}", name),
+            ScenarioModifier::AvoidTolls {
+                max_toll_cents,
+                to_mode,
+            } => format!(
+                "people who'd pay more than ${:.2} in tolls to drive {:?} instead",
+                (*max_toll_cents as f64) / 100.0,
+                to_mode.map(|m| m.verb())
+            ),
         }
     }
 }
@@ -119,14 +177,21 @@ impl ScenarioModifier {
 // needing LOTS of cars.
 fn repeat_days(mut s: Scenario, days: usize) -> Scenario {
     s.scenario_name = format!("{} (repeated {} days)", s.scenario_name, days);
+    // Passengers share a ride with the driver's trip from the same day
+    let trips_per_person: Vec<usize> = s.people.iter().map(|p| p.trips.len()).collect();
     for person in &mut s.people {
         let mut trips = Vec::new();
         let mut offset = Duration::ZERO;
-        for _ in 0..days {
+        for day in 0..days {
             for trip in &person.trips {
                 let mut new = trip.clone();
                 new.depart += offset;
                 new.modified = true;
+                if let Some(ref mut ride) = new.shared_ride {
+                    if let Some(n) = trips_per_person.get(ride.driver) {
+                        ride.driver_trip += day * n;
+                    }
+                }
                 trips.push(new);
             }
             offset += Duration::hours(24);
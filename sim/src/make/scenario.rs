@@ -10,12 +10,13 @@ use serde::{Deserialize, Serialize};
 use abstio::MapName;
 use abstutil::{prettyprint_usize, Counter, Timer};
 use geom::{Distance, Speed, Time};
-use map_model::{BuildingID, Map, OffstreetParking, RoadID};
+use map_model::{BuildingID, DirectedRoadID, Map, OffstreetParking, RoadID};
 
 use crate::make::fork_rng;
 use crate::{
-    OrigPersonID, ParkingSpot, Sim, StartTripArgs, TripEndpoint, TripInfo, TripMode, Vehicle,
-    VehicleSpec, VehicleType, BIKE_LENGTH, MAX_CAR_LENGTH, MIN_CAR_LENGTH,
+    DeliveryTour, EmergencyCall, OrigPersonID, ParkingSpot, RideHailFleet, Sim, StartTripArgs,
+    TripEndpoint, TripInfo, TripMode, Vehicle, VehicleSpec, VehicleType, BIKE_LENGTH,
+    MAX_CAR_LENGTH, MIN_CAR_LENGTH,
 };
 
 /// A Scenario describes all the input to a simulation. Usually a scenario covers one day.
@@ -27,6 +28,16 @@ pub struct Scenario {
     pub people: Vec<PersonSpec>,
     /// None means seed all buses. Otherwise the route name must be present here.
     pub only_seed_buses: Option<BTreeSet<String>>,
+    /// Every `TripMode::RideHail` trip is served by this fleet. If there's no fleet, those trips
+    /// get cancelled.
+    #[serde(default)]
+    pub ride_hail: Option<RideHailFleet>,
+    /// Each call sends an emergency vehicle from a station to an incident.
+    #[serde(default)]
+    pub emergency_calls: Vec<EmergencyCall>,
+    /// Vans and trucks making deliveries
+    #[serde(default)]
+    pub deliveries: Vec<DeliveryTour>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -49,6 +60,30 @@ pub struct IndividTrip {
     pub cancelled: bool,
     /// Did a ScenarioModifier affect this?
     pub modified: bool,
+    /// Only used for `TripMode::Carpool`; who's giving this person a ride?
+    #[serde(default)]
+    pub shared_ride: Option<SharedRide>,
+    /// Only used for `TripMode::Drive`. If set, drive along these roads instead of pathfinding,
+    /// as long as they connect the start and end of the trip. Usually these come from a
+    /// `DynamicTrafficAssignment`.
+    #[serde(default)]
+    pub route: Option<Vec<DirectedRoadID>>,
+}
+
+/// A passenger shares one car with a driver and maybe other passengers. The driver does a normal
+/// `TripMode::Drive` trip, stopping to pick up every passenger (in the order they're listed in the
+/// scenario), then stopping to drop them off (in the same order), before continuing to their own
+/// destination.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct SharedRide {
+    /// An index into the scenario's `people`
+    pub driver: usize,
+    /// An index into the driver's `trips`
+    pub driver_trip: usize,
+    /// The passenger walks here from their origin if needed, then waits to be picked up.
+    pub pickup: BuildingID,
+    /// The passenger gets out here, then walks to their destination if needed.
+    pub dropoff: BuildingID,
 }
 
 impl IndividTrip {
@@ -67,6 +102,8 @@ impl IndividTrip {
             purpose,
             cancelled: false,
             modified: false,
+            shared_ride: None,
+            route: None,
         }
     }
 }
@@ -146,8 +183,11 @@ impl Scenario {
         timer.start_iter("trips for People", self.people.len());
         let mut parked_cars: Vec<(Vehicle, BuildingID)> = Vec::new();
         let mut schedule_trips = Vec::new();
+        // Where does each person's first trip wind up in schedule_trips?
+        let mut first_trip_idx = Vec::new();
         for p in &self.people {
             timer.next();
+            first_trip_idx.push(schedule_trips.len());
 
             if let Err(err) = p.check_schedule() {
                 panic!("{}", err);
@@ -178,16 +218,54 @@ impl Scenario {
                     StartTripArgs {
                         retry_if_no_room,
                         use_vehicle: maybe_idx.map(|idx| person.vehicles[idx].id),
+                        route: trip.route.clone(),
                     },
                 ));
             }
         }
 
+        // Express each shared ride as (driver, passenger) indices into schedule_trips
+        let mut shared_rides = Vec::new();
+        for (person_idx, p) in self.people.iter().enumerate() {
+            for (trip_idx, trip) in p.trips.iter().enumerate() {
+                if trip.mode != TripMode::Carpool {
+                    continue;
+                }
+                // Passengers without a valid driver get cancelled when their trip starts
+                if let Some(ref ride) = trip.shared_ride {
+                    match self.people.get(ride.driver) {
+                        Some(driver) if ride.driver_trip < driver.trips.len() => {
+                            shared_rides.push((
+                                first_trip_idx[ride.driver] + ride.driver_trip,
+                                first_trip_idx[person_idx] + trip_idx,
+                                ride.pickup,
+                                ride.dropoff,
+                            ));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
         // parked_cars is stable over map edits, so don't fork.
         parked_cars.shuffle(rng);
         seed_parked_cars(parked_cars, sim, map, rng, timer);
 
-        sim.spawn_trips(schedule_trips, map, timer);
+        if let Some(ref fleet) = self.ride_hail {
+            sim.seed_ride_hail_fleet(fleet, map);
+        }
+        for call in &self.emergency_calls {
+            if let Err(err) = sim.dispatch_emergency_vehicle(call, map) {
+                warn!("Skipping emergency call at {}: {}", call.time, err);
+            }
+        }
+        for tour in &self.deliveries {
+            if let Err(err) = sim.start_delivery_tour(tour.clone(), map) {
+                warn!("Skipping delivery tour at {}: {}", tour.depart, err);
+            }
+        }
+        sim.spawn_trips(schedule_trips, shared_rides, map, timer);
         timer.stop(format!("Instantiating {}", self.scenario_name));
     }
 
@@ -204,6 +282,9 @@ impl Scenario {
             map_name: map.get_name().clone(),
             people: Vec::new(),
             only_seed_buses: Some(BTreeSet::new()),
+            ride_hail: None,
+            emergency_calls: Vec::new(),
+            deliveries: Vec::new(),
         }
     }
 
@@ -264,13 +345,38 @@ impl Scenario {
 
     pub fn remove_weird_schedules(mut self) -> Scenario {
         let orig = self.people.len();
-        self.people.retain(|person| match person.check_schedule() {
-            Ok(()) => true,
-            Err(err) => {
-                println!("{}", err);
-                false
+        let keep: Vec<bool> = self
+            .people
+            .iter()
+            .map(|person| match person.check_schedule() {
+                Ok(()) => true,
+                Err(err) => {
+                    println!("{}", err);
+                    false
+                }
+            })
+            .collect();
+        let mut iter = keep.iter();
+        self.people.retain(|_| *iter.next().unwrap());
+        // Shared rides refer to drivers by index, so fix those up. If the driver was removed, the
+        // passenger is left without a ride.
+        let mut new_idx = Vec::new();
+        let mut next = 0;
+        for keep in keep {
+            new_idx.push(if keep { Some(next) } else { None });
+            if keep {
+                next += 1;
             }
-        });
+        }
+        for person in &mut self.people {
+            for trip in &mut person.trips {
+                if let Some(ride) = trip.shared_ride.take() {
+                    if let Some(driver) = new_idx.get(ride.driver).cloned().flatten() {
+                        trip.shared_ride = Some(SharedRide { driver, ..ride });
+                    }
+                }
+            }
+        }
         warn!(
             "{} of {} people have nonsense schedules",
             prettyprint_usize(orig - self.people.len()),
@@ -339,6 +445,7 @@ fn seed_parked_cars(
     }
 
     timer.start_iter("seed parked cars", parked_cars.len());
+    let now = sim.time();
     let mut ok = true;
     let total_cars = parked_cars.len();
     let mut seeded = 0;
@@ -347,7 +454,7 @@ fn seed_parked_cars(
         if !ok {
             continue;
         }
-        if let Some(spot) = find_spot_near_building(b, &mut open_spots_per_road, map) {
+        if let Some(spot) = find_spot_near_building(b, &mut open_spots_per_road, now, map) {
             seeded += 1;
             sim.seed_parked_car(vehicle, spot);
         } else {
@@ -363,12 +470,20 @@ fn seed_parked_cars(
 
 // Pick a parking spot for this building. If the building's road has a free spot, use it. If not,
 // start BFSing out from the road in a deterministic way until finding a nearby road with an open
-// spot.
+// spot. On-street spots in a residential permit zone are only used for buildings in the zone.
 fn find_spot_near_building(
     b: BuildingID,
     open_spots_per_road: &mut BTreeMap<RoadID, Vec<(ParkingSpot, Option<BuildingID>)>>,
+    now: Time,
     map: &Map,
 ) -> Option<ParkingSpot> {
+    let permitted = |spot: &ParkingSpot| match spot {
+        ParkingSpot::Onstreet(l, _) => map
+            .get_permit_zone_for_lane(*l)
+            .map(|zone| !zone.in_effect(now) || zone.is_resident(b, map))
+            .unwrap_or(true),
+        ParkingSpot::Offstreet(_, _) | ParkingSpot::Lot(_, _) => true,
+    };
     let mut roads_queue: VecDeque<RoadID> = VecDeque::new();
     let mut visited: HashSet<RoadID> = HashSet::new();
     {
@@ -390,7 +505,7 @@ fn find_spot_near_building(
             }
             if let Some(idx) = spots
                 .iter()
-                .position(|(_, restriction)| restriction.is_none())
+                .position(|(spot, restriction)| restriction.is_none() && permitted(spot))
             {
                 return Some(spots.remove(idx).0);
             }
@@ -470,7 +585,12 @@ impl PersonSpec {
         // TODO If the trip is cancelled, this should be affected...
         for trip in &self.trips {
             let use_for_trip = match trip.mode {
-                TripMode::Walk | TripMode::Transit => None,
+                // Passengers use the driver's car
+                TripMode::Walk
+                | TripMode::Transit
+                | TripMode::Carpool
+                | TripMode::RideHail
+                | TripMode::BikeShare => None,
                 TripMode::Bike => {
                     if bike_idx.is_none() {
                         bike_idx = Some(vehicle_specs.len());
@@ -6,10 +6,11 @@ use serde::{Deserialize, Serialize};
 
 use geom::Pt2D;
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, Map, PathConstraints, PathRequest, Position,
+    BuildingID, BusRouteID, BusStopID, DirectedRoadID, IntersectionID, Map, PathConstraints,
+    PathRequest, Position,
 };
 
-use crate::{CarID, DrivingGoal, SidewalkSpot, TripLeg, TripMode, VehicleType, SPAWN_DIST};
+use crate::{CarID, DrivingGoal, SidewalkSpot, TripID, TripLeg, TripMode, VehicleType, SPAWN_DIST};
 
 /// We need to remember a few things from scenario instantiation that're used for starting the
 /// trip.
@@ -17,6 +18,8 @@ use crate::{CarID, DrivingGoal, SidewalkSpot, TripLeg, TripMode, VehicleType, SP
 pub(crate) struct StartTripArgs {
     pub retry_if_no_room: bool,
     pub use_vehicle: Option<CarID>,
+    /// Drive along these roads, instead of pathfinding
+    pub route: Option<Vec<DirectedRoadID>>,
 }
 
 // TODO Some of these fields are unused now that we separately pass TripEndpoint
@@ -57,6 +60,20 @@ pub(crate) enum TripSpec {
         stop1: BusStopID,
         maybe_stop2: Option<BusStopID>,
     },
+    /// Riding in somebody else's car
+    UsingCarpool {
+        start: SidewalkSpot,
+        goal: SidewalkSpot,
+        /// The driver's trip
+        driver: TripID,
+        pickup: BuildingID,
+        dropoff: BuildingID,
+    },
+    /// Waiting inside a building for a ride-hail vehicle, then getting dropped off at another
+    UsingRideHail { start: BuildingID, goal: BuildingID },
+    /// Walking to a bike-share dock near the start, riding to one near the goal, and walking the
+    /// rest of the way. Which docks to use is decided when the trip starts.
+    UsingBikeShare { start: BuildingID, goal: BuildingID },
 }
 
 impl TripSpec {
@@ -203,11 +220,54 @@ impl TripSpec {
                     legs = vec![TripLeg::Walk(walk_to), TripLeg::RideBus(*route, None)];
                 }
             }
+            TripSpec::UsingCarpool {
+                start,
+                goal,
+                driver,
+                pickup,
+                dropoff,
+            } => {
+                // Skip walking if the ride starts or ends right at the building
+                let pickup_spot = SidewalkSpot::building(*pickup, map);
+                if *start != pickup_spot {
+                    legs.push(TripLeg::Walk(pickup_spot));
+                }
+                legs.push(TripLeg::RideInCar(*driver, *dropoff));
+                if *goal != SidewalkSpot::building(*dropoff, map) {
+                    legs.push(TripLeg::Walk(goal.clone()));
+                }
+            }
+            TripSpec::UsingRideHail { goal, .. } => {
+                legs.push(TripLeg::RideHail(*goal));
+            }
+            TripSpec::UsingBikeShare { goal, .. } => {
+                // If docks are available when the trip starts, the walking and riding legs to get
+                // near the goal are added then
+                legs.push(TripLeg::Walk(SidewalkSpot::building(*goal, map)));
+            }
         };
 
         (self, legs)
     }
 
+    /// Plan a passenger's trip, given the driver they share a ride with.
+    pub fn carpool(
+        from: TripEndpoint,
+        to: TripEndpoint,
+        driver: TripID,
+        pickup: BuildingID,
+        dropoff: BuildingID,
+        map: &Map,
+    ) -> Result<TripSpec> {
+        Ok(TripSpec::UsingCarpool {
+            start: from.start_sidewalk_spot(map)?,
+            goal: to.end_sidewalk_spot(map)?,
+            driver,
+            pickup,
+            dropoff,
+        })
+    }
+
     /// Turn an origin/destination pair and mode into a specific plan for instantiating a trip.
     /// Decisions like how to use public transit happen here.
     pub fn maybe_new(
@@ -290,6 +350,20 @@ impl TripSpec {
                     TripSpec::JustWalking { start, goal }
                 }
             }
+            // Passengers are planned with TripSpec::carpool
+            TripMode::Carpool => bail!("nobody is giving this passenger a ride"),
+            TripMode::RideHail => match (from, to) {
+                (TripEndpoint::Bldg(start), TripEndpoint::Bldg(goal)) if start != goal => {
+                    TripSpec::UsingRideHail { start, goal }
+                }
+                _ => bail!("ride-hail trips must go between two different buildings"),
+            },
+            TripMode::BikeShare => match (from, to) {
+                (TripEndpoint::Bldg(start), TripEndpoint::Bldg(goal)) if start != goal => {
+                    TripSpec::UsingBikeShare { start, goal }
+                }
+                _ => bail!("bike-share trips must go between two different buildings"),
+            },
         })
     }
 }
@@ -318,9 +392,11 @@ impl TripEndpoint {
         let end = to.pos(mode, false, map)?;
         Some(match mode {
             TripMode::Walk | TripMode::Transit => PathRequest::walking(start, end),
-            TripMode::Bike => PathRequest::vehicle(start, end, PathConstraints::Bike),
+            TripMode::Bike | TripMode::BikeShare => {
+                PathRequest::vehicle(start, end, PathConstraints::Bike)
+            }
             // Only cars leaving from a building might turn out from the driveway in a special way
-            TripMode::Drive => {
+            TripMode::Drive | TripMode::Carpool | TripMode::RideHail => {
                 if matches!(from, TripEndpoint::Bldg(_)) {
                     PathRequest::leave_from_driveway(start, end, PathConstraints::Car, map)
                 } else {
@@ -377,7 +453,11 @@ impl TripEndpoint {
             })
             .ok()
             .map(|spot| spot.sidewalk_pos),
-            TripMode::Drive | TripMode::Bike => {
+            TripMode::Drive
+            | TripMode::Bike
+            | TripMode::Carpool
+            | TripMode::RideHail
+            | TripMode::BikeShare => {
                 if from {
                     match self {
                         // Fall through and use DrivingGoal also to start.
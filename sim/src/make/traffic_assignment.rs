@@ -0,0 +1,246 @@
+//! Dynamic traffic assignment finds routes for drivers that account for congestion. The same
+//! scenario is simulated over and over. After each simulated day, some drivers switch to the route
+//! that would've been fastest, given the travel times everybody experienced that day. This repeats
+//! until few drivers could do better by switching, approximating an equilibrium.
+
+use std::collections::BTreeSet;
+
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+
+use abstutil::Timer;
+use geom::{Duration, Time};
+use map_model::{
+    DirectedRoadID, Map, PathConstraints, PathRequest, PathStepV2, Position, TravelTimeProfile,
+};
+
+use crate::{Analytics, Scenario, Sim, SimFlags, SimOptions, TripEndpoint, TripID, TripMode};
+
+/// Settings for iteratively assigning routes to drivers
+#[derive(Clone, Debug)]
+pub struct DynamicTrafficAssignment {
+    /// Stop after simulating this many days, even if the routes haven't converged
+    pub max_iterations: usize,
+    /// Stop once the relative gap -- the total time drivers could save by switching to the fastest
+    /// route, divided by the total time they spent driving -- drops below this.
+    pub convergence_gap: f64,
+    /// After each day, this fraction of the drivers who could do better switch routes. Switching
+    /// everyone at once tends to just move the congestion somewhere else.
+    pub replan_fraction: f64,
+    /// Used to choose which drivers switch
+    pub rng_seed: u64,
+}
+
+/// What happened on one simulated day
+#[derive(Clone, Debug)]
+pub struct AssignmentIteration {
+    pub relative_gap: f64,
+    /// How many driving trips were measured
+    pub num_trips: usize,
+    /// How many of those trips switched routes for the next day
+    pub num_replanned: usize,
+    /// The total time spent driving, according to the travel times measured that day
+    pub total_driving_time: Duration,
+}
+
+impl Default for DynamicTrafficAssignment {
+    fn default() -> DynamicTrafficAssignment {
+        DynamicTrafficAssignment {
+            max_iterations: 10,
+            convergence_gap: 0.01,
+            replan_fraction: 0.2,
+            rng_seed: SimFlags::RNG_SEED,
+        }
+    }
+}
+
+/// One driving trip's choice between its current route and the fastest one
+struct RouteChoice {
+    person: usize,
+    trip: usize,
+    current_route: Vec<DirectedRoadID>,
+    current_cost: Duration,
+    best_route: Vec<DirectedRoadID>,
+    best_cost: Duration,
+}
+
+impl DynamicTrafficAssignment {
+    /// Repeatedly simulate the scenario, re-routing some drivers after each day. Returns the
+    /// scenario with a route filled out for every driving trip that was measured, so simulating
+    /// it again reproduces the final assignment, along with the results of each day.
+    pub fn run(
+        &self,
+        mut scenario: Scenario,
+        map: &Map,
+        opts: &SimOptions,
+        timer: &mut Timer,
+    ) -> (Scenario, Vec<AssignmentIteration>) {
+        let mut rng = XorShiftRng::seed_from_u64(self.rng_seed);
+        let mut results = Vec::new();
+        for iteration in 1..=self.max_iterations {
+            timer.start(format!("traffic assignment, day {}", iteration));
+            let analytics = simulate(&scenario, map, opts, timer);
+            let choices = measure_route_choices(&scenario, &analytics, map);
+
+            let total_driving_time: Duration = choices.iter().map(|c| c.current_cost).sum();
+            let total_savings: Duration = choices
+                .iter()
+                .map(|c| (c.current_cost - c.best_cost).max(Duration::ZERO))
+                .sum();
+            let relative_gap = if total_driving_time == Duration::ZERO {
+                0.0
+            } else {
+                total_savings / total_driving_time
+            };
+            let done = relative_gap <= self.convergence_gap || iteration == self.max_iterations;
+
+            // Everybody sticks with the route they drove today, except for a few who switch
+            let mut switch: Vec<usize> = Vec::new();
+            if !done {
+                switch = (0..choices.len())
+                    .filter(|idx| choices[*idx].best_cost < choices[*idx].current_cost)
+                    .collect();
+                switch.shuffle(&mut rng);
+                switch.truncate((self.replan_fraction * (switch.len() as f64)).ceil() as usize);
+            }
+            let switch: BTreeSet<usize> = switch.into_iter().collect();
+            results.push(AssignmentIteration {
+                relative_gap,
+                num_trips: choices.len(),
+                num_replanned: switch.len(),
+                total_driving_time,
+            });
+            for (idx, choice) in choices.into_iter().enumerate() {
+                scenario.people[choice.person].trips[choice.trip].route =
+                    Some(if switch.contains(&idx) {
+                        choice.best_route
+                    } else {
+                        choice.current_route
+                    });
+            }
+
+            info!(
+                "Day {}: relative gap is {:.4}, {} of {} drivers switching routes",
+                iteration,
+                relative_gap,
+                switch.len(),
+                results.last().unwrap().num_trips
+            );
+            timer.stop(format!("traffic assignment, day {}", iteration));
+            if done {
+                break;
+            }
+        }
+        (scenario, results)
+    }
+}
+
+fn simulate(scenario: &Scenario, map: &Map, opts: &SimOptions, timer: &mut Timer) -> Analytics {
+    let mut opts = opts.clone();
+    opts.skip_analytics = false;
+    opts.record_travel_times = true;
+    let mut sim = Sim::new(map, opts);
+    // Use the same RNG every day, so everybody starts in the same place
+    let mut rng = XorShiftRng::seed_from_u64(SimFlags::RNG_SEED);
+    scenario.instantiate(&mut sim, map, &mut rng, timer);
+    let duration = sim.get_end_of_day() - Time::START_OF_DAY;
+    sim.timed_step(map, duration, &mut None, timer);
+    sim.get_analytics().clone()
+}
+
+/// Compare the route each driving trip took to the fastest one, using the travel times that
+/// everyone experienced.
+fn measure_route_choices(
+    scenario: &Scenario,
+    analytics: &Analytics,
+    map: &Map,
+) -> Vec<RouteChoice> {
+    let profile = analytics.road_travel_times.as_ref().unwrap();
+
+    // Drivers giving somebody a ride have to make extra stops, so leave them alone
+    let mut carpool_drivers = BTreeSet::new();
+    for person in &scenario.people {
+        for trip in &person.trips {
+            if let Some(ref ride) = trip.shared_ride {
+                carpool_drivers.insert((ride.driver, ride.driver_trip));
+            }
+        }
+    }
+
+    let mut choices = Vec::new();
+    // Trips are numbered in the order they appear in the scenario
+    let mut id = 0;
+    for (person_idx, person) in scenario.people.iter().enumerate() {
+        for (trip_idx, trip) in person.trips.iter().enumerate() {
+            let trip_id = TripID(id);
+            id += 1;
+            if trip.mode != TripMode::Drive
+                || trip.cancelled
+                || carpool_drivers.contains(&(person_idx, trip_idx))
+            {
+                continue;
+            }
+            let driven = match analytics.driven_routes.get(&trip_id) {
+                Some(route) => route,
+                None => continue,
+            };
+            if let Some((current_route, current_cost, best_route, best_cost)) = compare_routes(
+                driven,
+                trip.origin,
+                trip.destination,
+                trip.depart,
+                profile,
+                map,
+            ) {
+                choices.push(RouteChoice {
+                    person: person_idx,
+                    trip: trip_idx,
+                    current_route,
+                    current_cost,
+                    best_route,
+                    best_cost,
+                });
+            }
+        }
+    }
+    choices
+}
+
+/// Returns the current route and cost, then the fastest route and cost. Both routes start where the
+/// car did, and stop at the destination, even if the driver actually kept going to find parking.
+fn compare_routes(
+    driven: &[DirectedRoadID],
+    origin: TripEndpoint,
+    destination: TripEndpoint,
+    departure: Time,
+    profile: &TravelTimeProfile,
+    map: &Map,
+) -> Option<(Vec<DirectedRoadID>, Duration, Vec<DirectedRoadID>, Duration)> {
+    let goal = TripEndpoint::path_req(origin, destination, TripMode::Drive, map)?.end;
+    let goal_road = map.get_l(goal.lane()).get_directed_parent();
+    let mut current_route = driven.to_vec();
+    let idx = current_route.iter().position(|dr| *dr == goal_road)?;
+    current_route.truncate(idx + 1);
+    let params = map.routing_params_at(departure, PathConstraints::Car);
+    let current_cost = profile.cost_along(
+        &current_route,
+        departure,
+        PathConstraints::Car,
+        &params,
+        map,
+    )?;
+
+    let start = *current_route[0].lanes(PathConstraints::Car, map).get(0)?;
+    let req = PathRequest::vehicle(Position::start(start), goal, PathConstraints::Car);
+    let best = map.pathfind_v2_with_profile(req, departure, profile).ok()?;
+    let best_route = best
+        .get_steps()
+        .iter()
+        .filter_map(|step| match step {
+            PathStepV2::Along(dr) => Some(*dr),
+            _ => None,
+        })
+        .collect();
+    Some((current_route, current_cost, best_route, best.get_cost()))
+}
@@ -2,7 +2,7 @@ use std::collections::{BTreeSet, VecDeque};
 
 use serde::{Deserialize, Serialize};
 
-use geom::{Distance, Duration, PolyLine, Time, EPSILON_DIST};
+use geom::{Distance, Duration, PolyLine, Speed, Time, EPSILON_DIST};
 use map_model::{Direction, LaneID, Map, Traversable};
 
 use crate::{
@@ -26,9 +26,22 @@ pub(crate) struct Car {
     /// vehicle.length.
     pub last_steps: VecDeque<Traversable>,
 
-    /// Since lane over-taking isn't implemented yet, a vehicle tends to be stuck behind a slow
-    /// leader for a while. Avoid duplicate events.
+    /// A vehicle may be stuck behind a slow leader for a while, if there's no way to overtake
+    /// them. Avoid duplicate events.
     pub wants_to_overtake: BTreeSet<CarID>,
+
+    /// Does this driver know about current traffic, and reroute around queues?
+    pub informed: bool,
+    /// When did this driver last reconsider the rest of their route?
+    pub last_reroute_check: Time,
+
+    /// If true, the vehicle gradually speeds up and slows down, instead of instantly changing
+    /// speed.
+    pub accelerates: bool,
+    /// When did the vehicle last finish crossing something, and how fast was it going then? If
+    /// it starts crossing the next thing at the same time, it keeps that speed. Otherwise it
+    /// starts from a stop. Only tracked if `accelerates`.
+    pub last_exit: Option<(Time, Speed)>,
 }
 
 impl Car {
@@ -60,7 +73,12 @@ impl Car {
                 self.vehicle.vehicle_type.to_constraints(),
                 map,
             );
-        let dt = (dist_int.end - dist_int.start) / speed;
+        let dt = if self.accelerates {
+            self.accelerating_crossing(&dist_int, start_time, speed, map)
+                .0
+        } else {
+            (dist_int.end - dist_int.start) / speed
+        };
         CarState::Crossing {
             time_int: TimeInterval::new(start_time, start_time + dt),
             dist_int,
@@ -68,6 +86,83 @@ impl Car {
         }
     }
 
+    /// If the vehicle speeds up and slows down gradually, how long does it take to cover some
+    /// distance, and how fast is it going at the end?
+    fn accelerating_crossing(
+        &self,
+        dist_int: &DistanceInterval,
+        start_time: Time,
+        max_speed: Speed,
+        map: &Map,
+    ) -> (Duration, Speed) {
+        let start_speed = match self.last_exit {
+            Some((t, speed)) if t == start_time => speed.min(max_speed),
+            _ => Speed::ZERO,
+        };
+        let (accel, decel) = self.vehicle.vehicle_type.acceleration_limits();
+        kinematic_crossing(
+            dist_int.end - dist_int.start,
+            start_speed,
+            max_speed,
+            accel,
+            decel,
+            self.router.must_stop_at_end(map),
+        )
+    }
+
+    /// The vehicle is done with its current Crossing state. Remember how fast it was going.
+    pub fn finish_crossing(&mut self, now: Time, map: &Map) {
+        if !self.accelerates {
+            return;
+        }
+        if let CarState::Crossing {
+            ref time_int,
+            ref dist_int,
+            ..
+        } = self.state
+        {
+            let speed = self.router.get_path().current_step().max_speed_along(
+                self.vehicle.max_speed,
+                self.vehicle.vehicle_type.to_constraints(),
+                map,
+            );
+            let (_, exit_speed) = self.accelerating_crossing(dist_int, time_int.start, speed, map);
+            self.last_exit = Some((now, exit_speed));
+        }
+    }
+
+    /// The vehicle's Crossing state is about to be recalculated partway through. Assume it keeps
+    /// going at its average speed so far, instead of stopping.
+    pub fn interrupt_crossing(&mut self, now: Time) {
+        if !self.accelerates {
+            return;
+        }
+        let (time_int, dist_int) = match self.state {
+            CarState::Crossing {
+                ref time_int,
+                ref dist_int,
+                ..
+            } => (time_int, dist_int),
+            CarState::ChangingLanes {
+                ref new_time,
+                ref new_dist,
+                ..
+            } => (new_time, new_dist),
+            _ => {
+                return;
+            }
+        };
+        let speed = if time_int.end > time_int.start {
+            Speed::meters_per_second(
+                (dist_int.end - dist_int.start).inner_meters()
+                    / (time_int.end - time_int.start).inner_seconds(),
+            )
+        } else {
+            Speed::ZERO
+        };
+        self.last_exit = Some((now, speed));
+    }
+
     pub fn get_draw_car(
         &self,
         front: Distance,
@@ -145,12 +240,20 @@ impl Car {
                 from,
                 to,
                 ref lc_time,
+                oncoming,
                 ..
             } => {
-                let percent_time = 1.0 - lc_time.percent(now);
+                // The car's body is already at 'to'. After changing lanes, shift back towards
+                // 'from'. While passing, swing out into the oncoming lane and back.
+                let (other, percent_time) = match oncoming {
+                    Some(l) => {
+                        let pct = lc_time.percent(now);
+                        (l, (3.0 * pct.min(1.0 - pct)).min(1.0))
+                    }
+                    None => (from, 1.0 - lc_time.percent(now)),
+                };
                 // TODO Can probably simplify this! Lifted from the parking case
-                // The car's body is already at 'to', so shift back
-                let mut diff = (to.offset as isize) - (from.offset as isize);
+                let mut diff = (to.offset as isize) - (other.offset as isize);
                 let from = map.get_l(from);
                 if from.dir == Direction::Fwd {
                     diff *= -1;
@@ -329,6 +432,9 @@ pub(crate) enum CarState {
         new_dist: DistanceInterval,
         // How long does the lane-changing itself last? This must end before new_time_int does.
         lc_time: TimeInterval,
+        // If the car is passing somebody by briefly using an oncoming lane, then 'from' and 'to'
+        // are the same lane, and this lane is blocked off until lc_time ends.
+        oncoming: Option<LaneID>,
     },
     Queued {
         blocked_since: Time,
@@ -370,3 +476,114 @@ impl CarState {
         }
     }
 }
+
+/// How long does it take to cover some distance, starting at some speed, accelerating up to a
+/// maximum speed, and possibly braking to a stop at the end? Returns the duration and the speed at
+/// the end. Accelerations are in meters per second squared.
+fn kinematic_crossing(
+    dist: Distance,
+    start_speed: Speed,
+    max_speed: Speed,
+    accel: f64,
+    decel: f64,
+    stop_at_end: bool,
+) -> (Duration, Speed) {
+    let d = dist.inner_meters();
+    let v0 = start_speed.inner_meters_per_second();
+    let vmax = max_speed.inner_meters_per_second();
+    if d <= 0.0 {
+        return (Duration::ZERO, start_speed);
+    }
+
+    let dist_to_max = (vmax * vmax - v0 * v0) / (2.0 * accel);
+    if !stop_at_end {
+        if d <= dist_to_max {
+            let v1 = (v0 * v0 + 2.0 * accel * d).sqrt();
+            return (
+                Duration::seconds((v1 - v0) / accel),
+                Speed::meters_per_second(v1),
+            );
+        }
+        return (
+            Duration::seconds((vmax - v0) / accel + (d - dist_to_max) / vmax),
+            max_speed,
+        );
+    }
+
+    let dist_to_stop = vmax * vmax / (2.0 * decel);
+    if dist_to_max + dist_to_stop <= d {
+        return (
+            Duration::seconds(
+                (vmax - v0) / accel + vmax / decel + (d - dist_to_max - dist_to_stop) / vmax,
+            ),
+            Speed::ZERO,
+        );
+    }
+    // There's no room to reach the max speed. Speed up to a peak, then immediately brake.
+    let peak = ((2.0 * accel * decel * d + decel * v0 * v0) / (accel + decel)).sqrt();
+    if peak <= v0 {
+        // Too close to stop comfortably, so brake harder
+        return (Duration::seconds(2.0 * d / v0), Speed::ZERO);
+    }
+    (
+        Duration::seconds((peak - v0) / accel + peak / decel),
+        Speed::ZERO,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kinematic_crossing() {
+        let max_speed = Speed::meters_per_second(10.0);
+        // Already at max speed, so no time is lost
+        let (dt, exit) = kinematic_crossing(
+            Distance::meters(100.0),
+            max_speed,
+            max_speed,
+            2.0,
+            2.0,
+            false,
+        );
+        assert_eq!(dt, Duration::seconds(10.0));
+        assert_eq!(exit, max_speed);
+
+        // Starting from a stop takes 5s and 25m to reach max speed, then 7.5s to cover the rest
+        let (dt, exit) = kinematic_crossing(
+            Distance::meters(100.0),
+            Speed::ZERO,
+            max_speed,
+            2.0,
+            2.0,
+            false,
+        );
+        assert_eq!(dt, Duration::seconds(12.5));
+        assert_eq!(exit, max_speed);
+
+        // Braking at the end takes another 25m and 5s, so the total is 15s
+        let (dt, exit) = kinematic_crossing(
+            Distance::meters(100.0),
+            Speed::ZERO,
+            max_speed,
+            2.0,
+            2.0,
+            true,
+        );
+        assert_eq!(dt, Duration::seconds(15.0));
+        assert_eq!(exit, Speed::ZERO);
+
+        // A short distance never reaches max speed: 8m is covered by speeding up to 4 m/s over
+        // 2s, then braking for 2s.
+        let (dt, _) = kinematic_crossing(
+            Distance::meters(8.0),
+            Speed::ZERO,
+            max_speed,
+            2.0,
+            2.0,
+            true,
+        );
+        assert_eq!(dt, Duration::seconds(4.0));
+    }
+}
@@ -1,23 +1,38 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_hashmap, serialize_hashmap, FixedMap, IndexableKey};
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{DrivingSide, IntersectionID, LaneID, Map, Path, PathStep, Position, Traversable};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
+use map_model::{
+    DirectedRoadID, DrivingSide, IntersectionID, LaneID, Map, Path, PathConstraints, PathStep,
+    Position, Traversable, TurnID,
+};
 
 use crate::mechanics::car::{Car, CarState};
 use crate::mechanics::queue::{Queue, QueueEntry, Queued};
 use crate::sim::Ctx;
 use crate::{
     ActionAtEnd, AgentID, AgentProperties, CarID, CarStatus, Command, CreateCar, DelayCause,
-    DistanceInterval, DrawCarInput, Event, IntersectionSimState, ParkedCar, ParkingSim,
-    ParkingSpot, PersonID, Problem, SimOptions, TimeInterval, TransitSimState, TripID, TripManager,
-    UnzoomedAgent, Vehicle, VehicleType, WalkingSimState, FOLLOWING_DISTANCE, MAX_CAR_LENGTH,
+    DistanceInterval, DrawCarInput, Event, IntersectionSimState, OvertakeManeuver, ParkedCar,
+    ParkingSim, ParkingSpot, PersonID, Problem, RerouteReason, SimOptions, TimeInterval,
+    TransitSimState, TripID, TripManager, UnzoomedAgent, Vehicle, VehicleType, WalkingSimState,
+    FOLLOWING_DISTANCE, MAX_CAR_LENGTH,
 };
 
-const TIME_TO_WAIT_AT_BUS_STOP: Duration = Duration::const_seconds(10.0);
+const TIME_TO_WAIT_AT_CARPOOL_STOP: Duration = Duration::const_seconds(30.0);
+const TIME_TO_WAIT_AT_RIDE_HAIL_STOP: Duration = Duration::const_seconds(30.0);
 const TIME_TO_CHANGE_LANES: Duration = Duration::const_seconds(1.0);
+/// Don't block off an oncoming lane for longer than this to pass somebody.
+const MAX_TIME_TO_PASS: Duration = Duration::const_seconds(10.0);
+/// When estimating the delay from a queue, how long does each vehicle waiting in it take to get
+/// moving?
+const QUEUE_DISCHARGE_HEADWAY: Duration = Duration::const_seconds(2.0);
+/// Emergency vehicles only claim an intersection once they're expected to reach it within this
+/// long. Until then, traffic and signals there carry on as normal.
+const EMERGENCY_PREEMPTION_HORIZON: Duration = Duration::const_seconds(20.0);
 
 // TODO Do something else.
 pub const BLIND_RETRY_TO_CREEP_FORWARDS: Duration = Duration::const_seconds(0.1);
@@ -46,6 +61,13 @@ pub(crate) struct DrivingSimState {
     recalc_lanechanging: bool,
     handle_uber_turns: bool,
 
+    informed_drivers: f64,
+    reroute_after_blocked: Duration,
+    reroute_periodically: Option<Duration>,
+
+    use_acceleration: bool,
+    allow_oncoming_overtaking: bool,
+
     time_to_unpark_onstreet: Duration,
     time_to_park_onstreet: Duration,
     time_to_unpark_offstreet: Duration,
@@ -63,6 +85,13 @@ impl DrivingSimState {
             handle_uber_turns: !opts.dont_handle_uber_turns,
             waiting_to_spawn: BTreeMap::new(),
 
+            informed_drivers: opts.informed_drivers,
+            reroute_after_blocked: opts.reroute_after_blocked,
+            reroute_periodically: opts.reroute_periodically,
+
+            use_acceleration: opts.use_acceleration,
+            allow_oncoming_overtaking: opts.allow_oncoming_overtaking,
+
             time_to_unpark_onstreet: Duration::seconds(10.0),
             time_to_park_onstreet: Duration::seconds(15.0),
             time_to_unpark_offstreet: Duration::seconds(5.0),
@@ -178,6 +207,7 @@ impl DrivingSimState {
             &self.cars,
             &self.queues,
         ) {
+            let informed = self.is_informed(&params.vehicle);
             let mut car = Car {
                 vehicle: params.vehicle,
                 router: params.router,
@@ -191,6 +221,10 @@ impl DrivingSimState {
                 total_blocked_time: Duration::ZERO,
                 trip_and_person: params.trip_and_person,
                 wants_to_overtake: BTreeSet::new(),
+                informed,
+                last_reroute_check: now,
+                accelerates: self.use_acceleration,
+                last_exit: None,
             };
             if let Some(p) = params.maybe_parked_car {
                 let delay = match p.spot {
@@ -233,6 +267,7 @@ impl DrivingSimState {
                 if car.router.last_step() {
                     match car.router.maybe_handle_end(
                         start_dist,
+                        now,
                         &car.vehicle,
                         ctx.parking,
                         ctx.map,
@@ -240,6 +275,14 @@ impl DrivingSimState {
                         &mut self.events,
                     ) {
                         None | Some(ActionAtEnd::GotoLaneEnd) => {}
+                        // The first carpool stop might be right where the car starts. It'll stop
+                        // there immediately after spawning.
+                        Some(ActionAtEnd::CarpoolAtStop) => {}
+                        // Same for a ride-hail vehicle dispatched from a depot right next to the
+                        // pickup
+                        Some(ActionAtEnd::RideHailAtStop) => {}
+                        // Or a freight vehicle with consecutive deliveries on the same block
+                        Some(ActionAtEnd::DeliveryAtStop) => {}
                         x => {
                             panic!(
                                 "Car with one-step route {:?} had unexpected result from \
@@ -265,6 +308,20 @@ impl DrivingSimState {
                     }
                 }
 
+                // Vehicles entering the map at a border are already moving
+                if car.accelerates
+                    && start_dist == Distance::ZERO
+                    && ctx.map.get_i(ctx.map.get_l(first_lane).src_i).is_border()
+                {
+                    car.last_exit = Some((
+                        now,
+                        car.router.get_path().current_step().max_speed_along(
+                            car.vehicle.max_speed,
+                            car.vehicle.vehicle_type.to_constraints(),
+                            ctx.map,
+                        ),
+                    ));
+                }
                 car.state = car.crossing_state(start_dist, now, ctx.map);
             }
             ctx.scheduler
@@ -273,6 +330,11 @@ impl DrivingSimState {
                 .get_mut(&Traversable::Lane(first_lane))
                 .unwrap()
                 .insert_car_at_idx(idx, &car);
+            if car.vehicle.vehicle_type == VehicleType::Emergency {
+                if let Some(Traversable::Turn(t)) = car.router.maybe_next() {
+                    emergency_vehicle_heading_to(now, &car, t, ctx);
+                }
+            }
             self.waiting_to_spawn.remove(&car.vehicle.id);
             self.cars.insert(car.vehicle.id, car);
             return None;
@@ -286,6 +348,11 @@ impl DrivingSimState {
         self.waiting_to_spawn.insert(id, (pos, person));
     }
 
+    /// A vehicle waiting to spawn gave up.
+    pub fn vehicle_not_spawning(&mut self, id: CarID) {
+        self.waiting_to_spawn.remove(&id);
+    }
+
     /// State transitions for this car:
     ///
     /// Crossing -> Queued or WaitingToAdvance
@@ -319,6 +386,8 @@ impl DrivingSimState {
             let car = &self.cars[&id];
             match car.state {
                 CarState::Queued { .. } => car.router.last_step(),
+                // The blockage left behind by passing is in the same queue
+                CarState::ChangingLanes { oncoming, .. } => oncoming.is_some(),
                 CarState::Parking(_, _, _) => true,
                 CarState::IdlingAtStop(_, _) => true,
                 _ => false,
@@ -372,6 +441,7 @@ impl DrivingSimState {
     ) -> bool {
         match car.state {
             CarState::Crossing { .. } => {
+                car.finish_crossing(now, ctx.map);
                 car.state = CarState::Queued {
                     blocked_since: now,
                     want_to_change_lanes: None,
@@ -384,6 +454,11 @@ impl DrivingSimState {
                 if queue.is_car_at_front(car.vehicle.id) {
                     // Want to re-run, but no urgency about it happening immediately.
                     car.state = CarState::WaitingToAdvance { blocked_since: now };
+                    if let Some(period) = self.reroute_periodically {
+                        if car.informed && now - car.last_reroute_check >= period {
+                            self.maybe_reroute(car, now, RerouteReason::Periodic, ctx);
+                        }
+                    }
                     if self.recalc_lanechanging {
                         car.router.opportunistically_lanechange(
                             &self.queues,
@@ -451,6 +526,7 @@ impl DrivingSimState {
                     // end_dist.
                     car.router.maybe_handle_end(
                         front,
+                        now,
                         &car.vehicle,
                         ctx.parking,
                         ctx.map,
@@ -463,6 +539,12 @@ impl DrivingSimState {
                     .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
             }
             CarState::WaitingToAdvance { blocked_since } => {
+                if car.informed
+                    && now - blocked_since.max(car.last_reroute_check) >= self.reroute_after_blocked
+                {
+                    self.maybe_reroute(car, now, RerouteReason::Blocked(now - blocked_since), ctx);
+                }
+
                 // 'car' is the leader.
                 let from = car.router.head();
                 let goto = car.router.next();
@@ -482,7 +564,15 @@ impl DrivingSimState {
                         ctx.scheduler,
                         Some((car, &self.cars, &mut self.queues)),
                     ) {
-                        // Don't schedule a retry here.
+                        // Don't schedule a retry here. But if the driver might find a way around
+                        // whatever's blocking them, make sure they wake up to look for one.
+                        if car.informed && car.router.reroute_request(ctx.map).is_some() {
+                            ctx.scheduler.update_unless_sooner(
+                                blocked_since.max(car.last_reroute_check)
+                                    + self.reroute_after_blocked,
+                                Command::UpdateCar(car.vehicle.id),
+                            );
+                        }
                         return false;
                     }
                     if let Some((trip, _)) = car.trip_and_person {
@@ -504,6 +594,7 @@ impl DrivingSimState {
                 // way, until laggy_head is None.
 
                 let last_step = car.router.advance(
+                    now,
                     &car.vehicle,
                     ctx.parking,
                     ctx.map,
@@ -525,6 +616,25 @@ impl DrivingSimState {
                     },
                 ));
 
+                // Let a signal ahead know that a transit or emergency vehicle is coming.
+                if let (Traversable::Lane(_), Some(Traversable::Turn(t))) =
+                    (goto, car.router.maybe_next())
+                {
+                    if car.vehicle.vehicle_type == VehicleType::Emergency {
+                        emergency_vehicle_heading_to(now, car, t, ctx);
+                    } else if car.vehicle.vehicle_type.is_transit() {
+                        ctx.intersections.transit_vehicle_approaching(
+                            now,
+                            car.vehicle.id,
+                            t,
+                            car.state.get_end_time(),
+                            transit.is_behind_schedule(car.vehicle.id),
+                            ctx.map,
+                            ctx.scheduler,
+                        );
+                    }
+                }
+
                 // Don't mark turn_finished until our back is out of the turn.
                 car.last_steps.push_front(last_step);
 
@@ -553,6 +663,7 @@ impl DrivingSimState {
                 from,
                 new_time,
                 new_dist,
+                oncoming: None,
                 ..
             } => {
                 // The car is already in the target queue. Just set them in the crossing state; we
@@ -580,6 +691,9 @@ impl DrivingSimState {
                     .unwrap()
                     .clear_dynamic_blockage(car.vehicle.id, idx);
             }
+            CarState::ChangingLanes {
+                oncoming: Some(_), ..
+            } => unreachable!(),
             CarState::Queued { .. } => unreachable!(),
             CarState::Parking(_, _, _) => unreachable!(),
             CarState::IdlingAtStop(_, _) => unreachable!(),
@@ -605,7 +719,42 @@ impl DrivingSimState {
             CarState::Crossing { .. }
             | CarState::Unparking { .. }
             | CarState::WaitingToAdvance { .. }
-            | CarState::ChangingLanes { .. } => unreachable!(),
+            | CarState::ChangingLanes { oncoming: None, .. } => unreachable!(),
+            CarState::ChangingLanes {
+                from,
+                new_time,
+                new_dist,
+                oncoming: Some(oncoming),
+                ..
+            } => {
+                // Done passing. The car is already in front of whoever they passed, so just set
+                // them in the crossing state.
+                car.state = CarState::Crossing {
+                    time_int: new_time,
+                    dist_int: new_dist,
+                    steep_uphill: false,
+                };
+                ctx.scheduler
+                    .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+
+                // Remove the blockage left behind in this queue
+                let blockage = dists
+                    .iter()
+                    .position(|entry| match entry.member {
+                        Queued::DynamicBlockage { cause, .. } => cause == car.vehicle.id,
+                        _ => false,
+                    })
+                    .unwrap();
+                self.update_follower(blockage, dists, now, ctx);
+                self.queues
+                    .get_mut(&Traversable::Lane(from))
+                    .unwrap()
+                    .clear_dynamic_blockage(car.vehicle.id, blockage);
+
+                // And open up the oncoming lane again
+                self.clear_blockage(Traversable::Lane(oncoming), car.vehicle.id, now, ctx);
+                true
+            }
             CarState::Queued {
                 blocked_since,
                 want_to_change_lanes,
@@ -613,12 +762,18 @@ impl DrivingSimState {
                 // Two totally different reasons we'll wind up here: we want to lane-change, and
                 // we're on our last step.
                 if let Some(target_lane) = want_to_change_lanes {
-                    self.try_start_lc(car, our_dist, idx, target_lane, now, ctx);
+                    let current_lane = car.router.head().as_lane();
+                    if ctx.map.get_l(target_lane).dir == ctx.map.get_l(current_lane).dir {
+                        self.try_start_lc(car, our_dist, idx, target_lane, now, ctx);
+                    } else {
+                        self.try_start_oncoming_pass(car, dists, idx, target_lane, now, ctx);
+                    }
                     return true;
                 }
 
                 match car.router.maybe_handle_end(
                     our_dist,
+                    now,
                     &car.vehicle,
                     ctx.parking,
                     ctx.map,
@@ -642,6 +797,12 @@ impl DrivingSimState {
                     }
                     Some(ActionAtEnd::GiveUpOnParking) => {
                         car.total_blocked_time += now - blocked_since;
+                        self.events.extend(car.router.parking_search_finished(
+                            our_dist,
+                            now,
+                            car.trip_and_person,
+                            false,
+                        ));
                         trips.cancel_trip(
                             now,
                             car.trip_and_person.unwrap().0,
@@ -655,6 +816,12 @@ impl DrivingSimState {
                     }
                     Some(ActionAtEnd::StartParking(spot)) => {
                         car.total_blocked_time += now - blocked_since;
+                        self.events.extend(car.router.parking_search_finished(
+                            our_dist,
+                            now,
+                            car.trip_and_person,
+                            true,
+                        ));
                         let delay = match spot {
                             ParkingSpot::Onstreet(_, _) => self.time_to_park_onstreet,
                             ParkingSpot::Offstreet(_, _) | ParkingSpot::Lot(_, _) => {
@@ -692,10 +859,12 @@ impl DrivingSimState {
                     }
                     Some(ActionAtEnd::BusAtStop) => {
                         car.total_blocked_time += now - blocked_since;
-                        if transit.bus_arrived_at_stop(now, car.vehicle.id, trips, walking, ctx) {
+                        if let Some(dwell_time) =
+                            transit.bus_arrived_at_stop(now, car.vehicle.id, trips, walking, ctx)
+                        {
                             car.state = CarState::IdlingAtStop(
                                 our_dist,
-                                TimeInterval::new(now, now + TIME_TO_WAIT_AT_BUS_STOP),
+                                TimeInterval::new(now, now + dwell_time),
                             );
                             ctx.scheduler
                                 .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
@@ -705,6 +874,65 @@ impl DrivingSimState {
                             false
                         }
                     }
+                    Some(ActionAtEnd::CarpoolAtStop) => {
+                        car.total_blocked_time += now - blocked_since;
+                        trips.car_reached_carpool_stop(now, car.vehicle.id, ctx);
+                        car.state = CarState::IdlingAtStop(
+                            our_dist,
+                            TimeInterval::new(now, now + TIME_TO_WAIT_AT_CARPOOL_STOP),
+                        );
+                        ctx.scheduler
+                            .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                        true
+                    }
+                    Some(ActionAtEnd::EmergencyAtScene(incident, called_at)) => {
+                        car.total_blocked_time += now - blocked_since;
+                        self.events.push(Event::EmergencyVehicleArrived(
+                            car.vehicle.id,
+                            incident,
+                            now - called_at,
+                        ));
+                        false
+                    }
+                    Some(ActionAtEnd::RideHailAtStop) => {
+                        car.total_blocked_time += now - blocked_since;
+                        if trips.ride_hail_vehicle_reached_stop(
+                            now,
+                            car.vehicle.id,
+                            car.router.get_path().total_length(),
+                            ctx,
+                        ) {
+                            car.state = CarState::IdlingAtStop(
+                                our_dist,
+                                TimeInterval::new(now, now + TIME_TO_WAIT_AT_RIDE_HAIL_STOP),
+                            );
+                            ctx.scheduler
+                                .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                            true
+                        } else {
+                            // Back at the depot
+                            false
+                        }
+                    }
+                    Some(ActionAtEnd::DeliveryAtStop) => {
+                        car.total_blocked_time += now - blocked_since;
+                        let pos = Position::new(car.router.head().as_lane(), our_dist);
+                        if let Some(dwell_time) =
+                            trips.freight_vehicle_reached_stop(now, car.vehicle.id, pos, ctx)
+                        {
+                            // Double-park, blocking the lane
+                            car.state = CarState::IdlingAtStop(
+                                our_dist,
+                                TimeInterval::new(now, now + dwell_time),
+                            );
+                            ctx.scheduler
+                                .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                            true
+                        } else {
+                            // Pulled into a loading zone, or back at the depot
+                            false
+                        }
+                    }
                     None => {
                         ctx.scheduler.push(
                             now + BLIND_RETRY_TO_REACH_END_DIST,
@@ -750,6 +978,73 @@ impl DrivingSimState {
                 );
                 false
             }
+            CarState::IdlingAtStop(dist, _) if car.vehicle.vehicle_type.is_freight() => {
+                let start = Position::new(car.router.head().as_lane(), dist);
+                if let Some(router) =
+                    trips.freight_vehicle_left_stop(now, car.vehicle.id, start, ctx)
+                {
+                    car.router = router;
+                    self.events
+                        .push(Event::PathAmended(car.router.get_path().clone()));
+                    car.state = car.crossing_state(dist, now, ctx.map);
+                    ctx.scheduler
+                        .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                    self.update_follower(idx, dists, now, ctx);
+                    true
+                } else {
+                    // Nowhere to go; the tour is abandoned
+                    false
+                }
+            }
+            CarState::IdlingAtStop(dist, _)
+                if car.vehicle.vehicle_type == VehicleType::Car
+                    && car.trip_and_person.is_none() =>
+            {
+                // A ride-hail vehicle
+                let start = Position::new(car.router.head().as_lane(), dist);
+                if let Some(router) =
+                    trips.ride_hail_vehicle_left_stop(now, car.vehicle.id, start, ctx)
+                {
+                    car.router = router;
+                    self.events
+                        .push(Event::PathAmended(car.router.get_path().clone()));
+                    car.state = car.crossing_state(dist, now, ctx.map);
+                    ctx.scheduler
+                        .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                    self.update_follower(idx, dists, now, ctx);
+                    true
+                } else {
+                    // Nowhere to go; the vehicle will start over from its depot
+                    false
+                }
+            }
+            CarState::IdlingAtStop(dist, _) if car.vehicle.vehicle_type == VehicleType::Car => {
+                // Keep waiting for passengers who haven't shown up yet
+                if trips.keep_waiting_at_carpool_stop(now, car.vehicle.id) {
+                    car.state = CarState::IdlingAtStop(
+                        dist,
+                        TimeInterval::new(now, now + TIME_TO_WAIT_AT_CARPOOL_STOP),
+                    );
+                    ctx.scheduler
+                        .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                    return true;
+                }
+
+                let start = Position::new(car.router.head().as_lane(), dist);
+                if let Some(router) = trips.car_left_carpool_stop(now, &car.vehicle, start, ctx) {
+                    car.router = router;
+                    self.events
+                        .push(Event::PathAmended(car.router.get_path().clone()));
+                    car.state = car.crossing_state(dist, now, ctx.map);
+                    ctx.scheduler
+                        .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                    self.update_follower(idx, dists, now, ctx);
+                    true
+                } else {
+                    // The trip was cancelled
+                    false
+                }
+            }
             CarState::IdlingAtStop(dist, _) => {
                 car.router = transit.bus_departed_from_stop(car.vehicle.id, ctx.map);
                 self.events
@@ -770,6 +1065,14 @@ impl DrivingSimState {
     pub fn delete_car(&mut self, c: CarID, now: Time, ctx: &mut Ctx) -> Vehicle {
         self.waiting_to_spawn.remove(&c);
 
+        // Don't leave behind blockages from changing lanes or passing
+        if let CarState::ChangingLanes { from, oncoming, .. } = self.cars[&c].state {
+            self.clear_blockage(Traversable::Lane(from), c, now, ctx);
+            if let Some(l) = oncoming {
+                self.clear_blockage(Traversable::Lane(l), c, now, ctx);
+            }
+        }
+
         let dists = self.queues[&self.cars[&c].router.head()].get_car_positions(
             now,
             &self.cars,
@@ -887,6 +1190,7 @@ impl DrivingSimState {
                     // If the follower was still Crossing, they might not've been blocked by the
                     // leader yet. But recalculating their Crossing state isn't necessarily a no-op
                     // -- this could prevent them from suddenly warping past a blockage.
+                    follower.interrupt_crossing(now);
                     follower.state = follower.crossing_state(follower_dist, now, ctx.map);
                     ctx.scheduler.update(
                         follower.state.get_end_time(),
@@ -894,7 +1198,11 @@ impl DrivingSimState {
                     );
                 }
                 CarState::ChangingLanes {
-                    from, to, lc_time, ..
+                    from,
+                    to,
+                    lc_time,
+                    oncoming,
+                    ..
                 } => {
                     // This is a fun case -- something stopped blocking somebody that was in the
                     // process of lane-changing! Similar to the Crossing case above, we just have
@@ -902,10 +1210,15 @@ impl DrivingSimState {
                     // middle of their lane-changing. It's guaranteed that lc_time will continue to
                     // finish before the new time interval, because there's no possible way
                     // recalculating this crossing state here will speed things up from the
-                    // original estimate.
-                    let (new_time, new_dist) = match follower.crossing_state_with_end_dist(
+                    // original estimate. (Except with acceleration, since the vehicle might be going
+                    // faster now.)
+                    // Somebody passing in an oncoming lane doesn't continue from their new place
+                    // until the pass is done.
+                    follower.interrupt_crossing(now);
+                    let start_time = if oncoming.is_some() { lc_time.end } else { now };
+                    let (mut new_time, new_dist) = match follower.crossing_state_with_end_dist(
                         DistanceInterval::new_driving(follower_dist, ctx.map.get_l(to).length()),
-                        now,
+                        start_time,
                         ctx.map,
                     ) {
                         CarState::Crossing {
@@ -913,6 +1226,9 @@ impl DrivingSimState {
                         } => (time_int, dist_int),
                         _ => unreachable!(),
                     };
+                    if follower.accelerates && new_time.end < lc_time.end {
+                        new_time = TimeInterval::new(new_time.start, lc_time.end);
+                    }
                     assert!(new_time.end >= lc_time.end);
                     follower.state = CarState::ChangingLanes {
                         from,
@@ -920,6 +1236,7 @@ impl DrivingSimState {
                         new_time,
                         new_dist,
                         lc_time,
+                        oncoming,
                     };
                 }
                 // They weren't blocked
@@ -931,6 +1248,22 @@ impl DrivingSimState {
         }
     }
 
+    /// An emergency vehicle is now close enough to the next intersection to claim it, as long as
+    /// it's still around and headed for the same turn.
+    pub fn emergency_vehicle_near(&self, id: CarID, turn: TurnID, now: Time, ctx: &mut Ctx) {
+        if let Some(car) = self.cars.get(&id) {
+            if car.router.maybe_next() == Some(Traversable::Turn(turn)) {
+                ctx.intersections.emergency_vehicle_approaching(
+                    now,
+                    id,
+                    turn,
+                    ctx.map,
+                    ctx.scheduler,
+                );
+            }
+        }
+    }
+
     pub fn update_laggy_head(&mut self, id: CarID, now: Time, ctx: &mut Ctx) {
         let currently_on = self.cars[&id].router.head();
         // This car must be the tail.
@@ -1077,8 +1410,8 @@ impl DrivingSimState {
     }
 
     /// If the car wants to over-take somebody, what adjacent lane should they use?
-    /// - The lane must be in the same direction as the current; no support for crossing the road's
-    ///   yellow line yet.
+    /// - Prefer a lane in the same direction as the current. Only if there isn't one, and if
+    ///   allowed, briefly cross into an oncoming lane.
     /// - Prefer passing on the left (for DrivingSide::Right)
     /// For now, just pick one candidate lane, even if both might be usable.
     fn pick_overtaking_lane(&self, car: &Car, map: &Map) -> Option<LaneID> {
@@ -1098,12 +1431,9 @@ impl DrivingSimState {
             candidates.reverse();
         }
 
+        let mut oncoming = None;
         for l in candidates {
             let target_lane = map.get_l(l);
-            // Must be the same direction -- no crossing into oncoming traffic yet
-            if current_lane.dir != target_lane.dir {
-                continue;
-            }
             // The lane types can differ, as long as the vehicle can use the target. Imagine
             // overtaking a slower cyclist in a bike lane using the rest of the road.
             if !car
@@ -1114,6 +1444,19 @@ impl DrivingSimState {
             {
                 continue;
             }
+            if current_lane.dir != target_lane.dir {
+                // Whether it's actually clear gets checked later. Since the car returns to the
+                // current lane after passing, the path doesn't matter.
+                // Emergency vehicles cross into oncoming lanes whenever they need to.
+                if !car.router.last_step()
+                    && (car.vehicle.vehicle_type == VehicleType::Emergency
+                        || (self.allow_oncoming_overtaking
+                            && road.allows_oncoming_overtaking(current_lane.dir)))
+                {
+                    oncoming = Some(target_lane.id);
+                }
+                continue;
+            }
             // Is this other lane compatible with the path? We won't make any attempts to return to the
             // original lane after changing.
             if !car
@@ -1125,7 +1468,7 @@ impl DrivingSimState {
             return Some(target_lane.id);
         }
 
-        None
+        oncoming
     }
 
     fn try_start_lc(
@@ -1214,12 +1557,204 @@ impl DrivingSimState {
                 new_time,
                 new_dist,
                 lc_time,
+                oncoming: None,
             };
             ctx.scheduler
                 .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+            self.events.push(Event::Overtook(
+                car.vehicle.id,
+                car.trip_and_person.map(|(t, _)| t),
+                OvertakeManeuver::ChangedLanes {
+                    from: current_lane,
+                    to: target_lane,
+                },
+            ));
         }
     }
 
+    /// Try to pass the slower vehicle directly in front by briefly using an oncoming lane. The car
+    /// immediately takes a place in the queue in front of the vehicle being passed, leaving a
+    /// dynamic blockage behind. Until the pass is done, the stretch of the oncoming lane being
+    /// used is blocked off, so oncoming traffic has to wait, and the car only continues along the
+    /// lane from its new place once the pass finishes.
+    fn try_start_oncoming_pass(
+        &mut self,
+        car: &mut Car,
+        dists: &[QueueEntry],
+        idx: usize,
+        oncoming: LaneID,
+        now: Time,
+        ctx: &mut Ctx,
+    ) {
+        // Like lane-changing, don't start if our back is still sticking out of a turn.
+        if !car.last_steps.is_empty() || car.router.last_step() || idx == 0 {
+            return;
+        }
+        let leader = match dists[idx - 1].member {
+            Queued::Vehicle(id) => id,
+            _ => {
+                return;
+            }
+        };
+        // If they've stopped, there's nothing to gain by passing them -- unless they're pulling
+        // aside for an emergency vehicle.
+        let emergency = car.vehicle.vehicle_type == VehicleType::Emergency;
+        if !emergency && !matches!(self.cars[&leader].state, CarState::Crossing { .. }) {
+            return;
+        }
+        // Only use the oncoming lane if it's totally clear, and nobody's about to enter it.
+        if !self.queues[&Traversable::Lane(oncoming)].is_empty() {
+            return;
+        }
+
+        let lane = car.router.head().as_lane();
+        let our_speed = PathStep::Lane(lane).max_speed_along(
+            car.vehicle.max_speed,
+            car.vehicle.vehicle_type.to_constraints(),
+            ctx.map,
+        );
+        let their_speed = if emergency {
+            Speed::ZERO
+        } else {
+            PathStep::Lane(lane).max_speed_along(
+                self.cars[&leader].vehicle.max_speed,
+                leader.vehicle_type.to_constraints(),
+                ctx.map,
+            )
+        };
+        if our_speed <= their_speed {
+            return;
+        }
+
+        // Is there room to pull back in front of them?
+        let new_front = dists[idx - 1].front + FOLLOWING_DISTANCE + car.vehicle.length;
+        let bound = if idx == 1 {
+            let queue = &self.queues[&car.router.head()];
+            if queue.laggy_head.is_some() {
+                return;
+            }
+            queue.geom_len
+        } else {
+            dists[idx - 2].back - FOLLOWING_DISTANCE
+        };
+        if new_front > bound {
+            return;
+        }
+
+        // Can we finish passing quickly, and before the end of the lane?
+        let pass_time = (new_front - dists[idx].front) / (our_speed - their_speed);
+        if pass_time > MAX_TIME_TO_PASS {
+            return;
+        }
+        let lc_time = TimeInterval::new(now, now + pass_time);
+        let (new_time, new_dist) = match car.crossing_state(new_front, lc_time.end, ctx.map) {
+            CarState::Crossing {
+                time_int, dist_int, ..
+            } => (time_int, dist_int),
+            _ => unreachable!(),
+        };
+        if lc_time.end >= new_time.end {
+            return;
+        }
+
+        // Where exactly in the oncoming lane will we be?
+        let block_front = Position::new(lane, dists[idx].back.max(Distance::ZERO))
+            .equiv_pos(oncoming, ctx.map)
+            .dist_along();
+        let block_back = Position::new(lane, new_front)
+            .equiv_pos(oncoming, ctx.map)
+            .dist_along();
+        if block_front <= block_back || block_back < FOLLOWING_DISTANCE {
+            return;
+        }
+
+        {
+            let queue = self.queues.get_mut(&car.router.head()).unwrap();
+            queue.replace_car_with_dynamic_blockage(car, idx);
+            queue.insert_car_at_idx(idx - 1, car);
+        }
+        self.queues
+            .get_mut(&Traversable::Lane(oncoming))
+            .unwrap()
+            .add_static_blockage(car.vehicle.id, block_front, block_back, 0);
+
+        car.state = CarState::ChangingLanes {
+            from: lane,
+            to: lane,
+            new_time,
+            new_dist,
+            lc_time,
+            oncoming: Some(oncoming),
+        };
+        ctx.scheduler
+            .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+        self.events.push(Event::Overtook(
+            car.vehicle.id,
+            car.trip_and_person.map(|(t, _)| t),
+            OvertakeManeuver::UsedOncomingLane {
+                lane,
+                oncoming,
+                passed: leader,
+            },
+        ));
+    }
+
+    /// Remove a static or dynamic blockage that a car left in some queue, and let anybody stuck
+    /// behind it continue. The car must either still exist, or not be in that queue itself.
+    fn clear_blockage(&mut self, on: Traversable, cause: CarID, now: Time, ctx: &mut Ctx) {
+        let dists = self.queues[&on].get_car_positions(now, &self.cars, &self.queues);
+        let idx = dists
+            .iter()
+            .position(|entry| match entry.member {
+                Queued::StaticBlockage { cause: c, .. }
+                | Queued::DynamicBlockage { cause: c, .. } => c == cause,
+                Queued::Vehicle(_) => false,
+            })
+            .unwrap();
+        self.update_follower(idx, &dists, now, ctx);
+
+        let queue = self.queues.get_mut(&on).unwrap();
+        if matches!(dists[idx].member, Queued::StaticBlockage { .. }) {
+            queue.clear_static_blockage(cause, idx);
+        } else {
+            queue.clear_dynamic_blockage(cause, idx);
+        }
+    }
+
+    /// An informed driver at the end of a lane reconsiders the rest of their route, given the
+    /// current queues. If there's a faster way, they switch to it.
+    fn maybe_reroute(&mut self, car: &mut Car, now: Time, reason: RerouteReason, ctx: &mut Ctx) {
+        car.last_reroute_check = now;
+        let req = match car.router.reroute_request(ctx.map) {
+            Some(req) => req,
+            None => {
+                return;
+            }
+        };
+        let map = ctx.map;
+        let path = match map.pathfind_with_delays(req, now, |dr| self.current_delay(dr, now, map)) {
+            Ok(path) => path,
+            Err(_) => {
+                return;
+            }
+        };
+        let old_turn = car.router.next();
+        if !car.router.reroute(path, map) {
+            return;
+        }
+        // The driver may have already asked to make the old turn
+        if let Traversable::Turn(t) = old_turn {
+            ctx.intersections
+                .cancel_request(AgentID::Car(car.vehicle.id), t);
+        }
+        self.events.push(Event::PathRerouted(
+            car.vehicle.id,
+            car.trip_and_person.map(|(t, _)| t),
+            reason,
+            car.router.get_path().clone(),
+        ));
+    }
+
     pub fn collect_events(&mut self) -> Vec<Event> {
         std::mem::take(&mut self.events)
     }
@@ -1561,6 +2096,22 @@ impl DrivingSimState {
         }
     }
 
+    /// Acts like a loop detector covering the last `detector_length` of a lane: is any vehicle
+    /// there right now?
+    pub fn detect_vehicle_near_end(&self, now: Time, l: LaneID, detector_length: Distance) -> bool {
+        let queue = match self.queues.get(&Traversable::Lane(l)) {
+            Some(q) => q,
+            None => {
+                return false;
+            }
+        };
+        let start = queue.geom_len - detector_length;
+        queue
+            .get_car_positions(now, &self.cars, &self.queues)
+            .into_iter()
+            .any(|entry| matches!(entry.member, Queued::Vehicle(_)) && entry.front >= start)
+    }
+
     pub fn debug_queue_lengths(&self, l: LaneID) -> Option<(Distance, Distance)> {
         let queue = self.queues.get(&Traversable::Lane(l))?;
         Some((queue.reserved_length, queue.geom_len))
@@ -1606,6 +2157,42 @@ impl DrivingSimState {
         graph
     }
 
+    /// Deterministically decide if a driver knows about current traffic, so the same drivers are
+    /// informed every time a scenario is run.
+    fn is_informed(&self, vehicle: &Vehicle) -> bool {
+        if vehicle.vehicle_type != VehicleType::Car || self.informed_drivers <= 0.0 {
+            return false;
+        }
+        let mut rng = XorShiftRng::seed_from_u64(vehicle.id.id as u64);
+        rng.gen_bool(self.informed_drivers.min(1.0))
+    }
+
+    /// How long would a car entering some road right now be held up by the queues there? If the
+    /// road has a few lanes, assume the car picks the least congested one.
+    fn current_delay(&self, dr: DirectedRoadID, now: Time, map: &Map) -> Duration {
+        dr.lanes(PathConstraints::Car, map)
+            .into_iter()
+            .filter_map(|l| self.queues.get(&Traversable::Lane(l)))
+            .map(|queue| {
+                let mut longest_wait = Duration::ZERO;
+                let mut num_waiting = 0;
+                for id in queue.get_active_cars() {
+                    // The car being updated right now isn't in self.cars
+                    match self.cars.get(&id).map(|car| &car.state) {
+                        Some(CarState::Queued { blocked_since, .. })
+                        | Some(CarState::WaitingToAdvance { blocked_since }) => {
+                            longest_wait = longest_wait.max(now - *blocked_since);
+                            num_waiting += 1;
+                        }
+                        _ => {}
+                    }
+                }
+                longest_wait + (num_waiting as f64) * QUEUE_DISCHARGE_HEADWAY
+            })
+            .min()
+            .unwrap_or(Duration::ZERO)
+    }
+
     fn get_car_front(&self, now: Time, car: &Car) -> Distance {
         self.queues[&car.router.head()]
             .get_car_positions(now, &self.cars, &self.queues)
@@ -1620,6 +2207,11 @@ impl DrivingSimState {
         let queue = &self.queues[&car.router.head()];
         let leader = &self.cars[&queue.get_leader(car.vehicle.id)?];
 
+        // Everybody pulls aside to let an emergency vehicle by, no matter how fast they're going.
+        if car.vehicle.vehicle_type == VehicleType::Emergency {
+            return Some(leader.vehicle.id);
+        }
+
         // Are we faster than them?
         // TODO This shouldn't be a blocking check; we also want to pass parking cars and buses
         // waiting at stops.
@@ -1644,11 +2236,9 @@ impl DrivingSimState {
             _ => {}
         }
 
-        // TODO Check if there's room in front of them to pass. Handle passing two bikes?
-        // TODO Check remaining distance before next intersection
-        // TODO Check relative speed difference, and time to pass them
-        // TODO Eventually, check if there's room to do the maneuever (lane-changing to
-        // the left only, or popping into an oncoming lane!
+        // Whether there's actually room to pass them and enough time before the next
+        // intersection is checked in try_start_lc and try_start_oncoming_pass.
+        // TODO Handle passing two bikes?
 
         Some(leader.vehicle.id)
     }
@@ -1656,6 +2246,24 @@ impl DrivingSimState {
 
 // This implementation relies on the fact that car IDs are unique just by their number. Vehicle
 // type is also in there, but during lookup, it'll be ignored!
+/// An emergency vehicle started along a lane towards a turn. Once it's expected to arrive soon,
+/// it claims the intersection.
+fn emergency_vehicle_heading_to(now: Time, car: &Car, turn: TurnID, ctx: &mut Ctx) {
+    let near = car.state.get_end_time() - EMERGENCY_PREEMPTION_HORIZON;
+    if near <= now {
+        ctx.intersections.emergency_vehicle_approaching(
+            now,
+            car.vehicle.id,
+            turn,
+            ctx.map,
+            ctx.scheduler,
+        );
+    } else {
+        ctx.scheduler
+            .update(near, Command::EmergencyVehicleNear(car.vehicle.id, turn));
+    }
+}
+
 impl IndexableKey for CarID {
     fn index(&self) -> usize {
         self.id
@@ -1,22 +1,29 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_btreemap, prettyprint_usize, serialize_btreemap, FixedMap};
-use geom::{Duration, Time};
+use geom::{Distance, Duration, Time};
 use map_model::{
-    ControlStopSign, ControlTrafficSignal, Intersection, IntersectionID, LaneID, Map, StageType,
-    Traversable, TurnID, TurnPriority, TurnType, UberTurn,
+    ControlStopSign, ControlTrafficSignal, Intersection, IntersectionID, LaneID, Map, Recall,
+    Stage, StageType, TransitPriority, Traversable, TurnID, TurnPriority, TurnType, UberTurn,
 };
 
 use crate::mechanics::car::{Car, CarState};
-use crate::mechanics::Queue;
+use crate::mechanics::{DrivingSimState, Queue};
 use crate::{
     AgentID, AlertLocation, CarID, Command, DelayCause, Event, Scheduler, SimOptions, Speed,
+    VehicleType,
 };
 
 const WAIT_AT_STOP_SIGN: Duration = Duration::const_seconds(0.5);
 const WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL: Duration = Duration::const_seconds(0.2);
+/// When a signal holds a green light for a transit vehicle, give it this much time to get through.
+const TRANSIT_PRIORITY_MARGIN: Duration = Duration::const_seconds(2.0);
+/// Even when ending a stage early for transit, keep it green for at least this long, so nobody's
+/// stranded halfway through a turn by a flicker of green.
+const MIN_GREEN_FOR_TRANSIT_PRIORITY: Duration = Duration::const_seconds(5.0);
 
 /// Manages conflicts at intersections. When an agent has reached the end of a lane, they call
 /// maybe_start_turn to make a Request. Based on the intersection type (stop sign, traffic signal,
@@ -66,18 +73,31 @@ struct State {
     // In some cases, a turn completing at one intersection may affect agents waiting to start an
     // uber-turn at nearby intersections.
     uber_turn_neighbors: Vec<IntersectionID>,
+    // Emergency vehicles approaching this intersection and the turn they'll make. Nobody else may
+    // start a turn crossing their path until they're through.
+    emergency: BTreeSet<Request>,
 
     signal: Option<SignalState>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct SignalState {
+    // Which timing plan is in effect. See ControlTrafficSignal::plan_at.
+    current_plan: usize,
     // The current stage of the signal, zero based
     current_stage: usize,
+    // When the current stage started
+    stage_started_at: Time,
     // The time when the signal is checked for advancing
     stage_ends_at: Time,
     // The number of times a variable signal has been extended during the current stage.
     extensions_count: usize,
+    // Something outside the simulation picks the stage; just hold the current one until told
+    // otherwise.
+    externally_controlled: bool,
+    // Has an approaching transit vehicle already changed when the current stage ends? Only one
+    // adjustment per stage is allowed.
+    transit_priority_used: bool,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Debug)]
@@ -114,6 +134,7 @@ impl IntersectionSimState {
                 waiting: BTreeMap::new(),
                 reserved: BTreeSet::new(),
                 uber_turn_neighbors: Vec::new(),
+                emergency: BTreeSet::new(),
                 signal: None,
             };
             if i.is_traffic_signal() {
@@ -141,6 +162,7 @@ impl IntersectionSimState {
         assert!(state.accepted.remove(&Request { agent, turn }));
 
         state.reserved.remove(&Request { agent, turn });
+        state.emergency.retain(|req| req.agent != agent);
         if !handling_live_edits && map.get_t(turn).turn_type != TurnType::SharedSidewalkCorner {
             self.wakeup_waiting(now, turn.parent, scheduler, map);
         }
@@ -184,6 +206,11 @@ impl IntersectionSimState {
     /// turn.
     pub fn vehicle_gone(&mut self, car: CarID) {
         self.blocked_by.retain(|(c1, c2)| *c1 != car && *c2 != car);
+        if car.vehicle_type == VehicleType::Emergency {
+            for state in self.state.values_mut() {
+                state.emergency.retain(|req| req.agent != AgentID::Car(car));
+            }
+        }
     }
 
     pub fn agent_deleted_mid_turn(&mut self, agent: AgentID, turn: TurnID) {
@@ -194,6 +221,7 @@ impl IntersectionSimState {
         // uber-turn. It's a blunt response to just clear them all out, but it should be correct.
         for state in self.state.values_mut() {
             state.reserved.retain(|req| req.agent != agent);
+            state.emergency.retain(|req| req.agent != agent);
         }
     }
 
@@ -219,8 +247,8 @@ impl IntersectionSimState {
                 protected.push(req);
             }
         } else if let Some(signal) = map.maybe_get_traffic_signal(i) {
-            let current_stage = self.state[&i].signal.as_ref().unwrap().current_stage;
-            let stage = &signal.stages[current_stage];
+            let signal_state = self.state[&i].signal.as_ref().unwrap();
+            let stage = &signal.plan_stages(signal_state.current_plan)[signal_state.current_stage];
             let reserved = &self.state[&i].reserved;
             let i = map.get_i(i);
             for (req, _, _) in all {
@@ -231,9 +259,10 @@ impl IntersectionSimState {
                     TurnPriority::Yield => {
                         yielding.push(req);
                     }
-                    // No need to wake up unless it has reserved
+                    // No need to wake up unless it has reserved, or it's an emergency vehicle that
+                    // doesn't wait for the signal
                     TurnPriority::Banned => {
-                        if reserved.contains(&req) {
+                        if reserved.contains(&req) || is_emergency_vehicle(req.agent) {
                             protected.push(req);
                         }
                     }
@@ -279,47 +308,87 @@ impl IntersectionSimState {
         id: IntersectionID,
         map: &Map,
         scheduler: &mut Scheduler,
+        driving: &DrivingSimState,
     ) {
         let i = map.get_i(id);
 
-        // trivial function that advances the signal stage and returns duration
+        // Advances the signal stage and returns duration. Skips stages that nobody needs.
         fn advance(
             signal_state: &mut SignalState,
-            signal: &ControlTrafficSignal,
-            i: &Intersection,
-            allow_crosswalk_skip: bool,
+            stages: &[Stage],
+            allow_all_walk_skip: &dyn Fn(&Stage) -> bool,
+            has_call: &dyn Fn(&Stage) -> bool,
         ) -> Duration {
-            signal_state.current_stage = (signal_state.current_stage + 1) % signal.stages.len();
-            let stage = &signal.stages[signal_state.current_stage];
-            // only skip for variable all-walk crosswalk
-            if let StageType::Variable(_, _, _) = stage.stage_type {
-                if allow_crosswalk_skip && stage.max_crosswalk_time(i).is_some() {
-                    // we can skip this stage, as its all walk and we're allowed to skip (no
-                    // pedestrian waiting).
-                    signal_state.current_stage =
-                        (signal_state.current_stage + 1) % signal.stages.len();
-                }
-            }
-            signal.stages[signal_state.current_stage]
+            signal_state.current_stage = next_stage(
+                stages,
+                signal_state.current_stage,
+                allow_all_walk_skip,
+                has_call,
+            );
+            stages[signal_state.current_stage]
                 .stage_type
                 .simple_duration()
         }
         let state = self.state.get_mut(&id).unwrap();
         let signal_state = state.signal.as_mut().unwrap();
         let signal = map.get_traffic_signal(id);
+        let emergency_approaching = !state.emergency.is_empty();
         let ped_waiting = state.waiting.keys().any(|req| {
             if let AgentID::Pedestrian(_) = req.agent {
                 return true;
             }
             false
         });
+        // Variable all-walk stages can be skipped when no pedestrian is waiting
+        let allow_all_walk_skip =
+            |stage: &Stage| !ped_waiting && stage.max_crosswalk_time(i).is_some();
+        // A stage is called if somebody's waiting for one of its protected movements, or if a
+        // detector sees a vehicle approaching one of them.
+        let waiting = &state.waiting;
+        let has_call = |stage: &Stage| {
+            waiting
+                .keys()
+                .any(|req| stage.get_priority_of_turn(req.turn, i) == TurnPriority::Protected)
+                || match stage.stage_type {
+                    StageType::Actuated {
+                        detector_length, ..
+                    } => detectors_occupied(stage, i, detector_length, now, driving),
+                    _ => false,
+                }
+        };
         let duration: Duration;
         // Switch to a new stage?
         assert_eq!(now, signal_state.stage_ends_at);
-        let old_stage = &signal.stages[signal_state.current_stage];
+        let stages = signal.plan_stages(signal_state.current_plan);
+        let old_stage = &stages[signal_state.current_stage];
+        let (old_plan_idx, old_stage_idx) = (signal_state.current_plan, signal_state.current_stage);
+        let new_plan = signal.plan_at(now);
         match old_stage.stage_type {
+            _ if signal_state.externally_controlled => {
+                duration = old_stage.stage_type.simple_duration();
+            }
+            // Hold the stage until emergency vehicles are through
+            _ if emergency_approaching => {
+                duration = old_stage.stage_type.simple_duration();
+            }
+            // Every stage has lasted at least its minimum duration by now, so it's safe to
+            // change plans. Start from whatever stage the new plan's offset says, but run it for
+            // its full duration.
+            _ if new_plan != signal_state.current_plan => {
+                let (stage, _) = stage_by_offset(
+                    signal.plan_stages(new_plan),
+                    signal.plan_offset(new_plan),
+                    now,
+                );
+                signal_state.current_plan = new_plan;
+                signal_state.current_stage = stage;
+                signal_state.extensions_count = 0;
+                duration = signal.plan_stages(new_plan)[stage]
+                    .stage_type
+                    .simple_duration();
+            }
             StageType::Fixed(_) => {
-                duration = advance(signal_state, signal, i, !ped_waiting);
+                duration = advance(signal_state, stages, &allow_all_walk_skip, &has_call);
             }
             StageType::Variable(min, delay, additional) => {
                 // test if anyone is waiting in current stage, and if so, extend the signal cycle.
@@ -337,7 +406,7 @@ impl IntersectionSimState {
                             min, delay, additional, signal_state.extensions_count
                         ),
                     ));
-                    duration = advance(signal_state, signal, i, !ped_waiting);
+                    duration = advance(signal_state, stages, &allow_all_walk_skip, &has_call);
                     signal_state.extensions_count = 0;
                 } else if state.waiting.keys().all(|req| {
                     if let AgentID::Pedestrian(_) = req.agent {
@@ -348,7 +417,7 @@ impl IntersectionSimState {
                     old_stage.get_priority_of_turn(req.turn, i) != TurnPriority::Protected
                 }) {
                     signal_state.extensions_count = 0;
-                    duration = advance(signal_state, signal, i, !ped_waiting);
+                    duration = advance(signal_state, stages, &allow_all_walk_skip, &has_call);
                 } else {
                     signal_state.extensions_count += 1;
                     duration = delay;
@@ -361,13 +430,175 @@ impl IntersectionSimState {
                     ));
                 }
             }
+            StageType::Actuated {
+                min_green,
+                passage_time,
+                max_green,
+                detector_length,
+                recall,
+            } => {
+                let demand = recall == Recall::Maximum
+                    || detectors_occupied(old_stage, i, detector_length, now, driving);
+                if let Some(dt) = actuated_extension(
+                    min_green,
+                    passage_time,
+                    max_green,
+                    signal_state.extensions_count,
+                    demand,
+                ) {
+                    signal_state.extensions_count += 1;
+                    duration = dt;
+                } else {
+                    // Either nobody's there anymore and the stage gaps out, or it's maxed out
+                    if demand && recall != Recall::Maximum {
+                        self.events.push(Event::Alert(
+                            AlertLocation::Intersection(id),
+                            format!("Actuated stage maxed out after {}", max_green),
+                        ));
+                    }
+                    signal_state.extensions_count = 0;
+                    duration = advance(signal_state, stages, &allow_all_walk_skip, &has_call);
+                }
+            }
         }
 
+        if (signal_state.current_plan, signal_state.current_stage) != (old_plan_idx, old_stage_idx)
+        {
+            signal_state.stage_started_at = now;
+            signal_state.transit_priority_used = false;
+        }
         signal_state.stage_ends_at = now + duration;
         scheduler.push(signal_state.stage_ends_at, Command::UpdateIntersection(id));
         self.wakeup_waiting(now, id, scheduler, map);
     }
 
+    /// A transit vehicle just started approaching a traffic signal, expecting to reach it at `eta`
+    /// and then make some turn. If the signal gives transit priority, it may hold the current stage
+    /// a little longer so the vehicle doesn't just miss it, or end a stage that doesn't serve the
+    /// vehicle early.
+    pub fn transit_vehicle_approaching(
+        &mut self,
+        now: Time,
+        car: CarID,
+        turn: TurnID,
+        eta: Time,
+        behind_schedule: bool,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
+        let id = turn.parent;
+        let signal = match map.maybe_get_traffic_signal(id) {
+            Some(ts) => ts,
+            None => return,
+        };
+        let tsp = match signal.transit_priority {
+            Some(ref tsp) => tsp,
+            None => return,
+        };
+        if tsp.only_late_vehicles && !behind_schedule {
+            return;
+        }
+        let signal_state = match self.state.get_mut(&id).unwrap().signal.as_mut() {
+            Some(signal_state) => signal_state,
+            None => return,
+        };
+        if signal_state.externally_controlled || signal_state.transit_priority_used {
+            return;
+        }
+
+        let i = map.get_i(id);
+        let stages = signal.plan_stages(signal_state.current_plan);
+        let current_stage = &stages[signal_state.current_stage];
+        let serves_vehicle = current_stage.get_priority_of_turn(turn, i) == TurnPriority::Protected;
+        // Ending this stage early only helps if some stage serves the vehicle
+        if !serves_vehicle
+            && !stages
+                .iter()
+                .any(|stage| stage.get_priority_of_turn(turn, i) == TurnPriority::Protected)
+        {
+            return;
+        }
+        let new_end = match transit_priority_stage_end(
+            tsp,
+            now,
+            eta,
+            serves_vehicle,
+            signal_state.stage_started_at + min_green(&current_stage.stage_type),
+            signal_state.stage_ends_at,
+        ) {
+            Some(t) => t,
+            None => return,
+        };
+
+        self.events.push(Event::TransitSignalPriority(
+            id,
+            car,
+            new_end - signal_state.stage_ends_at,
+        ));
+        signal_state.transit_priority_used = true;
+        signal_state.stage_ends_at = new_end;
+        scheduler.update(new_end, Command::UpdateIntersection(id));
+    }
+
+    /// An emergency vehicle is about to reach an intersection, planning to make some turn.
+    /// Until it's through, nobody else may start a turn crossing its path. If this is a traffic
+    /// signal and the current stage doesn't serve the vehicle, immediately switch to one that
+    /// does, then hold it.
+    pub fn emergency_vehicle_approaching(
+        &mut self,
+        now: Time,
+        car: CarID,
+        turn: TurnID,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
+        let id = turn.parent;
+        let agent = AgentID::Car(car);
+        let state = self.state.get_mut(&id).unwrap();
+        state.emergency.retain(|req| req.agent != agent);
+        state.emergency.insert(Request { agent, turn });
+
+        // If another emergency vehicle is already on its way, don't pull the green out from under
+        // it. This one doesn't need to wait for the signal anyway.
+        if state.emergency.len() > 1 {
+            return;
+        }
+        let signal = match map.maybe_get_traffic_signal(id) {
+            Some(ts) => ts,
+            None => return,
+        };
+        let signal_state = match state.signal.as_mut() {
+            Some(signal_state) => signal_state,
+            None => return,
+        };
+        if signal_state.externally_controlled {
+            return;
+        }
+        let i = map.get_i(id);
+        let stages = signal.plan_stages(signal_state.current_plan);
+        if stages[signal_state.current_stage].get_priority_of_turn(turn, i)
+            == TurnPriority::Protected
+        {
+            return;
+        }
+        let stage = match stages
+            .iter()
+            .position(|stage| stage.get_priority_of_turn(turn, i) == TurnPriority::Protected)
+        {
+            Some(stage) => stage,
+            None => return,
+        };
+
+        signal_state.current_stage = stage;
+        signal_state.stage_started_at = now;
+        signal_state.extensions_count = 0;
+        signal_state.transit_priority_used = false;
+        signal_state.stage_ends_at = now + stages[stage].stage_type.simple_duration();
+        scheduler.update(signal_state.stage_ends_at, Command::UpdateIntersection(id));
+        self.events.push(Event::EmergencySignalPreemption(id, car));
+        self.wakeup_waiting(now, id, scheduler, map);
+    }
+
     /// For cars: The head car calls this when they're at the end of the lane WaitingToAdvance. If
     /// this returns true, then the head car MUST actually start this turn.
     /// For peds: Likewise -- only called when the ped is at the start of the turn. They must
@@ -427,6 +658,10 @@ impl IntersectionSimState {
         {
             // It's never OK to perform a conflicting turn
             false
+        } else if is_emergency_vehicle(agent) {
+            // Emergency vehicles don't wait for signals or stop signs, just for conflicting turns
+            // already in progress
+            true
         } else if maybe_cars_and_queues
             .as_ref()
             .map(|(car, _, _)| started_uber_turn(self, *car))
@@ -445,6 +680,8 @@ impl IntersectionSimState {
             }
 
             true
+        } else if self.must_yield_to_emergency_vehicle(&req, map) {
+            false
         } else if self.use_freeform_policy_everywhere {
             // If we made it this far, we don't conflict with an accepted turn
             true
@@ -569,6 +806,56 @@ impl IntersectionSimState {
         true
     }
 
+    /// Immediately switch a traffic signal to some stage, and keep it there until this is called
+    /// again or the signal is released.
+    pub fn force_signal_stage(
+        &mut self,
+        now: Time,
+        id: IntersectionID,
+        stage: usize,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) -> Result<()> {
+        let signal = map
+            .maybe_get_traffic_signal(id)
+            .ok_or_else(|| anyhow!("{} isn't a traffic signal", id))?;
+        let signal_state = self
+            .state
+            .get_mut(&id)
+            .unwrap()
+            .signal
+            .as_mut()
+            .ok_or_else(|| anyhow!("{} isn't a traffic signal", id))?;
+        let stages = signal.plan_stages(signal_state.current_plan);
+        if stage >= stages.len() {
+            bail!(
+                "{} only has {} stages, so can't switch to stage {}",
+                id,
+                stages.len(),
+                stage
+            );
+        }
+        signal_state.externally_controlled = true;
+        if signal_state.current_stage == stage {
+            return Ok(());
+        }
+        signal_state.current_stage = stage;
+        signal_state.stage_started_at = now;
+        signal_state.extensions_count = 0;
+        signal_state.transit_priority_used = false;
+        signal_state.stage_ends_at = now + stages[stage].stage_type.simple_duration();
+        scheduler.update(signal_state.stage_ends_at, Command::UpdateIntersection(id));
+        self.wakeup_waiting(now, id, scheduler, map);
+        Ok(())
+    }
+
+    /// Let a traffic signal resume its normal timing, starting from its current stage.
+    pub fn release_signal(&mut self, id: IntersectionID) {
+        if let Some(signal_state) = self.state.get_mut(&id).and_then(|s| s.signal.as_mut()) {
+            signal_state.externally_controlled = false;
+        }
+    }
+
     pub fn collect_events(&mut self) -> Vec<Event> {
         std::mem::take(&mut self.events)
     }
@@ -585,9 +872,15 @@ impl IntersectionSimState {
                 state.signal.as_mut(),
             ) {
                 (Some(ts), Some(signal_state)) => {
-                    if signal_state.current_stage >= ts.stages.len() {
+                    if signal_state.current_plan > ts.plans.len() {
+                        // The plan was deleted; switch at the end of this stage.
+                        signal_state.current_plan = 0;
+                    }
+                    if signal_state.current_stage >= ts.plan_stages(signal_state.current_plan).len()
+                    {
                         // Just jump back to the first one. Shrug.
                         signal_state.current_stage = 0;
+                        signal_state.stage_started_at = now;
                         println!(
                             "WARNING: Traffic signal {} was live-edited in the middle of a stage, \
                              so jumping back to the first stage",
@@ -716,6 +1009,11 @@ impl IntersectionSimState {
         (state.current_stage, state.stage_ends_at - now)
     }
 
+    /// Which timing plan a traffic signal is using. See `ControlTrafficSignal::plan_at`.
+    pub fn current_signal_plan(&self, i: IntersectionID) -> usize {
+        self.state[&i].signal.as_ref().unwrap().current_plan
+    }
+
     pub fn describe_stats(&self) -> Vec<String> {
         vec![
             "intersection stats".to_string(),
@@ -803,6 +1101,16 @@ impl IntersectionSimState {
 
 // Stuff to support maybe_start_turn
 impl IntersectionSimState {
+    /// Is an emergency vehicle approaching and about to cross the path of this turn? Agents in
+    /// the same lane as the emergency vehicle still go, since they're in its way.
+    fn must_yield_to_emergency_vehicle(&self, req: &Request, map: &Map) -> bool {
+        let turn = map.get_t(req.turn);
+        self.state[&req.turn.parent]
+            .emergency
+            .iter()
+            .any(|e| e.turn.src != req.turn.src && map.get_t(e.turn).conflicts_with(turn))
+    }
+
     fn stop_sign_policy(
         &mut self,
         req: &Request,
@@ -857,7 +1165,7 @@ impl IntersectionSimState {
 
         let state = &self.state[&req.turn.parent];
         let signal_state = state.signal.as_ref().unwrap();
-        let stage = &signal.stages[signal_state.current_stage];
+        let stage = &signal.plan_stages(signal_state.current_plan)[signal_state.current_stage];
         let full_stage_duration = stage.stage_type.simple_duration();
         let remaining_stage_time = signal_state.stage_ends_at - now;
         let (our_time, _) = state.waiting[req];
@@ -1034,35 +1342,166 @@ impl IntersectionSimState {
 
 impl SignalState {
     fn new(id: IntersectionID, now: Time, map: &Map, scheduler: &mut Scheduler) -> SignalState {
-        let mut state = SignalState {
-            current_stage: 0,
-            stage_ends_at: now,
+        let signal = map.get_traffic_signal(id);
+        let current_plan = signal.plan_at(now);
+        // What stage are we starting with?
+        let (current_stage, remaining) = stage_by_offset(
+            signal.plan_stages(current_plan),
+            signal.plan_offset(current_plan),
+            now,
+        );
+        let state = SignalState {
+            current_plan,
+            current_stage,
+            // Part of the stage might've already passed, but conservatively say it just started
+            stage_started_at: now,
+            stage_ends_at: now + remaining,
             extensions_count: 0,
+            externally_controlled: false,
+            transit_priority_used: false,
+        };
+        scheduler.push(state.stage_ends_at, Command::UpdateIntersection(id));
+        state
+    }
+}
+
+/// When should a stage end, given a transit vehicle arriving at `eta`? A stage serving the vehicle
+/// is extended if it'd otherwise just miss the green. A stage not serving it ends early, but not
+/// before `earliest_end`. None means no change.
+fn transit_priority_stage_end(
+    tsp: &TransitPriority,
+    now: Time,
+    eta: Time,
+    serves_vehicle: bool,
+    earliest_end: Time,
+    stage_ends_at: Time,
+) -> Option<Time> {
+    let new_end = if serves_vehicle {
+        if eta < stage_ends_at || eta >= stage_ends_at + tsp.max_extension {
+            return None;
+        }
+        (eta + TRANSIT_PRIORITY_MARGIN).min(stage_ends_at + tsp.max_extension)
+    } else {
+        // Careful not to go before midnight
+        let shortened = if stage_ends_at - now > tsp.max_early_end {
+            stage_ends_at - tsp.max_early_end
+        } else {
+            now
         };
+        shortened.max(earliest_end)
+    };
+    if new_end == stage_ends_at || (!serves_vehicle && new_end > stage_ends_at) {
+        return None;
+    }
+    Some(new_end)
+}
 
-        let signal = map.get_traffic_signal(id);
-        // What stage are we starting with?
-        let mut offset = (now - Time::START_OF_DAY) + signal.offset;
-        loop {
-            let dt = signal.stages[state.current_stage]
-                .stage_type
-                .simple_duration();
-            if offset >= dt {
-                offset -= dt;
-                state.current_stage += 1;
-                if state.current_stage == signal.stages.len() {
-                    state.current_stage = 0;
-                }
-            } else {
-                state.stage_ends_at = now + dt - offset;
-                break;
+/// A stage ended early for transit still has to stay green for at least this long.
+fn min_green(stage_type: &StageType) -> Duration {
+    match stage_type {
+        StageType::Fixed(_) => MIN_GREEN_FOR_TRANSIT_PRIORITY,
+        StageType::Variable(min, _, _) => (*min).max(MIN_GREEN_FOR_TRANSIT_PRIORITY),
+        StageType::Actuated { min_green, .. } => (*min_green).max(MIN_GREEN_FOR_TRANSIT_PRIORITY),
+    }
+}
+
+/// If a plan had been running since midnight, which stage would it be in now, and how much time
+/// would be left in that stage?
+fn stage_by_offset(stages: &[Stage], offset: Duration, now: Time) -> (usize, Duration) {
+    let cycle: Duration = stages.iter().map(|s| s.stage_type.simple_duration()).sum();
+    let mut offset = (now - Time::START_OF_DAY) + offset;
+    if cycle > Duration::ZERO {
+        offset = offset % cycle;
+    }
+    let mut idx = 0;
+    loop {
+        let dt = stages[idx].stage_type.simple_duration();
+        if offset >= dt {
+            offset -= dt;
+            idx += 1;
+            if idx == stages.len() {
+                idx = 0;
             }
+        } else {
+            return (idx, dt - offset);
         }
-        scheduler.push(state.stage_ends_at, Command::UpdateIntersection(id));
-        state
     }
 }
 
+/// Returns the stage after `current` that should be served next. Actuated stages without recall
+/// are skipped if nobody's calling them, and variable all-walk stages may be skipped. If every
+/// other stage is skipped, stays in `current`.
+fn next_stage(
+    stages: &[Stage],
+    current: usize,
+    allow_all_walk_skip: &dyn Fn(&Stage) -> bool,
+    has_call: &dyn Fn(&Stage) -> bool,
+) -> usize {
+    let mut idx = current;
+    for _ in 0..stages.len() {
+        idx = (idx + 1) % stages.len();
+        let stage = &stages[idx];
+        let skip = match stage.stage_type {
+            StageType::Fixed(_) => false,
+            StageType::Variable(_, _, _) => allow_all_walk_skip(stage),
+            StageType::Actuated { recall, .. } => recall == Recall::None && !has_call(stage),
+        };
+        if !skip {
+            break;
+        }
+    }
+    idx
+}
+
+/// An actuated stage has been green for its minimum time, plus `extensions_count` extensions. If
+/// there's still demand, returns how much longer to extend it, never going past the maximum
+/// green. Returns None when the stage should end.
+fn actuated_extension(
+    min_green: Duration,
+    passage_time: Duration,
+    max_green: Duration,
+    extensions_count: usize,
+    demand: bool,
+) -> Option<Duration> {
+    let passage_time = std::cmp::max(Duration::const_seconds(1.0), passage_time);
+    // The stage is always extended by the passage time, except for maybe the last extension
+    let elapsed = std::cmp::min(
+        min_green + passage_time * (extensions_count as f64),
+        max_green,
+    );
+    if elapsed >= max_green || !demand {
+        return None;
+    }
+    Some(std::cmp::min(passage_time, max_green - elapsed))
+}
+
+/// Are the virtual loop detectors on any lane feeding one of the stage's protected vehicle
+/// movements occupied?
+fn detectors_occupied(
+    stage: &Stage,
+    i: &Intersection,
+    detector_length: Distance,
+    now: Time,
+    driving: &DrivingSimState,
+) -> bool {
+    let mut lanes: BTreeSet<LaneID> = BTreeSet::new();
+    for m in &stage.protected_movements {
+        if m.crosswalk {
+            continue;
+        }
+        for t in &i.movements[m].members {
+            lanes.insert(t.src);
+        }
+    }
+    lanes
+        .into_iter()
+        .any(|l| driving.detect_vehicle_near_end(now, l, detector_length))
+}
+
+fn is_emergency_vehicle(agent: AgentID) -> bool {
+    matches!(agent, AgentID::Car(c) if c.vehicle_type == VehicleType::Emergency)
+}
+
 fn allow_block_the_box(i: &Intersection) -> bool {
     // Degenerate intersections are often just artifacts of how roads are split up in OSM. Allow
     // vehicles to get stuck in them, since the only possible thing they could block is pedestrians
@@ -1102,3 +1541,144 @@ fn allow_block_the_box(i: &Intersection) -> bool {
     }
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transit_priority() {
+        let tsp = TransitPriority {
+            max_extension: Duration::seconds(10.0),
+            max_early_end: Duration::seconds(15.0),
+            only_late_vehicles: false,
+        };
+        let t = |secs: f64| Time::START_OF_DAY + Duration::seconds(secs);
+        // A stage started at 0s, and normally ends at 30s. Nobody can end it before 5s.
+        let earliest_end = t(5.0);
+        let stage_ends_at = t(30.0);
+
+        // The stage serves the vehicle, which would just miss the green, so extend it
+        assert_eq!(
+            transit_priority_stage_end(&tsp, t(20.0), t(34.0), true, earliest_end, stage_ends_at),
+            Some(t(36.0))
+        );
+        // ... but not by more than the maximum
+        assert_eq!(
+            transit_priority_stage_end(&tsp, t(20.0), t(39.0), true, earliest_end, stage_ends_at),
+            Some(t(40.0))
+        );
+        // No need to extend if the vehicle makes it anyway, or if it wouldn't help
+        assert_eq!(
+            transit_priority_stage_end(&tsp, t(20.0), t(25.0), true, earliest_end, stage_ends_at),
+            None
+        );
+        assert_eq!(
+            transit_priority_stage_end(&tsp, t(20.0), t(45.0), true, earliest_end, stage_ends_at),
+            None
+        );
+
+        // The stage doesn't serve the vehicle, so end it early
+        assert_eq!(
+            transit_priority_stage_end(&tsp, t(10.0), t(25.0), false, earliest_end, stage_ends_at),
+            Some(t(15.0))
+        );
+        // ... but not before now
+        assert_eq!(
+            transit_priority_stage_end(&tsp, t(20.0), t(25.0), false, earliest_end, stage_ends_at),
+            Some(t(20.0))
+        );
+        // ... and not before the minimum green is over. If the stage just started, it still gets
+        // that much green.
+        assert_eq!(
+            transit_priority_stage_end(&tsp, t(1.0), t(25.0), false, earliest_end, t(12.0)),
+            Some(t(5.0))
+        );
+        // A stage that's already at its minimum isn't cut any shorter
+        assert_eq!(
+            transit_priority_stage_end(&tsp, t(1.0), t(25.0), false, earliest_end, t(5.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_stage_by_offset() {
+        let stages: Vec<Stage> = vec![30.0, 20.0, 10.0]
+            .into_iter()
+            .map(|secs| {
+                let mut stage = Stage::new();
+                stage.stage_type = StageType::Fixed(Duration::seconds(secs));
+                stage
+            })
+            .collect();
+        let secs = Duration::seconds;
+        let at = |offset: f64, now: f64| {
+            stage_by_offset(&stages, secs(offset), Time::START_OF_DAY + secs(now))
+        };
+
+        // The 60s cycle started at midnight
+        assert_eq!(at(0.0, 0.0), (0, secs(30.0)));
+        assert_eq!(at(0.0, 30.0), (1, secs(20.0)));
+        assert_eq!(at(0.0, 55.0), (2, secs(5.0)));
+        // ... and repeats
+        assert_eq!(at(0.0, 3600.0 + 45.0), (1, secs(5.0)));
+        // An offset shifts the whole cycle. A plan switching in at 7am starts partway through.
+        assert_eq!(at(10.0, 45.0), (2, secs(5.0)));
+        assert_eq!(at(10.0, 7.0 * 3600.0), (0, secs(20.0)));
+    }
+
+    #[test]
+    fn test_actuated_extension() {
+        let secs = Duration::seconds;
+        let extend = |extensions_count, demand| {
+            actuated_extension(secs(10.0), secs(4.0), secs(20.0), extensions_count, demand)
+        };
+        // A waiting vehicle keeps extending the stage by the passage time...
+        assert_eq!(extend(0, true), Some(secs(4.0)));
+        assert_eq!(extend(1, true), Some(secs(4.0)));
+        // ... and the last extension stops exactly at the maximum green
+        assert_eq!(extend(2, true), Some(secs(2.0)));
+        // ... after which the stage ends, even though somebody's still there
+        assert_eq!(extend(3, true), None);
+        // Without any demand, the stage gaps out
+        assert_eq!(extend(0, false), None);
+        assert_eq!(extend(1, false), None);
+    }
+
+    #[test]
+    fn test_actuated_recall() {
+        let actuated = |recall| {
+            let mut stage = Stage::new();
+            stage.stage_type = StageType::Actuated {
+                min_green: Duration::seconds(10.0),
+                passage_time: Duration::seconds(4.0),
+                max_green: Duration::seconds(30.0),
+                detector_length: Distance::meters(20.0),
+                recall,
+            };
+            stage
+        };
+        let stages = vec![
+            Stage::new(),
+            actuated(Recall::None),
+            actuated(Recall::Minimum),
+            actuated(Recall::None),
+        ];
+        let no_all_walk_skip = |_: &Stage| false;
+
+        // Nobody's calling the actuated stages, so only the one with recall is served
+        let no_calls = |_: &Stage| false;
+        assert_eq!(next_stage(&stages, 0, &no_all_walk_skip, &no_calls), 2);
+        assert_eq!(next_stage(&stages, 2, &no_all_walk_skip, &no_calls), 0);
+
+        // A call brings back a stage without recall
+        let called =
+            |stage: &Stage| std::ptr::eq(stage, &stages[1]) || std::ptr::eq(stage, &stages[3]);
+        assert_eq!(next_stage(&stages, 0, &no_all_walk_skip, &called), 1);
+        assert_eq!(next_stage(&stages, 2, &no_all_walk_skip, &called), 3);
+
+        // If every other stage is skipped, stay in the current one
+        let stages = vec![actuated(Recall::None), actuated(Recall::None)];
+        assert_eq!(next_stage(&stages, 1, &no_all_walk_skip, &no_calls), 1);
+    }
+}
@@ -9,7 +9,7 @@ use abstutil::{
     deserialize_btreemap, deserialize_multimap, serialize_btreemap, serialize_multimap, MultiMap,
     Timer,
 };
-use geom::{Distance, PolyLine, Pt2D};
+use geom::{Distance, PolyLine, Pt2D, Time};
 use map_model::{
     BuildingID, Lane, LaneID, LaneType, Map, OffstreetParking, ParkingLotID, PathConstraints,
     PathStep, Position, Traversable, TurnID,
@@ -43,7 +43,8 @@ pub trait ParkingSim {
     fn is_free(&self, spot: ParkingSpot) -> bool;
     fn get_car_at_spot(&self, spot: ParkingSpot) -> Option<&ParkedCar>;
     /// The vehicle's front is currently at the given driving_pos. Returns all valid spots and their
-    /// driving position.
+    /// driving position. During permit hours, on-street spots in a residential permit zone are
+    /// only valid for residents.
     fn get_all_free_spots(
         &self,
         driving_pos: Position,
@@ -51,6 +52,7 @@ pub trait ParkingSim {
         // Either the building where a seeded car starts or the target of a trip. For filtering
         // private spots.
         target: BuildingID,
+        now: Time,
         map: &Map,
     ) -> Vec<(ParkingSpot, Position)>;
     fn spot_to_driving_pos(&self, spot: ParkingSpot, vehicle: &Vehicle, map: &Map) -> Position;
@@ -70,11 +72,18 @@ pub trait ParkingSim {
         start: LaneID,
         vehicle: &Vehicle,
         target: BuildingID,
+        now: Time,
         map: &Map,
     ) -> Option<(Vec<PathStep>, ParkingSpot, Position)>;
     fn collect_events(&mut self) -> Vec<Event>;
     fn all_parked_car_positions(&self, map: &Map) -> Vec<(Position, PersonID)>;
     fn bldg_to_parked_cars(&self, b: BuildingID) -> Vec<CarID>;
+    /// Record where somebody lives, so they can park in the residential permit zone around their
+    /// home.
+    fn register_home(&mut self, person: PersonID, home: BuildingID);
+    /// Would this vehicle be turned away from on-street parking near a building right now, because
+    /// the building's in a residential permit zone and the owner doesn't live there?
+    fn needs_permit(&self, vehicle: &Vehicle, b: BuildingID, now: Time, map: &Map) -> bool;
 }
 
 #[enum_dispatch]
@@ -147,6 +156,13 @@ pub struct NormalParkingSimState {
     )]
     driving_to_lots: MultiMap<LaneID, ParkingLotID>,
 
+    /// Where people live, for residential permit zones
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    homes: BTreeMap<PersonID, BuildingID>,
+
     events: Vec<Event>,
 }
 
@@ -164,6 +180,8 @@ impl NormalParkingSimState {
             num_spots_per_lot: BTreeMap::new(),
             driving_to_lots: MultiMap::new(),
 
+            homes: BTreeMap::new(),
+
             events: Vec::new(),
         };
         for l in map.all_lanes() {
@@ -211,6 +229,18 @@ impl NormalParkingSimState {
 
         sim
     }
+
+    /// May this vehicle park on-street along a lane right now?
+    fn has_permit(&self, l: LaneID, vehicle: &Vehicle, now: Time, map: &Map) -> bool {
+        match map.get_permit_zone_for_lane(l) {
+            Some(zone) if zone.in_effect(now) => vehicle
+                .owner
+                .and_then(|p| self.homes.get(&p))
+                .map(|b| zone.is_resident(*b, map))
+                .unwrap_or(false),
+            _ => true,
+        }
+    }
 }
 
 impl ParkingSim for NormalParkingSimState {
@@ -446,11 +476,15 @@ impl ParkingSim for NormalParkingSimState {
         // Either the building where a seeded car starts or the target of a trip. For filtering
         // private spots.
         target: BuildingID,
+        now: Time,
         map: &Map,
     ) -> Vec<(ParkingSpot, Position)> {
         let mut candidates = Vec::new();
 
         for l in self.driving_to_parking_lanes.get(driving_pos.lane()) {
+            if !self.has_permit(*l, vehicle, now, map) {
+                continue;
+            }
             for spot in self.onstreet_lanes[l].spots() {
                 if self.is_free(spot)
                     && driving_pos.dist_along()
@@ -563,6 +597,7 @@ impl ParkingSim for NormalParkingSimState {
         start: LaneID,
         vehicle: &Vehicle,
         target: BuildingID,
+        now: Time,
         map: &Map,
     ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
         let mut backrefs: HashMap<LaneID, TurnID> = HashMap::new();
@@ -579,31 +614,35 @@ impl ParkingSim for NormalParkingSimState {
         let mut rng =
             XorShiftRng::seed_from_u64((vehicle.id.id + start.encode_u32() as usize) as u64);
 
-        while !queue.is_empty() {
-            let (dist_so_far, current) = queue.pop().unwrap();
+        // The cheapest spot found so far, weighing price against how far away it is
+        let mut best: Option<(Distance, LaneID, ParkingSpot, Position)> = None;
+        while let Some((dist_so_far, current)) = queue.pop() {
+            // Every lane left is farther away than the cost of the best spot, so stop
+            if let Some((cost, _, _, _)) = best {
+                if -dist_so_far >= cost {
+                    break;
+                }
+            }
             // If the current lane has a spot open, we wouldn't be asking. This can happen if a spot
             // opens up on the 'start' lane, but behind the car.
             if current != start {
-                // Pick the closest to the start of the lane, since that's closest to where we came
-                // from
-                if let Some((spot, pos)) = self
-                    .get_all_free_spots(Position::start(current), vehicle, target, map)
+                // Spots closer to the start of the lane are closer to where we came from. Cheaper
+                // spots might be worth driving a bit farther.
+                if let Some((cost, spot, pos)) = self
+                    .get_all_free_spots(Position::start(current), vehicle, target, now, map)
                     .into_iter()
-                    .min_by_key(|(_, pos)| pos.dist_along())
+                    .map(|(spot, pos)| {
+                        let cost =
+                            -dist_so_far + pos.dist_along() + spot.price_as_distance(now, map);
+                        (cost, spot, pos)
+                    })
+                    .min_by_key(|(cost, _, _)| *cost)
                 {
-                    let mut steps = vec![PathStep::Lane(current)];
-                    let mut current = current;
-                    loop {
-                        if current == start {
-                            // Don't include PathStep::Lane(start)
-                            steps.pop();
-                            steps.reverse();
-                            return Some((steps, spot, pos));
-                        }
-                        let turn = backrefs[&current];
-                        steps.push(PathStep::Turn(turn));
-                        steps.push(PathStep::Lane(turn.src));
-                        current = turn.src;
+                    if best
+                        .map(|(best_cost, _, _, _)| cost < best_cost)
+                        .unwrap_or(true)
+                    {
+                        best = Some((cost, current, spot, pos));
                     }
                 }
             }
@@ -625,7 +664,8 @@ impl ParkingSim for NormalParkingSimState {
             }
         }
 
-        None
+        let (_, end, spot, pos) = best?;
+        Some((trace_back(start, end, &backrefs), spot, pos))
     }
 
     fn collect_events(&mut self) -> Vec<Event> {
@@ -654,6 +694,33 @@ impl ParkingSim for NormalParkingSimState {
         }
         cars
     }
+
+    fn register_home(&mut self, person: PersonID, home: BuildingID) {
+        self.homes.insert(person, home);
+    }
+
+    fn needs_permit(&self, vehicle: &Vehicle, b: BuildingID, now: Time, map: &Map) -> bool {
+        !self.has_permit(map.get_b(b).sidewalk(), vehicle, now, map)
+    }
+}
+
+/// Using backreferences from a search starting at `start`, produce the path from `start` to `end`,
+/// not including the `start` lane.
+fn trace_back(start: LaneID, end: LaneID, backrefs: &HashMap<LaneID, TurnID>) -> Vec<PathStep> {
+    let mut steps = vec![PathStep::Lane(end)];
+    let mut current = end;
+    loop {
+        if current == start {
+            // Don't include PathStep::Lane(start)
+            steps.pop();
+            steps.reverse();
+            return steps;
+        }
+        let turn = backrefs[&current];
+        steps.push(PathStep::Turn(turn));
+        steps.push(PathStep::Lane(turn.src));
+        current = turn.src;
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -670,6 +737,10 @@ impl ParkingLane {
         if lane.lane_type != LaneType::Parking {
             return None;
         }
+        // Loading zones are only for freight vehicles making deliveries, which don't park
+        if map.is_loading_zone(lane.id) {
+            return None;
+        }
 
         let driving_lane = if let Some(l) = map.get_parent(lane.id).parking_to_driving(lane.id) {
             l
@@ -934,6 +1005,7 @@ impl ParkingSim for InfiniteParkingSimState {
         driving_pos: Position,
         vehicle: &Vehicle,
         target: BuildingID,
+        _: Time,
         map: &Map,
     ) -> Vec<(ParkingSpot, Position)> {
         // The target building may be blackholed, so fallback to a building on one of the
@@ -989,6 +1061,7 @@ impl ParkingSim for InfiniteParkingSimState {
         start: LaneID,
         vehicle: &Vehicle,
         target: BuildingID,
+        now: Time,
         map: &Map,
     ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
         // TODO This impl is copied from NormalParkingSimState. Instead, we already know the
@@ -1000,31 +1073,35 @@ impl ParkingSim for InfiniteParkingSimState {
         let mut queue: BinaryHeap<(Distance, LaneID)> = BinaryHeap::new();
         queue.push((Distance::ZERO, start));
 
-        while !queue.is_empty() {
-            let (dist_so_far, current) = queue.pop().unwrap();
+        // The cheapest spot found so far, weighing price against how far away it is
+        let mut best: Option<(Distance, LaneID, ParkingSpot, Position)> = None;
+        while let Some((dist_so_far, current)) = queue.pop() {
+            // Every lane left is farther away than the cost of the best spot, so stop
+            if let Some((cost, _, _, _)) = best {
+                if -dist_so_far >= cost {
+                    break;
+                }
+            }
             // If the current lane has a spot open, we wouldn't be asking. This can happen if a spot
             // opens up on the 'start' lane, but behind the car.
             if current != start {
-                // Pick the closest to the start of the lane, since that's closest to where we came
-                // from
-                if let Some((spot, pos)) = self
-                    .get_all_free_spots(Position::start(current), vehicle, target, map)
+                // Spots closer to the start of the lane are closer to where we came from. Cheaper
+                // spots might be worth driving a bit farther.
+                if let Some((cost, spot, pos)) = self
+                    .get_all_free_spots(Position::start(current), vehicle, target, now, map)
                     .into_iter()
-                    .min_by_key(|(_, pos)| pos.dist_along())
+                    .map(|(spot, pos)| {
+                        let cost =
+                            -dist_so_far + pos.dist_along() + spot.price_as_distance(now, map);
+                        (cost, spot, pos)
+                    })
+                    .min_by_key(|(cost, _, _)| *cost)
                 {
-                    let mut steps = vec![PathStep::Lane(current)];
-                    let mut current = current;
-                    loop {
-                        if current == start {
-                            // Don't include PathStep::Lane(start)
-                            steps.pop();
-                            steps.reverse();
-                            return Some((steps, spot, pos));
-                        }
-                        let turn = backrefs[&current];
-                        steps.push(PathStep::Turn(turn));
-                        steps.push(PathStep::Lane(turn.src));
-                        current = turn.src;
+                    if best
+                        .map(|(best_cost, _, _, _)| cost < best_cost)
+                        .unwrap_or(true)
+                    {
+                        best = Some((cost, current, spot, pos));
                     }
                 }
             }
@@ -1038,7 +1115,8 @@ impl ParkingSim for InfiniteParkingSimState {
             }
         }
 
-        None
+        let (_, end, spot, pos) = best?;
+        Some((trace_back(start, end, &backrefs), spot, pos))
     }
 
     fn collect_events(&mut self) -> Vec<Event> {
@@ -1069,4 +1147,11 @@ impl ParkingSim for InfiniteParkingSimState {
         }
         cars
     }
+
+    // On-street parking isn't used at all, so permit zones don't matter.
+    fn register_home(&mut self, _: PersonID, _: BuildingID) {}
+
+    fn needs_permit(&self, _: &Vehicle, _: BuildingID, _: Time, _: &Map) -> bool {
+        false
+    }
 }
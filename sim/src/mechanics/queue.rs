@@ -242,8 +242,14 @@ impl Queue {
                             ref new_dist,
                             ..
                         } => {
-                            // Same as the Crossing logic
-                            new_dist.lerp(new_time.percent_clamp_end(now)).min(bound)
+                            // Same as the Crossing logic. While passing using an oncoming lane,
+                            // the car doesn't start moving from its new place until the pass is
+                            // done.
+                            if now < new_time.start {
+                                new_dist.start.min(bound)
+                            } else {
+                                new_dist.lerp(new_time.percent_clamp_end(now)).min(bound)
+                            }
                         }
                         CarState::Unparking { front, .. } => front,
                         CarState::Parking(front, _, _) => front,
@@ -554,6 +560,13 @@ impl Queue {
     pub fn is_car_at_front(&self, car: CarID) -> bool {
         self.laggy_head.is_none() && self.members.get(0) == Some(&Queued::Vehicle(car))
     }
+
+    /// True if nothing is in this queue, and nobody has reserved space to enter it.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+            && self.laggy_head.is_none()
+            && self.reserved_length == Distance::ZERO
+    }
 }
 
 fn validate_positions(
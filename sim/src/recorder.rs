@@ -78,6 +78,9 @@ impl TrafficRecorder {
             map_name: map.get_name().clone(),
             people,
             only_seed_buses: None,
+            ride_hail: None,
+            emergency_calls: Vec::new(),
+            deliveries: Vec::new(),
         }
         .save();
     }
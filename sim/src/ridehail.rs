@@ -0,0 +1,502 @@
+//! On-demand ride-hail vehicles (or taxis, or microtransit) without fixed routes. A dispatcher
+//! assigns each request to a vehicle in the fleet. The vehicle drives empty to the passenger
+//! ("deadheading"), picks them up, and drives them to their destination. Then it either takes the
+//! oldest request still waiting for a vehicle, or cruises back to its depot.
+//!
+//! Passengers wait inside the building where their trip starts, and get dropped off right at the
+//! building where it ends. Idle vehicles wait off the road at their depot. Vehicles cruising back
+//! to their depot aren't dispatched until they get there.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Time};
+use map_model::{BuildingID, Map, PathConstraints, PathRequest, Position};
+
+use crate::sim::Ctx;
+use crate::{
+    CarID, Command, CreateCar, Event, Router, TripID, Vehicle, VehicleSpec, VehicleType,
+    MIN_CAR_LENGTH,
+};
+
+/// A fleet of ride-hail vehicles serving every `TripMode::RideHail` trip in a scenario.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RideHailFleet {
+    /// How many vehicles start at each depot. Vehicles return to their own depot when nobody needs
+    /// a ride.
+    pub depots: Vec<(BuildingID, usize)>,
+    pub dispatch: DispatchPolicy,
+}
+
+/// How to pick a vehicle for a new request, when several are idle.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DispatchPolicy {
+    /// Assign the idle vehicle with the fastest route to the pickup
+    NearestIdleVehicle,
+    /// Assign the vehicle that's been idle the longest, no matter how far away it is
+    LongestIdleVehicle,
+}
+
+/// What a ride-hail vehicle is doing, for measuring fleet utilization.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum RideHailActivity {
+    /// Waiting at a depot for a request
+    Idle,
+    /// Driving empty to pick somebody up
+    Deadheading,
+    /// Driving a passenger, including the time spent picking them up and dropping them off
+    Occupied,
+    /// Driving empty back to the depot
+    Cruising,
+}
+
+/// What should happen when a ride-hail vehicle reaches the end of its route?
+pub(crate) enum RideHailStop {
+    /// The passenger gets in
+    Pickup(TripID, BuildingID),
+    /// The passenger gets out, after riding some distance
+    Dropoff(TripID, BuildingID, Distance),
+    /// Nobody gets in or out; wait a moment before leaving
+    Wait,
+    /// The vehicle is back at its depot and should vanish until it's needed again
+    Vanish,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct RideHailSimState {
+    dispatch: DispatchPolicy,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    vehicles: BTreeMap<CarID, FleetVehicle>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    requests: BTreeMap<TripID, Request>,
+    /// Requests without a vehicle assigned yet, oldest first
+    pending: VecDeque<TripID>,
+
+    events: Vec<Event>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct FleetVehicle {
+    vehicle: Vehicle,
+    depot: BuildingID,
+    state: VehicleState,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+enum VehicleState {
+    /// Off the road at the depot since some time
+    Idle(Time),
+    ToPickup(TripID),
+    ToDropoff(TripID),
+    /// Stopped at the end of the route. If a passenger is inside, they're headed somewhere.
+    Stopped(Option<TripID>),
+    ToDepot,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Request {
+    pickup: BuildingID,
+    dropoff: BuildingID,
+    requested_at: Time,
+    /// Set once the passenger is in the vehicle
+    boarded: bool,
+}
+
+impl RideHailSimState {
+    pub fn new() -> RideHailSimState {
+        RideHailSimState {
+            dispatch: DispatchPolicy::NearestIdleVehicle,
+            vehicles: BTreeMap::new(),
+            requests: BTreeMap::new(),
+            pending: VecDeque::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Each vehicle starts idle at its depot. `new_car_id` hands out globally unique IDs.
+    pub fn seed_fleet<F: FnMut() -> usize>(
+        &mut self,
+        now: Time,
+        fleet: RideHailFleet,
+        mut new_car_id: F,
+        map: &Map,
+    ) {
+        self.dispatch = fleet.dispatch;
+        for (depot, count) in fleet.depots {
+            if map.get_b(depot).driving_connection(map).is_none() {
+                warn!(
+                    "Skipping {} ride-hail vehicles at {}; cars can't leave from there",
+                    count, depot
+                );
+                continue;
+            }
+            for _ in 0..count {
+                let vehicle = VehicleSpec {
+                    vehicle_type: VehicleType::Car,
+                    length: MIN_CAR_LENGTH,
+                    max_speed: None,
+                }
+                .make(
+                    CarID {
+                        id: new_car_id(),
+                        vehicle_type: VehicleType::Car,
+                    },
+                    None,
+                );
+                self.events
+                    .push(Event::RideHailActivity(vehicle.id, RideHailActivity::Idle));
+                self.vehicles.insert(
+                    vehicle.id,
+                    FleetVehicle {
+                        vehicle,
+                        depot,
+                        state: VehicleState::Idle(now),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Somebody inside `pickup` wants a ride to `dropoff`. If an idle vehicle is available, it
+    /// starts driving there immediately. Otherwise the request waits for the next vehicle to free
+    /// up.
+    pub fn request(
+        &mut self,
+        now: Time,
+        trip: TripID,
+        pickup: BuildingID,
+        dropoff: BuildingID,
+        ctx: &mut Ctx,
+    ) -> Result<()> {
+        if self.vehicles.is_empty() {
+            bail!("there are no ride-hail vehicles");
+        }
+        for b in [pickup, dropoff] {
+            if ctx.map.get_b(b).driving_connection(ctx.map).is_none() {
+                bail!("cars can't stop near {}", b);
+            }
+        }
+        self.requests.insert(
+            trip,
+            Request {
+                pickup,
+                dropoff,
+                requested_at: now,
+                boarded: false,
+            },
+        );
+
+        let idle: Vec<CarID> = self
+            .vehicles
+            .iter()
+            .filter(|(_, v)| matches!(v.state, VehicleState::Idle(_)))
+            .map(|(id, _)| *id)
+            .collect();
+        if idle.is_empty() {
+            self.pending.push_back(trip);
+            return Ok(());
+        }
+        if let Some((car, router)) = self.pick_idle_vehicle(idle, pickup, ctx.map) {
+            let v = self.vehicles.get_mut(&car).unwrap();
+            v.state = VehicleState::ToPickup(trip);
+            self.events
+                .push(Event::RideHailActivity(car, RideHailActivity::Deadheading));
+            ctx.scheduler.push(
+                now,
+                Command::SpawnCar(
+                    CreateCar {
+                        vehicle: v.vehicle.clone(),
+                        router,
+                        maybe_parked_car: None,
+                        trip_and_person: None,
+                        maybe_route: None,
+                    },
+                    true,
+                ),
+            );
+            Ok(())
+        } else {
+            self.requests.remove(&trip);
+            bail!("no ride-hail vehicle can reach {}", pickup);
+        }
+    }
+
+    fn pick_idle_vehicle(
+        &self,
+        mut idle: Vec<CarID>,
+        pickup: BuildingID,
+        map: &Map,
+    ) -> Option<(CarID, Router)> {
+        let end = driving_pos(pickup, map);
+        match self.dispatch {
+            DispatchPolicy::NearestIdleVehicle => {
+                // Vehicles at the same depot share a route, so only pathfind once per depot
+                let mut per_depot: BTreeMap<BuildingID, CarID> = BTreeMap::new();
+                for car in idle {
+                    per_depot.entry(self.vehicles[&car].depot).or_insert(car);
+                }
+                let mut best = None;
+                for (depot, car) in per_depot {
+                    let req =
+                        PathRequest::vehicle(driving_pos(depot, map), end, PathConstraints::Car);
+                    if let Ok(path) = map.pathfind_v2(req) {
+                        let cost = path.get_cost();
+                        if best.as_ref().map(|(c, _, _)| cost < *c).unwrap_or(true) {
+                            best = Some((cost, car, path));
+                        }
+                    }
+                }
+                let (_, car, path) = best?;
+                let path = path.into_v1(map).ok()?;
+                Some((car, Router::stop_for_ride_hail(car, path)))
+            }
+            DispatchPolicy::LongestIdleVehicle => {
+                idle.sort_by_key(|car| match self.vehicles[car].state {
+                    VehicleState::Idle(since) => since,
+                    _ => unreachable!(),
+                });
+                for car in idle {
+                    let req = PathRequest::vehicle(
+                        driving_pos(self.vehicles[&car].depot, map),
+                        end,
+                        PathConstraints::Car,
+                    );
+                    if let Ok(path) = map.pathfind(req) {
+                        return Some((car, Router::stop_for_ride_hail(car, path)));
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    pub fn vehicle_reached_stop(
+        &mut self,
+        now: Time,
+        car: CarID,
+        distance_crossed: Distance,
+    ) -> RideHailStop {
+        let v = self.vehicles.get_mut(&car).unwrap();
+        match v.state {
+            VehicleState::ToPickup(trip) => {
+                self.events.push(Event::RideHailDrove(
+                    car,
+                    RideHailActivity::Deadheading,
+                    distance_crossed,
+                ));
+                if let Some(req) = self.requests.get_mut(&trip) {
+                    req.boarded = true;
+                    v.state = VehicleState::Stopped(Some(trip));
+                    self.events
+                        .push(Event::RideHailPickup(trip, car, now - req.requested_at));
+                    self.events
+                        .push(Event::RideHailActivity(car, RideHailActivity::Occupied));
+                    RideHailStop::Pickup(trip, req.pickup)
+                } else {
+                    // The request was cancelled while the vehicle was on its way
+                    v.state = VehicleState::Stopped(None);
+                    RideHailStop::Wait
+                }
+            }
+            VehicleState::ToDropoff(trip) => {
+                self.events.push(Event::RideHailDrove(
+                    car,
+                    RideHailActivity::Occupied,
+                    distance_crossed,
+                ));
+                v.state = VehicleState::Stopped(None);
+                match self.requests.remove(&trip) {
+                    Some(req) => RideHailStop::Dropoff(trip, req.dropoff, distance_crossed),
+                    // The passenger's trip was cancelled on the way
+                    None => RideHailStop::Wait,
+                }
+            }
+            VehicleState::ToDepot => {
+                self.events.push(Event::RideHailDrove(
+                    car,
+                    RideHailActivity::Cruising,
+                    distance_crossed,
+                ));
+                if self.pending.is_empty() {
+                    v.state = VehicleState::Idle(now);
+                    self.events
+                        .push(Event::RideHailActivity(car, RideHailActivity::Idle));
+                    RideHailStop::Vanish
+                } else {
+                    // Somebody asked for a ride while this vehicle was cruising back
+                    v.state = VehicleState::Stopped(None);
+                    RideHailStop::Wait
+                }
+            }
+            VehicleState::Idle(_) | VehicleState::Stopped(_) => unreachable!(),
+        }
+    }
+
+    /// Returns the route to wherever the vehicle should go next, and any requests that had to be
+    /// abandoned because the vehicle couldn't get there. If there's no route at all, the vehicle
+    /// should vanish; it'll start over from its depot.
+    pub fn vehicle_left_stop(
+        &mut self,
+        now: Time,
+        car: CarID,
+        start: Position,
+        map: &Map,
+    ) -> (Option<Router>, Vec<TripID>) {
+        let mut abandoned = Vec::new();
+        let depot = self.vehicles[&car].depot;
+        let pathfind = |b: BuildingID| {
+            map.pathfind(PathRequest::vehicle(
+                start,
+                driving_pos(b, map),
+                PathConstraints::Car,
+            ))
+        };
+
+        match self.vehicles[&car].state {
+            VehicleState::Stopped(Some(trip)) => {
+                if let Some(req) = self.requests.get(&trip) {
+                    match pathfind(req.dropoff) {
+                        Ok(path) => {
+                            self.vehicles.get_mut(&car).unwrap().state =
+                                VehicleState::ToDropoff(trip);
+                            return (Some(Router::stop_for_ride_hail(car, path)), abandoned);
+                        }
+                        Err(_) => {
+                            abandoned.push(trip);
+                        }
+                    }
+                }
+            }
+            VehicleState::Stopped(None) => {}
+            _ => unreachable!(),
+        }
+
+        // The vehicle is free. Take the oldest waiting request.
+        while let Some(trip) = self.pending.pop_front() {
+            match pathfind(self.requests[&trip].pickup) {
+                Ok(path) => {
+                    self.vehicles.get_mut(&car).unwrap().state = VehicleState::ToPickup(trip);
+                    self.events
+                        .push(Event::RideHailActivity(car, RideHailActivity::Deadheading));
+                    return (Some(Router::stop_for_ride_hail(car, path)), abandoned);
+                }
+                Err(_) => {
+                    abandoned.push(trip);
+                }
+            }
+        }
+
+        // Nobody needs a ride, so cruise back to the depot
+        if let Ok(path) = pathfind(depot) {
+            self.vehicles.get_mut(&car).unwrap().state = VehicleState::ToDepot;
+            self.events
+                .push(Event::RideHailActivity(car, RideHailActivity::Cruising));
+            return (Some(Router::stop_for_ride_hail(car, path)), abandoned);
+        }
+        self.vehicles.get_mut(&car).unwrap().state = VehicleState::Idle(now);
+        self.events
+            .push(Event::RideHailActivity(car, RideHailActivity::Idle));
+        (None, abandoned)
+    }
+
+    /// A vehicle leaving its depot couldn't spawn, because map edits broke its route. It stays
+    /// idle at the depot. Returns the passenger it was headed to pick up, who won't get a ride.
+    pub fn vehicle_dropped(&mut self, now: Time, car: CarID) -> Option<TripID> {
+        let vehicle = self.vehicles.get_mut(&car)?;
+        let trip = match vehicle.state {
+            VehicleState::ToPickup(trip) | VehicleState::ToDropoff(trip) => Some(trip),
+            _ => None,
+        };
+        vehicle.state = VehicleState::Idle(now);
+        self.events
+            .push(Event::RideHailActivity(car, RideHailActivity::Idle));
+        trip
+    }
+
+    /// Forget about a request. If the passenger was still waiting to be picked up, returns the
+    /// building they're waiting in.
+    pub fn cancel_request(&mut self, trip: TripID) -> Option<BuildingID> {
+        let req = self.requests.remove(&trip)?;
+        self.pending.retain(|t| *t != trip);
+        // A vehicle might be on its way. When it arrives, it'll notice nobody's there.
+        if req.boarded {
+            None
+        } else {
+            Some(req.pickup)
+        }
+    }
+
+    /// Is this vehicle part of the ride-hail fleet?
+    pub fn is_fleet_vehicle(&self, car: CarID) -> bool {
+        self.vehicles.contains_key(&car)
+    }
+
+    /// If the passenger is riding right now, returns the vehicle.
+    pub fn passenger_riding_in(&self, trip: TripID) -> Option<CarID> {
+        if !self.requests.get(&trip)?.boarded {
+            return None;
+        }
+        self.vehicles.iter().find_map(|(id, v)| match v.state {
+            VehicleState::ToDropoff(t) | VehicleState::Stopped(Some(t)) if t == trip => Some(*id),
+            _ => None,
+        })
+    }
+
+    /// (vehicles on the road, passengers riding)
+    pub fn active_vehicles(&self) -> (usize, usize) {
+        let mut vehicles = 0;
+        let mut passengers = 0;
+        for v in self.vehicles.values() {
+            match v.state {
+                VehicleState::Idle(_) => {}
+                VehicleState::ToDropoff(_) | VehicleState::Stopped(Some(_)) => {
+                    vehicles += 1;
+                    passengers += 1;
+                }
+                VehicleState::ToPickup(_) | VehicleState::Stopped(None) | VehicleState::ToDepot => {
+                    vehicles += 1;
+                }
+            }
+        }
+        (vehicles, passengers)
+    }
+
+    pub fn fleet(&self) -> Option<RideHailFleet> {
+        if self.vehicles.is_empty() {
+            return None;
+        }
+        let mut depots: Vec<(BuildingID, usize)> = Vec::new();
+        for v in self.vehicles.values() {
+            match depots.iter_mut().find(|(b, _)| *b == v.depot) {
+                Some((_, count)) => {
+                    *count += 1;
+                }
+                None => {
+                    depots.push((v.depot, 1));
+                }
+            }
+        }
+        Some(RideHailFleet {
+            depots,
+            dispatch: self.dispatch,
+        })
+    }
+
+    pub fn collect_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+fn driving_pos(b: BuildingID, map: &Map) -> Position {
+    // Callers check this exists first
+    map.get_b(b).driving_connection(map).unwrap().0
+}
@@ -5,10 +5,10 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
-use geom::Distance;
+use geom::{Distance, Duration, Time};
 use map_model::{
-    BuildingID, IntersectionID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep,
-    Position, Traversable, Turn, TurnID,
+    BuildingID, DirectedRoadID, IntersectionID, LaneID, Map, Path, PathConstraints, PathRequest,
+    PathStep, Position, Traversable, Turn, TurnID, TurnPriority,
 };
 
 use crate::mechanics::Queue;
@@ -32,6 +32,14 @@ pub(crate) enum ActionAtEnd {
     GotoLaneEnd,
     StopBiking(SidewalkSpot),
     BusAtStop,
+    /// Pick up or drop off passengers sharing a ride
+    CarpoolAtStop,
+    /// A ride-hail vehicle picking up or dropping off a passenger, or returning to its depot
+    RideHailAtStop,
+    /// An emergency vehicle reached the incident it was called to at some time
+    EmergencyAtScene(BuildingID, Time),
+    /// A freight vehicle reached the next stop on its delivery tour, or got back to its depot
+    DeliveryAtStop,
     GiveUpOnParking,
 }
 
@@ -44,6 +52,9 @@ enum Goal {
         /// No parking available at all!
         stuck_end_dist: Option<Distance>,
         started_looking: bool,
+        /// When the driver reached their destination without a free spot in sight and started
+        /// circling, and how far along their path they were then
+        searching_since: Option<(Time, Distance)>,
     },
     EndAtBorder {
         end_dist: Distance,
@@ -55,6 +66,20 @@ enum Goal {
     FollowBusRoute {
         end_dist: Distance,
     },
+    StopForCarpool {
+        end_dist: Distance,
+    },
+    StopForRideHail {
+        end_dist: Distance,
+    },
+    RespondToEmergency {
+        end_dist: Distance,
+        incident: BuildingID,
+        called_at: Time,
+    },
+    StopForDelivery {
+        end_dist: Distance,
+    },
 }
 
 impl Router {
@@ -93,6 +118,7 @@ impl Router {
                 spot: None,
                 stuck_end_dist: None,
                 started_looking: false,
+                searching_since: None,
             },
             owner,
         }
@@ -116,6 +142,53 @@ impl Router {
         }
     }
 
+    pub fn stop_for_carpool(owner: CarID, path: Path) -> Router {
+        Router {
+            goal: Goal::StopForCarpool {
+                end_dist: path.get_req().end.dist_along(),
+            },
+            path,
+            owner,
+        }
+    }
+
+    pub fn stop_for_ride_hail(owner: CarID, path: Path) -> Router {
+        Router {
+            goal: Goal::StopForRideHail {
+                end_dist: path.get_req().end.dist_along(),
+            },
+            path,
+            owner,
+        }
+    }
+
+    pub fn respond_to_emergency(
+        owner: CarID,
+        path: Path,
+        incident: BuildingID,
+        called_at: Time,
+    ) -> Router {
+        Router {
+            goal: Goal::RespondToEmergency {
+                end_dist: path.get_req().end.dist_along(),
+                incident,
+                called_at,
+            },
+            path,
+            owner,
+        }
+    }
+
+    pub fn stop_for_delivery(owner: CarID, path: Path) -> Router {
+        Router {
+            goal: Goal::StopForDelivery {
+                end_dist: path.get_req().end.dist_along(),
+            },
+            path,
+            owner,
+        }
+    }
+
     pub fn head(&self) -> Traversable {
         self.path.current_step().as_traversable()
     }
@@ -144,6 +217,10 @@ impl Router {
             } => stuck_end_dist.unwrap_or_else(|| spot.unwrap().1),
             Goal::BikeThenStop { ref goal } => goal.sidewalk_pos.dist_along(),
             Goal::FollowBusRoute { end_dist } => end_dist,
+            Goal::StopForCarpool { end_dist } => end_dist,
+            Goal::StopForRideHail { end_dist } => end_dist,
+            Goal::RespondToEmergency { end_dist, .. } => end_dist,
+            Goal::StopForDelivery { end_dist } => end_dist,
         }
     }
 
@@ -154,6 +231,7 @@ impl Router {
     /// Returns the step just finished
     pub fn advance(
         &mut self,
+        now: Time,
         vehicle: &Vehicle,
         parking: &ParkingSimState,
         map: &Map,
@@ -165,6 +243,7 @@ impl Router {
             // Do this to trigger the side-effect of looking for parking.
             self.maybe_handle_end(
                 Distance::ZERO,
+                now,
                 vehicle,
                 parking,
                 map,
@@ -192,6 +271,7 @@ impl Router {
     pub fn maybe_handle_end(
         &mut self,
         front: Distance,
+        now: Time,
         vehicle: &Vehicle,
         parking: &ParkingSimState,
         map: &Map,
@@ -214,6 +294,7 @@ impl Router {
                 ref mut stuck_end_dist,
                 target,
                 ref mut started_looking,
+                ref mut searching_since,
             } => {
                 if let Some(d) = stuck_end_dist {
                     if *d == front {
@@ -234,28 +315,25 @@ impl Router {
                         Position::new(current_lane, front),
                         vehicle,
                         target,
+                        now,
                         map,
                     );
-                    let best =
-                        if let Some((driving_pos, _)) = map.get_b(target).driving_connection(map) {
-                            if driving_pos.lane() == current_lane {
-                                let target_dist = driving_pos.dist_along();
-                                // Closest to the building
-                                candidates
-                                    .into_iter()
-                                    .min_by_key(|(_, pos)| (pos.dist_along() - target_dist).abs())
-                            } else {
-                                // Closest to the road endpoint, I guess
-                                candidates
-                                    .into_iter()
-                                    .min_by_key(|(_, pos)| pos.dist_along())
-                            }
-                        } else {
+                    let target_dist = map
+                        .get_b(target)
+                        .driving_connection(map)
+                        .map(|(pos, _)| pos)
+                        .filter(|pos| pos.lane() == current_lane)
+                        .map(|pos| pos.dist_along());
+                    // Trade off walking distance and price
+                    let best = candidates.into_iter().min_by_key(|(spot, pos)| {
+                        let walk = match target_dist {
+                            // Closest to the building
+                            Some(dist) => (pos.dist_along() - dist).abs(),
                             // Closest to the road endpoint, I guess
-                            candidates
-                                .into_iter()
-                                .min_by_key(|(_, pos)| pos.dist_along())
+                            None => pos.dist_along(),
                         };
+                        walk + spot.price_as_distance(now, map)
+                    });
                     if let Some((new_spot, new_pos)) = best {
                         if let Some((t, p)) = trip_and_person {
                             events.push(Event::TripPhaseStarting(
@@ -273,8 +351,11 @@ impl Router {
                         assert!(new_pos.dist_along() >= front);
                         *spot = Some((new_spot, new_pos.dist_along()));
                     } else {
-                        if let Some((new_path_steps, new_spot, new_pos)) =
-                            parking.path_to_free_parking_spot(current_lane, vehicle, target, map)
+                        if searching_since.is_none() {
+                            *searching_since = Some((now, self.path.crossed_so_far() + front));
+                        }
+                        if let Some((new_path_steps, new_spot, new_pos)) = parking
+                            .path_to_free_parking_spot(current_lane, vehicle, target, now, map)
                         {
                             assert!(!new_path_steps.is_empty());
                             for step in new_path_steps {
@@ -332,6 +413,38 @@ impl Router {
                     None
                 }
             }
+            Goal::StopForCarpool { end_dist } => {
+                if end_dist == front {
+                    Some(ActionAtEnd::CarpoolAtStop)
+                } else {
+                    None
+                }
+            }
+            Goal::StopForRideHail { end_dist } => {
+                if end_dist == front {
+                    Some(ActionAtEnd::RideHailAtStop)
+                } else {
+                    None
+                }
+            }
+            Goal::RespondToEmergency {
+                end_dist,
+                incident,
+                called_at,
+            } => {
+                if end_dist == front {
+                    Some(ActionAtEnd::EmergencyAtScene(incident, called_at))
+                } else {
+                    None
+                }
+            }
+            Goal::StopForDelivery { end_dist } => {
+                if end_dist == front {
+                    Some(ActionAtEnd::DeliveryAtStop)
+                } else {
+                    None
+                }
+            }
         }
     }
 
@@ -466,6 +579,85 @@ impl Router {
         }
     }
 
+    /// If this car is at the end of its current lane, about to turn, and could take a different
+    /// route for the rest of the trip, how should it pathfind? None if it's committed to the
+    /// current route -- like when it's following a transit route, making stops, already looking
+    /// for parking, or in the middle of an uber-turn.
+    pub fn reroute_request(&self, map: &Map) -> Option<PathRequest> {
+        if self.owner.vehicle_type != VehicleType::Car
+            || self.path.currently_inside_ut().is_some()
+            || self.path.about_to_start_ut().is_some()
+        {
+            return None;
+        }
+        match self.goal {
+            Goal::ParkNearBuilding {
+                started_looking: false,
+                stuck_end_dist: None,
+                ..
+            }
+            | Goal::EndAtBorder { .. } => {}
+            _ => {
+                return None;
+            }
+        }
+        let current = match (self.path.current_step(), self.path.maybe_next_step()) {
+            (PathStep::Lane(l), Some(PathStep::Turn(_))) => l,
+            _ => {
+                return None;
+            }
+        };
+        let end = self.path.get_req().end;
+        if self.path.last_step() != PathStep::Lane(end.lane())
+            || map.get_l(current).get_directed_parent()
+                == map.get_l(end.lane()).get_directed_parent()
+        {
+            return None;
+        }
+        Some(PathRequest::vehicle(
+            Position::new(current, map.get_l(current).length()),
+            end,
+            PathConstraints::Car,
+        ))
+    }
+
+    /// Switch to a new route for the rest of the trip, found using `reroute_request`. Returns
+    /// false if the new route follows the same roads as the current one.
+    pub fn reroute(&mut self, new_path: Path, map: &Map) -> bool {
+        let roads = |path: &Path| -> Vec<DirectedRoadID> {
+            path.get_steps()
+                .iter()
+                .filter_map(|step| match step {
+                    PathStep::Lane(l) => Some(map.get_l(*l).get_directed_parent()),
+                    _ => None,
+                })
+                .collect()
+        };
+        if roads(&new_path) == roads(&self.path) {
+            return false;
+        }
+        if let Err(err) = self.path.replace_remaining(new_path, map) {
+            warn!("{} can't reroute: {}", self.owner, err);
+            return false;
+        }
+        true
+    }
+
+    /// Must the vehicle come to a full stop at the end of the current step? This is true at the
+    /// end of the trip (unless leaving the map) and before stop signs. It might also have to stop
+    /// for a traffic signal or a queue, but it won't know until it gets there.
+    pub fn must_stop_at_end(&self, map: &Map) -> bool {
+        if self.last_step() {
+            return !matches!(self.goal, Goal::EndAtBorder { .. });
+        }
+        if let PathStep::Turn(t) = self.path.next_step() {
+            if let Some(sign) = map.maybe_get_stop_sign(t.parent) {
+                return sign.get_priority(t, map) == TurnPriority::Yield;
+            }
+        }
+        false
+    }
+
     pub fn can_lanechange(&self, from: LaneID, to: LaneID, map: &Map) -> bool {
         let steps = self.path.get_steps();
         if steps.len() < 3 {
@@ -506,6 +698,36 @@ impl Router {
         }
     }
 
+    /// The driver just started parking or gave up. Describe how long they spent circling for a
+    /// spot and how far they drove doing so.
+    pub fn parking_search_finished(
+        &self,
+        front: Distance,
+        now: Time,
+        trip_and_person: Option<(TripID, PersonID)>,
+        found_spot: bool,
+    ) -> Option<Event> {
+        let searching_since = match self.goal {
+            Goal::ParkNearBuilding {
+                searching_since, ..
+            } => searching_since,
+            _ => {
+                return None;
+            }
+        };
+        let (time_searching, cruising_distance) = match searching_since {
+            Some((since, dist)) => (now - since, self.path.crossed_so_far() + front - dist),
+            None => (Duration::ZERO, Distance::ZERO),
+        };
+        Some(Event::ParkingSearchFinished {
+            car: self.owner,
+            trip: trip_and_person.map(|(t, _)| t),
+            time_searching,
+            cruising_distance,
+            found_spot,
+        })
+    }
+
     pub fn get_parking_spot_goal(&self) -> Option<&ParkingSpot> {
         match self.goal {
             Goal::ParkNearBuilding { ref spot, .. } => spot.as_ref().map(|(s, _)| s),
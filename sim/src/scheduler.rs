@@ -6,7 +6,7 @@ use serde::{Deserialize, Serialize};
 
 use abstutil::Counter;
 use geom::{Duration, Histogram, Time};
-use map_model::{BusRouteID, IntersectionID};
+use map_model::{BusRouteID, IntersectionID, Position, TurnID};
 
 use crate::{
     pandemic, AgentID, CarID, CreateCar, CreatePedestrian, PedestrianID, StartTripArgs, TripID,
@@ -27,6 +27,11 @@ pub(crate) enum Command {
     Pandemic(pandemic::Cmd),
     /// The Time is redundant, just used to dedupe commands
     StartBus(BusRouteID, Time),
+    /// A freight vehicle leaves its depot or a loading zone from this position, heading to its
+    /// next stop
+    ContinueDeliveryTour(CarID, Position),
+    /// An emergency vehicle is about to reach this turn
+    EmergencyVehicleNear(CarID, TurnID),
 }
 
 impl Command {
@@ -50,6 +55,8 @@ impl Command {
             Command::Callback(_) => CommandType::Callback,
             Command::Pandemic(ref p) => CommandType::Pandemic(p.clone()),
             Command::StartBus(r, t) => CommandType::StartBus(*r, *t),
+            Command::ContinueDeliveryTour(id, _) => CommandType::Car(*id),
+            Command::EmergencyVehicleNear(id, _) => CommandType::EmergencyVehicleNear(*id),
         }
     }
 
@@ -65,6 +72,8 @@ impl Command {
             Command::Callback(_) => SimpleCommandType::Callback,
             Command::Pandemic(_) => SimpleCommandType::Pandemic,
             Command::StartBus(_, _) => SimpleCommandType::StartBus,
+            Command::ContinueDeliveryTour(_, _) => SimpleCommandType::Car,
+            Command::EmergencyVehicleNear(_, _) => SimpleCommandType::EmergencyVehicleNear,
         }
     }
 }
@@ -81,6 +90,7 @@ enum CommandType {
     Callback,
     Pandemic(pandemic::Cmd),
     StartBus(BusRouteID, Time),
+    EmergencyVehicleNear(CarID),
 }
 
 /// A more compressed form of CommandType, just used for keeping stats on event processing.
@@ -94,6 +104,7 @@ enum SimpleCommandType {
     Callback,
     Pandemic,
     StartBus,
+    EmergencyVehicleNear,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -199,6 +210,16 @@ impl Scheduler {
         });
     }
 
+    /// Like `update`, but if the command is already scheduled to happen sooner, leave it alone.
+    pub fn update_unless_sooner(&mut self, new_time: Time, cmd: Command) {
+        if let Some((_, existing_time)) = self.queued_commands.get(&cmd.to_type()) {
+            if *existing_time <= new_time {
+                return;
+            }
+        }
+        self.update(new_time, cmd);
+    }
+
     pub fn cancel(&mut self, cmd: Command) {
         // It's fine if a previous command hasn't actually been scheduled.
         self.queued_commands.remove(&cmd.to_type());
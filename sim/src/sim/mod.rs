@@ -19,11 +19,12 @@ use map_model::{
 
 pub use self::queries::{AgentProperties, DelayCause};
 use crate::{
-    AgentID, AlertLocation, Analytics, CarID, Command, CreateCar, DrivingSimState, Event,
-    IntersectionSimState, OrigPersonID, PandemicModel, ParkedCar, ParkingSim, ParkingSimState,
-    ParkingSpot, Person, PersonID, Router, Scheduler, SidewalkPOI, SidewalkSpot, StartTripArgs,
-    TrafficRecorder, TransitSimState, TripID, TripInfo, TripManager, TripPhaseType, Vehicle,
-    VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH, LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH,
+    AgentID, AlertLocation, Analytics, CarID, Command, CreateCar, DeliveryTour, DrivingSimState,
+    EmergencyCall, Event, IntersectionSimState, OrigPersonID, PandemicModel, ParkedCar, ParkingSim,
+    ParkingSimState, ParkingSpot, Person, PersonID, RideHailFleet, Router, Scheduler, SidewalkPOI,
+    SidewalkSpot, StartTripArgs, TrafficRecorder, TransitSimState, TripEndpoint, TripID, TripInfo,
+    TripManager, TripPhaseType, Vehicle, VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH,
+    LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH,
 };
 
 mod queries;
@@ -61,6 +62,10 @@ pub struct Sim {
 
     #[serde(skip_serializing, skip_deserializing)]
     alerts: AlertHandler,
+    /// How many of the alerts not cleared yet have already been passed to a callback? With
+    /// `AlertHandler::Block`, they pile up until the UI clears them.
+    #[serde(skip_serializing, skip_deserializing)]
+    num_alerts_sent: usize,
 }
 
 pub(crate) struct Ctx<'a> {
@@ -123,6 +128,43 @@ pub struct SimOptions {
     /// quickly.
     #[structopt(long)]
     pub skip_analytics: bool,
+    /// Record how long cars take to cross each road and the roads each car trip drives along, so
+    /// a later run can route with `--travel-times-from`. This makes saved analytics much larger.
+    #[structopt(long)]
+    pub record_travel_times: bool,
+    /// The fraction of drivers who know about current traffic, and reroute around queues. Other
+    /// drivers keep the route they planned when starting their trip.
+    #[structopt(long, default_value = "0.0")]
+    pub informed_drivers: f64,
+    /// Informed drivers reconsider the rest of their route after waiting to turn for this long,
+    /// like "2:00" for 2 minutes.
+    #[structopt(long, parse(try_from_str = Duration::parse), default_value = "2:00")]
+    pub reroute_after_blocked: Duration,
+    /// If set, informed drivers also reconsider their route this often, when reaching the end of
+    /// a lane.
+    #[structopt(long, parse(try_from_str = Duration::parse))]
+    pub reroute_periodically: Option<Duration>,
+    /// Vehicles gradually speed up and brake, limited by their type, instead of instantly
+    /// changing speed. They come to a full stop at stop signs and at the end of their trip.
+    #[structopt(long)]
+    pub use_acceleration: bool,
+    /// Where a road allows it, vehicles stuck behind somebody slower may briefly use an empty
+    /// oncoming lane to pass them.
+    #[structopt(long)]
+    pub allow_oncoming_overtaking: bool,
+    /// Transit vehicles running ahead wait at timepoints. Vehicles on routes with a schedule wait
+    /// for their scheduled time; otherwise they wait to stay evenly spaced behind the previous
+    /// vehicle.
+    #[structopt(long)]
+    pub transit_holding: bool,
+    /// With transit holding, every this many stops along a route is a timepoint where vehicles may
+    /// wait, starting with the first stop.
+    #[structopt(long, default_value = "4")]
+    pub transit_timepoint_every_n_stops: usize,
+    /// With transit holding, never hold a vehicle longer than this at one stop, like "3:00" for 3
+    /// minutes.
+    #[structopt(long, parse(try_from_str = Duration::parse), default_value = "3:00")]
+    pub transit_max_hold_time: Duration,
 }
 
 impl SimOptions {
@@ -139,6 +181,15 @@ impl SimOptions {
             infinite_parking: false,
             disable_turn_conflicts: false,
             skip_analytics: false,
+            record_travel_times: false,
+            informed_drivers: 0.0,
+            reroute_after_blocked: Duration::minutes(2),
+            reroute_periodically: None,
+            use_acceleration: false,
+            allow_oncoming_overtaking: false,
+            transit_holding: false,
+            transit_timepoint_every_n_stops: 4,
+            transit_max_hold_time: Duration::minutes(3),
         }
     }
 }
@@ -206,7 +257,7 @@ impl Sim {
             parking: ParkingSimState::new(map, opts.infinite_parking, &mut timer),
             walking: WalkingSimState::new(),
             intersections: IntersectionSimState::new(map, &mut scheduler, &opts),
-            transit: TransitSimState::new(map),
+            transit: TransitSimState::new(map, &opts),
             trips: TripManager::new(),
             pandemic: opts.enable_pandemic_model.map(PandemicModel::new),
             scheduler,
@@ -218,27 +269,56 @@ impl Sim {
             step_count: 0,
             highlighted_people: None,
             alerts: opts.alerts,
+            num_alerts_sent: 0,
 
-            analytics: Analytics::new(!opts.skip_analytics),
+            analytics: Analytics::new(!opts.skip_analytics, opts.record_travel_times),
             recorder: None,
         }
     }
 
+    /// Each shared ride is expressed as (driver, passenger, pickup, dropoff), with the trips given
+    /// as indices into `input`.
     pub(crate) fn spawn_trips(
         &mut self,
         input: Vec<(PersonID, TripInfo, StartTripArgs)>,
+        shared_rides: Vec<(usize, usize, BuildingID, BuildingID)>,
         map: &Map,
         timer: &mut Timer,
     ) {
         timer.start_iter("spawn trips", input.len());
+        let mut trips = Vec::new();
         for (p, info, args) in input {
             timer.next();
 
+            // People start the day at home, which matters for residential parking permits
+            if self.trips.get_person(p).unwrap().trips.is_empty() {
+                if let TripEndpoint::Bldg(b) = info.start {
+                    self.parking.register_home(p, b);
+                }
+            }
+
             let trip = self.trips.new_trip(p, info.clone());
             // This might be immediately true due to ScenarioModifiers
             if let Some(msg) = info.cancellation_reason {
                 self.trips.cancel_unstarted_trip(trip, msg);
-            } else {
+            }
+            trips.push((trip, args));
+        }
+
+        // This has to happen before any trip starts, and might cancel some passengers
+        self.trips.new_shared_rides(
+            shared_rides
+                .into_iter()
+                .map(|(driver, passenger, pickup, dropoff)| {
+                    (trips[driver].0, trips[passenger].0, pickup, dropoff)
+                })
+                .collect(),
+            map,
+        );
+
+        for (trip, args) in trips {
+            let info = self.trips.trip_info(trip);
+            if info.cancellation_reason.is_none() {
                 self.scheduler
                     .push(info.departure, Command::StartTrip(trip, args));
             }
@@ -290,14 +370,18 @@ impl Sim {
         // TODO Refactor the logic in router
         let spot = if let Some((spot, _)) = self
             .parking
-            .get_all_free_spots(Position::start(driving_lane), &vehicle, b, map)
+            .get_all_free_spots(Position::start(driving_lane), &vehicle, b, self.time, map)
             .get(0)
         {
             *spot
         } else {
-            let (_, spot, _) =
-                self.parking
-                    .path_to_free_parking_spot(driving_lane, &vehicle, b, map)?;
+            let (_, spot, _) = self.parking.path_to_free_parking_spot(
+                driving_lane,
+                &vehicle,
+                b,
+                self.time,
+                map,
+            )?;
             spot
         };
 
@@ -323,13 +407,18 @@ impl Sim {
         });
     }
 
+    pub(crate) fn seed_ride_hail_fleet(&mut self, fleet: &RideHailFleet, map: &Map) {
+        self.trips
+            .seed_ride_hail_fleet(self.time, fleet.clone(), map);
+    }
+
     pub(crate) fn seed_bus_route(&mut self, route: &BusRoute) {
         for t in &route.spawn_times {
             self.scheduler.push(*t, Command::StartBus(route.id, *t));
         }
     }
 
-    fn start_bus(&mut self, route: &BusRoute, map: &Map) {
+    fn start_bus(&mut self, route: &BusRoute, scheduled: Time, map: &Map) {
         // Spawn one bus for the first leg.
         let path = self.transit.create_empty_route(route, map);
 
@@ -361,7 +450,7 @@ impl Sim {
                     vehicle,
                     maybe_parked_car: None,
                     trip_and_person: None,
-                    maybe_route: Some(route.id),
+                    maybe_route: Some((route.id, scheduled)),
                 },
                 true,
             ),
@@ -465,13 +554,28 @@ impl Sim {
                     }
                 }
                 if !ok {
-                    self.trips.cancel_trip(
-                        self.time,
-                        create_car.trip_and_person.unwrap().0,
-                        "path is no longer valid after map edits".to_string(),
-                        Some(create_car.vehicle),
-                        &mut ctx,
-                    );
+                    if let Some((trip, _)) = create_car.trip_and_person {
+                        self.trips.cancel_trip(
+                            self.time,
+                            trip,
+                            "path is no longer valid after map edits".to_string(),
+                            Some(create_car.vehicle),
+                            &mut ctx,
+                        );
+                    } else {
+                        // Vehicles without a trip, like emergency vehicles, ride-hail vehicles,
+                        // and freight, just vanish.
+                        warn!(
+                            "{}'s path is no longer valid after map edits; dropping it",
+                            create_car.vehicle.id
+                        );
+                        self.driving.vehicle_not_spawning(create_car.vehicle.id);
+                        self.trips.vehicle_dropped_before_spawning(
+                            self.time,
+                            create_car.vehicle.id,
+                            &mut ctx,
+                        );
+                    }
                 } else {
                     // create_car contains a Path, which is expensive to clone. We need different
                     // parts of create_car after attempting start_car_on_lane.
@@ -541,8 +645,8 @@ impl Sim {
                             }
                             self.parking.remove_parked_car(parked_car);
                         }
-                        if let Some(route) = maybe_route {
-                            self.transit.bus_created(id, route);
+                        if let Some((route, scheduled)) = maybe_route {
+                            self.transit.bus_created(id, route, scheduled, map);
                         }
                         self.analytics
                             .record_demand(self.driving.get_path(id).unwrap(), map);
@@ -600,6 +704,10 @@ impl Sim {
             Command::UpdateLaggyHead(car) => {
                 self.driving.update_laggy_head(car, self.time, &mut ctx);
             }
+            Command::EmergencyVehicleNear(car, turn) => {
+                self.driving
+                    .emergency_vehicle_near(car, turn, self.time, &mut ctx);
+            }
             Command::UpdatePed(ped) => {
                 self.walking.update_ped(
                     ped,
@@ -610,8 +718,13 @@ impl Sim {
                 );
             }
             Command::UpdateIntersection(i) => {
-                self.intersections
-                    .update_intersection(self.time, i, map, &mut self.scheduler);
+                self.intersections.update_intersection(
+                    self.time,
+                    i,
+                    map,
+                    &mut self.scheduler,
+                    &self.driving,
+                );
             }
             Command::Callback(frequency) => {
                 self.scheduler
@@ -626,8 +739,17 @@ impl Sim {
                     .unwrap()
                     .handle_cmd(self.time, cmd, &mut self.scheduler);
             }
-            Command::StartBus(r, _) => {
-                self.start_bus(map.get_br(r), map);
+            Command::StartBus(r, scheduled) => {
+                self.start_bus(map.get_br(r), scheduled, map);
+            }
+            Command::ContinueDeliveryTour(car, start) => {
+                if let Some(create_car) = self
+                    .trips
+                    .freight_vehicle_departing(self.time, car, start, map)
+                {
+                    self.scheduler
+                        .push(self.time, Command::SpawnCar(create_car, true));
+                }
             }
         }
 
@@ -672,23 +794,8 @@ impl Sim {
             if self.minimal_step(map, end_time - self.time, maybe_cb) {
                 break;
             }
-            if !self.analytics.alerts.is_empty() {
-                match self.alerts {
-                    AlertHandler::Print => {
-                        for (t, loc, msg) in self.analytics.alerts.drain(..) {
-                            println!("Alert at {} ({:?}): {}", t, loc, msg);
-                        }
-                    }
-                    AlertHandler::Block => {
-                        for (t, loc, msg) in &self.analytics.alerts {
-                            println!("Alert at {} ({:?}): {}", t, loc, msg);
-                        }
-                        break;
-                    }
-                    AlertHandler::Silence => {
-                        self.analytics.alerts.clear();
-                    }
-                }
+            if self.handle_alerts(maybe_cb) {
+                break;
             }
             if Duration::realtime_elapsed(last_update) >= Duration::seconds(1.0) {
                 // TODO Not timer?
@@ -726,23 +833,41 @@ impl Sim {
             if self.minimal_step(map, end_time - self.time, maybe_cb) {
                 break;
             }
-            if !self.analytics.alerts.is_empty() {
-                match self.alerts {
-                    AlertHandler::Print => {
-                        for (t, loc, msg) in self.analytics.alerts.drain(..) {
-                            println!("Alert at {} ({:?}): {}", t, loc, msg);
-                        }
-                    }
-                    AlertHandler::Block => {
-                        for (t, loc, msg) in &self.analytics.alerts {
-                            println!("Alert at {} ({:?}): {}", t, loc, msg);
-                        }
-                        break;
-                    }
-                    AlertHandler::Silence => {
-                        self.analytics.alerts.clear();
-                    }
+            if self.handle_alerts(maybe_cb) {
+                break;
+            }
+        }
+    }
+
+    // Returns true if the sim should stop advancing, because an alert needs to be cleared first.
+    fn handle_alerts(&mut self, maybe_cb: &mut Option<Box<dyn SimCallback>>) -> bool {
+        if self.analytics.alerts.is_empty() {
+            return false;
+        }
+        if let Some(cb) = maybe_cb {
+            if self.analytics.alerts.len() > self.num_alerts_sent {
+                cb.alerts(&self.analytics.alerts[self.num_alerts_sent..]);
+            }
+        }
+        self.num_alerts_sent = self.analytics.alerts.len();
+        match self.alerts {
+            AlertHandler::Print => {
+                for (t, loc, msg) in self.analytics.alerts.drain(..) {
+                    println!("Alert at {} ({:?}): {}", t, loc, msg);
                 }
+                self.num_alerts_sent = 0;
+                false
+            }
+            AlertHandler::Block => {
+                for (t, loc, msg) in &self.analytics.alerts {
+                    println!("Alert at {} ({:?}): {}", t, loc, msg);
+                }
+                true
+            }
+            AlertHandler::Silence => {
+                self.analytics.alerts.clear();
+                self.num_alerts_sent = 0;
+                false
             }
         }
     }
@@ -824,6 +949,49 @@ impl Sim {
     }
 }
 
+// External control
+impl Sim {
+    /// Switch a traffic signal to a particular stage right now. The signal stays in that stage
+    /// until this is called again or `release_traffic_signal` is used.
+    pub fn force_traffic_signal_stage(
+        &mut self,
+        map: &Map,
+        i: IntersectionID,
+        stage: usize,
+    ) -> Result<()> {
+        self.intersections
+            .force_signal_stage(self.time, i, stage, map, &mut self.scheduler)
+    }
+
+    /// Return a traffic signal to its normal timing plan.
+    pub fn release_traffic_signal(&mut self, i: IntersectionID) {
+        self.intersections.release_signal(i);
+    }
+
+    /// Send an emergency vehicle from a station to an incident. It leaves when the call comes in,
+    /// or right now if that's already passed. The response time is measured from the call.
+    pub fn dispatch_emergency_vehicle(&mut self, call: &EmergencyCall, map: &Map) -> Result<CarID> {
+        let create_car = call.make_vehicle(self.trips.new_car_id(), map)?;
+        let id = create_car.vehicle.id;
+        self.scheduler.push(
+            call.time.max(self.time),
+            Command::SpawnCar(create_car, true),
+        );
+        Ok(id)
+    }
+
+    /// Send a van or truck out from its depot on a delivery tour. It leaves at the tour's departure
+    /// time, or right away if that's already passed.
+    pub fn start_delivery_tour(&mut self, tour: DeliveryTour, map: &Map) -> Result<CarID> {
+        let depart = tour.depart.max(self.time);
+        let (id, start) = self.trips.start_delivery_tour(tour, map)?;
+        // Plan the route when the vehicle actually leaves, using traffic and map edits from then
+        self.scheduler
+            .push(depart, Command::ContinueDeliveryTour(id, start));
+        Ok(id)
+    }
+}
+
 // Live edits
 impl Sim {
     pub fn handle_live_edited_traffic_signals(&mut self, map: &Map) {
@@ -979,6 +1147,7 @@ impl Sim {
     }
 
     pub fn clear_alerts(&mut self) -> Vec<(Time, AlertLocation, String)> {
+        self.num_alerts_sent = 0;
         std::mem::take(&mut self.analytics.alerts)
     }
 }
@@ -987,6 +1156,9 @@ impl Sim {
 pub trait SimCallback: downcast_rs::Downcast {
     // Run at some scheduled time. If this returns true, halt simulation.
     fn run(&mut self, sim: &Sim, map: &Map) -> bool;
+    /// Called with newly raised alerts, before the sim's `AlertHandler` deals with them. Each alert
+    /// is only passed once, even if it hasn't been cleared yet.
+    fn alerts(&mut self, _: &[(Time, AlertLocation, String)]) {}
 }
 downcast_rs::impl_downcast!(SimCallback);
 
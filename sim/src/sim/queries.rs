@@ -16,7 +16,7 @@ use crate::{
     AgentID, AgentType, Analytics, CarID, CommutersVehiclesCounts, DrawCarInput, DrawPedCrowdInput,
     DrawPedestrianInput, OrigPersonID, PandemicModel, ParkedCar, ParkingSim, PedestrianID, Person,
     PersonID, PersonState, Scenario, Sim, TripEndpoint, TripID, TripInfo, TripMode, TripResult,
-    UnzoomedAgent, VehicleType,
+    UnzoomedAgent, VehicleType, SHARED_VEHICLE_SPEED,
 };
 
 // TODO Many of these just delegate to an inner piece. This is unorganized and hard to maintain.
@@ -338,11 +338,17 @@ impl Sim {
             .max(Time::START_OF_DAY + Duration::hours(24))
     }
 
+    /// The stage is an index into the stages of the signal's current plan.
     pub fn current_stage_and_remaining_time(&self, i: IntersectionID) -> (usize, Duration) {
         self.intersections
             .current_stage_and_remaining_time(self.time, i)
     }
 
+    /// Which timing plan a traffic signal is using. See `ControlTrafficSignal::plan_at`.
+    pub fn current_signal_plan(&self, i: IntersectionID) -> usize {
+        self.intersections.current_signal_plan(i)
+    }
+
     // TODO This is an awkward copy of raw_throughput
     // TODO And it does NOT count buses/trains spawning
     pub fn all_arrivals_at_border(
@@ -427,7 +433,7 @@ impl Sim {
                 let max_speed = match info.mode {
                     TripMode::Walk | TripMode::Transit => Some(person.ped_speed),
                     // TODO We should really search the vehicles and grab it from there
-                    TripMode::Drive => None,
+                    TripMode::Drive | TripMode::Carpool | TripMode::RideHail => None,
                     // Assume just one bike
                     TripMode::Bike => {
                         person
@@ -437,6 +443,7 @@ impl Sim {
                             .unwrap()
                             .max_speed
                     }
+                    TripMode::BikeShare => Some(SHARED_VEHICLE_SPEED),
                 };
                 Ok(path.estimate_duration(map, max_speed))
             }
@@ -3,18 +3,25 @@ use std::collections::{BTreeMap, BTreeSet};
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::Time;
+use geom::{Duration, Time};
 use map_model::{BusRoute, BusRouteID, BusStopID, Map, Path, PathRequest, Position};
 
 use crate::sim::Ctx;
 use crate::{
-    AgentID, CarID, DrivingSimState, Event, PedestrianID, PersonID, Router, TripID, TripManager,
-    TripPhaseType, UnzoomedAgent, VehicleType, WalkingSimState,
+    AgentID, CarID, DrivingSimState, Event, PedestrianID, PersonID, Router, SimOptions, TripID,
+    TripManager, TripPhaseType, UnzoomedAgent, VehicleType, WalkingSimState,
 };
 
 // These index stops along a route, not stops along a single sidewalk.
 type StopIdx = usize;
 
+/// Even if nobody gets on or off, a transit vehicle waits this long at each stop.
+const MIN_DWELL_TIME: Duration = Duration::const_seconds(5.0);
+/// How much longer a transit vehicle waits at a stop for each passenger boarding
+const TIME_PER_BOARDING: Duration = Duration::const_seconds(3.0);
+/// How much longer a transit vehicle waits at a stop for each passenger alighting
+const TIME_PER_ALIGHTING: Duration = Duration::const_seconds(2.0);
+
 #[derive(Serialize, Deserialize, Clone)]
 struct Stop {
     id: BusStopID,
@@ -28,6 +35,8 @@ struct Route {
     start: Path,
     end_at_border: Option<Path>,
     active_vehicles: BTreeSet<CarID>,
+    /// When did the most recent vehicle leave each stop?
+    last_departures: Vec<Option<Time>>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -36,9 +45,90 @@ struct Bus {
     route: BusRouteID,
     /// Where does each passenger want to deboard?
     passengers: Vec<(PersonID, Option<BusStopID>)>,
+    /// The most passengers that fit, seated and standing
+    capacity: usize,
+    /// When this vehicle was scheduled to start the route
+    scheduled_start: Time,
+    /// If the route has a schedule, how late the vehicle was at the last stop
+    lateness: Option<Duration>,
     state: BusState,
 }
 
+impl Bus {
+    fn is_full(&self) -> bool {
+        self.passengers.len() >= self.capacity
+    }
+}
+
+/// What happens to somebody waiting at a stop when a vehicle arrives
+#[derive(Debug, PartialEq)]
+enum Boarding {
+    Board,
+    /// The vehicle serves their route, but it's full
+    Denied,
+    /// They're waiting for a different route
+    KeepWaiting,
+}
+
+/// People waiting for a vehicle's route board in the order they started waiting, until the
+/// vehicle has no more room. Everybody else keeps waiting.
+fn decide_boarding(
+    waiting_for: impl Iterator<Item = BusRouteID>,
+    route: BusRouteID,
+    mut room: usize,
+) -> Vec<Boarding> {
+    waiting_for
+        .map(|r| {
+            if r != route {
+                Boarding::KeepWaiting
+            } else if room == 0 {
+                Boarding::Denied
+            } else {
+                room -= 1;
+                Boarding::Board
+            }
+        })
+        .collect()
+}
+
+/// Vehicles running ahead wait at timepoints, like the ones listed in a printed schedule.
+#[derive(Serialize, Deserialize, Clone)]
+struct Holding {
+    /// Every this many stops along a route is a timepoint, starting with the first
+    timepoint_every_n_stops: usize,
+    /// Never hold a vehicle longer than this at one stop
+    max_hold_time: Duration,
+}
+
+impl Holding {
+    /// Vehicles don't wait at the last stop, since there's nobody left to serve.
+    fn is_timepoint(&self, stop_idx: StopIdx, num_stops: usize) -> bool {
+        stop_idx % self.timepoint_every_n_stops == 0 && stop_idx != num_stops - 1
+    }
+
+    /// How long should a vehicle ready to leave a timepoint wait? If the route has a schedule, wait
+    /// until the scheduled time. Otherwise, keep the planned headway behind the previous vehicle
+    /// to leave the stop.
+    fn hold_time(
+        &self,
+        ready_to_leave: Time,
+        scheduled_arrival: Option<Time>,
+        prev_departure: Option<Time>,
+        planned_headway: Option<Duration>,
+    ) -> Duration {
+        let hold_until = match (scheduled_arrival, prev_departure, planned_headway) {
+            (Some(t), _, _) => t,
+            (None, Some(prev), Some(headway)) => prev + headway,
+            _ => {
+                return Duration::ZERO;
+            }
+        };
+        (hold_until - ready_to_leave)
+            .max(Duration::ZERO)
+            .min(self.max_hold_time)
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 enum BusState {
     DrivingToStop(StopIdx),
@@ -67,12 +157,14 @@ pub(crate) struct TransitSimState {
         deserialize_with = "deserialize_btreemap"
     )]
     peds_waiting: BTreeMap<BusStopID, Vec<(PedestrianID, BusRouteID, Option<BusStopID>, Time)>>,
+    /// If present, vehicles wait at timepoints to keep to their schedule or headway.
+    holding: Option<Holding>,
 
     events: Vec<Event>,
 }
 
 impl TransitSimState {
-    pub fn new(map: &Map) -> TransitSimState {
+    pub fn new(map: &Map, opts: &SimOptions) -> TransitSimState {
         // Keep this filled out always so get_passengers can return &Vec without a hassle
         let mut peds_waiting = BTreeMap::new();
         for bs in map.all_bus_stops().keys() {
@@ -83,6 +175,14 @@ impl TransitSimState {
             buses: BTreeMap::new(),
             routes: BTreeMap::new(),
             peds_waiting,
+            holding: if opts.transit_holding {
+                Some(Holding {
+                    timepoint_every_n_stops: opts.transit_timepoint_every_n_stops.max(1),
+                    max_hold_time: opts.transit_max_hold_time,
+                })
+            } else {
+                None
+            },
             events: Vec::new(),
         }
     }
@@ -144,6 +244,7 @@ impl TransitSimState {
             };
             Route {
                 active_vehicles: BTreeSet::new(),
+                last_departures: vec![None; stops.len()],
                 stops,
                 start,
                 end_at_border,
@@ -153,22 +254,27 @@ impl TransitSimState {
         self.routes[&bus_route.id].start.clone()
     }
 
-    pub fn bus_created(&mut self, bus: CarID, r: BusRouteID) {
+    pub fn bus_created(&mut self, bus: CarID, r: BusRouteID, scheduled_start: Time, map: &Map) {
         let route = self.routes.get_mut(&r).unwrap();
         route.active_vehicles.insert(bus);
+        let (seated, standing) = bus.vehicle_type.passenger_capacity_on(map.get_br(r));
         self.buses.insert(
             bus,
             Bus {
                 car: bus,
                 route: r,
                 passengers: Vec::new(),
+                capacity: seated + standing,
+                scheduled_start,
+                lateness: None,
                 state: BusState::DrivingToStop(0),
             },
         );
     }
 
-    /// If true, the bus is idling. If false, the bus actually arrived at a border and should now
-    /// vanish.
+    /// If the bus is idling, returns how long it should wait at the stop, depending on how many
+    /// people got on and off, and whether it's holding to regularize service. If None, the bus
+    /// actually arrived at a border and should now vanish.
     pub fn bus_arrived_at_stop(
         &mut self,
         now: Time,
@@ -176,19 +282,29 @@ impl TransitSimState {
         trips: &mut TripManager,
         walking: &mut WalkingSimState,
         ctx: &mut Ctx,
-    ) -> bool {
+    ) -> Option<Duration> {
         let mut bus = self.buses.get_mut(&id).unwrap();
         match bus.state {
             BusState::DrivingToStop(stop_idx) => {
                 bus.state = BusState::AtStop(stop_idx);
                 let stop1 = self.routes[&bus.route].stops[stop_idx].id;
+                let scheduled_arrival = ctx
+                    .map
+                    .get_br(bus.route)
+                    .scheduled_stop_offsets
+                    .get(stop_idx)
+                    .map(|offset| bus.scheduled_start + *offset);
+                bus.lateness = scheduled_arrival.map(|t| now - t);
                 self.events
-                    .push(Event::BusArrivedAtStop(id, bus.route, stop1));
+                    .push(Event::BusArrivedAtStop(id, bus.route, stop1, bus.lateness));
 
                 // Deboard existing passengers.
                 let mut still_riding = Vec::new();
+                let mut num_alighting = 0;
+                let mut num_boarding = 0;
                 for (person, maybe_stop2) in bus.passengers.drain(..) {
                     if Some(stop1) == maybe_stop2 {
+                        num_alighting += 1;
                         trips.person_left_bus(now, person, bus.car, ctx);
                         self.events.push(Event::PassengerAlightsTransit(
                             person, bus.car, bus.route, stop1,
@@ -199,12 +315,24 @@ impl TransitSimState {
                 }
                 bus.passengers = still_riding;
 
-                // Board new passengers.
+                // Board new passengers, in the order they started waiting, until the bus is full.
                 let mut still_waiting = Vec::new();
-                for (ped, route, maybe_stop2, started_waiting) in
-                    self.peds_waiting.remove(&stop1).unwrap()
+                let waiting = self.peds_waiting.remove(&stop1).unwrap();
+                let decisions = decide_boarding(
+                    waiting.iter().map(|(_, route, _, _)| *route),
+                    bus.route,
+                    bus.capacity.saturating_sub(bus.passengers.len()),
+                );
+                for ((ped, route, maybe_stop2, started_waiting), decision) in
+                    waiting.into_iter().zip(decisions)
                 {
-                    if bus.route == route {
+                    if decision == Boarding::Denied {
+                        self.events.push(Event::PassengerDeniedBoarding(
+                            ped, bus.car, bus.route, stop1,
+                        ));
+                        still_waiting.push((ped, route, maybe_stop2, started_waiting));
+                    } else if decision == Boarding::Board {
+                        num_boarding += 1;
                         let (trip, person) = trips.ped_boarded_bus(
                             now,
                             ped,
@@ -244,7 +372,37 @@ impl TransitSimState {
                     }
                 }
                 self.peds_waiting.insert(stop1, still_waiting);
-                true
+                let dwell_time = MIN_DWELL_TIME
+                    + (num_boarding as f64) * TIME_PER_BOARDING
+                    + (num_alighting as f64) * TIME_PER_ALIGHTING;
+
+                let route = self.routes.get_mut(&bus.route).unwrap();
+                let hold_time = match self.holding {
+                    Some(ref holding) if holding.is_timepoint(stop_idx, route.stops.len()) => {
+                        // Without a schedule, keep the planned gap behind the previous vehicle.
+                        let planned_headway = ctx
+                            .map
+                            .get_br(bus.route)
+                            .spawn_times
+                            .iter()
+                            .filter(|t| **t < bus.scheduled_start)
+                            .max()
+                            .map(|prev_start| bus.scheduled_start - *prev_start);
+                        holding.hold_time(
+                            now + dwell_time,
+                            scheduled_arrival,
+                            route.last_departures[stop_idx],
+                            planned_headway,
+                        )
+                    }
+                    _ => Duration::ZERO,
+                };
+                if hold_time > Duration::ZERO {
+                    self.events
+                        .push(Event::BusHeldAtStop(id, bus.route, stop1, hold_time));
+                }
+                route.last_departures[stop_idx] = Some(now + dwell_time + hold_time);
+                Some(dwell_time + hold_time)
             }
             BusState::DrivingOffMap => {
                 self.routes
@@ -263,7 +421,7 @@ impl TransitSimState {
                     }
                     trips.transit_rider_reached_border(now, person, id, ctx);
                 }
-                false
+                None
             }
             BusState::AtStop(_) | BusState::Done => unreachable!(),
         }
@@ -276,8 +434,12 @@ impl TransitSimState {
             BusState::DrivingToStop(_) | BusState::DrivingOffMap | BusState::Done => unreachable!(),
             BusState::AtStop(stop_idx) => {
                 let stop = &route.stops[stop_idx];
-                self.events
-                    .push(Event::BusDepartedFromStop(id, bus.route, stop.id));
+                self.events.push(Event::BusDepartedFromStop(
+                    id,
+                    bus.route,
+                    stop.id,
+                    bus.passengers.len(),
+                ));
                 if let Some(path) = stop.next_stop.clone() {
                     bus.state = BusState::DrivingToStop(stop_idx + 1);
                     Router::follow_bus_route(id, path)
@@ -300,7 +462,8 @@ impl TransitSimState {
         }
     }
 
-    /// Returns the bus if the pedestrian boarded immediately.
+    /// Returns the bus if the pedestrian boarded immediately. If the only vehicles at the stop are
+    /// full, they wait for the next one.
     pub fn ped_waiting_for_bus(
         &mut self,
         now: Time,
@@ -317,6 +480,11 @@ impl TransitSimState {
             for bus in &route.active_vehicles {
                 if let BusState::AtStop(idx) = self.buses[bus].state {
                     if route.stops[idx].id == stop1 {
+                        if self.buses[bus].is_full() {
+                            self.events
+                                .push(Event::PassengerDeniedBoarding(ped, *bus, route_id, stop1));
+                            continue;
+                        }
                         self.buses
                             .get_mut(bus)
                             .unwrap()
@@ -366,6 +534,14 @@ impl TransitSimState {
         self.buses[&bus].route
     }
 
+    /// Was the vehicle behind schedule at the last stop? False if the route has no schedule.
+    pub fn is_behind_schedule(&self, bus: CarID) -> bool {
+        self.buses[&bus]
+            .lateness
+            .map(|lateness| lateness > Duration::ZERO)
+            .unwrap_or(false)
+    }
+
     /// also stop idx that the bus is coming from
     pub fn buses_for_route(&self, route: BusRouteID) -> Vec<(CarID, Option<usize>)> {
         if let Some(r) = self.routes.get(&route) {
@@ -448,3 +624,77 @@ impl TransitSimState {
         results
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_holding() {
+        let holding = Holding {
+            timepoint_every_n_stops: 3,
+            max_hold_time: Duration::minutes(2),
+        };
+        // Every third stop is a timepoint, except the last
+        let timepoints: Vec<StopIdx> = (0..7).filter(|i| holding.is_timepoint(*i, 7)).collect();
+        assert_eq!(timepoints, vec![0, 3]);
+
+        let t = |secs: f64| Time::START_OF_DAY + Duration::seconds(secs);
+        let ready = t(600.0);
+        // Ahead of schedule, so wait
+        assert_eq!(
+            holding.hold_time(ready, Some(t(660.0)), None, None),
+            Duration::minutes(1)
+        );
+        // Behind schedule, so don't
+        assert_eq!(
+            holding.hold_time(ready, Some(t(540.0)), None, None),
+            Duration::ZERO
+        );
+        // Way ahead of schedule, but only wait so long
+        assert_eq!(
+            holding.hold_time(ready, Some(t(1200.0)), None, None),
+            Duration::minutes(2)
+        );
+        // Without a schedule, keep a 5 minute headway behind the previous vehicle, but only wait
+        // so long
+        assert_eq!(
+            holding.hold_time(ready, None, Some(t(480.0)), Some(Duration::minutes(5))),
+            Duration::minutes(2)
+        );
+        assert_eq!(
+            holding.hold_time(ready, None, Some(t(540.0)), Some(Duration::minutes(1))),
+            Duration::ZERO
+        );
+        assert_eq!(
+            holding.hold_time(ready, None, Some(t(540.0)), Some(Duration::minutes(2))),
+            Duration::minutes(1)
+        );
+        // The first vehicle has nobody to keep behind
+        assert_eq!(
+            holding.hold_time(ready, None, None, Some(Duration::minutes(5))),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_decide_boarding() {
+        let (r1, r2) = (BusRouteID(1), BusRouteID(2));
+        // Somebody waiting for another route doesn't use up room, and people board in the order
+        // they started waiting.
+        assert_eq!(
+            decide_boarding(vec![r1, r2, r1, r1].into_iter(), r1, 2),
+            vec![
+                Boarding::Board,
+                Boarding::KeepWaiting,
+                Boarding::Board,
+                Boarding::Denied
+            ]
+        );
+        // A full vehicle passes everybody by
+        assert_eq!(
+            decide_boarding(vec![r2, r2].into_iter(), r2, 0),
+            vec![Boarding::Denied, Boarding::Denied]
+        );
+    }
+}
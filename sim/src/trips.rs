@@ -1,22 +1,28 @@
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_btreemap, serialize_btreemap, Counter};
 use geom::{Distance, Duration, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, Map, PathConstraints, PathRequest, Position,
+    BikeShareDockID, BuildingID, BusRouteID, BusStopID, DirectedRoadID, IntersectionID, Map, Path,
+    PathConstraints, PathRequest, Position,
 };
 
 use crate::sim::Ctx;
 use crate::{
-    AgentID, AgentType, AlertLocation, CarID, Command, CreateCar, CreatePedestrian, DrivingGoal,
-    Event, IndividTrip, OrigPersonID, ParkedCar, ParkingSim, ParkingSpot, PedestrianID, PersonID,
-    PersonSpec, Scenario, SidewalkPOI, SidewalkSpot, StartTripArgs, TransitSimState, TripEndpoint,
-    TripID, TripPhaseType, TripPurpose, TripSpec, Vehicle, VehicleSpec, VehicleType,
-    WalkingSimState,
+    AgentID, AgentType, AlertLocation, BikeShareSimState, CarID, Command, CreateCar,
+    CreatePedestrian, DeliveryTour, DrivingGoal, Event, FreightSimState, IndividTrip, OrigPersonID,
+    ParkedCar, ParkingSim, ParkingSpot, PedestrianID, PersonID, PersonSpec, RideHailFleet,
+    RideHailSimState, RideHailStop, Router, Scenario, SharedRide, SidewalkPOI, SidewalkSpot,
+    StartTripArgs, TransitSimState, TripEndpoint, TripID, TripPhaseType, TripPurpose, TripSpec,
+    Vehicle, VehicleSpec, VehicleType, WalkingSimState, MAX_WALK_TO_DOCK,
 };
 
+/// A driver won't wait longer than this at a stop for passengers who haven't shown up.
+const MAX_WAIT_FOR_PASSENGERS: Duration = Duration::const_seconds(10.0 * 60.0);
+
 /// Manages people, each of which executes some trips through the day. Each trip is further broken
 /// down into legs -- for example, a driving trip might start with somebody walking to their car,
 /// driving somewhere, parking, and then walking to their final destination.
@@ -35,6 +41,28 @@ pub(crate) struct TripManager {
     active_trip_mode: BTreeMap<AgentID, TripID>,
     unfinished_trips: usize,
 
+    /// Keyed by the driver's trip
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    carpools: BTreeMap<TripID, Carpool>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    passengers: BTreeMap<TripID, Passenger>,
+    ride_hail: RideHailSimState,
+    freight: FreightSimState,
+    bike_share: BikeShareSimState,
+    /// Driving trips that follow a route decided ahead of time, instead of pathfinding when they
+    /// start
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    planned_routes: BTreeMap<TripID, Vec<DirectedRoadID>>,
+
     car_id_counter: usize,
 
     events: Vec<Event>,
@@ -48,6 +76,12 @@ impl TripManager {
             people: Vec::new(),
             active_trip_mode: BTreeMap::new(),
             unfinished_trips: 0,
+            carpools: BTreeMap::new(),
+            passengers: BTreeMap::new(),
+            ride_hail: RideHailSimState::new(),
+            freight: FreightSimState::new(),
+            bike_share: BikeShareSimState::new(),
+            planned_routes: BTreeMap::new(),
             car_id_counter: 0,
             events: Vec::new(),
         }
@@ -92,6 +126,31 @@ impl TripManager {
         id
     }
 
+    pub fn seed_ride_hail_fleet(&mut self, now: Time, fleet: RideHailFleet, map: &Map) {
+        let mut counter = self.car_id_counter;
+        self.ride_hail.seed_fleet(
+            now,
+            fleet,
+            || {
+                counter += 1;
+                counter - 1
+            },
+            map,
+        );
+        self.car_id_counter = counter;
+    }
+
+    /// Registers a delivery tour, returning the vehicle and where it starts. The caller schedules
+    /// `Command::ContinueDeliveryTour` for when it departs.
+    pub fn start_delivery_tour(
+        &mut self,
+        tour: DeliveryTour,
+        map: &Map,
+    ) -> Result<(CarID, Position)> {
+        let id = self.new_car_id();
+        self.freight.start_tour(id, tour, map)
+    }
+
     pub fn new_trip(&mut self, person: PersonID, info: TripInfo) -> TripID {
         let id = TripID(self.trips.len());
         let trip = Trip {
@@ -130,6 +189,93 @@ impl TripManager {
         id
     }
 
+    /// Link each passenger to the driver giving them a ride, as (driver, passenger, pickup,
+    /// dropoff). Passengers whose ride doesn't make sense are cancelled. This must happen before
+    /// any of the trips start.
+    pub fn new_shared_rides(
+        &mut self,
+        rides: Vec<(TripID, TripID, BuildingID, BuildingID)>,
+        map: &Map,
+    ) {
+        let mut per_driver: BTreeMap<TripID, Vec<(TripID, BuildingID, BuildingID)>> =
+            BTreeMap::new();
+        for (driver, passenger, pickup, dropoff) in rides {
+            if self.trips[passenger.0].info.cancellation_reason.is_some() {
+                continue;
+            }
+            if let Err(err) = self.check_shared_ride(driver, passenger, pickup, dropoff, map) {
+                self.cancel_unstarted_trip(passenger, err.to_string());
+                continue;
+            }
+            self.passengers.insert(
+                passenger,
+                Passenger {
+                    driver,
+                    pickup,
+                    dropoff,
+                },
+            );
+            per_driver
+                .entry(driver)
+                .or_insert_with(Vec::new)
+                .push((passenger, pickup, dropoff));
+        }
+
+        // Pick everybody up, then drop everybody off, in the same order
+        for (driver, list) in per_driver {
+            let mut stops = VecDeque::new();
+            for (passenger, pickup, _) in &list {
+                stop_at(&mut stops, *pickup).pickup.push(*passenger);
+            }
+            for (passenger, _, dropoff) in &list {
+                stop_at(&mut stops, *dropoff).dropoff.push(*passenger);
+            }
+            self.carpools.insert(
+                driver,
+                Carpool {
+                    stops,
+                    car: None,
+                    arrived_at_stop: None,
+                    waiting: BTreeSet::new(),
+                    riders: BTreeSet::new(),
+                },
+            );
+        }
+    }
+
+    fn check_shared_ride(
+        &self,
+        driver: TripID,
+        passenger: TripID,
+        pickup: BuildingID,
+        dropoff: BuildingID,
+        map: &Map,
+    ) -> Result<()> {
+        let driver_trip = &self.trips[driver.0];
+        if driver_trip.info.mode != TripMode::Drive {
+            bail!(
+                "{} is supposed to get a ride from {}, but that trip isn't driving",
+                passenger,
+                driver
+            );
+        }
+        if driver_trip.info.cancellation_reason.is_some() {
+            bail!("the driver's trip {} was cancelled", driver);
+        }
+        if driver_trip.person == self.trips[passenger.0].person {
+            bail!("{} can't give themselves a ride", driver_trip.person);
+        }
+        if pickup == dropoff {
+            bail!("{} gets picked up and dropped off at {}", passenger, pickup);
+        }
+        for b in [pickup, dropoff] {
+            if map.get_b(b).driving_connection(map).is_none() {
+                bail!("cars can't stop near {}", b);
+            }
+        }
+        Ok(())
+    }
+
     pub fn start_trip(&mut self, now: Time, trip: TripID, args: StartTripArgs, ctx: &mut Ctx) {
         assert!(self.trips[trip.0].info.cancellation_reason.is_none());
 
@@ -155,16 +301,29 @@ impl TripManager {
             return;
         }
         self.trips[trip.0].started = true;
+        if let Some(route) = args.route {
+            self.planned_routes.insert(trip, route);
+        }
 
         let info = &self.trips[trip.0].info;
-        let spec = match TripSpec::maybe_new(
-            info.start,
-            info.end,
-            info.mode,
-            args.use_vehicle,
-            args.retry_if_no_room,
-            ctx.map,
-        ) {
+        let maybe_spec = if info.mode == TripMode::Carpool {
+            match self.passengers.get(&trip) {
+                Some(p) => {
+                    TripSpec::carpool(info.start, info.end, p.driver, p.pickup, p.dropoff, ctx.map)
+                }
+                None => Err(anyhow!("nobody is giving this passenger a ride")),
+            }
+        } else {
+            TripSpec::maybe_new(
+                info.start,
+                info.end,
+                info.mode,
+                args.use_vehicle,
+                args.retry_if_no_room,
+                ctx.map,
+            )
+        };
+        let spec = match maybe_spec {
             Ok(spec) => spec,
             Err(error) => TripSpec::SpawningFailure {
                 use_vehicle: args.use_vehicle,
@@ -193,6 +352,7 @@ impl TripManager {
 
                 let vehicle = person.get_vehicle(use_vehicle);
                 assert!(ctx.parking.lookup_parked_car(vehicle.id).is_none());
+                let person = person.id;
                 let constraints = if use_vehicle.vehicle_type == VehicleType::Bike {
                     PathConstraints::Bike
                 } else {
@@ -200,14 +360,15 @@ impl TripManager {
                 };
                 let req = PathRequest::vehicle(
                     start_pos,
-                    goal.goal_pos(constraints, ctx.map).unwrap(),
+                    self.next_drive_end(trip, &goal, constraints, &vehicle, now, ctx)
+                        .unwrap(),
                     constraints,
                 );
-                let person = person.id;
 
-                match ctx.map.pathfind(req) {
+                match self.pathfind_driving(trip, req, now, ctx.map) {
                     Ok(path) => {
-                        let router = goal.make_router(vehicle.id, path, ctx.map);
+                        let router =
+                            self.make_driving_router(trip, vehicle.id, &goal, path, ctx.map);
                         ctx.scheduler.push(
                             now,
                             Command::SpawnCar(
@@ -280,28 +441,7 @@ impl TripManager {
             TripSpec::JustWalking { start, goal } => {
                 assert_eq!(
                     person.state,
-                    match start.connection {
-                        SidewalkPOI::Building(b) => PersonState::Inside(b),
-                        SidewalkPOI::Border(i) => {
-                            self.events.push(Event::PersonEntersMap(
-                                person.id,
-                                AgentID::Pedestrian(person.ped),
-                                i,
-                            ));
-                            PersonState::OffMap
-                        }
-                        SidewalkPOI::SuddenlyAppear => {
-                            // Unclear which end of the sidewalk this person should be associated
-                            // with. For interactively spawned people, doesn't really matter.
-                            self.events.push(Event::PersonEntersMap(
-                                person.id,
-                                AgentID::Pedestrian(person.ped),
-                                ctx.map.get_l(start.sidewalk_pos.lane()).src_i,
-                            ));
-                            PersonState::OffMap
-                        }
-                        _ => unreachable!(),
-                    }
+                    start_walking(person, &start, &mut self.events, ctx.map)
                 );
                 person.state = PersonState::Trip(trip);
 
@@ -382,28 +522,7 @@ impl TripManager {
             TripSpec::UsingTransit { start, stop1, .. } => {
                 assert_eq!(
                     person.state,
-                    match start.connection {
-                        SidewalkPOI::Building(b) => PersonState::Inside(b),
-                        SidewalkPOI::Border(i) => {
-                            self.events.push(Event::PersonEntersMap(
-                                person.id,
-                                AgentID::Pedestrian(person.ped),
-                                i,
-                            ));
-                            PersonState::OffMap
-                        }
-                        SidewalkPOI::SuddenlyAppear => {
-                            // Unclear which end of the sidewalk this person should be associated
-                            // with. For interactively spawned people, doesn't really matter.
-                            self.events.push(Event::PersonEntersMap(
-                                person.id,
-                                AgentID::Pedestrian(person.ped),
-                                ctx.map.get_l(start.sidewalk_pos.lane()).src_i,
-                            ));
-                            PersonState::OffMap
-                        }
-                        _ => unreachable!(),
-                    }
+                    start_walking(person, &start, &mut self.events, ctx.map)
                 );
                 person.state = PersonState::Trip(trip);
 
@@ -429,11 +548,76 @@ impl TripManager {
                     }
                 }
             }
+            TripSpec::UsingCarpool { start, .. } => {
+                assert_eq!(
+                    person.state,
+                    start_walking(person, &start, &mut self.events, ctx.map)
+                );
+                person.state = PersonState::Trip(trip);
+
+                if let TripLeg::Walk(_) = self.trips[trip.0].legs[0] {
+                    self.spawn_ped(now, trip, start, ctx);
+                } else {
+                    // They're already inside the building where they get picked up
+                    self.passenger_waiting(now, trip, ctx);
+                }
+            }
+            TripSpec::UsingRideHail { start, goal } => {
+                assert_eq!(person.state, PersonState::Inside(start));
+                person.state = PersonState::Trip(trip);
+                self.events.push(Event::TripPhaseStarting(
+                    trip,
+                    person.id,
+                    None,
+                    TripPhaseType::WaitingForRide,
+                ));
+
+                if let Err(err) = self.ride_hail.request(now, trip, start, goal, ctx) {
+                    self.events.push(Event::PersonLeavesBuilding(
+                        self.trips[trip.0].person,
+                        start,
+                    ));
+                    self.cancel_trip(now, trip, err.to_string(), None, ctx);
+                }
+            }
+            TripSpec::UsingBikeShare { start, goal } => {
+                assert_eq!(person.state, PersonState::Inside(start));
+                person.state = PersonState::Trip(trip);
+
+                // Only bother if there's a vehicle to pick up and room to return it nearby.
+                // Otherwise, just walk.
+                let pickup = self.bike_share.dock_with_vehicle(
+                    ctx.map.get_b(start).polygon.center(),
+                    Some(MAX_WALK_TO_DOCK),
+                    None,
+                    ctx.map,
+                );
+                let dropoff = self.bike_share.dock_with_room(
+                    ctx.map.get_b(goal).polygon.center(),
+                    Some(MAX_WALK_TO_DOCK),
+                    None,
+                    ctx.map,
+                );
+                if let (Some(pickup), Some(dropoff)) = (pickup, dropoff) {
+                    if pickup != dropoff {
+                        let legs = &mut self.trips[trip.0].legs;
+                        legs.push_front(TripLeg::BikeShare(pickup, dropoff));
+                        legs.push_front(TripLeg::Walk(
+                            SidewalkSpot::bike_share_dock(pickup, ctx.map).unwrap(),
+                        ));
+                    }
+                }
+                self.spawn_ped(now, trip, SidewalkSpot::building(start, ctx.map), ctx);
+            }
         }
     }
 
     pub fn collect_events(&mut self) -> Vec<Event> {
-        std::mem::take(&mut self.events)
+        let mut events = std::mem::take(&mut self.events);
+        events.extend(self.ride_hail.collect_events());
+        events.extend(self.freight.collect_events());
+        events.extend(self.bike_share.collect_events());
+        events
     }
 }
 
@@ -524,17 +708,27 @@ impl TripManager {
             _ => unreachable!(),
         };
 
+        let person = trip.person;
+        let trip = trip.id;
         let base_start =
             ctx.parking
                 .spot_to_driving_pos(parked_car.spot, &parked_car.vehicle, ctx.map);
-        let end = drive_to.goal_pos(PathConstraints::Car, ctx.map).unwrap();
+        let end = self
+            .next_drive_end(
+                trip,
+                &drive_to,
+                PathConstraints::Car,
+                &parked_car.vehicle,
+                now,
+                ctx,
+            )
+            .unwrap();
         let req = match spot {
             ParkingSpot::Onstreet(_, _) => {
                 PathRequest::vehicle(base_start, end, PathConstraints::Car)
             }
             ParkingSpot::Offstreet(b, _) => {
-                self.events
-                    .push(Event::PersonEntersBuilding(trip.person, b));
+                self.events.push(Event::PersonEntersBuilding(person, b));
                 PathRequest::leave_from_driveway(base_start, end, PathConstraints::Car, ctx.map)
             }
             ParkingSpot::Lot(_, _) => {
@@ -542,11 +736,10 @@ impl TripManager {
             }
         };
 
-        let person = trip.person;
-        let trip = trip.id;
-        match ctx.map.pathfind(req) {
+        match self.pathfind_driving(trip, req, now, ctx.map) {
             Ok(path) => {
-                let router = drive_to.make_router(parked_car.vehicle.id, path, ctx.map);
+                let router =
+                    self.make_driving_router(trip, parked_car.vehicle.id, &drive_to, path, ctx.map);
                 ctx.scheduler.push(
                     now,
                     Command::SpawnCar(
@@ -581,6 +774,11 @@ impl TripManager {
         trip.total_distance += distance_crossed;
 
         trip.assert_walking_leg(spot.clone());
+        if let TripLeg::BikeShare(pickup, dropoff) = trip.legs[0] {
+            let id = trip.id;
+            self.ped_reached_bike_share_dock(now, id, pickup, dropoff, spot, ctx);
+            return;
+        }
         let (bike, drive_to) = match trip.legs[0] {
             TripLeg::Drive(bike, ref to) => (bike, to.clone()),
             _ => unreachable!(),
@@ -613,7 +811,7 @@ impl TripManager {
             ))
         } else {
             ctx.map
-                .pathfind(req)
+                .pathfind_at(req, now)
                 .map(|path| drive_to.make_router(bike, path, ctx.map))
         };
         match maybe_router {
@@ -655,6 +853,44 @@ impl TripManager {
         trip.total_blocked_time += blocked_time;
         trip.total_distance += distance_crossed;
 
+        if let Some(TripLeg::BikeShare(pickup, dropoff)) = trip.legs.front().cloned() {
+            let id = trip.id;
+            if !self.bike_share.return_vehicle(id, dropoff, false, ctx.map) {
+                // The dock filled up. Ride on to the closest one with room, or squeeze in here if
+                // there's nowhere else.
+                let vehicle = self.bike_share.get_vehicle(id).unwrap().clone();
+                let person = self.trips[id.0].person;
+                if let Some((next, router)) = self
+                    .bike_share
+                    .dock_with_room(
+                        bike_rack.sidewalk_pos.pt(ctx.map),
+                        None,
+                        Some(dropoff),
+                        ctx.map,
+                    )
+                    .and_then(|next| {
+                        bike_share_router(now, bike, &bike_rack, next, ctx.map)
+                            .ok()
+                            .map(|router| (next, router))
+                    })
+                {
+                    self.trips[id.0].legs[0] = TripLeg::BikeShare(pickup, next);
+                    ctx.scheduler.push(
+                        now,
+                        Command::SpawnCar(
+                            CreateCar::for_appearing(vehicle, router, id, person),
+                            true,
+                        ),
+                    );
+                    return;
+                }
+                self.bike_share.return_vehicle(id, dropoff, true, ctx.map);
+            }
+            self.trips[id.0].legs.pop_front();
+            self.spawn_ped(now, id, bike_rack, ctx);
+            return;
+        }
+
         match trip.legs.pop_front() {
             Some(TripLeg::Drive(c, DrivingGoal::ParkNear(_))) => {
                 assert_eq!(c, bike);
@@ -666,6 +902,71 @@ impl TripManager {
         self.spawn_ped(now, id, bike_rack, ctx);
     }
 
+    /// Somebody walked to a bike-share dock. Take a vehicle and ride towards the dock near their
+    /// destination. If this dock is empty, try another one nearby, or give up and walk.
+    fn ped_reached_bike_share_dock(
+        &mut self,
+        now: Time,
+        id: TripID,
+        pickup: BikeShareDockID,
+        dropoff: BikeShareDockID,
+        spot: SidewalkSpot,
+        ctx: &mut Ctx,
+    ) {
+        let car_id = self.new_car_id();
+        if let Some(vehicle) = self.bike_share.take_vehicle(id, pickup, car_id, ctx.map) {
+            match bike_share_router(now, vehicle.id, &spot, dropoff, ctx.map) {
+                Ok(router) => {
+                    let person = self.trips[id.0].person;
+                    ctx.scheduler.push(
+                        now,
+                        Command::SpawnCar(
+                            CreateCar::for_appearing(vehicle, router, id, person),
+                            true,
+                        ),
+                    );
+                    return;
+                }
+                Err(err) => {
+                    warn!(
+                        "{} can't ride from {}, walking instead: {}",
+                        id, pickup, err
+                    );
+                    self.bike_share.return_vehicle(id, pickup, true, ctx.map);
+                }
+            }
+        } else if let Some(next) = self.bike_share.dock_with_vehicle(
+            spot.sidewalk_pos.pt(ctx.map),
+            Some(MAX_WALK_TO_DOCK),
+            Some(pickup),
+            ctx.map,
+        ) {
+            if next != dropoff {
+                let legs = &mut self.trips[id.0].legs;
+                legs[0] = TripLeg::BikeShare(next, dropoff);
+                legs.push_front(TripLeg::Walk(
+                    SidewalkSpot::bike_share_dock(next, ctx.map).unwrap(),
+                ));
+                self.spawn_ped(
+                    now,
+                    id,
+                    SidewalkSpot::suddenly_appear(spot.sidewalk_pos, ctx.map),
+                    ctx,
+                );
+                return;
+            }
+        }
+
+        // Walk the rest of the way
+        self.trips[id.0].legs.pop_front();
+        self.spawn_ped(
+            now,
+            id,
+            SidewalkSpot::suddenly_appear(spot.sidewalk_pos, ctx.map),
+            ctx,
+        );
+    }
+
     pub fn ped_reached_building(
         &mut self,
         now: Time,
@@ -684,12 +985,17 @@ impl TripManager {
         trip.total_distance += distance_crossed;
 
         trip.assert_walking_leg(SidewalkSpot::building(bldg, ctx.map));
-
-        self.people[trip.person.0].state = PersonState::Inside(bldg);
         self.events
             .push(Event::PersonEntersBuilding(trip.person, bldg));
 
         let id = trip.id;
+        if let Some(TripLeg::RideInCar(_, _)) = trip.legs.front() {
+            // Wait inside to get picked up
+            self.passenger_waiting(now, id, ctx);
+            return;
+        }
+
+        self.people[trip.person.0].state = PersonState::Inside(bldg);
         self.trip_finished(now, id, ctx);
     }
 
@@ -891,7 +1197,364 @@ impl TripManager {
         self.trip_finished(now, id, ctx);
     }
 
+    /// A passenger is at their pickup, ready to get in the car.
+    fn passenger_waiting(&mut self, now: Time, id: TripID, ctx: &mut Ctx) {
+        let passenger = self.passengers[&id].clone();
+        let person = self.trips[id.0].person;
+        // Is the car still going to stop here?
+        if !self
+            .carpools
+            .get(&passenger.driver)
+            .map(|c| c.stops.iter().any(|stop| stop.pickup.contains(&id)))
+            .unwrap_or(false)
+        {
+            self.events
+                .push(Event::PersonLeavesBuilding(person, passenger.pickup));
+            self.cancel_trip(
+                now,
+                id,
+                format!("missed their ride with {}", passenger.driver),
+                None,
+                ctx,
+            );
+            return;
+        }
+
+        self.events.push(Event::TripPhaseStarting(
+            id,
+            person,
+            None,
+            TripPhaseType::WaitingForRide,
+        ));
+        let carpool = self.carpools.get_mut(&passenger.driver).unwrap();
+        if carpool.arrived_at_stop.is_some() && carpool.stops[0].pickup.contains(&id) {
+            // The car is already here
+            carpool.riders.insert(id);
+            let car = carpool.car.unwrap();
+            self.passenger_boarded(id, car);
+        } else {
+            carpool.waiting.insert(id);
+        }
+    }
+
+    fn passenger_boarded(&mut self, id: TripID, car: CarID) {
+        let person = self.trips[id.0].person;
+        self.events.push(Event::PersonLeavesBuilding(
+            person,
+            self.passengers[&id].pickup,
+        ));
+        self.events.push(Event::TripPhaseStarting(
+            id,
+            person,
+            None,
+            TripPhaseType::RidingInCar(car),
+        ));
+    }
+
+    fn passenger_dropped_off(&mut self, now: Time, id: TripID, ctx: &mut Ctx) {
+        let trip = &mut self.trips[id.0];
+        let dropoff = match trip.legs.pop_front() {
+            Some(TripLeg::RideInCar(_, b)) => b,
+            _ => unreachable!(),
+        };
+        self.events
+            .push(Event::PersonEntersBuilding(trip.person, dropoff));
+
+        if trip.legs.is_empty() {
+            self.people[trip.person.0].state = PersonState::Inside(dropoff);
+            self.trip_finished(now, id, ctx);
+        } else {
+            self.spawn_ped(now, id, SidewalkSpot::building(dropoff, ctx.map), ctx);
+        }
+    }
+
+    /// A car giving people a ride has stopped to drop off and pick up passengers.
+    pub fn car_reached_carpool_stop(&mut self, now: Time, car: CarID, ctx: &mut Ctx) {
+        let carpool = self
+            .carpools
+            .get_mut(&self.active_trip_mode[&AgentID::Car(car)])
+            .unwrap();
+        carpool.car = Some(car);
+        carpool.arrived_at_stop = Some(now);
+
+        let stop = &carpool.stops[0];
+        let dropoff: Vec<TripID> = stop
+            .dropoff
+            .iter()
+            .filter(|p| carpool.riders.contains(p))
+            .cloned()
+            .collect();
+        let pickup: Vec<TripID> = stop
+            .pickup
+            .iter()
+            .filter(|p| carpool.waiting.contains(p))
+            .cloned()
+            .collect();
+        for p in &dropoff {
+            carpool.riders.remove(p);
+        }
+        for p in &pickup {
+            carpool.waiting.remove(p);
+            carpool.riders.insert(*p);
+        }
+
+        for p in dropoff {
+            self.passenger_dropped_off(now, p, ctx);
+        }
+        for p in pickup {
+            self.passenger_boarded(p, car);
+        }
+    }
+
+    /// Should a car at a carpool stop keep waiting for passengers who haven't shown up yet?
+    pub fn keep_waiting_at_carpool_stop(&self, now: Time, car: CarID) -> bool {
+        let carpool = &self.carpools[&self.active_trip_mode[&AgentID::Car(car)]];
+        if now - carpool.arrived_at_stop.unwrap() >= MAX_WAIT_FOR_PASSENGERS {
+            return false;
+        }
+        carpool.stops[0].pickup.iter().any(|p| {
+            let trip = &self.trips[p.0];
+            !carpool.riders.contains(p)
+                && trip.finished_at.is_none()
+                && trip.info.cancellation_reason.is_none()
+        })
+    }
+
+    /// Returns the route to the next carpool stop or the driver's destination. If there's no way
+    /// to get there, the driver's trip is cancelled, the car should vanish, and None is returned.
+    pub fn car_left_carpool_stop(
+        &mut self,
+        now: Time,
+        vehicle: &Vehicle,
+        start: Position,
+        ctx: &mut Ctx,
+    ) -> Option<Router> {
+        let id = self.active_trip_mode[&AgentID::Car(vehicle.id)];
+        let carpool = self.carpools.get_mut(&id).unwrap();
+        // Anybody who didn't show up in time misses their ride. They'll find out when they get to
+        // the stop or try to start their trip.
+        carpool.stops.pop_front();
+        carpool.arrived_at_stop = None;
+
+        let goal = match self.trips[id.0].legs[0] {
+            TripLeg::Drive(_, ref goal) => goal.clone(),
+            _ => unreachable!(),
+        };
+        let maybe_path =
+            match self.next_drive_end(id, &goal, PathConstraints::Car, vehicle, now, ctx) {
+                Some(end) => ctx
+                    .map
+                    .pathfind_at(PathRequest::vehicle(start, end, PathConstraints::Car), now),
+                None => Err(anyhow!("can't drive to {:?}", goal)),
+            };
+        match maybe_path {
+            Ok(path) => Some(self.make_driving_router(id, vehicle.id, &goal, path, ctx.map)),
+            Err(err) => {
+                self.active_trip_mode.remove(&AgentID::Car(vehicle.id));
+                self.cancel_trip(now, id, err.to_string(), Some(vehicle.clone()), ctx);
+                None
+            }
+        }
+    }
+
+    /// Drivers giving people a ride visit every carpool stop before heading to their goal.
+    /// Visitors to a residential permit zone know they can't park on the street there, so unless
+    /// there's a lot or garage by their destination, they head straight for the closest free spot
+    /// outside the zone.
+    fn next_drive_end(
+        &self,
+        trip: TripID,
+        goal: &DrivingGoal,
+        constraints: PathConstraints,
+        vehicle: &Vehicle,
+        now: Time,
+        ctx: &Ctx,
+    ) -> Option<Position> {
+        let map = ctx.map;
+        if let Some(stop) = self.carpools.get(&trip).and_then(|c| c.stops.front()) {
+            return map
+                .get_b(stop.bldg)
+                .driving_connection(map)
+                .map(|(pos, _)| pos);
+        }
+        let end = goal.goal_pos(constraints, map)?;
+        if let DrivingGoal::ParkNear(b) = goal {
+            if constraints == PathConstraints::Car
+                && ctx.parking.needs_permit(vehicle, *b, now, map)
+                && ctx
+                    .parking
+                    .get_all_free_spots(Position::start(end.lane()), vehicle, *b, now, map)
+                    .is_empty()
+            {
+                if let Some((_, _, pos)) =
+                    ctx.parking
+                        .path_to_free_parking_spot(end.lane(), vehicle, *b, now, map)
+                {
+                    return Some(pos);
+                }
+            }
+        }
+        Some(end)
+    }
+
+    /// Drivers with a route planned ahead of time follow it, unless it doesn't fit where they're
+    /// starting or going. Otherwise, pathfind like usual.
+    fn pathfind_driving(
+        &mut self,
+        trip: TripID,
+        req: PathRequest,
+        now: Time,
+        map: &Map,
+    ) -> Result<Path> {
+        if let Some(route) = self.planned_routes.remove(&trip) {
+            if let Ok(path) = map.pathfind_along_roads(req.clone(), route) {
+                return Ok(path);
+            }
+        }
+        map.pathfind_at(req, now)
+    }
+
+    fn make_driving_router(
+        &self,
+        trip: TripID,
+        car: CarID,
+        goal: &DrivingGoal,
+        path: Path,
+        map: &Map,
+    ) -> Router {
+        if self
+            .carpools
+            .get(&trip)
+            .map(|c| !c.stops.is_empty())
+            .unwrap_or(false)
+        {
+            Router::stop_for_carpool(car, path)
+        } else {
+            goal.make_router(car, path, map)
+        }
+    }
+
+    /// A ride-hail vehicle reached the end of its route. Returns false if the vehicle should
+    /// vanish back into its depot.
+    pub fn ride_hail_vehicle_reached_stop(
+        &mut self,
+        now: Time,
+        car: CarID,
+        distance_crossed: Distance,
+        ctx: &mut Ctx,
+    ) -> bool {
+        match self
+            .ride_hail
+            .vehicle_reached_stop(now, car, distance_crossed)
+        {
+            RideHailStop::Pickup(id, pickup) => {
+                let person = self.trips[id.0].person;
+                self.events
+                    .push(Event::PersonLeavesBuilding(person, pickup));
+                self.events.push(Event::TripPhaseStarting(
+                    id,
+                    person,
+                    None,
+                    TripPhaseType::RidingInCar(car),
+                ));
+                true
+            }
+            RideHailStop::Dropoff(id, dropoff, dist) => {
+                let trip = &mut self.trips[id.0];
+                trip.total_distance += dist;
+                match trip.legs.pop_front() {
+                    Some(TripLeg::RideHail(b)) => assert_eq!(b, dropoff),
+                    _ => unreachable!(),
+                }
+                assert!(trip.legs.is_empty());
+                let person = trip.person;
+                self.events
+                    .push(Event::PersonEntersBuilding(person, dropoff));
+                self.people[person.0].state = PersonState::Inside(dropoff);
+                self.trip_finished(now, id, ctx);
+                true
+            }
+            RideHailStop::Wait => true,
+            RideHailStop::Vanish => false,
+        }
+    }
+
+    /// Returns the route to wherever the ride-hail vehicle should go next. If there's no way to
+    /// go anywhere, returns None and the vehicle should vanish.
+    pub fn ride_hail_vehicle_left_stop(
+        &mut self,
+        now: Time,
+        car: CarID,
+        start: Position,
+        ctx: &mut Ctx,
+    ) -> Option<Router> {
+        let (router, abandoned) = self.ride_hail.vehicle_left_stop(now, car, start, ctx.map);
+        for id in abandoned {
+            self.cancel_trip(
+                now,
+                id,
+                format!("{} couldn't reach the pickup or dropoff", car),
+                None,
+                ctx,
+            );
+        }
+        router
+    }
+
+    /// A freight vehicle reached a delivery stop or its depot. Returns how long to double-park in
+    /// the lane, or None if the vehicle should leave the road.
+    pub fn freight_vehicle_reached_stop(
+        &mut self,
+        now: Time,
+        car: CarID,
+        pos: Position,
+        ctx: &mut Ctx,
+    ) -> Option<Duration> {
+        self.freight.vehicle_reached_stop(now, car, pos, ctx)
+    }
+
+    /// A vehicle not belonging to any trip, like a ride-hail, freight, or emergency vehicle, was
+    /// about to spawn, but map edits broke its route. Give up on whatever it was doing.
+    pub fn vehicle_dropped_before_spawning(&mut self, now: Time, car: CarID, ctx: &mut Ctx) {
+        if car.vehicle_type.is_freight() {
+            self.freight.abandon_tour(car);
+        } else if let Some(trip) = self.ride_hail.vehicle_dropped(now, car) {
+            self.cancel_trip(
+                now,
+                trip,
+                format!("{} couldn't leave its depot after map edits", car),
+                None,
+                ctx,
+            );
+        }
+    }
+
+    /// A freight vehicle is ready to leave its depot or a loading zone. Returns the vehicle to
+    /// spawn, or None if it can't go anywhere.
+    pub fn freight_vehicle_departing(
+        &mut self,
+        now: Time,
+        car: CarID,
+        start: Position,
+        map: &Map,
+    ) -> Option<CreateCar> {
+        self.freight.plan_next_leg(now, car, start, map)
+    }
+
+    /// Returns the route to the next delivery stop or back to the depot. If there's no way to go
+    /// anywhere, returns None and the vehicle should vanish.
+    pub fn freight_vehicle_left_stop(
+        &mut self,
+        now: Time,
+        car: CarID,
+        start: Position,
+        ctx: &mut Ctx,
+    ) -> Option<Router> {
+        self.freight.vehicle_left_stop(now, car, start, ctx.map)
+    }
+
     fn trip_finished(&mut self, now: Time, id: TripID, ctx: &mut Ctx) {
+        self.carpools.remove(&id);
         let trip = &mut self.trips[id.0];
         assert!(trip.legs.is_empty());
         assert!(!trip.finished_at.is_some());
@@ -978,6 +1641,27 @@ impl TripManager {
         abandoned_vehicle: Option<Vehicle>,
         ctx: &mut Ctx,
     ) {
+        // Return any shared bike or scooter to the dock where it was headed
+        if let Some(TripLeg::BikeShare(_, dropoff)) = self.trips[id.0].legs.front() {
+            self.bike_share.cancel_ride(id, *dropoff, ctx.map);
+        }
+        // A passenger waiting to get picked up gives up
+        if let Some(b) = self.ride_hail.cancel_request(id) {
+            self.events
+                .push(Event::PersonLeavesBuilding(self.trips[id.0].person, b));
+        }
+        if let Some(passenger) = self.passengers.get(&id) {
+            if let Some(carpool) = self.carpools.get_mut(&passenger.driver) {
+                if carpool.waiting.remove(&id) {
+                    self.events.push(Event::PersonLeavesBuilding(
+                        self.trips[id.0].person,
+                        passenger.pickup,
+                    ));
+                }
+                carpool.riders.remove(&id);
+            }
+        }
+
         let trip = &mut self.trips[id.0];
         self.unfinished_trips -= 1;
         trip.info.cancellation_reason = Some(reason);
@@ -1016,14 +1700,20 @@ impl TripManager {
                     let driving_lane = ctx.map.find_driving_lane_near_building(b);
                     if let Some(spot) = ctx
                         .parking
-                        .get_all_free_spots(Position::start(driving_lane), &vehicle, b, ctx.map)
+                        .get_all_free_spots(
+                            Position::start(driving_lane),
+                            &vehicle,
+                            b,
+                            now,
+                            ctx.map,
+                        )
                         // TODO Could pick something closer, but meh, cancelled trips are bugs
                         // anyway
                         .get(0)
                         .map(|(spot, _)| *spot)
                         .or_else(|| {
                             ctx.parking
-                                .path_to_free_parking_spot(driving_lane, &vehicle, b, ctx.map)
+                                .path_to_free_parking_spot(driving_lane, &vehicle, b, now, ctx.map)
                                 .map(|(_, spot, _)| spot)
                         })
                     {
@@ -1061,6 +1751,21 @@ impl TripManager {
             }
         }
 
+        // Anybody waiting for or riding with this driver is stranded
+        if let Some(carpool) = self.carpools.remove(&id) {
+            let reason = format!("the driver's trip {} was cancelled", id);
+            for p in carpool.waiting {
+                self.events.push(Event::PersonLeavesBuilding(
+                    self.trips[p.0].person,
+                    self.passengers[&p].pickup,
+                ));
+                self.cancel_trip(now, p, reason.clone(), None, ctx);
+            }
+            for p in carpool.riders {
+                self.cancel_trip(now, p, reason.clone(), None, ctx);
+            }
+        }
+
         self.start_delayed_trip(now, person, ctx);
     }
 
@@ -1102,6 +1807,25 @@ impl TripManager {
             TripLeg::Walk(_) => AgentID::Pedestrian(person.ped),
             TripLeg::Drive(c, _) => AgentID::Car(*c),
             TripLeg::RideBus(_, _) => AgentID::BusPassenger(person.id, person.on_bus.unwrap()),
+            // Follow the driver's car
+            TripLeg::RideInCar(driver, _) => {
+                return match self.carpools.get(driver) {
+                    Some(carpool) if carpool.riders.contains(&id) => {
+                        TripResult::Ok(AgentID::Car(carpool.car.unwrap()))
+                    }
+                    _ => TripResult::ModeChange,
+                };
+            }
+            TripLeg::RideHail(_) => {
+                return match self.ride_hail.passenger_riding_in(id) {
+                    Some(car) => TripResult::Ok(AgentID::Car(car)),
+                    None => TripResult::ModeChange,
+                };
+            }
+            TripLeg::BikeShare(_, _) => match self.bike_share.get_vehicle(id) {
+                Some(vehicle) => AgentID::Car(vehicle.id),
+                None => return TripResult::ModeChange,
+            },
         };
         if self.active_trip_mode.get(&a) == Some(&id) {
             TripResult::Ok(a)
@@ -1139,6 +1863,7 @@ impl TripManager {
         let (buses, trains) = transit.active_vehicles();
         cnt.add(AgentType::Bus, buses);
         cnt.add(AgentType::Train, trains);
+        cnt.add(AgentType::Car, self.ride_hail.active_vehicles().0);
         cnt
     }
     pub fn num_commuters_vehicles(
@@ -1147,6 +1872,7 @@ impl TripManager {
         walking: &WalkingSimState,
     ) -> CommutersVehiclesCounts {
         let (buses, trains) = transit.active_vehicles();
+        let (ride_hail_vehicles, ride_hail_passengers) = self.ride_hail.active_vehicles();
         let mut cnt = CommutersVehiclesCounts {
             walking_commuters: 0,
             walking_to_from_transit: 0,
@@ -1156,6 +1882,10 @@ impl TripManager {
             cyclists: 0,
 
             sov_drivers: 0,
+            carpool_drivers: 0,
+            carpool_passengers: 0,
+            ride_hail_vehicles,
+            ride_hail_passengers,
 
             buses,
             trains,
@@ -1163,16 +1893,35 @@ impl TripManager {
             train_riders: 0,
         };
 
-        for a in self.active_trip_mode.keys() {
+        for (a, trip) in &self.active_trip_mode {
             match a {
                 AgentID::Car(c) => match c.vehicle_type {
                     VehicleType::Car => {
-                        cnt.sov_drivers += 1;
+                        match self
+                            .carpools
+                            .get(trip)
+                            .map(|carpool| carpool.riders.len())
+                            .unwrap_or(0)
+                        {
+                            0 => {
+                                cnt.sov_drivers += 1;
+                            }
+                            n => {
+                                cnt.carpool_drivers += 1;
+                                cnt.carpool_passengers += n;
+                            }
+                        }
                     }
                     VehicleType::Bike => {
                         cnt.cyclists += 1;
                     }
-                    VehicleType::Bus | VehicleType::Train => unreachable!(),
+                    VehicleType::Bus
+                    | VehicleType::Train
+                    | VehicleType::Emergency
+                    | VehicleType::Van
+                    | VehicleType::Truck => {
+                        unreachable!()
+                    }
                 },
                 AgentID::BusPassenger(_, c) => match c.vehicle_type {
                     VehicleType::Bus => {
@@ -1181,7 +1930,13 @@ impl TripManager {
                     VehicleType::Train => {
                         cnt.train_riders += 1;
                     }
-                    VehicleType::Car | VehicleType::Bike => unreachable!(),
+                    VehicleType::Car
+                    | VehicleType::Bike
+                    | VehicleType::Emergency
+                    | VehicleType::Van
+                    | VehicleType::Truck => {
+                        unreachable!()
+                    }
                 },
                 // These're counted separately
                 AgentID::Pedestrian(_) => {}
@@ -1267,6 +2022,11 @@ impl TripManager {
                         // TODO Not true for long. People will be able to spawn at borders already
                         // on a bus.
                         TripMode::Transit => AgentType::Pedestrian,
+                        // Passengers walk to their pickup
+                        TripMode::Carpool => AgentType::Pedestrian,
+                        TripMode::RideHail => AgentType::Car,
+                        // Only buildings have docks nearby
+                        TripMode::BikeShare => AgentType::Pedestrian,
                     };
                     times.push((t.info.departure, agent_type));
                 }
@@ -1288,17 +2048,34 @@ impl TripManager {
                     .iter()
                     .map(|t| {
                         let trip = &self.trips[t.0];
-                        IndividTrip::new(
+                        let mut individ_trip = IndividTrip::new(
                             trip.info.departure,
                             trip.info.purpose,
                             trip.info.start,
                             trip.info.end,
                             trip.info.mode,
-                        )
+                        );
+                        // People are created in the same order as the original scenario
+                        if let Some(passenger) = self.passengers.get(t) {
+                            let driver = &self.people[self.trips[passenger.driver.0].person.0];
+                            individ_trip.shared_ride = Some(SharedRide {
+                                driver: driver.id.0,
+                                driver_trip: driver
+                                    .trips
+                                    .iter()
+                                    .position(|x| *x == passenger.driver)
+                                    .unwrap(),
+                                pickup: passenger.pickup,
+                                dropoff: passenger.dropoff,
+                            });
+                        }
+                        individ_trip
                     })
                     .collect(),
             });
         }
+        scenario.ride_hail = self.ride_hail.fleet();
+        scenario.deliveries = self.freight.tours();
         scenario
     }
 }
@@ -1349,6 +2126,12 @@ pub(crate) enum TripLeg {
     Drive(CarID, DrivingGoal),
     /// Maybe get off at a stop, maybe ride off-map
     RideBus(BusRouteID, Option<BusStopID>),
+    /// The driver's trip, and where to get out
+    RideInCar(TripID, BuildingID),
+    /// Where to get dropped off
+    RideHail(BuildingID),
+    /// Ride a shared vehicle from one dock to another
+    BikeShare(BikeShareDockID, BikeShareDockID),
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord)]
@@ -1357,6 +2140,12 @@ pub enum TripMode {
     Bike,
     Transit,
     Drive,
+    /// Riding as a passenger in somebody else's car
+    Carpool,
+    /// Riding in an on-demand taxi or ride-hail vehicle
+    RideHail,
+    /// Riding a shared bike or e-scooter between docks
+    BikeShare,
 }
 
 impl TripMode {
@@ -1366,6 +2155,9 @@ impl TripMode {
             TripMode::Bike,
             TripMode::Transit,
             TripMode::Drive,
+            TripMode::Carpool,
+            TripMode::RideHail,
+            TripMode::BikeShare,
         ]
     }
 
@@ -1375,6 +2167,9 @@ impl TripMode {
             TripMode::Bike => "bike",
             TripMode::Transit => "use transit",
             TripMode::Drive => "drive",
+            TripMode::Carpool => "carpool",
+            TripMode::RideHail => "take a ride-hail",
+            TripMode::BikeShare => "use bike-share",
         }
     }
 
@@ -1385,6 +2180,9 @@ impl TripMode {
             TripMode::Bike => "biking",
             TripMode::Transit => "using transit",
             TripMode::Drive => "driving",
+            TripMode::Carpool => "carpooling",
+            TripMode::RideHail => "riding in a ride-hail",
+            TripMode::BikeShare => "using bike-share",
         }
     }
 
@@ -1394,16 +2192,19 @@ impl TripMode {
             TripMode::Bike => "Bike",
             TripMode::Transit => "Bus",
             TripMode::Drive => "Car",
+            TripMode::Carpool => "Car passenger",
+            TripMode::RideHail => "Ride-hail passenger",
+            TripMode::BikeShare => "Bike-share rider",
         }
     }
 
     pub fn to_constraints(self) -> PathConstraints {
         match self {
             TripMode::Walk => PathConstraints::Pedestrian,
-            TripMode::Bike => PathConstraints::Bike,
+            TripMode::Bike | TripMode::BikeShare => PathConstraints::Bike,
             // TODO WRONG
             TripMode::Transit => PathConstraints::Bus,
-            TripMode::Drive => PathConstraints::Car,
+            TripMode::Drive | TripMode::Carpool | TripMode::RideHail => PathConstraints::Car,
         }
     }
 
@@ -1469,6 +2270,100 @@ impl Person {
     }
 }
 
+/// Somebody giving other people a ride
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Carpool {
+    /// The stops left to visit, in order
+    stops: VecDeque<CarpoolStop>,
+    /// Filled out once the car first reaches a stop
+    car: Option<CarID>,
+    /// Set while the car is idling at the first stop
+    arrived_at_stop: Option<Time>,
+    /// Passengers who reached their pickup before the car did
+    waiting: BTreeSet<TripID>,
+    /// Passengers in the car right now
+    riders: BTreeSet<TripID>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CarpoolStop {
+    bldg: BuildingID,
+    pickup: Vec<TripID>,
+    dropoff: Vec<TripID>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Passenger {
+    /// The trip giving this passenger a ride
+    driver: TripID,
+    pickup: BuildingID,
+    dropoff: BuildingID,
+}
+
+/// Consecutive stops at the same building are merged.
+fn stop_at(stops: &mut VecDeque<CarpoolStop>, bldg: BuildingID) -> &mut CarpoolStop {
+    if stops.back().map(|stop| stop.bldg != bldg).unwrap_or(true) {
+        stops.push_back(CarpoolStop {
+            bldg,
+            pickup: Vec::new(),
+            dropoff: Vec::new(),
+        });
+    }
+    stops.back_mut().unwrap()
+}
+
+/// Where must somebody be before they start walking from this spot? If they're appearing from
+/// off-map, this records them entering.
+fn start_walking(
+    person: &Person,
+    start: &SidewalkSpot,
+    events: &mut Vec<Event>,
+    map: &Map,
+) -> PersonState {
+    match start.connection {
+        SidewalkPOI::Building(b) => PersonState::Inside(b),
+        SidewalkPOI::Border(i) => {
+            events.push(Event::PersonEntersMap(
+                person.id,
+                AgentID::Pedestrian(person.ped),
+                i,
+            ));
+            PersonState::OffMap
+        }
+        SidewalkPOI::SuddenlyAppear => {
+            // Unclear which end of the sidewalk this person should be associated with. For
+            // interactively spawned people, doesn't really matter.
+            events.push(Event::PersonEntersMap(
+                person.id,
+                AgentID::Pedestrian(person.ped),
+                map.get_l(start.sidewalk_pos.lane()).src_i,
+            ));
+            PersonState::OffMap
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Route a shared bike or scooter from where it is to a bike-share dock.
+fn bike_share_router(
+    now: Time,
+    vehicle: CarID,
+    from: &SidewalkSpot,
+    dock: BikeShareDockID,
+    map: &Map,
+) -> Result<Router> {
+    let goal = SidewalkSpot::bike_share_dock(dock, map)
+        .ok_or_else(|| anyhow!("no bike connection at {}", dock))?;
+    let req = match (&from.connection, &goal.connection) {
+        (SidewalkPOI::BikeRack(start), SidewalkPOI::BikeRack(end)) => {
+            PathRequest::vehicle(*start, *end, PathConstraints::Bike)
+        }
+        _ => unreachable!(),
+    };
+    let path = map.pathfind_at(req, now)?;
+    Ok(Router::bike_then_stop(vehicle, path, goal))
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub enum PersonState {
     Trip(TripID),
@@ -1486,6 +2381,12 @@ pub struct CommutersVehiclesCounts {
     pub cyclists: usize,
 
     pub sov_drivers: usize,
+    /// Drivers with at least one passenger
+    pub carpool_drivers: usize,
+    pub carpool_passengers: usize,
+    /// Ride-hail vehicles on the road, with or without a passenger
+    pub ride_hail_vehicles: usize,
+    pub ride_hail_passengers: usize,
 
     pub buses: usize,
     pub trains: usize,
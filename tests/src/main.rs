@@ -1,5 +1,6 @@
 //! Integration tests
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::File;
 use std::io::Write;
 
@@ -9,13 +10,30 @@ use rand::seq::SliceRandom;
 use abstio::{CityName, MapName};
 use abstutil::Timer;
 use geom::{Distance, Duration, Time};
-use map_model::{IntersectionID, Map};
-use sim::{IndividTrip, PersonSpec, Scenario, TripEndpoint, TripMode, TripPurpose};
+use map_model::{BikeShareDockID, BuildingID, DirectedRoadID, IntersectionID, Map};
+use sim::{
+    AgentID, CurbUse, DeliveryTour, DispatchPolicy, DynamicTrafficAssignment, EmergencyCall,
+    IndividTrip, OvertakeManeuver, PersonSpec, RerouteReason, RideHailActivity, RideHailFleet,
+    Scenario, SharedRide, TripEndpoint, TripID, TripMode, TripPhaseType, TripPurpose, TripResult,
+    VehicleType,
+};
 
 fn main() -> Result<()> {
     test_lane_changing(&import_map(abstio::path(
         "../tests/input/lane_selection.osm",
     )))?;
+    test_oncoming_pass(&import_map(abstio::path(
+        "../tests/input/oncoming_pass.osm",
+    )))?;
+    let reroute = import_map(abstio::path("../tests/input/reroute.osm"));
+    test_reroute(&reroute)?;
+    test_carpool(&reroute)?;
+    test_traffic_assignment(&reroute)?;
+    let mut curbside = import_map(abstio::path("../tests/input/curbside.osm"));
+    test_emergency_response(&curbside)?;
+    test_deliveries(&mut curbside)?;
+    test_bike_share(&import_map(abstio::path("../tests/input/bike_share.osm")))?;
+    test_ride_hail(&import_map(abstio::path("../tests/input/ride_hail.osm")))?;
     test_map_importer()?;
     check_proposals()?;
     smoke_test()?;
@@ -65,6 +83,8 @@ fn import_map(path: String) -> Map {
             extra_buildings: None,
             skip_local_roads: false,
             filter_crosswalks: false,
+            elevation: convert_osm::ElevationSource::Docker,
+            bike_share_stations: None,
         },
         &mut timer,
     );
@@ -238,3 +258,774 @@ fn test_lane_changing(map: &Map) -> Result<()> {
 
     Ok(())
 }
+
+/// Verify that a car passing a slow bike by using the oncoming lane doesn't get anywhere sooner
+/// than it would on an empty road.
+fn test_oncoming_pass(map: &Map) -> Result<()> {
+    // The map is one straight two-way road between two borders
+    let borders = borders_west_to_east(map);
+    let (west, east) = (borders[0], *borders.last().unwrap());
+
+    // Returns how long the car took, and whether it passed anybody
+    let run = |with_bike: bool| -> (Duration, bool) {
+        let mut scenario = Scenario::empty(map, "oncoming_pass");
+        let mut modes = vec![(Duration::seconds(5.0), TripMode::Drive)];
+        if with_bike {
+            modes.push((Duration::ZERO, TripMode::Bike));
+        }
+        for (depart, mode) in modes {
+            scenario.people.push(PersonSpec {
+                orig_id: None,
+                trips: vec![IndividTrip::new(
+                    Time::START_OF_DAY + depart,
+                    TripPurpose::Shopping,
+                    TripEndpoint::Border(west),
+                    TripEndpoint::Border(east),
+                    mode,
+                )],
+            });
+        }
+
+        let mut opts = sim::SimOptions::new("test_oncoming_pass");
+        opts.alerts = sim::AlertHandler::Silence;
+        opts.allow_oncoming_overtaking = true;
+        let mut sim = sim::Sim::new(map, opts);
+        let mut rng = sim::SimFlags::for_test("test_oncoming_pass").make_rng();
+        scenario.instantiate(&mut sim, map, &mut rng, &mut Timer::throwaway());
+        while !sim.is_done() {
+            sim.tiny_step(map, &mut None);
+        }
+
+        let analytics = sim.get_analytics();
+        let duration = analytics
+            .finished_trips
+            .iter()
+            .find(|(_, _, mode, _)| *mode == TripMode::Drive)
+            .and_then(|(_, _, _, duration)| *duration)
+            .expect("the car didn't finish its trip");
+        let passed = analytics.overtakes.iter().any(|(_, _, _, maneuver)| {
+            matches!(maneuver, OvertakeManeuver::UsedOncomingLane { .. })
+        });
+        (duration, passed)
+    };
+
+    let (unimpeded, _) = run(false);
+    let (with_bike, passed) = run(true);
+    if !passed {
+        panic!("The car never passed the bike using the oncoming lane");
+    }
+    // Passing shouldn't skip the car ahead. Allow a tiny bit of slack for rounding.
+    if with_bike + Duration::seconds(0.1) < unimpeded {
+        panic!(
+            "The car took {} after passing a bike, but {} on an empty road",
+            with_bike, unimpeded
+        );
+    }
+
+    Ok(())
+}
+
+/// Verify an emergency vehicle answers a call, and its response time is recorded.
+fn test_emergency_response(map: &Map) -> Result<()> {
+    let bldgs = buildings_west_to_east(map);
+    let (station, incident) = (bldgs[0], bldgs[1]);
+    let call_time = Time::START_OF_DAY + Duration::seconds(30.0);
+
+    let mut scenario = Scenario::empty(map, "emergency_response");
+    scenario.emergency_calls.push(EmergencyCall {
+        time: call_time,
+        station,
+        incident,
+    });
+    let mut opts = sim::SimOptions::new("test_emergency_response");
+    opts.alerts = sim::AlertHandler::Silence;
+    let mut sim = sim::Sim::new(map, opts);
+    let mut rng = sim::SimFlags::for_test("test_emergency_response").make_rng();
+    let mut timer = Timer::throwaway();
+    scenario.instantiate(&mut sim, map, &mut rng, &mut timer);
+    // Emergency vehicles aren't trips, so the sim doesn't wait for them to finish
+    sim.timed_step(map, Duration::minutes(10), &mut None, &mut timer);
+
+    let responses = &sim.get_analytics().emergency_responses;
+    if responses.len() != 1 {
+        panic!("Expected one emergency response, but got {:?}", responses);
+    }
+    let (arrived, _, b, response_time) = responses[0];
+    if b != incident || arrived - call_time != response_time {
+        panic!(
+            "The emergency vehicle arrived at {} at {} with a response time of {}, but the call \
+             was to {} at {}",
+            b, arrived, response_time, incident, call_time
+        );
+    }
+    // The route goes around the block, a bit over a kilometer
+    if response_time <= Duration::ZERO || response_time > Duration::minutes(5) {
+        panic!("The response time was {}", response_time);
+    }
+
+    Ok(())
+}
+
+/// Verify freight vehicles visit their stops in order and return to their depot, and only
+/// double-park when the loading zone at a stop is full.
+fn test_deliveries(map: &mut Map) -> Result<()> {
+    let bldgs = buildings_west_to_east(map);
+    let (depot, shop, cafe) = (bldgs[0], bldgs[1], bldgs[2]);
+
+    // Only the short stretch of road in front of the shop has a loading zone, with room for a few
+    // vans
+    let mut timer = Timer::throwaway();
+    let mut edits = map.get_edits().clone();
+    edits.loading_zones.insert(map.get_b(shop).sidewalk().road);
+    map.must_apply_edits(edits, &mut timer);
+    map.recalculate_pathfinding_after_edits(&mut timer);
+    let map: &Map = map;
+
+    let start = Time::START_OF_DAY;
+    let mut scenario = Scenario::empty(map, "deliveries");
+    // One van makes a tour by itself
+    scenario.deliveries.push(DeliveryTour {
+        depart: start,
+        depot,
+        stops: vec![shop, cafe],
+        vehicle_type: VehicleType::Van,
+        dwell_time: Duration::minutes(1),
+    });
+    // Later, more vans show up at the shop at about the same time than the loading zone can hold
+    let num_crowded = 8;
+    for i in 0..num_crowded {
+        scenario.deliveries.push(DeliveryTour {
+            depart: start + Duration::minutes(10) + Duration::seconds(5.0 * (i as f64)),
+            depot,
+            stops: vec![shop],
+            vehicle_type: VehicleType::Van,
+            dwell_time: Duration::minutes(10),
+        });
+    }
+
+    let mut opts = sim::SimOptions::new("test_deliveries");
+    opts.alerts = sim::AlertHandler::Silence;
+    let mut sim = sim::Sim::new(map, opts);
+    let mut rng = sim::SimFlags::for_test("test_deliveries").make_rng();
+    scenario.instantiate(&mut sim, map, &mut rng, &mut timer);
+    // Freight vehicles aren't trips, so the sim doesn't wait for them to finish
+    sim.timed_step(map, Duration::hours(3), &mut None, &mut timer);
+    let analytics = sim.get_analytics();
+
+    if analytics.delivery_tours.len() != 1 + num_crowded {
+        panic!(
+            "Only {} of {} delivery tours finished",
+            analytics.delivery_tours.len(),
+            1 + num_crowded
+        );
+    }
+
+    // The first van visits both stops in order
+    let first_van = analytics.deliveries[0].1;
+    let visited: Vec<BuildingID> = analytics
+        .deliveries
+        .iter()
+        .filter(|(_, car, _, _, _)| *car == first_van)
+        .map(|(_, _, b, _, _)| *b)
+        .collect();
+    if visited != vec![shop, cafe] {
+        panic!(
+            "{} should've visited {} then {}, but went to {:?}",
+            first_van, shop, cafe, visited
+        );
+    }
+
+    // Vans only double-park at the shop while somebody else is using the loading zone
+    let mut in_loading_zone = Vec::new();
+    let mut double_parked = Vec::new();
+    for (time, car, b, curb, dwell_time) in &analytics.deliveries {
+        if *b != shop || *car == first_van {
+            continue;
+        }
+        match curb {
+            CurbUse::LoadingZone(_) => in_loading_zone.push((*time, *time + *dwell_time)),
+            CurbUse::DoubleParked(_) => double_parked.push(*time),
+        }
+    }
+    if in_loading_zone.is_empty() || double_parked.is_empty() {
+        panic!(
+            "At the shop, {} vans used the loading zone and {} double-parked; expected some of \
+             both",
+            in_loading_zone.len(),
+            double_parked.len()
+        );
+    }
+    for time in double_parked {
+        if !in_loading_zone
+            .iter()
+            .any(|(arrive, leave)| *arrive <= time && time < *leave)
+        {
+            panic!(
+                "A van double-parked at {}, even though the loading zone was empty",
+                time
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Verify that an informed driver stuck waiting to turn onto a gridlocked road finds another way
+/// around, and the reroute is recorded.
+fn test_reroute(map: &Map) -> Result<()> {
+    // Two roads connect the west and east borders: a short direct one with a shop along it, and a
+    // longer detour.
+    let borders = borders_west_to_east(map);
+    let (west, east) = (borders[0], *borders.last().unwrap());
+    let bldgs = buildings_west_to_east(map);
+    let (depot, shop) = (bldgs[0], bldgs[1]);
+    let start = Time::START_OF_DAY;
+
+    let mut scenario = Scenario::empty(map, "reroute");
+    // A van double-parks in front of the shop for a while, blocking the direct road
+    let dwell_time = Duration::minutes(20);
+    scenario.deliveries.push(DeliveryTour {
+        depart: start,
+        depot,
+        stops: vec![shop],
+        vehicle_type: VehicleType::Van,
+        dwell_time,
+    });
+    // Plenty of cars try to use the direct road and fill it up
+    for idx in 0..40 {
+        scenario.people.push(PersonSpec {
+            orig_id: None,
+            trips: vec![IndividTrip::new(
+                start + Duration::seconds(30.0 + 3.0 * (idx as f64)),
+                TripPurpose::Shopping,
+                TripEndpoint::Border(west),
+                TripEndpoint::Border(east),
+                TripMode::Drive,
+            )],
+        });
+    }
+
+    let mut opts = sim::SimOptions::new("test_reroute");
+    opts.alerts = sim::AlertHandler::Silence;
+    opts.informed_drivers = 1.0;
+    opts.reroute_after_blocked = Duration::seconds(30.0);
+    let mut sim = sim::Sim::new(map, opts);
+    let mut rng = sim::SimFlags::for_test("test_reroute").make_rng();
+    scenario.instantiate(&mut sim, map, &mut rng, &mut Timer::throwaway());
+    while !sim.is_done() {
+        sim.tiny_step(map, &mut None);
+    }
+    let analytics = sim.get_analytics();
+
+    let van_leaves = match analytics.deliveries.get(0) {
+        Some((time, _, _, CurbUse::DoubleParked(_), _)) => *time + dwell_time,
+        x => panic!(
+            "The van should've double-parked, but the delivery was {:?}",
+            x
+        ),
+    };
+    let rerouted: BTreeSet<TripID> = analytics
+        .reroutes
+        .iter()
+        .filter(|(_, _, _, reason)| matches!(reason, RerouteReason::Blocked(_)))
+        .filter_map(|(_, _, trip, _)| *trip)
+        .collect();
+    if rerouted.is_empty() {
+        panic!("Nobody rerouted around the blocked road");
+    }
+    // Everybody who took the detour should get through long before the road clears
+    for (time, trip, _, _) in &analytics.finished_trips {
+        if rerouted.contains(trip) && *time >= van_leaves {
+            panic!(
+                "{} rerouted, but didn't finish until {}, after the van left at {}",
+                trip, time, van_leaves
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Verify drivers pick up and drop off their passengers, a passenger showing up too late misses
+/// their ride, and passengers are stranded when their driver's trip is cancelled.
+fn test_carpool(map: &Map) -> Result<()> {
+    let borders = borders_west_to_east(map);
+    let (west, east) = (borders[0], *borders.last().unwrap());
+    let bldgs = buildings_west_to_east(map);
+    let (home, work) = (bldgs[0], bldgs[1]);
+    let start = Time::START_OF_DAY;
+
+    let drive = |depart: Duration| {
+        IndividTrip::new(
+            start + depart,
+            TripPurpose::Work,
+            TripEndpoint::Border(west),
+            TripEndpoint::Border(east),
+            TripMode::Drive,
+        )
+    };
+    let passenger = |depart: Duration, driver: usize| {
+        let mut trip = IndividTrip::new(
+            start + depart,
+            TripPurpose::Work,
+            TripEndpoint::Bldg(home),
+            TripEndpoint::Bldg(work),
+            TripMode::Carpool,
+        );
+        trip.shared_ride = Some(SharedRide {
+            driver,
+            driver_trip: 0,
+            pickup: home,
+            dropoff: work,
+        });
+        trip
+    };
+    let mut scenario = Scenario::empty(map, "carpool");
+    // Everybody takes one trip, so their TripID matches their index here
+    for trip in vec![
+        // The first driver picks up somebody already waiting at home
+        drive(Duration::ZERO),
+        passenger(Duration::ZERO, 0),
+        // The driver waits a while for a second passenger, who shows up much too late
+        passenger(Duration::hours(1), 0),
+        // Later, another driver picks up somebody, then their trip is cancelled
+        drive(Duration::minutes(30)),
+        passenger(Duration::minutes(25), 3),
+    ] {
+        scenario.people.push(PersonSpec {
+            orig_id: None,
+            trips: vec![trip],
+        });
+    }
+    let (driver1, rider1, late_rider, driver2, rider2) =
+        (TripID(0), TripID(1), TripID(2), TripID(3), TripID(4));
+
+    let mut opts = sim::SimOptions::new("test_carpool");
+    opts.alerts = sim::AlertHandler::Silence;
+    let mut sim = sim::Sim::new(map, opts);
+    let mut rng = sim::SimFlags::for_test("test_carpool").make_rng();
+    scenario.instantiate(&mut sim, map, &mut rng, &mut Timer::throwaway());
+    let rode_in_car = |sim: &sim::Sim, id: TripID| {
+        sim.get_analytics()
+            .trip_log
+            .iter()
+            .any(|(_, trip, _, phase)| {
+                *trip == id && matches!(phase, TripPhaseType::RidingInCar(_))
+            })
+    };
+    let mut deleted_driver2 = false;
+    while !sim.is_done() {
+        sim.tiny_step(map, &mut None);
+        if !deleted_driver2 && rode_in_car(&sim, rider2) {
+            match sim.trip_to_agent(driver2) {
+                TripResult::Ok(AgentID::Car(car)) => sim.delete_car(car, map),
+                _ => panic!("{} picked up {}, but isn't driving", driver2, rider2),
+            }
+            deleted_driver2 = true;
+        }
+    }
+    if !deleted_driver2 {
+        panic!("{} never picked up {}", driver2, rider2);
+    }
+    let analytics = sim.get_analytics();
+
+    // The first passenger rides along, and gets dropped off before the driver leaves the map
+    let finished_at = |id: TripID| {
+        analytics
+            .finished_trips
+            .iter()
+            .find(|(_, trip, _, dur)| *trip == id && dur.is_some())
+            .map(|(time, _, _, _)| *time)
+    };
+    if !rode_in_car(&sim, rider1) {
+        panic!("{} never got in the car", rider1);
+    }
+    match (finished_at(driver1), finished_at(rider1)) {
+        (Some(t1), Some(t2)) if t2 < t1 => {}
+        x => panic!(
+            "{} should finish before {}, but they finished at {:?}",
+            rider1, driver1, x
+        ),
+    }
+
+    // Everybody else's trip is cancelled
+    for (id, expected) in [
+        (late_rider, "missed their ride"),
+        (driver2, "deleted manually"),
+        (rider2, "was cancelled"),
+    ] {
+        let reason = sim.trip_info(id).cancellation_reason.unwrap_or_default();
+        if !reason.contains(expected) {
+            panic!(
+                "{} should've been cancelled with a reason like {:?}, but got {:?}",
+                id, expected, reason
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Verify traffic assignment moves some drivers off a congested road onto the detour, so the
+/// relative gap shrinks, and that drivers replaying the assigned routes follow them exactly.
+fn test_traffic_assignment(map: &Map) -> Result<()> {
+    let borders = borders_west_to_east(map);
+    let (west, east) = (borders[0], *borders.last().unwrap());
+    let mut scenario = Scenario::empty(map, "traffic_assignment");
+    // Everybody starts on the direct road, which can't handle this many cars at once
+    for idx in 0..60 {
+        scenario.people.push(PersonSpec {
+            orig_id: None,
+            trips: vec![IndividTrip::new(
+                Time::START_OF_DAY + Duration::seconds(2.0 * (idx as f64)),
+                TripPurpose::Work,
+                TripEndpoint::Border(west),
+                TripEndpoint::Border(east),
+                TripMode::Drive,
+            )],
+        });
+    }
+
+    let mut opts = sim::SimOptions::new("test_traffic_assignment");
+    opts.alerts = sim::AlertHandler::Silence;
+    let dta = DynamicTrafficAssignment {
+        max_iterations: 6,
+        // Always run every day
+        convergence_gap: 0.0,
+        replan_fraction: 0.5,
+        rng_seed: sim::SimFlags::RNG_SEED,
+    };
+    let (assigned, results) = dta.run(scenario, map, &opts, &mut Timer::throwaway());
+    let first_gap = results[0].relative_gap;
+    let last_gap = results.last().unwrap().relative_gap;
+    if first_gap <= 0.0 || results[0].num_replanned == 0 {
+        panic!(
+            "Everybody took the direct road, but nobody could do better: {:?}",
+            results[0]
+        );
+    }
+    if last_gap >= first_gap {
+        panic!(
+            "The relative gap didn't shrink, going from {} to {}",
+            first_gap, last_gap
+        );
+    }
+
+    let routes: BTreeMap<TripID, Vec<DirectedRoadID>> = assigned
+        .people
+        .iter()
+        .enumerate()
+        .map(|(idx, person)| (TripID(idx), person.trips[0].route.clone().unwrap()))
+        .collect();
+    if routes.values().collect::<BTreeSet<_>>().len() < 2 {
+        panic!("Nobody was assigned the detour");
+    }
+
+    // Replay the final assignment
+    let mut opts = sim::SimOptions::new("test_traffic_assignment");
+    opts.alerts = sim::AlertHandler::Silence;
+    opts.record_travel_times = true;
+    let mut sim = sim::Sim::new(map, opts);
+    let mut rng = sim::SimFlags::for_test("test_traffic_assignment").make_rng();
+    assigned.instantiate(&mut sim, map, &mut rng, &mut Timer::throwaway());
+    while !sim.is_done() {
+        sim.tiny_step(map, &mut None);
+    }
+    let driven_routes = &sim.get_analytics().driven_routes;
+    for (trip, route) in routes {
+        let driven = &driven_routes[&trip];
+        if !driven.starts_with(&route) {
+            panic!(
+                "{} was assigned {:?}, but drove along {:?}",
+                trip, route, driven
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Verify bike-share riders keep the dock inventory consistent, try another dock when theirs is
+/// empty, and ride on to another dock when theirs is full.
+fn test_bike_share(map: &Map) -> Result<()> {
+    // A home and a workplace along one road. Each has a nearby dock with room for 2 vehicles, so
+    // it starts with 1, and a spare dock a bit farther away.
+    let bldgs = buildings_west_to_east(map);
+    let (home, work) = (bldgs[0], bldgs[1]);
+    let mut docks: Vec<_> = map.all_bike_share_docks().iter().collect();
+    docks.sort_by(|a, b| a.point.x().partial_cmp(&b.point.x()).unwrap());
+    let docks: Vec<BikeShareDockID> = docks.into_iter().map(|d| d.id).collect();
+    let (home_dock, spare_home_dock, work_dock, spare_work_dock) =
+        (docks[0], docks[1], docks[2], docks[3]);
+
+    // Two people set off at about the same time and both plan to use the nearby docks. The first
+    // to arrive takes the only vehicle and fills the dock at work.
+    let start = Time::START_OF_DAY;
+    let mut scenario = Scenario::empty(map, "bike_share");
+    for idx in 0..2 {
+        scenario.people.push(PersonSpec {
+            orig_id: None,
+            trips: vec![IndividTrip::new(
+                start + Duration::seconds(idx as f64),
+                TripPurpose::Work,
+                TripEndpoint::Bldg(home),
+                TripEndpoint::Bldg(work),
+                TripMode::BikeShare,
+            )],
+        });
+    }
+
+    let mut opts = sim::SimOptions::new("test_bike_share");
+    opts.alerts = sim::AlertHandler::Silence;
+    let mut sim = sim::Sim::new(map, opts);
+    let mut rng = sim::SimFlags::for_test("test_bike_share").make_rng();
+    scenario.instantiate(&mut sim, map, &mut rng, &mut Timer::throwaway());
+    while !sim.is_done() {
+        sim.tiny_step(map, &mut None);
+    }
+    let analytics = sim.get_analytics();
+
+    if analytics.finished_trips.len() != 2
+        || analytics
+            .finished_trips
+            .iter()
+            .any(|(_, _, _, dur)| dur.is_none())
+    {
+        panic!(
+            "Both trips should finish, but got {:?}",
+            analytics.finished_trips
+        );
+    }
+
+    // The second person finds the home dock empty and walks to the spare one, then finds the work
+    // dock full and rides on to the spare one.
+    let empty: Vec<(TripID, BikeShareDockID)> = analytics
+        .bike_share_empty_docks
+        .iter()
+        .map(|(_, trip, dock)| (*trip, *dock))
+        .collect();
+    let full: Vec<(TripID, BikeShareDockID)> = analytics
+        .bike_share_full_docks
+        .iter()
+        .map(|(_, trip, dock)| (*trip, *dock))
+        .collect();
+    if empty.len() != 1 || empty[0].1 != home_dock {
+        panic!(
+            "Only {} should've been empty, but got {:?}",
+            home_dock, empty
+        );
+    }
+    if full.len() != 1 || full[0].1 != work_dock {
+        panic!("Only {} should've been full, but got {:?}", work_dock, full);
+    }
+    if empty[0].0 != full[0].0 {
+        panic!(
+            "{} found the home dock empty, but {} found the work dock full",
+            empty[0].0, full[0].0
+        );
+    }
+
+    // Every vehicle taken was returned somewhere, and no dock ever held more than its capacity
+    for (dock, expected) in [
+        (home_dock, 0),
+        (spare_home_dock, 0),
+        (work_dock, 2),
+        (spare_work_dock, 6),
+    ] {
+        let history = analytics
+            .bike_share_inventory
+            .get(&dock)
+            .cloned()
+            .unwrap_or_default();
+        let capacity = map.get_dock(dock).capacity;
+        if let Some((time, count)) = history.iter().find(|(_, count)| *count > capacity) {
+            panic!(
+                "{} had {} vehicles at {}, but only has room for {}",
+                dock, count, time, capacity
+            );
+        }
+        let last = history
+            .last()
+            .map(|(_, count)| *count)
+            .unwrap_or(capacity / 2);
+        if last != expected {
+            panic!(
+                "{} wound up with {} vehicles, but should have {}",
+                dock, last, expected
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Verify both ride-hail dispatch policies serve every passenger, and compare their wait times and
+/// empty driving.
+fn test_ride_hail(map: &Map) -> Result<()> {
+    // Buildings along one side of a block. One depot can reach home directly; the other has to
+    // loop around the block.
+    let bldgs = buildings_west_to_east(map);
+    let (west_depot, home, office, east_depot) = (bldgs[0], bldgs[1], bldgs[2], bldgs[3]);
+
+    // Returns (the total wait, how many vehicles were dispatched, the distance driven to pickups,
+    // the distance driven with passengers)
+    let run = |dispatch: DispatchPolicy| -> (Duration, usize, Distance, Distance) {
+        let start = Time::START_OF_DAY;
+        let mut scenario = Scenario::empty(map, "ride_hail");
+        scenario.ride_hail = Some(RideHailFleet {
+            depots: vec![(west_depot, 1), (east_depot, 1)],
+            dispatch,
+        });
+        // Somebody needs a ride, and then somebody else long after the first vehicle returns to
+        // its depot
+        for depart in [Duration::ZERO, Duration::minutes(30)] {
+            scenario.people.push(PersonSpec {
+                orig_id: None,
+                trips: vec![IndividTrip::new(
+                    start + depart,
+                    TripPurpose::Work,
+                    TripEndpoint::Bldg(home),
+                    TripEndpoint::Bldg(office),
+                    TripMode::RideHail,
+                )],
+            });
+        }
+
+        let mut opts = sim::SimOptions::new("test_ride_hail");
+        opts.alerts = sim::AlertHandler::Silence;
+        let mut sim = sim::Sim::new(map, opts);
+        let mut rng = sim::SimFlags::for_test("test_ride_hail").make_rng();
+        let mut timer = Timer::throwaway();
+        scenario.instantiate(&mut sim, map, &mut rng, &mut timer);
+        // Vehicles cruising back to their depot aren't trips, so the sim doesn't wait for them
+        sim.timed_step(map, Duration::hours(2), &mut None, &mut timer);
+        let analytics = sim.get_analytics();
+
+        let finished = analytics
+            .finished_trips
+            .iter()
+            .filter(|(_, _, mode, dur)| *mode == TripMode::RideHail && dur.is_some())
+            .count();
+        if finished != 2 || analytics.ride_hail_waits.len() != 2 {
+            panic!(
+                "With {:?}, {} passengers arrived and {} were picked up; expected both",
+                dispatch,
+                finished,
+                analytics.ride_hail_waits.len()
+            );
+        }
+        let total_wait: Duration = analytics
+            .ride_hail_waits
+            .iter()
+            .map(|(_, _, wait)| *wait)
+            .sum();
+
+        let mut dispatched = BTreeSet::new();
+        let mut last_activity = BTreeMap::new();
+        for (_, car, activity) in &analytics.ride_hail_activity {
+            if *activity == RideHailActivity::Deadheading {
+                dispatched.insert(*car);
+            }
+            last_activity.insert(*car, *activity);
+        }
+        // Every vehicle winds up back at its depot, after driving empty to get there
+        if last_activity
+            .values()
+            .any(|activity| *activity != RideHailActivity::Idle)
+        {
+            panic!(
+                "With {:?}, some vehicles never returned to their depot: {:?}",
+                dispatch, last_activity
+            );
+        }
+        let distance = |activity| {
+            analytics
+                .ride_hail_distance
+                .get(&activity)
+                .cloned()
+                .unwrap_or(Distance::ZERO)
+        };
+        let deadheading = distance(RideHailActivity::Deadheading);
+        let cruising = distance(RideHailActivity::Cruising);
+        if cruising == Distance::ZERO
+            || analytics.ride_hail_empty_distance() != deadheading + cruising
+        {
+            panic!(
+                "With {:?}, vehicles drove {} empty, but {} to pickups and {} back to the depot",
+                dispatch,
+                analytics.ride_hail_empty_distance(),
+                deadheading,
+                cruising
+            );
+        }
+        match analytics.ride_hail_utilization(sim.time()) {
+            Some(x) if x > 0.0 && x < 1.0 => {}
+            x => panic!("With {:?}, fleet utilization was {:?}", dispatch, x),
+        }
+
+        (
+            total_wait,
+            dispatched.len(),
+            deadheading,
+            distance(RideHailActivity::Occupied),
+        )
+    };
+
+    let nearest = run(DispatchPolicy::NearestIdleVehicle);
+    let longest = run(DispatchPolicy::LongestIdleVehicle);
+    // The closest vehicle serves both requests. Otherwise, the vehicle idle since the start of
+    // the day has to come from farther away for one of them.
+    if nearest.1 != 1 || longest.1 != 2 {
+        panic!(
+            "The nearest vehicle policy dispatched {} vehicles, and the longest idle policy \
+             dispatched {}",
+            nearest.1, longest.1
+        );
+    }
+    if nearest.0 >= longest.0 || nearest.2 >= longest.2 {
+        panic!(
+            "Dispatching the nearest vehicle should be faster, but passengers waited {} and {}, \
+             and vehicles drove {} and {} to pickups",
+            nearest.0, longest.0, nearest.2, longest.2
+        );
+    }
+    // Either way, passengers ride the same route
+    if (nearest.3 - longest.3).abs() > Distance::meters(1.0) || nearest.3 == Distance::ZERO {
+        panic!(
+            "Passengers rode {} with one policy and {} with the other",
+            nearest.3, longest.3
+        );
+    }
+
+    Ok(())
+}
+
+/// All borders on the map, sorted from west to east
+fn borders_west_to_east(map: &Map) -> Vec<IntersectionID> {
+    let mut borders: Vec<_> = map
+        .all_intersections()
+        .iter()
+        .filter(|i| i.is_border())
+        .collect();
+    borders.sort_by(|a, b| {
+        a.polygon
+            .center()
+            .x()
+            .partial_cmp(&b.polygon.center().x())
+            .unwrap()
+    });
+    borders.into_iter().map(|i| i.id).collect()
+}
+
+/// All buildings on the map, sorted from west to east
+fn buildings_west_to_east(map: &Map) -> Vec<BuildingID> {
+    let mut bldgs: Vec<_> = map.all_buildings().iter().collect();
+    bldgs.sort_by(|a, b| {
+        a.polygon
+            .center()
+            .x()
+            .partial_cmp(&b.polygon.center().x())
+            .unwrap()
+    });
+    bldgs.into_iter().map(|b| b.id).collect()
+}
@@ -15,6 +15,22 @@ pub struct TrafficSignal {
     /// order of ascending `start_time_seconds`, the first plan must begin at `0` (midnight), and
     /// the last plan must not start after 24 hours.
     pub plans: Vec<Plan>,
+    /// If present, the signal gives priority to approaching transit vehicles, no matter which plan
+    /// is in effect.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transit_priority: Option<TransitPriority>,
+}
+
+/// Transit signal priority. When a bus or train approaches, the signal can hold a green light for
+/// it a little longer, or end a stage that doesn't serve it a little sooner.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TransitPriority {
+    /// A stage serving the vehicle can last at most this many extra seconds.
+    pub max_extension_seconds: usize,
+    /// A stage not serving the vehicle can end at most this many seconds early.
+    pub max_early_end_seconds: usize,
+    /// Only give priority to vehicles running behind their schedule.
+    pub only_late_vehicles: bool,
 }
 
 /// A plan describes how a traffic signal is configured during some period of time. Multiple plans
@@ -60,6 +76,33 @@ pub enum StageType {
     /// is 20, and additional is 40, the maximum cycle duration is 60.
     /// If there are crosswalks, the minimum is the minimum for the maximum crosswalks
     Variable(usize, usize, usize),
+    /// Actuated control using virtual loop detectors. Each detector covers the last
+    /// `detector_length_meters` of a lane feeding one of the stage's protected turns.
+    Actuated {
+        /// Once the stage starts, it lasts at least this many seconds.
+        min_green: usize,
+        /// After the minimum, a vehicle on any detector extends the stage by this many seconds. If
+        /// the detectors are empty, the stage ends early ("gaps out").
+        passage_time: usize,
+        /// The stage never lasts longer than this many seconds, even if vehicles keep arriving
+        /// ("maxes out").
+        max_green: usize,
+        detector_length_meters: usize,
+        /// What happens when nobody calls for the stage.
+        recall: Recall,
+    },
+}
+
+/// Does an actuated stage get served even when no vehicle is detected and no pedestrian is
+/// waiting?
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Recall {
+    /// Skip the stage if there are no calls.
+    None,
+    /// Always serve the stage for at least the minimum green time.
+    Minimum,
+    /// Always serve the stage for the maximum green time, ignoring the detectors.
+    Maximum,
 }
 
 /// A movement through an intersection.